@@ -23,6 +23,9 @@ pub struct Envelope<T> {
     pub value: T,
 }
 
+/// JSON schema of [`Compartment`] (regenerated by the `export_json_schema` test).
+pub const COMPARTMENT_JSON_SCHEMA: &str = include_str!("generated/realearn.schema.json");
+
 #[derive(Serialize, Deserialize)]
 #[serde(tag = "kind")]
 pub enum ApiObject {
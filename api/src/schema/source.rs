@@ -17,6 +17,12 @@ pub enum Source {
     MidiDeviceChanges(MidiDeviceChangesSource),
     RealearnInstanceStart(RealearnInstanceStartSource),
     Timer(TimerSource),
+    MusicalTimer(MusicalTimerSource),
+    MarkerCrossed(MarkerCrossedSource),
+    RegionEntered(RegionEnteredSource),
+    PlayStateChanges(PlayStateChangesSource),
+    ProjectSwitch(ProjectSwitchSource),
+    TrackListChanges(TrackListChangesSource),
     // MIDI
     MidiNoteVelocity(MidiNoteVelocitySource),
     MidiNoteKeyNumber(MidiNoteKeyNumberSource),
@@ -328,6 +334,9 @@ mod osc {
 
 mod reaper {
     use super::*;
+    use derive_more::Display;
+    use enum_iterator::IntoEnumIterator;
+    use num_enum::{IntoPrimitive, TryFromPrimitive};
 
     #[derive(Default, PartialEq, Serialize, Deserialize, JsonSchema)]
     #[serde(deny_unknown_fields)]
@@ -338,6 +347,72 @@ mod reaper {
     pub struct TimerSource {
         pub duration: u64,
     }
+
+    #[derive(Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+    #[serde(deny_unknown_fields)]
+    pub struct MusicalTimerSource {
+        /// Defaults to beats.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub unit: Option<MusicalTimerUnit>,
+        /// Fires every n units. Fractions such as 0.25 fire on subdivisions. Defaults to 1.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub interval: Option<f64>,
+        /// Shifts the firing positions by the given number of units. Defaults to 0.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub offset: Option<f64>,
+        /// If `true`, doesn't fire while the project is stopped or paused. Defaults to `false`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub only_while_playing: Option<bool>,
+    }
+
+    /// Musical unit of a musical timer
+    #[derive(
+        Clone,
+        Copy,
+        Debug,
+        PartialEq,
+        Eq,
+        Serialize,
+        Deserialize,
+        IntoEnumIterator,
+        TryFromPrimitive,
+        IntoPrimitive,
+        Display,
+        JsonSchema,
+    )]
+    #[repr(usize)]
+    pub enum MusicalTimerUnit {
+        #[display(fmt = "Beats")]
+        Beat,
+        #[display(fmt = "Bars")]
+        Bar,
+    }
+
+    impl Default for MusicalTimerUnit {
+        fn default() -> Self {
+            MusicalTimerUnit::Beat
+        }
+    }
+
+    #[derive(Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+    #[serde(deny_unknown_fields)]
+    pub struct MarkerCrossedSource;
+
+    #[derive(Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+    #[serde(deny_unknown_fields)]
+    pub struct RegionEnteredSource;
+
+    #[derive(Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+    #[serde(deny_unknown_fields)]
+    pub struct PlayStateChangesSource;
+
+    #[derive(Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+    #[serde(deny_unknown_fields)]
+    pub struct ProjectSwitchSource;
+
+    #[derive(Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+    #[serde(deny_unknown_fields)]
+    pub struct TrackListChangesSource;
 }
 
 mod keyboard {
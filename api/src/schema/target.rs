@@ -50,6 +50,7 @@ pub enum Target {
     ClipSeek(ClipSeekTarget),
     ClipVolume(ClipVolumeTarget),
     ClipManagement(ClipManagementTarget),
+    ClipViewport(ClipViewportTarget),
     SendMidi(SendMidiTarget),
     SendOsc(SendOscTarget),
     EnableInstances(EnableInstancesTarget),
@@ -543,6 +544,19 @@ pub enum ClipManagementAction {
     ClearSlot,
     FillSlotWithSelectedItem,
     EditClip,
+    ExportClipToItem,
+    /// Starts arrangement recording when switched on (logging all clip starts and stops with
+    /// their timeline positions) and stops it when switched off. The slot of this target is not
+    /// used.
+    RecordArrangement,
+    /// Writes the last arrangement recording as media items onto the column tracks. The slot of
+    /// this target is not used.
+    WriteArrangementToTimeline,
+    QuantizeClip,
+    UndoAudioOverdub,
+    /// Waits until any column receives input and then records into the first empty slot of that
+    /// column (global record). The slot of this target is not used.
+    DetectInputAndRecord,
 }
 
 impl Default for ClipManagementAction {
@@ -551,6 +565,40 @@ impl Default for ClipManagementAction {
     }
 }
 
+#[derive(PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ClipViewportTarget {
+    #[serde(flatten)]
+    pub commons: TargetCommons,
+    pub action: ClipViewportAction,
+    /// Number of columns (when scrolling horizontally) or rows (when scrolling vertically) which
+    /// the controller can display at once. Defaults to 8.
+    ///
+    /// Used as step size when scrolling by page. Scrolling to the right or down stops as soon as
+    /// the last page is reached.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_size: Option<u32>,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "kind")]
+pub enum ClipViewportAction {
+    ScrollLeft,
+    ScrollRight,
+    ScrollUp,
+    ScrollDown,
+    PageLeft,
+    PageRight,
+    PageUp,
+    PageDown,
+}
+
+impl Default for ClipViewportAction {
+    fn default() -> Self {
+        Self::ScrollRight
+    }
+}
+
 #[derive(PartialEq, Default, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct SendMidiTarget {
@@ -1035,10 +1083,12 @@ impl Default for TrackRouteKind {
 #[serde(tag = "address")]
 pub enum ClipSlotDescriptor {
     Selected,
+    /// Indexes are relative to the clip matrix viewport of the instance.
     ByIndex {
         column_index: usize,
         row_index: usize,
     },
+    /// Evaluated indexes are relative to the clip matrix viewport of the instance.
     Dynamic {
         column_expression: String,
         row_expression: String,
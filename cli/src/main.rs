@@ -0,0 +1,305 @@
+//! Command-line tool for validating and converting ReaLearn presets without launching REAPER.
+use jsonschema::JSONSchema;
+use realearn::diff::diff_compartments;
+use realearn::headless::{HeadlessPreset, PresetKind};
+use realearn::merge::merge_compartments;
+use realearn_api::schema::{Compartment, COMPARTMENT_JSON_SCHEMA};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const USAGE: &str = "\
+Usage:
+  realearn-cli validate [--controller|--main] <file or dir>...
+  realearn-cli to-lua [--controller|--main] [--include-defaults] [--out-dir <dir>] <file or dir>...
+  realearn-cli to-json [--out-dir <dir>] <file or dir>...
+  realearn-cli diff <old file> <new file>
+  realearn-cli merge [--controller|--main] <base file> <local file> <updated file>
+
+Controller/main is derived from the preset path (presets/controller or presets/main) or the Lua
+result if not given. Converting a single file without --out-dir writes to stdout.
+
+The merge command applies the changes between the base and the updated preset to the locally
+modified preset and writes the result to stdout. Conflicting changes are reported, the local
+version wins.
+
+Exits with code 1 if at least one file couldn't be processed and with code 2 on usage errors.";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let exit_code = match run(&args) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(msg) => {
+            eprintln!("{}\n\n{}", msg, USAGE);
+            2
+        }
+    };
+    std::process::exit(exit_code);
+}
+
+#[derive(Default)]
+struct Options {
+    kind: Option<PresetKind>,
+    include_default_values: bool,
+    out_dir: Option<PathBuf>,
+    paths: Vec<PathBuf>,
+}
+
+#[derive(Copy, Clone)]
+enum Conversion {
+    ToLua,
+    ToJson,
+}
+
+impl Conversion {
+    fn source_extension(self) -> &'static str {
+        match self {
+            Conversion::ToLua => "json",
+            Conversion::ToJson => "lua",
+        }
+    }
+
+    fn target_extension(self) -> &'static str {
+        match self {
+            Conversion::ToLua => "lua",
+            Conversion::ToJson => "json",
+        }
+    }
+}
+
+/// Returns whether all files have been processed successfully.
+fn run(args: &[String]) -> Result<bool, String> {
+    let (command, args) = args.split_first().ok_or("No command given")?;
+    let options = parse_options(args)?;
+    if options.paths.is_empty() {
+        return Err("No files given".to_string());
+    }
+    let success = match command.as_str() {
+        "validate" => validate(&options),
+        "to-lua" => convert(&options, Conversion::ToLua),
+        "to-json" => convert(&options, Conversion::ToJson),
+        "diff" => match options.paths.as_slice() {
+            [old, new] => report_error(diff(old, new, options.kind)),
+            _ => return Err("diff needs exactly 2 files".to_string()),
+        },
+        "merge" => match options.paths.as_slice() {
+            [base, local, update] => report_error(merge(base, local, update, options.kind)),
+            _ => return Err("merge needs exactly 3 files".to_string()),
+        },
+        _ => return Err(format!("Unknown command \"{}\"", command)),
+    };
+    Ok(success)
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--controller" => options.kind = Some(PresetKind::Controller),
+            "--main" => options.kind = Some(PresetKind::Main),
+            "--include-defaults" => options.include_default_values = true,
+            "--out-dir" => {
+                let dir = args.next().ok_or("--out-dir needs a directory")?;
+                options.out_dir = Some(PathBuf::from(dir));
+            }
+            a if a.starts_with("--") => return Err(format!("Unknown option \"{}\"", a)),
+            path => options.paths.push(PathBuf::from(path)),
+        }
+    }
+    Ok(options)
+}
+
+fn validate(options: &Options) -> bool {
+    let schema_json = serde_json::from_str(COMPARTMENT_JSON_SCHEMA).expect("invalid schema JSON");
+    let schema = JSONSchema::compile(&schema_json).expect("invalid schema");
+    let mut success = true;
+    for path in collect_preset_files(&options.paths, &["json", "lua"]) {
+        match validate_file(&path, options.kind, &schema) {
+            Ok(report) => println!("OK    {}: {}", path.display(), report),
+            Err(e) => {
+                success = false;
+                println!("ERROR {}: {}", path.display(), e);
+            }
+        }
+    }
+    success
+}
+
+fn validate_file(
+    path: &Path,
+    kind: Option<PresetKind>,
+    schema: &JSONSchema,
+) -> Result<String, Box<dyn Error>> {
+    let preset = HeadlessPreset::load(path, kind)?;
+    let api_json = preset.validate()?;
+    if let Err(errors) = schema.validate(&api_json) {
+        let messages: Vec<_> = errors
+            .map(|e| format!("{} (at {})", e, e.instance_path))
+            .collect();
+        let msg = format!(
+            "Doesn't conform to the ReaLearn schema:\n  {}",
+            messages.join("\n  ")
+        );
+        return Err(msg.into());
+    }
+    let mut report = format!(
+        "\"{}\" ({} mappings, {} groups)",
+        preset.name(),
+        preset.mapping_count(),
+        preset.group_count()
+    );
+    if let Some(base) = preset.base() {
+        report.push_str(&format!("\n      Extends: {}", base));
+    }
+    for include in preset.includes() {
+        report.push_str(&format!("\n      Includes: {}", include));
+    }
+    for variable in preset.variables() {
+        report.push_str(&format!(
+            "\n      Variable: {} (default: {})",
+            variable.key,
+            variable.default_value()
+        ));
+    }
+    for migration in preset.pending_migrations() {
+        report.push_str(&format!("\n      Migration: {}", migration));
+    }
+    Ok(report)
+}
+
+fn report_error(result: Result<(), Box<dyn Error>>) -> bool {
+    match result {
+        Ok(_) => true,
+        Err(e) => {
+            eprintln!("ERROR {}", e);
+            false
+        }
+    }
+}
+
+fn diff(old: &Path, new: &Path, kind: Option<PresetKind>) -> Result<(), Box<dyn Error>> {
+    let old = load_api_compartment(old, kind)?;
+    let new = load_api_compartment(new, kind)?;
+    let diff = diff_compartments(&old, &new)?;
+    if diff.is_empty() {
+        println!("No differences");
+    } else {
+        print!("{}", diff);
+    }
+    Ok(())
+}
+
+fn merge(
+    base: &Path,
+    local: &Path,
+    update: &Path,
+    kind: Option<PresetKind>,
+) -> Result<(), Box<dyn Error>> {
+    let local_preset = HeadlessPreset::load(local, kind)?;
+    let result = merge_compartments(
+        &load_api_compartment(base, kind)?,
+        &local_preset.to_api_compartment()?,
+        &load_api_compartment(update, kind)?,
+    )?;
+    for conflict in &result.conflicts {
+        eprintln!("CONFLICT {}", conflict);
+    }
+    let merged_preset = local_preset.with_api_compartment(result.compartment)?;
+    println!("{}", merged_preset.to_json()?);
+    Ok(())
+}
+
+fn load_api_compartment(
+    path: &Path,
+    kind: Option<PresetKind>,
+) -> Result<Compartment, Box<dyn Error>> {
+    let preset =
+        HeadlessPreset::load(path, kind).map_err(|e| format!("{}: {}", path.display(), e))?;
+    preset.to_api_compartment()
+}
+
+fn convert(options: &Options, conversion: Conversion) -> bool {
+    let paths = collect_preset_files(&options.paths, &[conversion.source_extension()]);
+    let write_to_stdout = paths.len() == 1 && options.out_dir.is_none();
+    let mut success = true;
+    for path in paths {
+        if let Err(e) = convert_file(&path, options, conversion, write_to_stdout) {
+            success = false;
+            eprintln!("ERROR {}: {}", path.display(), e);
+        }
+    }
+    success
+}
+
+fn convert_file(
+    path: &Path,
+    options: &Options,
+    conversion: Conversion,
+    write_to_stdout: bool,
+) -> Result<(), Box<dyn Error>> {
+    let preset = HeadlessPreset::load(path, options.kind)?;
+    for migration in preset.pending_migrations() {
+        eprintln!(
+            "WARNING {}: Conversion doesn't apply pending migration: {}",
+            path.display(),
+            migration
+        );
+    }
+    let text = match conversion {
+        Conversion::ToLua => preset.to_lua(options.include_default_values)?,
+        Conversion::ToJson => preset.to_json()?,
+    };
+    if write_to_stdout {
+        println!("{}", text);
+        return Ok(());
+    }
+    let dir = match &options.out_dir {
+        Some(d) => d.clone(),
+        None => path.parent().map(|p| p.to_path_buf()).unwrap_or_default(),
+    };
+    let file_name = path
+        .with_extension(conversion.target_extension())
+        .file_name()
+        .ok_or("invalid file name")?
+        .to_owned();
+    fs::create_dir_all(&dir)?;
+    let target_path = dir.join(file_name);
+    fs::write(&target_path, text)?;
+    eprintln!("Written {}", target_path.display());
+    Ok(())
+}
+
+/// Expands directories (recursively) to the contained files with one of the given extensions.
+fn collect_preset_files(paths: &[PathBuf], extensions: &[&str]) -> Vec<PathBuf> {
+    let mut files = vec![];
+    for path in paths {
+        if path.is_dir() {
+            collect_preset_files_in_dir(path, extensions, &mut files);
+        } else {
+            files.push(path.clone());
+        }
+    }
+    files
+}
+
+fn collect_preset_files_in_dir(dir: &Path, extensions: &[&str], files: &mut Vec<PathBuf>) {
+    let mut entries: Vec<_> = match fs::read_dir(dir) {
+        Ok(entries) => entries.flatten().map(|e| e.path()).collect(),
+        Err(_) => return,
+    };
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            collect_preset_files_in_dir(&path, extensions, files);
+        } else if path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| extensions.contains(&e))
+            .unwrap_or(false)
+        {
+            files.push(path);
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use crate::application::{
-    Affected, GroupModel, GroupProp, MappingCommand, MappingModel, MappingProp,
+    Affected, Change, GroupModel, GroupProp, MappingCommand, MappingModel, MappingProp,
 };
 use crate::domain::{CompartmentParamIndex, GroupId, MappingId, ParamSetting};
 
@@ -11,6 +11,42 @@ pub struct CompartmentModel {
     pub mappings: Vec<MappingModel>,
 }
 
+impl CompartmentModel {
+    /// Lets the given compartment model override this one.
+    ///
+    /// Groups and mappings with the same key are replaced in place, all others are appended.
+    /// Parameter settings are replaced per parameter. The default group of this model is kept.
+    pub fn override_with(&mut self, other: CompartmentModel) {
+        for (index, setting) in other.parameters {
+            match self.parameters.iter_mut().find(|(i, _)| *i == index) {
+                None => self.parameters.push((index, setting)),
+                Some(p) => p.1 = setting,
+            }
+        }
+        for group in other.groups {
+            match self.groups.iter_mut().find(|g| g.key() == group.key()) {
+                None => self.groups.push(group),
+                Some(existing_group) => {
+                    let old_group_id = existing_group.id();
+                    let new_group_id = group.id();
+                    *existing_group = group;
+                    for m in &mut self.mappings {
+                        if m.group_id() == old_group_id {
+                            m.change(MappingCommand::SetGroupId(new_group_id));
+                        }
+                    }
+                }
+            }
+        }
+        for mapping in other.mappings {
+            match self.mappings.iter_mut().find(|m| m.key() == mapping.key()) {
+                None => self.mappings.push(mapping),
+                Some(existing_mapping) => *existing_mapping = mapping,
+            }
+        }
+    }
+}
+
 pub enum CompartmentCommand {
     ChangeMapping(MappingId, MappingCommand),
 }
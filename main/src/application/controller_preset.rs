@@ -1,4 +1,7 @@
 use crate::application::{CompartmentModel, Preset};
+use crate::base::default_util::is_default;
+use crate::domain::{MidiDeviceIdentity, MidiManufacturerId};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
 
@@ -8,6 +11,7 @@ pub struct ControllerPreset {
     name: String,
     data: CompartmentModel,
     custom_data: HashMap<String, serde_json::Value>,
+    device_matchers: Vec<ControllerDeviceMatcher>,
 }
 
 impl ControllerPreset {
@@ -16,15 +20,22 @@ impl ControllerPreset {
         name: String,
         data: CompartmentModel,
         custom_data: HashMap<String, serde_json::Value>,
+        device_matchers: Vec<ControllerDeviceMatcher>,
     ) -> ControllerPreset {
         ControllerPreset {
             id,
             name,
             data,
             custom_data,
+            device_matchers,
         }
     }
 
+    /// Rules which decide whether a connected MIDI device is the controller this preset is for.
+    pub fn device_matchers(&self) -> &[ControllerDeviceMatcher] {
+        &self.device_matchers
+    }
+
     pub fn custom_data(&self) -> &HashMap<String, serde_json::Value> {
         &self.custom_data
     }
@@ -57,3 +68,170 @@ impl fmt::Display for ControllerPreset {
         f.write_str(self.name())
     }
 }
+
+/// Describes a MIDI device that a controller preset is made for.
+///
+/// All specified criteria must be met. A matcher without any criteria never matches.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ControllerDeviceMatcher {
+    /// Pattern for the MIDI device name, can contain wildcards (`*` and `?`).
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub name_pattern: Option<String>,
+    /// Manufacturer ID as sent in the SysEx identity reply, e.g. `41` or `00 20 29`.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub manufacturer_id: Option<MidiManufacturerId>,
+    /// Device family code as sent in the SysEx identity reply.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub family_code: Option<u16>,
+}
+
+impl ControllerDeviceMatcher {
+    /// Returns whether the device must be probed with an identity request in order to decide.
+    pub fn needs_identity(&self) -> bool {
+        self.manufacturer_id.is_some() || self.family_code.is_some()
+    }
+
+    pub fn matches(&self, device_name: &str, identity: Option<&MidiDeviceIdentity>) -> bool {
+        if self.name_pattern.is_none() && !self.needs_identity() {
+            return false;
+        }
+        if let Some(pattern) = &self.name_pattern {
+            if !wildmatch::WildMatch::new(pattern).matches(device_name) {
+                return false;
+            }
+        }
+        if !self.needs_identity() {
+            return true;
+        }
+        let identity = match identity {
+            None => return false,
+            Some(i) => i,
+        };
+        if let Some(manufacturer_id) = self.manufacturer_id {
+            if manufacturer_id != identity.manufacturer_id {
+                return false;
+            }
+        }
+        if let Some(family_code) = self.family_code {
+            if family_code != identity.family_code {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn novation_identity() -> MidiDeviceIdentity {
+        MidiDeviceIdentity {
+            manufacturer_id: MidiManufacturerId::Extended(0x20, 0x29),
+            family_code: 0x0113,
+            model_number: 0x0000,
+        }
+    }
+
+    #[test]
+    fn matcher_without_criteria_never_matches() {
+        // Given
+        let matcher = ControllerDeviceMatcher::default();
+        // When
+        // Then
+        assert!(!matcher.matches("Launchpad X", Some(&novation_identity())));
+        assert!(!matcher.matches("Launchpad X", None));
+    }
+
+    #[test]
+    fn match_by_name_pattern() {
+        // Given
+        let matcher = ControllerDeviceMatcher {
+            name_pattern: Some("*Launchpad X*".to_owned()),
+            ..Default::default()
+        };
+        // When
+        // Then
+        assert!(!matcher.needs_identity());
+        assert!(matcher.matches("MIDIIN2 (Launchpad X)", None));
+        assert!(!matcher.matches("Launchpad Pro", None));
+    }
+
+    #[test]
+    fn match_by_extended_manufacturer_id() {
+        // Given
+        let matcher = ControllerDeviceMatcher {
+            manufacturer_id: Some(MidiManufacturerId::Extended(0x20, 0x29)),
+            ..Default::default()
+        };
+        let roland_identity = MidiDeviceIdentity {
+            manufacturer_id: MidiManufacturerId::Short(0x41),
+            ..novation_identity()
+        };
+        // When
+        // Then
+        assert!(matcher.needs_identity());
+        assert!(matcher.matches("Foo", Some(&novation_identity())));
+        assert!(!matcher.matches("Foo", Some(&roland_identity)));
+        assert!(!matcher.matches("Foo", None));
+    }
+
+    #[test]
+    fn match_by_short_manufacturer_id() {
+        // Given
+        let matcher = ControllerDeviceMatcher {
+            manufacturer_id: Some(MidiManufacturerId::Short(0x41)),
+            ..Default::default()
+        };
+        let roland_identity = MidiDeviceIdentity {
+            manufacturer_id: MidiManufacturerId::Short(0x41),
+            ..novation_identity()
+        };
+        // When
+        // Then
+        assert!(matcher.matches("Foo", Some(&roland_identity)));
+        assert!(!matcher.matches("Foo", Some(&novation_identity())));
+    }
+
+    #[test]
+    fn match_by_all_criteria() {
+        // Given
+        let matcher = ControllerDeviceMatcher {
+            name_pattern: Some("Launchpad*".to_owned()),
+            manufacturer_id: Some(MidiManufacturerId::Extended(0x20, 0x29)),
+            family_code: Some(0x0113),
+        };
+        let other_family_identity = MidiDeviceIdentity {
+            family_code: 0x0114,
+            ..novation_identity()
+        };
+        // When
+        // Then
+        assert!(matcher.matches("Launchpad X", Some(&novation_identity())));
+        assert!(!matcher.matches("Launchpad X", Some(&other_family_identity)));
+        assert!(!matcher.matches("APC40", Some(&novation_identity())));
+    }
+
+    #[test]
+    fn deserialize_matcher() {
+        // Given
+        let json =
+            r#"{"namePattern": "Launchpad*", "manufacturerId": "00 20 29", "familyCode": 275}"#;
+        // When
+        let matcher: ControllerDeviceMatcher = serde_json::from_str(json).unwrap();
+        // Then
+        assert_eq!(
+            matcher,
+            ControllerDeviceMatcher {
+                name_pattern: Some("Launchpad*".to_owned()),
+                manufacturer_id: Some(MidiManufacturerId::Extended(0x20, 0x29)),
+                family_code: Some(275),
+            }
+        );
+        assert!(serde_json::from_str::<ControllerDeviceMatcher>(
+            r#"{"manufacturerId": "Novation"}"#
+        )
+        .is_err());
+    }
+}
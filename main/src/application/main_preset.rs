@@ -5,17 +5,103 @@ use std::fmt;
 pub struct MainPreset {
     id: String,
     name: String,
+    /// ID of the preset which this preset extends.
+    base_preset_id: Option<String>,
+    /// IDs of (usually partial) presets whose mappings are included, in the given order.
+    included_preset_ids: Vec<String>,
     data: CompartmentModel,
 }
 
 impl MainPreset {
-    pub fn new(id: String, name: String, data: CompartmentModel) -> MainPreset {
-        MainPreset { id, name, data }
+    pub fn new(
+        id: String,
+        name: String,
+        base_preset_id: Option<String>,
+        included_preset_ids: Vec<String>,
+        data: CompartmentModel,
+    ) -> MainPreset {
+        MainPreset {
+            id,
+            name,
+            base_preset_id,
+            included_preset_ids,
+            data,
+        }
     }
 
     pub fn update_data(&mut self, data: CompartmentModel) {
         self.data = data;
     }
+
+    pub fn base_preset_id(&self) -> Option<&str> {
+        self.base_preset_id.as_deref()
+    }
+
+    pub fn included_preset_ids(&self) -> &[String] {
+        &self.included_preset_ids
+    }
+
+    /// Returns whether this preset is made up of other presets.
+    pub fn is_composed(&self) -> bool {
+        self.base_preset_id.is_some() || !self.included_preset_ids.is_empty()
+    }
+
+    /// Resolves the base preset and the included presets (recursively) into the data of this
+    /// preset.
+    ///
+    /// The base preset comes first, then the included presets in the given order, then the
+    /// mappings of this preset itself. Later groups and mappings override earlier ones with the
+    /// same key. The default group is taken from the base preset if there is one.
+    pub fn resolve(
+        self,
+        find_by_id: &impl Fn(&str) -> Result<MainPreset, String>,
+    ) -> Result<MainPreset, String> {
+        self.resolve_internal(find_by_id, &mut vec![])
+    }
+
+    fn resolve_internal(
+        self,
+        find_by_id: &impl Fn(&str) -> Result<MainPreset, String>,
+        resolution_path: &mut Vec<String>,
+    ) -> Result<MainPreset, String> {
+        if !self.is_composed() {
+            return Ok(self);
+        }
+        if resolution_path.contains(&self.id) {
+            return Err(format!(
+                "Main preset \"{}\" extends or includes itself: {} -> {}",
+                self.id,
+                resolution_path.join(" -> "),
+                self.id
+            ));
+        }
+        resolution_path.push(self.id.clone());
+        let mut resolved_part = |part_id: &str| -> Result<CompartmentModel, String> {
+            let part = find_by_id(part_id).map_err(|e| {
+                format!(
+                    "Main preset \"{}\" refers to main preset \"{}\": {}",
+                    self.id, part_id, e
+                )
+            })?;
+            Ok(part.resolve_internal(find_by_id, resolution_path)?.data)
+        };
+        let mut data = match self.base_preset_id.as_ref() {
+            None => CompartmentModel {
+                parameters: vec![],
+                default_group: self.data.default_group.clone(),
+                groups: vec![],
+                mappings: vec![],
+            },
+            Some(base_preset_id) => resolved_part(base_preset_id)?,
+        };
+        for included_preset_id in &self.included_preset_ids {
+            data.override_with(resolved_part(included_preset_id)?);
+        }
+        resolution_path.pop();
+        data.override_with(self.data);
+        let resolved = MainPreset { data, ..self };
+        Ok(resolved)
+    }
 }
 
 impl Preset for MainPreset {
@@ -43,3 +129,89 @@ pub trait MainPresetManager: fmt::Debug {
 
     fn mappings_are_dirty(&self, id: &str, mappings: &[SharedMapping]) -> bool;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::{Change, GroupModel, MappingCommand, MappingModel};
+    use crate::domain::{GroupId, MappingCompartment};
+
+    fn preset(
+        id: &str,
+        base_preset_id: Option<&str>,
+        included_preset_ids: &[&str],
+        mapping_keys: &[&str],
+    ) -> MainPreset {
+        let compartment = MappingCompartment::MainMappings;
+        let mappings = mapping_keys
+            .iter()
+            .map(|key| {
+                let mut m =
+                    MappingModel::new(compartment, GroupId::default(), key.to_string().into());
+                m.change(MappingCommand::SetName(format!("{} from {}", key, id)));
+                m
+            })
+            .collect();
+        let data = CompartmentModel {
+            parameters: vec![],
+            default_group: GroupModel::default_for_compartment(compartment),
+            groups: vec![],
+            mappings,
+        };
+        MainPreset::new(
+            id.to_string(),
+            id.to_string(),
+            base_preset_id.map(|id| id.to_string()),
+            included_preset_ids
+                .iter()
+                .map(|id| id.to_string())
+                .collect(),
+            data,
+        )
+    }
+
+    fn find_by_id(id: &str) -> Result<MainPreset, String> {
+        let p = match id {
+            "base" => preset("base", None, &[], &["a", "b"]),
+            "transport" => preset("transport", None, &[], &["b", "c"]),
+            "mixer" => preset("mixer", Some("base"), &[], &["d"]),
+            "cycle-1" => preset("cycle-1", Some("cycle-2"), &[], &[]),
+            "cycle-2" => preset("cycle-2", None, &["cycle-1"], &[]),
+            _ => return Err(format!("Preset \"{}\" doesn't exist", id)),
+        };
+        Ok(p)
+    }
+
+    #[test]
+    fn resolve_base_and_includes() {
+        // Given
+        let p = preset("derived", Some("mixer"), &["transport"], &["a", "e"]);
+        // When
+        let resolved = p.resolve(&find_by_id).unwrap();
+        // Then
+        let names: Vec<_> = resolved.data().mappings.iter().map(|m| m.name()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "a from derived",
+                "b from transport",
+                "d from mixer",
+                "c from transport",
+                "e from derived"
+            ]
+        );
+        assert!(resolved.is_composed());
+    }
+
+    #[test]
+    fn detect_cycles() {
+        let result = find_by_id("cycle-1").unwrap().resolve(&find_by_id);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn detect_missing_parts() {
+        let result = preset("derived", Some("unknown"), &[], &[]).resolve(&find_by_id);
+        assert!(result.is_err());
+    }
+}
@@ -1,4 +1,7 @@
 use crate::application::CompartmentModel;
+use crate::base::default_util::is_default;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt;
 use std::fmt::Debug;
 
@@ -11,5 +14,103 @@ pub trait Preset: Debug {
 pub trait PresetManager: fmt::Debug {
     type PresetType;
 
+    /// Returns the preset with its variables (if any) set to their default values.
     fn find_by_id(&self, id: &str) -> Option<Self::PresetType>;
+
+    /// Returns the preset with its variables set to the given values. Variables without value
+    /// fall back to their default values.
+    ///
+    /// Fails if the preset doesn't exist or can't be resolved with the given values.
+    fn find_by_id_with_variable_values(
+        &self,
+        id: &str,
+        values: &PresetVariableValues,
+    ) -> Result<Self::PresetType, String>;
+
+    /// Returns the variables declared by the given preset.
+    fn variables(&self, id: &str) -> Vec<PresetVariable>;
+}
+
+/// Values of preset variables by variable key.
+pub type PresetVariableValues = HashMap<String, PresetVariableValue>;
+
+/// A variable which a preset declares in order to be usable in different setups.
+///
+/// Mappings refer to it as `${key}`, e.g. in a track name or as MIDI channel.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresetVariable {
+    pub key: String,
+    /// Label to be displayed when asking the user for a value.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub name: Option<String>,
+    #[serde(rename = "type", default, skip_serializing_if = "is_default")]
+    pub r#type: PresetVariableType,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub default: Option<PresetVariableValue>,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PresetVariableType {
+    Text,
+    Integer,
+}
+
+impl Default for PresetVariableType {
+    fn default() -> Self {
+        Self::Text
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PresetVariableValue {
+    Integer(i64),
+    Text(String),
+}
+
+impl PresetVariable {
+    pub fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.key)
+    }
+
+    pub fn default_value(&self) -> PresetVariableValue {
+        if let Some(v) = &self.default {
+            return v.clone();
+        }
+        match self.r#type {
+            PresetVariableType::Text => PresetVariableValue::Text(String::new()),
+            PresetVariableType::Integer => PresetVariableValue::Integer(0),
+        }
+    }
+
+    /// Returns the given value if there is one, otherwise the default value.
+    pub fn effective_value(&self, values: &PresetVariableValues) -> PresetVariableValue {
+        values
+            .get(&self.key)
+            .cloned()
+            .unwrap_or_else(|| self.default_value())
+    }
+
+    /// Parses a value entered by the user.
+    pub fn parse_value(&self, text: &str) -> Result<PresetVariableValue, String> {
+        match self.r#type {
+            PresetVariableType::Text => Ok(PresetVariableValue::Text(text.to_string())),
+            PresetVariableType::Integer => text
+                .trim()
+                .parse()
+                .map(PresetVariableValue::Integer)
+                .map_err(|_| format!("\"{}\" must be an integer", self.label())),
+        }
+    }
+}
+
+impl fmt::Display for PresetVariableValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PresetVariableValue::Integer(v) => v.fmt(f),
+            PresetVariableValue::Text(v) => f.write_str(v),
+        }
+    }
 }
@@ -10,6 +10,12 @@ use std::fmt::Formatter;
 
 pub trait PresetLinkManager: fmt::Debug {
     fn find_preset_linked_to_fx(&self, fx_id: &FxId) -> Option<String>;
+
+    fn find_preset_linked_to_track(&self, track_name: &str) -> Option<String>;
+
+    fn find_preset_linked_to_instrument_fx(&self, fx_id: &FxId) -> Option<String>;
+
+    fn find_preset_linked_to_project(&self, project_name: &str) -> Option<String>;
 }
 
 pub trait PresetLinkMutator {
@@ -26,8 +32,8 @@ pub struct FxPresetLinkConfig {
     links: Vec<FxPresetLink>,
 }
 
-impl PresetLinkManager for FxPresetLinkConfig {
-    fn find_preset_linked_to_fx(&self, fx_id: &FxId) -> Option<String> {
+impl FxPresetLinkConfig {
+    pub fn find_preset_linked_to_fx(&self, fx_id: &FxId) -> Option<String> {
         // Let the links with preset name have precedence.
         find_match(
             self.links.iter().filter(|l| l.fx_id.has_preset_name()),
@@ -72,6 +78,67 @@ impl FxPresetLinkConfig {
     }
 }
 
+/// Links name patterns (e.g. of tracks or projects) to presets.
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NamePresetLinkConfig {
+    links: Vec<NamePresetLink>,
+}
+
+impl NamePresetLinkConfig {
+    pub fn links(&self) -> impl Iterator<Item = &NamePresetLink> + ExactSizeIterator + '_ {
+        self.links.iter()
+    }
+
+    pub fn find_preset_linked_to_name(&self, name: &str) -> Option<String> {
+        // Let the exact links have precedence over the ones with wildcards.
+        let (exact_links, wildcard_links): (Vec<_>, Vec<_>) =
+            self.links.iter().partition(|l| !l.has_wildcards());
+        exact_links
+            .into_iter()
+            .chain(wildcard_links)
+            .find(|l| l.matches(name))
+            .map(|l| l.preset_id.clone())
+    }
+
+    pub fn update_pattern(&mut self, old_pattern: &str, new_pattern: String) {
+        if let Some(l) = self.links.iter_mut().find(|l| l.pattern == old_pattern) {
+            l.pattern = new_pattern;
+        }
+    }
+
+    pub fn remove_link(&mut self, pattern: &str) {
+        self.links.retain(|l| l.pattern != pattern);
+    }
+
+    pub fn link_preset_to_name(&mut self, preset_id: String, pattern: String) {
+        let link = NamePresetLink { pattern, preset_id };
+        if let Some(l) = self.links.iter_mut().find(|l| l.pattern == link.pattern) {
+            *l = link;
+        } else {
+            self.links.push(link);
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NamePresetLink {
+    /// Name pattern which can contain wildcards (`*` and `?`).
+    pub pattern: String,
+    pub preset_id: String,
+}
+
+impl NamePresetLink {
+    fn has_wildcards(&self) -> bool {
+        self.pattern.contains(&['*', '?'][..])
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        wildmatch::WildMatch::new(&self.pattern).matches(name)
+    }
+}
+
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FxPresetLink {
@@ -213,6 +280,15 @@ pub enum MainPresetAutoLoadMode {
     #[serde(rename = "focused-fx")]
     #[display(fmt = "Depending on focused FX")]
     FocusedFx,
+    #[serde(rename = "selected-track")]
+    #[display(fmt = "Depending on selected track")]
+    SelectedTrack,
+    #[serde(rename = "selected-track-instrument")]
+    #[display(fmt = "Depending on instrument of selected track")]
+    SelectedTrackInstrument,
+    #[serde(rename = "project")]
+    #[display(fmt = "Depending on project")]
+    Project,
 }
 
 impl Default for MainPresetAutoLoadMode {
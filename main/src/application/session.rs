@@ -2,8 +2,9 @@ use crate::application::{
     share_group, share_mapping, Affected, Change, ChangeResult, CompartmentCommand,
     CompartmentModel, CompartmentProp, ControllerPreset, FxId, FxPresetLinkConfig, GroupCommand,
     GroupModel, MainPreset, MainPresetAutoLoadMode, MappingCommand, MappingModel, MappingProp,
-    Preset, PresetLinkManager, PresetManager, ProcessingRelevance, SharedGroup, SharedMapping,
-    SourceModel, TargetCategory, TargetModel, TargetProp, VirtualControlElementType,
+    Preset, PresetLinkManager, PresetManager, PresetVariable, PresetVariableValue,
+    PresetVariableValues, ProcessingRelevance, SharedGroup, SharedMapping, SourceModel,
+    TargetCategory, TargetModel, TargetProp, VirtualControlElementType,
 };
 use crate::base::{
     prop, when, AsyncNotifier, Global, NamedChannelSender, Prop, SenderToNormalThread,
@@ -15,11 +16,11 @@ use crate::domain::{
     DomainEventHandler, ExtendedProcessorContext, FeedbackAudioHookTask, FeedbackOutput,
     FeedbackRealTimeTask, GroupId, GroupKey, IncomingCompoundSourceValue, InputDescriptor,
     InstanceContainer, InstanceId, InstanceState, MainMapping, MappingCompartment, MappingId,
-    MappingKey, MappingMatchedEvent, MessageCaptureEvent, MidiControlInput, NormalMainTask,
-    NormalRealTimeTask, OscFeedbackTask, ParamSetting, PluginParams, ProcessorContext,
-    ProjectionFeedbackValue, QualifiedMappingId, RealearnTarget, ReaperTarget, SharedInstanceState,
-    SourceFeedbackValue, Tag, TargetValueChangedEvent, VirtualControlElementId, VirtualSource,
-    VirtualSourceValue,
+    MappingKey, MappingMatchedEvent, MessageCaptureEvent, MidiControlInput, MidiDestination,
+    NormalMainTask, NormalRealTimeTask, OscFeedbackTask, ParamSetting, PluginParams,
+    ProcessorContext, ProjectionFeedbackValue, QualifiedMappingId, RealearnTarget, ReaperTarget,
+    SharedInstanceState, SourceFeedbackValue, Tag, TargetValueChangedEvent,
+    VirtualControlElementId, VirtualSource, VirtualSourceValue,
 };
 use derivative::Derivative;
 use enum_map::EnumMap;
@@ -35,7 +36,8 @@ use std::fmt::Debug;
 use core::iter;
 use helgoboss_learn::AbsoluteValue;
 use itertools::Itertools;
-use reaper_medium::RecordingInput;
+use playtime_clip_engine::main::ClipMatrixEvent;
+use reaper_medium::{MasterTrackBehavior, MidiInputDeviceId, MidiOutputDeviceId, RecordingInput};
 use std::rc::{Rc, Weak};
 
 pub trait SessionUi {
@@ -43,6 +45,7 @@ pub trait SessionUi {
     fn target_value_changed(&self, event: TargetValueChangedEvent);
     fn parameters_changed(&self, session: &Session);
     fn send_projection_feedback(&self, session: &Session, value: ProjectionFeedbackValue);
+    fn send_clip_matrix_events(&self, session: &Session, events: &[ClipMatrixEvent]);
     fn mapping_matched(&self, event: MappingMatchedEvent);
     fn handle_affected(
         &self,
@@ -120,6 +123,13 @@ pub struct Session {
     global_preset_link_manager: Box<dyn PresetLinkManager>,
     instance_preset_link_config: FxPresetLinkConfig,
     use_instance_preset_links_only: bool,
+    auto_select_controller_by_device: bool,
+    /// Values of the variables declared by presets. They are kept when switching presets, so
+    /// presets with variables of the same name share their values.
+    preset_variable_values: EnumMap<MappingCompartment, PresetVariableValues>,
+    /// The data of the active preset as it was when it was loaded or saved the last time. Serves
+    /// as base when merging an update of the preset into a modified compartment.
+    preset_snapshots: EnumMap<MappingCompartment, Option<CompartmentModel>>,
     instance_state: SharedInstanceState,
     global_feedback_audio_hook_task_sender: &'static SenderToRealTimeThread<FeedbackAudioHookTask>,
     feedback_real_time_task_sender: SenderToRealTimeThread<FeedbackRealTimeTask>,
@@ -127,6 +137,11 @@ pub struct Session {
     /// Is set as long as this ReaLearn instance wants to use a clip matrix from a foreign ReaLearn
     /// instance but this instance is not yet loaded.
     unresolved_foreign_clip_matrix_session_id: Option<String>,
+    /// Is set if this ReaLearn instance wants to use the clip matrix of the ReaLearn instance
+    /// which has the given instance tag (and owns a clip matrix).
+    ///
+    /// In contrast to the unresolved foreign session ID, this is kept even after resolving.
+    foreign_clip_matrix_instance_tag: Option<Tag>,
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -256,11 +271,15 @@ impl Session {
             global_preset_link_manager: Box::new(preset_link_manager),
             instance_preset_link_config: Default::default(),
             use_instance_preset_links_only: false,
+            auto_select_controller_by_device: false,
+            preset_variable_values: Default::default(),
+            preset_snapshots: Default::default(),
             instance_state,
             global_feedback_audio_hook_task_sender,
             feedback_real_time_task_sender,
             global_osc_feedback_task_sender,
             unresolved_foreign_clip_matrix_session_id: None,
+            foreign_clip_matrix_instance_tag: None,
         }
     }
 
@@ -287,6 +306,14 @@ impl Session {
         self.unresolved_foreign_clip_matrix_session_id = None;
     }
 
+    pub fn foreign_clip_matrix_instance_tag(&self) -> Option<&Tag> {
+        self.foreign_clip_matrix_instance_tag.as_ref()
+    }
+
+    pub fn set_foreign_clip_matrix_instance_tag(&mut self, tag: Option<Tag>) {
+        self.foreign_clip_matrix_instance_tag = tag;
+    }
+
     pub fn receives_input_from(&self, input_descriptor: &InputDescriptor) -> bool {
         match input_descriptor {
             InputDescriptor::Midi { device_id, channel } => match self.control_input() {
@@ -481,7 +508,7 @@ impl Session {
                 .merge(Global::control_surface_rx().fx_preset_changed().map_to(()))
                 .take_until(self.party_is_over()),
         )
-        .with(weak_session)
+        .with(weak_session.clone())
         // Doing this async is important to let REAPER digest the info about "Is the window open?"
         // and "What FX is focused?"
         .do_async(move |s, _| {
@@ -501,6 +528,21 @@ impl Session {
                 s.borrow_mut().auto_load_preset_linked_to_fx(fx_id);
             }
         });
+        // When track selection, track FX or project changes, maybe trigger main preset change
+        when(
+            Global::control_surface_rx()
+                .track_selected_changed()
+                .map_to(())
+                .merge(Global::control_surface_rx().track_name_changed().map_to(()))
+                .merge(Global::control_surface_rx().fx_added().map_to(()))
+                .merge(Global::control_surface_rx().fx_removed().map_to(()))
+                .merge(Global::control_surface_rx().project_switched().map_to(()))
+                .take_until(self.party_is_over()),
+        )
+        .with(weak_session)
+        .do_async(move |s, _| {
+            s.borrow_mut().auto_load_preset_linked_to_context();
+        });
     }
 
     pub fn activate_main_preset_auto_load_mode(&mut self, mode: MainPresetAutoLoadMode) {
@@ -508,6 +550,7 @@ impl Session {
             self.activate_main_preset(None).unwrap();
         }
         self.main_preset_auto_load_mode.set(mode);
+        self.auto_load_preset_linked_to_context();
     }
 
     pub fn main_preset_auto_load_is_active(&self) -> bool {
@@ -516,6 +559,10 @@ impl Session {
 
     fn auto_load_preset_linked_to_fx(&mut self, fx_id: Option<FxId>) {
         let final_preset_id = self.find_preset_linked_to_fx(fx_id);
+        self.auto_load_main_preset(final_preset_id);
+    }
+
+    fn auto_load_main_preset(&mut self, final_preset_id: Option<String>) {
         // Activate preset if not active already.
         if self.active_main_preset_id == final_preset_id {
             return;
@@ -523,6 +570,45 @@ impl Session {
         let _ = self.activate_main_preset(final_preset_id);
     }
 
+    /// Takes care of all auto-load modes which don't depend on the focused FX.
+    ///
+    /// If the table of the chosen mode doesn't contain a matching link, the tables of the more
+    /// general modes are consulted: instrument of selected track, then selected track, then
+    /// project.
+    fn auto_load_preset_linked_to_context(&mut self) {
+        use MainPresetAutoLoadMode as M;
+        let project = self.processor_context.project_or_current_project();
+        let selected_track =
+            || project.first_selected_track(MasterTrackBehavior::ExcludeMasterTrack);
+        let links = &self.global_preset_link_manager;
+        let find_instrument_link = || {
+            selected_track()
+                .and_then(|t| t.normal_fx_chain().first_instrument_fx())
+                .and_then(|fx| FxId::from_fx(&fx, false).ok())
+                .and_then(|fx_id| links.find_preset_linked_to_instrument_fx(&fx_id))
+        };
+        let find_track_link = || {
+            selected_track()
+                .and_then(|t| t.name())
+                .and_then(|name| links.find_preset_linked_to_track(name.to_str()))
+        };
+        let find_project_link = || {
+            project
+                .file()
+                .and_then(|f| Some(f.file_stem()?.to_string_lossy().to_string()))
+                .and_then(|name| links.find_preset_linked_to_project(&name))
+        };
+        let final_preset_id = match self.main_preset_auto_load_mode.get() {
+            M::Off | M::FocusedFx => return,
+            M::SelectedTrackInstrument => find_instrument_link()
+                .or_else(find_track_link)
+                .or_else(find_project_link),
+            M::SelectedTrack => find_track_link().or_else(find_project_link),
+            M::Project => find_project_link(),
+        };
+        self.auto_load_main_preset(final_preset_id);
+    }
+
     fn find_preset_linked_to_fx(&self, fx_id: Option<FxId>) -> Option<String> {
         let fx_id = fx_id?;
         if let Some(preset_id) = self
@@ -1766,6 +1852,101 @@ impl Session {
         self.use_instance_preset_links_only = value;
     }
 
+    /// If enabled, the controller preset and MIDI I/O are chosen automatically whenever a
+    /// controller matching one of the controller presets is connected.
+    pub fn auto_select_controller_by_device(&self) -> bool {
+        self.auto_select_controller_by_device
+    }
+
+    pub fn set_auto_select_controller_by_device(&mut self, value: bool) {
+        self.auto_select_controller_by_device = value;
+    }
+
+    pub fn preset_variable_values(&self, compartment: MappingCompartment) -> &PresetVariableValues {
+        &self.preset_variable_values[compartment]
+    }
+
+    pub fn set_preset_variable_values(
+        &mut self,
+        compartment: MappingCompartment,
+        values: PresetVariableValues,
+    ) {
+        self.preset_variable_values[compartment] = values;
+    }
+
+    pub fn set_preset_variable_value(
+        &mut self,
+        compartment: MappingCompartment,
+        key: String,
+        value: PresetVariableValue,
+    ) {
+        self.preset_variable_values[compartment].insert(key, value);
+    }
+
+    pub fn preset_snapshot(&self, compartment: MappingCompartment) -> Option<&CompartmentModel> {
+        self.preset_snapshots[compartment].as_ref()
+    }
+
+    pub fn set_preset_snapshot(
+        &mut self,
+        compartment: MappingCompartment,
+        snapshot: Option<CompartmentModel>,
+    ) {
+        self.preset_snapshots[compartment] = snapshot;
+    }
+
+    /// Replaces the compartment with the result of merging an update of the active preset into
+    /// the local modifications.
+    pub fn apply_merged_preset_update(
+        &mut self,
+        compartment: MappingCompartment,
+        merged: CompartmentModel,
+        update: CompartmentModel,
+    ) {
+        self.replace_compartment(compartment, Some(merged));
+        self.preset_snapshots[compartment] = Some(update);
+        self.compartment_is_dirty[compartment].set(true);
+    }
+
+    /// Returns the variables declared by the given preset.
+    pub fn preset_variables(
+        &self,
+        compartment: MappingCompartment,
+        id: &str,
+    ) -> Vec<PresetVariable> {
+        match compartment {
+            MappingCompartment::ControllerMappings => self.controller_preset_manager.variables(id),
+            MappingCompartment::MainMappings => self.main_preset_manager.variables(id),
+        }
+    }
+
+    /// Switches to the given controller preset and MIDI device I/O (as long as the user hasn't
+    /// modified the controller compartment).
+    pub fn auto_select_controller(
+        &mut self,
+        preset_id: &str,
+        input_device_id: MidiInputDeviceId,
+        output_device_id: Option<MidiOutputDeviceId>,
+    ) -> Result<(), String> {
+        if self.compartment_or_preset_is_dirty(MappingCompartment::ControllerMappings) {
+            return Err("controller compartment has unsaved modifications".to_string());
+        }
+        let control_input = ControlInput::Midi(MidiControlInput::Device(input_device_id));
+        if self.control_input() != control_input {
+            self.control_input.set(control_input);
+        }
+        if let Some(dev_id) = output_device_id {
+            let feedback_output = Some(FeedbackOutput::Midi(MidiDestination::Device(dev_id)));
+            if self.feedback_output() != feedback_output {
+                self.feedback_output.set(feedback_output);
+            }
+        }
+        if self.active_controller_preset_id() != Some(preset_id) {
+            self.activate_controller_preset(Some(preset_id.to_string()))?;
+        }
+        Ok(())
+    }
+
     pub fn instance_preset_link_config(&self) -> &FxPresetLinkConfig {
         &self.instance_preset_link_config
     }
@@ -1805,13 +1986,19 @@ impl Session {
     }
 
     pub fn active_controller_preset(&self) -> Option<ControllerPreset> {
-        let id = self.active_preset_id(MappingCompartment::ControllerMappings)?;
-        self.controller_preset_manager.find_by_id(id)
+        let compartment = MappingCompartment::ControllerMappings;
+        let id = self.active_preset_id(compartment)?;
+        self.controller_preset_manager
+            .find_by_id_with_variable_values(id, &self.preset_variable_values[compartment])
+            .ok()
     }
 
     pub fn active_main_preset(&self) -> Option<MainPreset> {
-        let id = self.active_preset_id(MappingCompartment::MainMappings)?;
-        self.main_preset_manager.find_by_id(id)
+        let compartment = MappingCompartment::MainMappings;
+        let id = self.active_preset_id(compartment)?;
+        self.main_preset_manager
+            .find_by_id_with_variable_values(id, &self.preset_variable_values[compartment])
+            .ok()
     }
 
     /// Returns `true` if the preset has unsaved changes (if a preset is active) or if at least one
@@ -1826,37 +2013,37 @@ impl Session {
         }
     }
 
-    pub fn activate_controller_preset(&mut self, id: Option<String>) -> Result<(), &'static str> {
+    pub fn activate_controller_preset(&mut self, id: Option<String>) -> Result<(), String> {
         let compartment = MappingCompartment::ControllerMappings;
         let model = if let Some(id) = id.as_ref() {
             let preset = self
                 .controller_preset_manager
-                .find_by_id(id)
-                .ok_or("controller preset not found")?;
+                .find_by_id_with_variable_values(id, &self.preset_variable_values[compartment])?;
             Some(preset.data().clone())
         } else {
             // <None> preset
             None
         };
         self.active_controller_preset_id = id;
+        self.preset_snapshots[compartment] = model.clone();
         self.replace_compartment(compartment, model);
         self.compartment_is_dirty[compartment].set(false);
         Ok(())
     }
 
-    pub fn activate_main_preset(&mut self, id: Option<String>) -> Result<(), &'static str> {
+    pub fn activate_main_preset(&mut self, id: Option<String>) -> Result<(), String> {
         let compartment = MappingCompartment::MainMappings;
         let model = if let Some(id) = id.as_ref() {
             let preset = self
                 .main_preset_manager
-                .find_by_id(id)
-                .ok_or("main preset not found")?;
+                .find_by_id_with_variable_values(id, &self.preset_variable_values[compartment])?;
             Some(preset.data().clone())
         } else {
             // <None> preset
             None
         };
         self.active_main_preset_id = id;
+        self.preset_snapshots[compartment] = model.clone();
         self.replace_compartment(compartment, model);
         self.compartment_is_dirty[compartment].set(false);
         Ok(())
@@ -2369,6 +2556,11 @@ impl DomainEventHandler for WeakSession {
                     s.ui.send_projection_feedback(&s, value);
                 }
             }
+            ClipMatrixChanged(events) => {
+                if let Ok(s) = session.try_borrow() {
+                    s.ui.send_clip_matrix_events(&s, events);
+                }
+            }
             MappingMatched(event) => {
                 if let Ok(s) = session.try_borrow() {
                     s.ui.mapping_matched(event);
@@ -4,8 +4,8 @@ use crate::application::{
 use crate::domain::{
     BackboneState, CompoundMappingSource, EelMidiSourceScript, ExtendedSourceCharacter,
     FlexibleMidiSourceScript, KeySource, Keystroke, LuaMidiSourceScript, MappingCompartment,
-    MidiSource, ReaperSource, TimerSource, VirtualControlElement, VirtualControlElementId,
-    VirtualSource, VirtualTarget,
+    MidiSource, MusicalTimerSource, ReaperSource, TimerSource, VirtualControlElement,
+    VirtualControlElementId, VirtualSource, VirtualTarget,
 };
 use derive_more::Display;
 use enum_iterator::IntoEnumIterator;
@@ -16,7 +16,7 @@ use helgoboss_learn::{
 };
 use helgoboss_midi::{Channel, U14, U7};
 use num_enum::{IntoPrimitive, TryFromPrimitive};
-use realearn_api::schema::MidiScriptKind;
+use realearn_api::schema::{MidiScriptKind, MusicalTimerUnit};
 use serde::{Deserialize, Serialize};
 use serde_repr::*;
 use std::borrow::Cow;
@@ -50,6 +50,10 @@ pub enum SourceCommand {
     SetOscFeedbackArgs(Vec<String>),
     SetReaperSourceType(ReaperSourceType),
     SetTimerMillis(u64),
+    SetMusicalTimerUnit(MusicalTimerUnit),
+    SetMusicalTimerInterval(f64),
+    SetMusicalTimerOffset(f64),
+    SetMusicalTimerOnlyWhilePlaying(bool),
     SetKeystroke(Option<Keystroke>),
     SetControlElementType(VirtualControlElementType),
     SetControlElementId(VirtualControlElementId),
@@ -82,6 +86,10 @@ pub enum SourceProp {
     ControlElementType,
     ControlElementId,
     TimerMillis,
+    MusicalTimerUnit,
+    MusicalTimerInterval,
+    MusicalTimerOffset,
+    MusicalTimerOnlyWhilePlaying,
     Keystroke,
 }
 
@@ -201,6 +209,22 @@ impl<'a> Change<'a> for SourceModel {
                 self.timer_millis = v;
                 One(P::TimerMillis)
             }
+            C::SetMusicalTimerUnit(v) => {
+                self.musical_timer_unit = v;
+                One(P::MusicalTimerUnit)
+            }
+            C::SetMusicalTimerInterval(v) => {
+                self.musical_timer_interval = v;
+                One(P::MusicalTimerInterval)
+            }
+            C::SetMusicalTimerOffset(v) => {
+                self.musical_timer_offset = v;
+                One(P::MusicalTimerOffset)
+            }
+            C::SetMusicalTimerOnlyWhilePlaying(v) => {
+                self.musical_timer_only_while_playing = v;
+                One(P::MusicalTimerOnlyWhilePlaying)
+            }
             C::SetKeystroke(v) => {
                 self.keystroke = v;
                 One(P::Keystroke)
@@ -239,6 +263,10 @@ pub struct SourceModel {
     // REAPER
     reaper_source_type: ReaperSourceType,
     timer_millis: u64,
+    musical_timer_unit: MusicalTimerUnit,
+    musical_timer_interval: f64,
+    musical_timer_offset: f64,
+    musical_timer_only_while_playing: bool,
     // Key
     keystroke: Option<Keystroke>,
     // Virtual
@@ -274,6 +302,10 @@ impl Default for SourceModel {
             osc_feedback_args: vec![],
             reaper_source_type: Default::default(),
             timer_millis: Default::default(),
+            musical_timer_unit: Default::default(),
+            musical_timer_interval: 1.0,
+            musical_timer_offset: 0.0,
+            musical_timer_only_while_playing: false,
             keystroke: None,
         }
     }
@@ -376,6 +408,22 @@ impl SourceModel {
         self.timer_millis
     }
 
+    pub fn musical_timer_unit(&self) -> MusicalTimerUnit {
+        self.musical_timer_unit
+    }
+
+    pub fn musical_timer_interval(&self) -> f64 {
+        self.musical_timer_interval
+    }
+
+    pub fn musical_timer_offset(&self) -> f64 {
+        self.musical_timer_offset
+    }
+
+    pub fn musical_timer_only_while_playing(&self) -> bool {
+        self.musical_timer_only_while_playing
+    }
+
     pub fn control_element_type(&self) -> VirtualControlElementType {
         self.control_element_type
     }
@@ -641,6 +689,12 @@ impl SourceModel {
                     MidiDeviceChanges => ReaperSource::MidiDeviceChanges,
                     RealearnInstanceStart => ReaperSource::RealearnInstanceStart,
                     Timer => ReaperSource::Timer(self.create_timer_source()),
+                    MusicalTimer => ReaperSource::MusicalTimer(self.create_musical_timer_source()),
+                    MarkerCrossed => ReaperSource::MarkerCrossed,
+                    RegionEntered => ReaperSource::RegionEntered,
+                    PlayStateChanges => ReaperSource::PlayStateChanges,
+                    ProjectSwitch => ReaperSource::ProjectSwitch,
+                    TrackListChanges => ReaperSource::TrackListChanges,
                 };
                 CompoundMappingSource::Reaper(reaper_source)
             }
@@ -658,6 +712,15 @@ impl SourceModel {
         TimerSource::new(Duration::from_millis(self.timer_millis))
     }
 
+    fn create_musical_timer_source(&self) -> MusicalTimerSource {
+        MusicalTimerSource::new(
+            self.musical_timer_unit,
+            self.musical_timer_interval,
+            self.musical_timer_offset,
+            self.musical_timer_only_while_playing,
+        )
+    }
+
     fn display_spec(&self) -> DisplaySpec {
         use DisplayType::*;
         match self.display_type {
@@ -1132,6 +1195,24 @@ pub enum ReaperSourceType {
     #[serde(rename = "timer")]
     #[display(fmt = "Timer")]
     Timer,
+    #[serde(rename = "musical-timer")]
+    #[display(fmt = "Musical timer")]
+    MusicalTimer,
+    #[serde(rename = "marker-crossed")]
+    #[display(fmt = "Marker crossed")]
+    MarkerCrossed,
+    #[serde(rename = "region-entered")]
+    #[display(fmt = "Region entered")]
+    RegionEntered,
+    #[serde(rename = "play-state-changes")]
+    #[display(fmt = "Play state changes")]
+    PlayStateChanges,
+    #[serde(rename = "project-switch")]
+    #[display(fmt = "Project switch")]
+    ProjectSwitch,
+    #[serde(rename = "track-list-changes")]
+    #[display(fmt = "Track list changes")]
+    TrackListChanges,
 }
 
 impl Default for ReaperSourceType {
@@ -1147,6 +1228,12 @@ impl ReaperSourceType {
             MidiDeviceChanges => Self::MidiDeviceChanges,
             RealearnInstanceStart => Self::RealearnInstanceStart,
             Timer(_) => Self::Timer,
+            MusicalTimer(_) => Self::MusicalTimer,
+            MarkerCrossed => Self::MarkerCrossed,
+            RegionEntered => Self::RegionEntered,
+            PlayStateChanges => Self::PlayStateChanges,
+            ProjectSwitch => Self::ProjectSwitch,
+            TrackListChanges => Self::TrackListChanges,
         }
     }
 }
@@ -25,8 +25,8 @@ use crate::domain::{
     TrackRouteDescriptor, TrackRouteSelector, TrackRouteType, TransportAction,
     UnresolvedActionTarget, UnresolvedAllTrackFxEnableTarget, UnresolvedAnyOnTarget,
     UnresolvedAutomationModeOverrideTarget, UnresolvedClipManagementTarget,
-    UnresolvedClipSeekTarget, UnresolvedClipTransportTarget, UnresolvedClipVolumeTarget,
-    UnresolvedCompoundMappingTarget, UnresolvedEnableInstancesTarget,
+    UnresolvedClipSeekTarget, UnresolvedClipTransportTarget, UnresolvedClipViewportTarget,
+    UnresolvedClipVolumeTarget, UnresolvedCompoundMappingTarget, UnresolvedEnableInstancesTarget,
     UnresolvedEnableMappingsTarget, UnresolvedFxEnableTarget, UnresolvedFxNavigateTarget,
     UnresolvedFxOnlineTarget, UnresolvedFxOpenTarget, UnresolvedFxParameterTarget,
     UnresolvedFxParameterTouchStateTarget, UnresolvedFxPresetTarget, UnresolvedGoToBookmarkTarget,
@@ -50,7 +50,9 @@ use std::borrow::Cow;
 use std::error::Error;
 
 use playtime_clip_engine::main::SlotPlayOptions;
-use realearn_api::schema::{ClipManagementAction, ClipSlotDescriptor, MonitoringMode};
+use realearn_api::schema::{
+    ClipManagementAction, ClipSlotDescriptor, ClipViewportAction, MonitoringMode,
+};
 use reaper_medium::{
     AutomationMode, BookmarkId, GlobalAutomationModeOverride, InputMonitoringMode, TrackArea,
     TrackLocation, TrackSendDirection,
@@ -122,6 +124,8 @@ pub enum TargetCommand {
     SetOscDevId(Option<OscDeviceId>),
     SetClipSlot(ClipSlotDescriptor),
     SetClipManagementAction(ClipManagementAction),
+    SetClipViewportAction(ClipViewportAction),
+    SetClipViewportPageSize(Option<u32>),
     SetPollForFeedback(bool),
     SetTags(Vec<Tag>),
     SetExclusivity(Exclusivity),
@@ -195,6 +199,8 @@ pub enum TargetProp {
     OscDevId,
     ClipSlot,
     ClipManagementAction,
+    ClipViewportAction,
+    ClipViewportPageSize,
     PollForFeedback,
     Tags,
     Exclusivity,
@@ -478,6 +484,14 @@ impl<'a> Change<'a> for TargetModel {
                 self.clip_management_action = v;
                 One(P::ClipManagementAction)
             }
+            C::SetClipViewportAction(v) => {
+                self.clip_viewport_action = v;
+                One(P::ClipViewportAction)
+            }
+            C::SetClipViewportPageSize(v) => {
+                self.clip_viewport_page_size = v;
+                One(P::ClipViewportPageSize)
+            }
         };
         Some(affected)
     }
@@ -578,6 +592,8 @@ pub struct TargetModel {
     // # For clip targets
     clip_slot: ClipSlotDescriptor,
     clip_management_action: ClipManagementAction,
+    clip_viewport_action: ClipViewportAction,
+    clip_viewport_page_size: Option<u32>,
     // # For targets that might have to be polled in order to get automatic feedback in all cases.
     poll_for_feedback: bool,
     tags: Vec<Tag>,
@@ -658,6 +674,8 @@ impl Default for TargetModel {
             active_mappings_only: false,
             clip_slot: ClipSlotDescriptor::Selected,
             clip_management_action: Default::default(),
+            clip_viewport_action: Default::default(),
+            clip_viewport_page_size: None,
         }
     }
 }
@@ -903,6 +921,14 @@ impl TargetModel {
         self.clip_management_action
     }
 
+    pub fn clip_viewport_action(&self) -> ClipViewportAction {
+        self.clip_viewport_action
+    }
+
+    pub fn clip_viewport_page_size(&self) -> Option<u32> {
+        self.clip_viewport_page_size
+    }
+
     pub fn poll_for_feedback(&self) -> bool {
         self.poll_for_feedback
     }
@@ -1889,6 +1915,12 @@ impl TargetModel {
                             action: self.clip_management_action,
                         })
                     }
+                    ClipViewport => {
+                        UnresolvedReaperTarget::ClipViewport(UnresolvedClipViewportTarget {
+                            action: self.clip_viewport_action,
+                            page_size: self.clip_viewport_page_size,
+                        })
+                    }
                     LoadMappingSnapshot => UnresolvedReaperTarget::LoadMappingSnapshot(
                         UnresolvedLoadMappingSnapshotTarget {
                             scope: TagScope {
@@ -2296,7 +2328,7 @@ impl<'a> Display for TargetModelFormatMultiLine<'a> {
                 use ReaperTargetType::*;
                 let tt = self.target.r#type;
                 match tt {
-                    ClipTransport | ClipSeek | ClipVolume => {
+                    ClipTransport | ClipSeek | ClipVolume | ClipViewport => {
                         write!(f, "{}", tt)
                     }
                     Action => write!(
@@ -1,16 +1,17 @@
 use crate::base::non_blocking_lock;
 use crate::domain::{
     classify_midi_message, AudioBlockProps, ControlEvent, ControlEventTimestamp, Garbage,
-    GarbageBin, IncomingMidiMessage, InstanceId, MidiControlInput, MidiEvent,
-    MidiMessageClassification, MidiScanResult, MidiScanner, RealTimeProcessor,
+    GarbageBin, IncomingMidiMessage, InstanceId, MidiControlInput, MidiDeviceIdentity, MidiEvent,
+    MidiIdentityReply, MidiIdentityReplySender, MidiMessageClassification, MidiScanResult,
+    MidiScanner, RealTimeProcessor, SampleOffset,
 };
 use assert_no_alloc::*;
 use helgoboss_learn::{MidiSourceValue, RawMidiEvents};
-use helgoboss_midi::{Channel, DataEntryByteOrder, RawShortMessage};
+use helgoboss_midi::{Channel, DataEntryByteOrder, RawShortMessage, ShortMessage};
 use playtime_clip_engine::global_steady_timeline_state;
 use playtime_clip_engine::main::{
     ClipRecordDestination, ClipRecordHardwareInput, ClipRecordHardwareMidiInput,
-    VirtualClipRecordHardwareMidiInput,
+    InputDetectionTask, VirtualClipRecordAudioInput, VirtualClipRecordHardwareMidiInput,
 };
 use playtime_clip_engine::rt::supplier::{WriteAudioRequest, WriteMidiRequest};
 use playtime_clip_engine::rt::{AudioBuf, BasicAudioRequestProps, Column};
@@ -48,6 +49,9 @@ pub enum NormalAudioHookTask {
     StartCapturingMidi(MidiCaptureSender),
     StopCapturingMidi,
     StartClipRecording(HardwareInputClipRecordTask),
+    StartInputDetection(InputDetectionTask),
+    StartIdentifyingMidiDevice(MidiOutputDeviceId, MidiIdentityReplySender),
+    StopIdentifyingMidiDevice(MidiOutputDeviceId),
 }
 
 #[derive(Debug)]
@@ -75,6 +79,8 @@ pub struct RealearnAudioHook {
     time_of_last_run: Option<Instant>,
     garbage_bin: GarbageBin,
     clip_record_task: Option<HardwareInputClipRecordTask>,
+    input_detection_task: Option<InputDetectionTask>,
+    midi_identity_probes: SmallVec<[(MidiOutputDeviceId, MidiIdentityReplySender); 16]>,
     initialized: bool,
 }
 
@@ -103,6 +109,8 @@ impl RealearnAudioHook {
             time_of_last_run: None,
             garbage_bin,
             clip_record_task: None,
+            input_detection_task: None,
+            midi_identity_probes: Default::default(),
             initialized: false,
         }
     }
@@ -236,10 +244,37 @@ impl RealearnAudioHook {
         }
     }
 
+    /// Forwards identity replies arriving on any MIDI input device to all running device probes.
+    fn process_midi_identity_replies(&mut self) {
+        if self.midi_identity_probes.is_empty() {
+            return;
+        }
+        for dev in Reaper::get().midi_input_devices() {
+            dev.with_midi_input(|mi| {
+                if let Some(mi) = mi {
+                    for e in mi.get_read_buf() {
+                        if let Some(identity) =
+                            MidiDeviceIdentity::from_identity_reply(e.message().as_slice())
+                        {
+                            for (output_device_id, sender) in &self.midi_identity_probes {
+                                let reply = MidiIdentityReply {
+                                    output_device_id: *output_device_id,
+                                    input_device_id: dev.id(),
+                                    identity,
+                                };
+                                let _ = sender.try_send(reply);
+                            }
+                        }
+                    }
+                }
+            });
+        }
+    }
+
     fn process_clip_record_task(&mut self, args: &OnAudioBufferArgs) {
         if let Some(t) = &mut self.clip_record_task {
-            let its_our_turn = (t.destination.is_midi_overdub && args.is_post)
-                || (!t.destination.is_midi_overdub && !args.is_post);
+            let its_our_turn = (t.destination.is_overdub && args.is_post)
+                || (!t.destination.is_overdub && !args.is_post);
             if its_our_turn && !process_clip_record_task(args, t) {
                 tracing_debug!("Clearing clip record task from audio hook");
                 self.clip_record_task = None;
@@ -247,6 +282,25 @@ impl RealearnAudioHook {
         }
     }
 
+    fn process_input_detection_task(&mut self, args: &OnAudioBufferArgs) {
+        if let Some(t) = &mut self.input_detection_task {
+            if t.is_still_relevant()
+                && !process_input_detection_task(
+                    args,
+                    t,
+                    &mut self.clip_record_task,
+                    &self.garbage_bin,
+                )
+            {
+                return;
+            }
+            tracing_debug!("Clearing input detection task from audio hook");
+            if let Some(t) = self.input_detection_task.take() {
+                self.garbage_bin.dispose(Garbage::InputDetectionTask(t));
+            }
+        }
+    }
+
     fn distribute_midi_events_to_processors(
         &mut self,
         block_props: AudioBlockProps,
@@ -326,6 +380,43 @@ impl RealearnAudioHook {
                     tracing_debug!("Audio hook received clip record task");
                     self.clip_record_task = Some(task);
                 }
+                StartIdentifyingMidiDevice(output_device_id, sender) => {
+                    if let Some((_, old_sender)) = self
+                        .midi_identity_probes
+                        .iter_mut()
+                        .find(|(id, _)| *id == output_device_id)
+                    {
+                        let old_sender = std::mem::replace(old_sender, sender);
+                        self.garbage_bin
+                            .dispose(Garbage::MidiIdentityReplySender(old_sender));
+                    } else if self.midi_identity_probes.len()
+                        < self.midi_identity_probes.inline_size()
+                    {
+                        self.midi_identity_probes.push((output_device_id, sender));
+                    } else {
+                        // Growing the vector would allocate.
+                        self.garbage_bin
+                            .dispose(Garbage::MidiIdentityReplySender(sender));
+                    }
+                }
+                StopIdentifyingMidiDevice(output_device_id) => {
+                    if let Some(i) = self
+                        .midi_identity_probes
+                        .iter()
+                        .position(|(id, _)| *id == output_device_id)
+                    {
+                        let (_, sender) = self.midi_identity_probes.swap_remove(i);
+                        self.garbage_bin
+                            .dispose(Garbage::MidiIdentityReplySender(sender));
+                    }
+                }
+                StartInputDetection(task) => {
+                    tracing_debug!("Audio hook received input detection task");
+                    if let Some(old_task) = self.input_detection_task.replace(task) {
+                        self.garbage_bin
+                            .dispose(Garbage::InputDetectionTask(old_task));
+                    }
+                }
             }
         }
     }
@@ -365,7 +456,9 @@ impl OnAudioBuffer for RealearnAudioHook {
                     false
                 };
                 self.process_feedback_tasks();
+                self.process_midi_identity_replies();
                 self.call_real_time_processors(block_props, might_be_rebirth);
+                self.process_input_detection_task(&args);
             }
             self.process_clip_record_task(&args);
             // Process normal tasks after processing the clip record task so that clip recording
@@ -460,6 +553,7 @@ fn process_clip_record_task(
                     record_task.destination.slot_index,
                     dev,
                     specific_input.channel,
+                    record_task.destination.is_midi_replace,
                 );
             } else {
                 // Read from all open MIDI input devices
@@ -470,6 +564,7 @@ fn process_clip_record_task(
                         record_task.destination.slot_index,
                         dev,
                         specific_input.channel,
+                        record_task.destination.is_midi_replace,
                     );
                 }
             }
@@ -485,6 +580,118 @@ fn process_clip_record_task(
     true
 }
 
+/// Returns whether input has been detected.
+///
+/// As soon as input has been detected, this starts the recording which has been prepared for the
+/// detected column. It does so right in this audio block, so that the input which triggered the
+/// detection ends up in the recording as well. If the column is locked by another thread at that
+/// moment, the detection goes on in the next block.
+fn process_input_detection_task(
+    args: &OnAudioBufferArgs,
+    task: &mut InputDetectionTask,
+    clip_record_task: &mut Option<HardwareInputClipRecordTask>,
+    garbage_bin: &GarbageBin,
+) -> bool {
+    let block_props = BasicAudioRequestProps::from_on_audio_buffer_args(args);
+    // Find the first candidate with input and the frame within the block at which it occurred
+    let detected_candidate = task.candidates.iter().enumerate().find_map(|(i, c)| {
+        let frame_offset = match &c.input {
+            ClipRecordHardwareInput::Midi(VirtualClipRecordHardwareMidiInput::Specific(input)) => {
+                find_play_msg_in_midi_input(input.device_id, input.channel, block_props)
+            }
+            ClipRecordHardwareInput::Midi(VirtualClipRecordHardwareMidiInput::Detect) => {
+                find_play_msg_in_midi_input(None, None, block_props)
+            }
+            ClipRecordHardwareInput::Audio(input) => {
+                let range = match input {
+                    VirtualClipRecordAudioInput::Specific(range) => range,
+                    // Such columns are rejected when starting the detection.
+                    VirtualClipRecordAudioInput::Detect { .. } => return None,
+                };
+                let request = AudioHookWriteAudioRequest::new(
+                    args.reg,
+                    block_props,
+                    range.first_channel_index as _,
+                );
+                (0..range.channel_count as usize)
+                    .filter_map(|ch| {
+                        let buf = request.get_channel_buffer(ch)?;
+                        buf.data_as_slice()
+                            .iter()
+                            .position(|sample| sample.abs() > task.audio_threshold)
+                    })
+                    .min()
+            }
+        }?;
+        Some((i, frame_offset))
+    });
+    let (candidate_index, frame_offset) = match detected_candidate {
+        None => return false,
+        Some(c) => c,
+    };
+    let column_source = match task.candidates[candidate_index]
+        .destination
+        .column_source
+        .upgrade()
+    {
+        None => return true,
+        Some(s) => s,
+    };
+    let mut column = match column_source.try_lock() {
+        None => return false,
+        Some(c) => c,
+    };
+    let (candidate, instruction) = match task.claim_candidate(candidate_index) {
+        // Cancelled in the meantime
+        None => return true,
+        Some(c) => c,
+    };
+    let result = column.record_clip(
+        candidate.destination.slot_index,
+        instruction,
+        block_props,
+        frame_offset,
+    );
+    if result.is_ok() {
+        // Write the input of this block into the clip in the same audio callback.
+        let new_task = HardwareInputClipRecordTask {
+            input: candidate.input.clone(),
+            destination: candidate.destination.clone(),
+        };
+        if let Some(old_task) = clip_record_task.replace(new_task) {
+            garbage_bin.dispose(Garbage::ClipRecordTask(old_task));
+        }
+    }
+    true
+}
+
+/// Checks the given MIDI input device (or all if `None`) for play messages.
+///
+/// Returns the frame offset of the first play message within the current block.
+fn find_play_msg_in_midi_input(
+    device_id: Option<MidiInputDeviceId>,
+    channel: Option<Channel>,
+    block_props: BasicAudioRequestProps,
+) -> Option<usize> {
+    let find_in_dev = |dev: MidiInputDevice| {
+        dev.with_midi_input(|mi| {
+            let evt = mi?.get_read_buf().into_iter().find(|e| {
+                let msg = e.message();
+                playtime_clip_engine::midi_util::is_play_message(msg)
+                    && channel.map_or(true, |ch| msg.channel() == Some(ch))
+            })?;
+            // Frame offset is given in 1/1024000 of a second, *not* sample frames!
+            let offset =
+                SampleOffset::from_frame_offset(evt.frame_offset(), block_props.frame_rate);
+            Some(offset.get() as usize)
+        })
+    };
+    match device_id {
+        None => Reaper::get().midi_input_devices().find_map(find_in_dev),
+        Some(id) => find_in_dev(Reaper::get().midi_input_device_by_id(id)),
+    }
+}
+
 fn find_first_dev_with_play_msg() -> Option<MidiInputDeviceId> {
     for dev in Reaper::get().midi_input_devices() {
         let contains_play_msg = dev.with_midi_input(|mi| match mi {
@@ -507,6 +714,7 @@ fn write_midi_to_clip_slot(
     slot_index: usize,
     dev: MidiInputDevice,
     channel_filter: Option<Channel>,
+    erase_without_events: bool,
 ) {
     dev.with_midi_input(|mi| {
         let mi = match mi {
@@ -514,7 +722,9 @@ fn write_midi_to_clip_slot(
             Some(m) => m,
         };
         let events = mi.get_read_buf();
-        if events.get_size() == 0 {
+        // In MIDI replace mode, we also write if there are no events, because this erases the
+        // existing events.
+        if events.get_size() == 0 && !erase_without_events {
             return;
         }
         let req = WriteMidiRequest {
@@ -7,6 +7,7 @@ use crate::domain::{
 };
 use playtime_clip_engine::rt::WeakMatrix;
 use reaper_high::{Reaper, Track};
+use rxrust::prelude::*;
 use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
@@ -29,6 +30,7 @@ pub struct BackboneState {
     /// control the same clip matrix from different controllers.
     instance_states: RefCell<HashMap<InstanceId, WeakInstanceState>>,
     server_event_sender: tokio::sync::broadcast::Sender<ServerEventType>,
+    midi_devices_connected_subject: RefCell<LocalSubject<'static, (), ()>>,
 }
 
 pub type ServerEventType = f64;
@@ -43,6 +45,7 @@ impl BackboneState {
             upper_floor_instances: Default::default(),
             instance_states: Default::default(),
             server_event_sender: tokio::sync::broadcast::channel(1000).0,
+            midi_devices_connected_subject: Default::default(),
         }
     }
 
@@ -340,6 +343,17 @@ impl BackboneState {
         }
     }
 
+    /// Fires whenever the control surface detects newly connected MIDI devices.
+    pub fn midi_devices_connected(
+        &self,
+    ) -> impl LocalObservable<'static, Item = (), Err = ()> + 'static {
+        self.midi_devices_connected_subject.borrow().clone()
+    }
+
+    pub(super) fn notify_midi_devices_connected(&self) {
+        self.midi_devices_connected_subject.borrow_mut().next(());
+    }
+
     pub(super) fn set_last_touched_target(&self, target: ReaperTarget) {
         *self.last_touched_target.borrow_mut() = Some(target);
     }
@@ -1,23 +1,26 @@
 use crate::base::{Global, NamedChannelSender, SenderToNormalThread};
 use crate::domain::{
-    BackboneState, CompoundMappingSource, ControlEvent, ControlEventTimestamp,
+    BackboneState, BookmarkPayload, CompoundMappingSource, ControlEvent, ControlEventTimestamp,
     DeviceChangeDetector, DeviceControlInput, DeviceFeedbackOutput, DomainEventHandler,
-    EelTransformation, FeedbackOutput, FeedbackRealTimeTask, InstanceId, LifecycleMidiData,
-    MainProcessor, MidiCaptureSender, MidiDeviceChangePayload, NormalRealTimeTask, OscDeviceId,
-    OscInputDevice, OscScanResult, QualifiedClipMatrixEvent, RealTimeCompoundMappingTarget,
-    RealTimeMapping, RealTimeMappingUpdate, RealTimeTargetUpdate, ReaperMessage, ReaperTarget,
-    SharedMainProcessors, SharedRealTimeProcessor, SourceFeedbackValue, TouchedTrackParameterType,
+    EelTransformation, FeedbackOutput, FeedbackRealTimeTask, HardwareInputClipRecordTask,
+    InstanceId, LifecycleMidiData, MainProcessor, MidiCaptureSender, MidiDeviceChangePayload,
+    MidiIdentityReplySender, NormalRealTimeTask, OscDeviceId, OscInputDevice, OscScanResult,
+    PlayStatePayload, ProjectPayload, ProjectPlayState, QualifiedClipMatrixEvent,
+    RealTimeCompoundMappingTarget, RealTimeMapping, RealTimeMappingUpdate, RealTimeTargetUpdate,
+    ReaperMessage, ReaperTarget, SharedMainProcessors, SharedRealTimeProcessor,
+    SourceFeedbackValue, TouchedTrackParameterType,
 };
 use crossbeam_channel::Receiver;
 use helgoboss_learn::{ModeGarbage, RawMidiEvents};
 use reaper_high::{
-    ChangeDetectionMiddleware, ControlSurfaceEvent, ControlSurfaceMiddleware, FutureMiddleware, Fx,
-    FxParameter, MainTaskMiddleware, Project, Reaper,
+    BookmarkType, ChangeDetectionMiddleware, ControlSurfaceEvent, ControlSurfaceMiddleware,
+    FutureMiddleware, Fx, FxParameter, MainTaskMiddleware, Project, Reaper,
 };
 use reaper_rx::ControlSurfaceRxMiddleware;
 use rosc::{OscMessage, OscPacket};
 
 use itertools::{EitherOrBoth, Itertools};
+use playtime_clip_engine::main::InputDetectionTask;
 use playtime_clip_engine::rt::WeakMatrix;
 use reaper_medium::{
     CommandId, ExtSupportsExtendedTouchArgs, GetTouchStateArgs, MediaTrack, MidiInputDeviceId,
@@ -60,6 +63,7 @@ pub struct RealearnControlSurfaceMiddleware<EH: DomainEventHandler> {
     osc_input_devices: Vec<OscInputDevice>,
     garbage_receiver: crossbeam_channel::Receiver<Garbage>,
     device_change_detector: DeviceChangeDetector,
+    project_snapshot: Option<ProjectSnapshot>,
     control_surface_event_sender: SenderToNormalThread<ControlSurfaceEvent<'static>>,
     control_surface_event_receiver: crossbeam_channel::Receiver<ControlSurfaceEvent<'static>>,
 }
@@ -78,7 +82,10 @@ pub enum Garbage {
     NormalRealTimeTask(NormalRealTimeTask),
     FeedbackRealTimeTask(FeedbackRealTimeTask),
     MidiCaptureSender(MidiCaptureSender),
+    MidiIdentityReplySender(MidiIdentityReplySender),
     ClipMatrix(WeakMatrix),
+    InputDetectionTask(InputDetectionTask),
+    ClipRecordTask(HardwareInputClipRecordTask),
 }
 
 #[derive(Debug)]
@@ -226,6 +233,7 @@ impl<EH: DomainEventHandler> RealearnControlSurfaceMiddleware<EH> {
             osc_input_devices: vec![],
             garbage_receiver,
             device_change_detector,
+            project_snapshot: None,
             control_surface_event_sender,
             control_surface_event_receiver,
         }
@@ -270,6 +278,7 @@ impl<EH: DomainEventHandler> RealearnControlSurfaceMiddleware<EH> {
         self.process_instance_orchestration_events();
         self.emit_beats_as_feedback_events();
         self.emit_device_changes_as_reaper_source_messages(timestamp);
+        self.emit_project_changes_as_reaper_source_messages(timestamp);
         self.process_incoming_osc_messages(timestamp);
         self.poll_clip_matrixes();
         self.process_incoming_clip_matrix_events();
@@ -554,6 +563,69 @@ impl<EH: DomainEventHandler> RealearnControlSurfaceMiddleware<EH> {
                     p.process_reaper_message(evt);
                 }
             }
+            if msgs
+                .iter()
+                .any(|msg| matches!(msg, ReaperMessage::MidiDevicesConnected(_)))
+            {
+                BackboneState::get().notify_midi_devices_connected();
+            }
+        }
+    }
+
+    fn emit_project_changes_as_reaper_source_messages(&mut self, timestamp: ControlEventTimestamp) {
+        let new = ProjectSnapshot::take();
+        let old = match self.project_snapshot.replace(new) {
+            // First cycle. Don't fire anything.
+            None => return,
+            Some(old) => old,
+        };
+        let project = new.project;
+        let mut msgs = Vec::new();
+        if new.project != old.project {
+            msgs.push(ReaperMessage::ProjectSwitched(ProjectPayload { project }));
+        } else {
+            if new.play_state != old.play_state {
+                msgs.push(ReaperMessage::PlayStateChanged(PlayStatePayload {
+                    project,
+                    play_state: new.play_state,
+                }));
+            }
+            if new.track_count > old.track_count {
+                msgs.push(ReaperMessage::TracksAdded(ProjectPayload { project }));
+            } else if new.track_count < old.track_count {
+                msgs.push(ReaperMessage::TracksRemoved(ProjectPayload { project }));
+            }
+            // Only report markers and regions which have been reached by playing. Just starting
+            // playback somewhere in the middle of a region doesn't count as entering it.
+            if let (Some(old_pos), Some(new_pos)) = (old.play_position, new.play_position) {
+                let bookmarks = project.bookmarks().map(|b| {
+                    let info = b.basic_info();
+                    (info.bookmark_type(), info.position.get())
+                });
+                let reached =
+                    reached_bookmarks(bookmarks, old_pos.get(), new_pos.get(), new.loop_range);
+                for b in reached {
+                    let payload = BookmarkPayload {
+                        project,
+                        index: b.index,
+                        count: b.count,
+                    };
+                    let msg = match b.bookmark_type {
+                        BookmarkType::Marker => ReaperMessage::MarkerCrossed(payload),
+                        BookmarkType::Region => ReaperMessage::RegionEntered(payload),
+                    };
+                    msgs.push(msg);
+                }
+            }
+        }
+        if msgs.is_empty() {
+            return;
+        }
+        for p in &mut *self.main_processors.borrow_mut() {
+            for msg in &msgs {
+                let evt = ControlEvent::new(msg, timestamp);
+                p.process_reaper_message(evt);
+            }
         }
     }
 
@@ -800,3 +872,239 @@ fn reset_midi_devices(
         reaper_low.midi_init(input_arg, output_arg);
     }
 }
+
+/// The state of the current project which is relevant for project-related REAPER sources.
+#[derive(Copy, Clone, Debug)]
+struct ProjectSnapshot {
+    project: Project,
+    play_state: ProjectPlayState,
+    track_count: u32,
+    /// Only while playing or recording.
+    play_position: Option<PositionInSeconds>,
+    /// Start and end of the loop, only if repeat is enabled.
+    loop_range: Option<(f64, f64)>,
+}
+
+impl ProjectSnapshot {
+    fn take() -> Self {
+        let project = Reaper::get().current_project();
+        let play_state = project.play_state();
+        let play_state = ProjectPlayState::from_flags(
+            play_state.is_playing,
+            play_state.is_paused,
+            play_state.is_recording,
+        );
+        let play_position = if play_state.is_playing_or_recording() {
+            Some(project.play_position_next_audio_block())
+        } else {
+            None
+        };
+        let loop_range = if project.repeat_is_enabled() {
+            project
+                .loop_points()
+                .map(|r| (r.start.get(), r.end.get()))
+                .filter(|(start, end)| end > start)
+        } else {
+            None
+        };
+        Self {
+            project,
+            play_state,
+            track_count: project.track_count(),
+            play_position,
+            loop_range,
+        }
+    }
+}
+
+/// Maximum distance in seconds which the play position can advance between two main loop cycles
+/// without being considered a jump (e.g. a seek).
+const MAX_PLAY_POSITION_ADVANCE: f64 = 1.0;
+
+/// A marker or region which has been reached by playing.
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct ReachedBookmark {
+    bookmark_type: BookmarkType,
+    /// Index among all bookmarks of the same type.
+    index: u32,
+    /// Number of bookmarks of the same type in the project.
+    count: u32,
+}
+
+/// Returns all markers crossed and regions entered when playing from `old_pos` to `new_pos`, in
+/// playback order.
+///
+/// Expects the type and (start) position of all bookmarks of the project in project order.
+/// If repeat is enabled and the play position wrapped from the end of the given loop range to its
+/// start, this reports the bookmarks until the loop end followed by the ones from the loop start.
+/// Returns nothing if the play position moved backwards (other than by such a wrap) or too far,
+/// because that's a jump and not continuous playback.
+fn reached_bookmarks(
+    bookmarks: impl Iterator<Item = (BookmarkType, f64)>,
+    old_pos: f64,
+    new_pos: f64,
+    loop_range: Option<(f64, f64)>,
+) -> Vec<ReachedBookmark> {
+    // Each section is a range of positions which has been played, including its end. Its start
+    // is only included right after wrapping to the loop start.
+    let sections = if new_pos > old_pos {
+        if new_pos - old_pos > MAX_PLAY_POSITION_ADVANCE {
+            return vec![];
+        }
+        [Some((old_pos, new_pos, false)), None]
+    } else {
+        match loop_range {
+            Some((loop_start, loop_end))
+                if old_pos <= loop_end
+                    && new_pos >= loop_start
+                    && (loop_end - old_pos) + (new_pos - loop_start)
+                        <= MAX_PLAY_POSITION_ADVANCE =>
+            {
+                [
+                    Some((old_pos, loop_end, false)),
+                    Some((loop_start, new_pos, true)),
+                ]
+            }
+            _ => return vec![],
+        }
+    };
+    let mut marker_count = 0;
+    let mut region_count = 0;
+    let mut indexed_bookmarks = Vec::new();
+    for (bookmark_type, pos) in bookmarks {
+        let count = match bookmark_type {
+            BookmarkType::Marker => &mut marker_count,
+            BookmarkType::Region => &mut region_count,
+        };
+        indexed_bookmarks.push((bookmark_type, pos, *count));
+        *count += 1;
+    }
+    sections
+        .iter()
+        .flatten()
+        .flat_map(|&(start, end, include_start)| {
+            indexed_bookmarks.iter().filter(move |(_, pos, _)| {
+                (*pos > start || (include_start && *pos == start)) && *pos <= end
+            })
+        })
+        .map(|&(bookmark_type, _, index)| ReachedBookmark {
+            bookmark_type,
+            index,
+            count: match bookmark_type {
+                BookmarkType::Marker => marker_count,
+                BookmarkType::Region => region_count,
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bookmarks() -> impl Iterator<Item = (BookmarkType, f64)> {
+        use BookmarkType::*;
+        vec![
+            (Marker, 1.0),
+            (Region, 2.0),
+            (Marker, 2.5),
+            (Marker, 3.0),
+            (Region, 4.0),
+        ]
+        .into_iter()
+    }
+
+    fn reached(bookmark_type: BookmarkType, index: u32, count: u32) -> ReachedBookmark {
+        ReachedBookmark {
+            bookmark_type,
+            index,
+            count,
+        }
+    }
+
+    #[test]
+    fn reach_nothing_between_bookmarks() {
+        // Given
+        let bookmarks = bookmarks();
+        // When
+        let result = reached_bookmarks(bookmarks, 1.1, 1.9, None);
+        // Then
+        assert_eq!(result, vec![]);
+    }
+
+    #[test]
+    fn reach_single_bookmark() {
+        // Given
+        let bookmarks = bookmarks();
+        // When
+        let result = reached_bookmarks(bookmarks, 1.9, 2.0, None);
+        // Then
+        assert_eq!(result, vec![reached(BookmarkType::Region, 0, 2)]);
+    }
+
+    #[test]
+    fn reach_all_bookmarks_in_between() {
+        // Given
+        let bookmarks = bookmarks();
+        // When
+        let result = reached_bookmarks(bookmarks, 2.2, 3.1, None);
+        // Then
+        assert_eq!(
+            result,
+            vec![
+                reached(BookmarkType::Marker, 1, 3),
+                reached(BookmarkType::Marker, 2, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn ignore_bookmark_at_previous_position() {
+        // Given
+        let bookmarks = bookmarks();
+        // When
+        let result = reached_bookmarks(bookmarks, 1.0, 1.5, None);
+        // Then
+        assert_eq!(result, vec![]);
+    }
+
+    #[test]
+    fn ignore_jumps() {
+        // Given
+        let bookmarks = bookmarks().collect::<Vec<_>>();
+        // When
+        let backward = reached_bookmarks(bookmarks.iter().copied(), 3.5, 0.5, None);
+        let forward = reached_bookmarks(bookmarks.iter().copied(), 0.5, 3.5, None);
+        // Then
+        assert_eq!(backward, vec![]);
+        assert_eq!(forward, vec![]);
+    }
+
+    #[test]
+    fn reach_bookmarks_when_wrapping_at_loop_end() {
+        // Given
+        let bookmarks = bookmarks();
+        // When
+        let result = reached_bookmarks(bookmarks, 2.8, 2.1, Some((2.0, 3.0)));
+        // Then
+        assert_eq!(
+            result,
+            vec![
+                reached(BookmarkType::Marker, 2, 3),
+                reached(BookmarkType::Region, 0, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn ignore_jumps_within_loop() {
+        // Given
+        let bookmarks = bookmarks().collect::<Vec<_>>();
+        // When
+        let backward = reached_bookmarks(bookmarks.iter().copied(), 3.4, 2.6, Some((1.5, 3.5)));
+        let outside = reached_bookmarks(bookmarks.iter().copied(), 3.9, 1.6, Some((1.5, 3.5)));
+        // Then
+        assert_eq!(backward, vec![]);
+        assert_eq!(outside, vec![]);
+    }
+}
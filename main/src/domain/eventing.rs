@@ -3,6 +3,7 @@ use crate::domain::{
     PluginParams, ProjectionFeedbackValue, QualifiedMappingId, RawParamValue,
 };
 use helgoboss_learn::AbsoluteValue;
+use playtime_clip_engine::main::ClipMatrixEvent;
 use std::collections::HashSet;
 use std::fmt::Debug;
 
@@ -22,6 +23,8 @@ pub enum DomainEvent<'a> {
     MappingMatched(MappingMatchedEvent),
     FullResyncRequested,
     MappingEnabledChangeRequested(MappingEnabledChangeRequestedEvent),
+    /// Events of the clip matrix used by this instance (might be owned by another instance).
+    ClipMatrixChanged(&'a [ClipMatrixEvent]),
 }
 
 #[derive(Clone, Debug)]
@@ -5,6 +5,7 @@ use std::rc::{Rc, Weak};
 use enum_map::EnumMap;
 use reaper_high::Track;
 use rxrust::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use crate::base::{NamedChannelSender, Prop, SenderToNormalThread, SenderToRealTimeThread};
 use crate::domain::{
@@ -13,7 +14,7 @@ use crate::domain::{
     Tag,
 };
 use playtime_clip_engine::main::{
-    ClipMatrixEvent, ClipMatrixHandler, ClipRecordInput, ClipRecordTask, Matrix,
+    ClipMatrixEvent, ClipMatrixHandler, ClipRecordInput, ClipRecordTask, InputDetectionTask, Matrix,
 };
 use playtime_clip_engine::rt;
 
@@ -68,6 +69,13 @@ pub struct InstanceState {
     /// - Set by target "ReaLearn: Enable/disable instances".
     /// - Non-redundant state!
     active_instance_tags: HashSet<Tag>,
+    /// Which part of the clip matrix is addressed by this instance.
+    ///
+    /// - Slot descriptors addressing slots by index resolve relative to this viewport.
+    /// - Makes most sense when several instances share one clip matrix, each one serving its
+    ///   own pad controller.
+    /// - Non-redundant state!
+    clip_matrix_viewport: ClipMatrixViewport,
 }
 
 #[derive(Debug)]
@@ -76,6 +84,14 @@ pub enum ClipMatrixRef {
     Foreign(InstanceId),
 }
 
+/// Offset applied when addressing clip slots by index.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClipMatrixViewport {
+    pub column_offset: usize,
+    pub row_offset: usize,
+}
+
 #[derive(Debug)]
 pub struct RealearnClipMatrixHandler {
     instance_id: InstanceId,
@@ -128,6 +144,11 @@ impl ClipMatrixHandler for RealearnClipMatrixHandler {
         }
     }
 
+    fn request_input_detection(&self, task: InputDetectionTask) {
+        self.audio_hook_task_sender
+            .send_complaining(NormalAudioHookTask::StartInputDetection(task));
+    }
+
     fn emit_event(&self, event: ClipMatrixEvent) {
         let event = QualifiedClipMatrixEvent {
             instance_id: self.instance_id,
@@ -166,6 +187,7 @@ impl InstanceState {
             on_mappings: Default::default(),
             active_mapping_tags: Default::default(),
             active_instance_tags: Default::default(),
+            clip_matrix_viewport: Default::default(),
         }
     }
 
@@ -342,6 +364,23 @@ impl InstanceState {
             .send_complaining(InstanceStateChanged::ActiveInstanceTags);
     }
 
+    pub fn clip_matrix_viewport(&self) -> ClipMatrixViewport {
+        self.clip_matrix_viewport
+    }
+
+    pub fn set_clip_matrix_viewport_without_notification(&mut self, viewport: ClipMatrixViewport) {
+        self.clip_matrix_viewport = viewport;
+    }
+
+    pub fn set_clip_matrix_viewport(&mut self, viewport: ClipMatrixViewport) {
+        if viewport == self.clip_matrix_viewport {
+            return;
+        }
+        self.clip_matrix_viewport = viewport;
+        self.instance_feedback_event_sender
+            .send_complaining(InstanceStateChanged::ClipMatrixViewport);
+    }
+
     pub fn mapping_is_on(&self, id: QualifiedMappingId) -> bool {
         self.on_mappings.get_ref().contains(&id)
     }
@@ -463,4 +502,5 @@ pub enum InstanceStateChanged {
         compartment: MappingCompartment,
     },
     ActiveInstanceTags,
+    ClipMatrixViewport,
 }
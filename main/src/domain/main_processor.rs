@@ -448,6 +448,7 @@ impl<EH: DomainEventHandler> MainProcessor<EH> {
                     self.collections.mappings[compartment].get_mut(id)
                 {
                     let control_context = self.basics.control_context();
+                    let project = self.basics.context.project_or_current_project();
                     let processor_context = ExtendedProcessorContext::new(
                         &self.basics.context,
                         &self.collections.parameters,
@@ -476,7 +477,7 @@ impl<EH: DomainEventHandler> MainProcessor<EH> {
                         (false, mode_poll_result)
                     } else if m.source().wants_to_be_polled() && m.control_is_effectively_on() {
                         // Mode was either not polled at all or without result, poll source.
-                        let res = if let Some(source_control_value) = m.poll_source() {
+                        let res = if let Some(source_control_value) = m.poll_source(project) {
                             let control_event = ControlEvent::new(source_control_value, timestamp);
                             control_mapping_stage_one(
                                 &self.basics,
@@ -689,6 +690,7 @@ impl<EH: DomainEventHandler> MainProcessor<EH> {
     }
 
     fn process_instance_feedback_events(&mut self) {
+        let mut clip_matrix_viewport_changed = false;
         for event in self
             .basics
             .channels
@@ -696,6 +698,9 @@ impl<EH: DomainEventHandler> MainProcessor<EH> {
             .try_iter()
             .take(FEEDBACK_TASK_BULK_SIZE)
         {
+            if matches!(event, InstanceStateChanged::ClipMatrixViewport) {
+                clip_matrix_viewport_changed = true;
+            }
             self.process_feedback_related_reaper_event(|mapping, target| {
                 mapping.process_change_event(
                     target,
@@ -704,6 +709,11 @@ impl<EH: DomainEventHandler> MainProcessor<EH> {
                 )
             });
         }
+        if clip_matrix_viewport_changed {
+            // Clip slot targets resolve relative to the viewport, so they might point to other
+            // slots now.
+            self.refresh_all_targets();
+        }
     }
 
     /// Polls the clip matrix of this ReaLearn instance, if existing and only if it's an owned one
@@ -733,6 +743,9 @@ impl<EH: DomainEventHandler> MainProcessor<EH> {
         for event in events {
             self.process_clip_matrix_event_internal(event);
         }
+        self.basics
+            .event_handler
+            .handle_event(DomainEvent::ClipMatrixChanged(events));
     }
 
     /// Processes the given clip matrix event if it's relevant to this instance.
@@ -745,7 +758,12 @@ impl<EH: DomainEventHandler> MainProcessor<EH> {
         {
             return;
         }
-        self.process_clip_matrix_event_internal(&event.event)
+        self.process_clip_matrix_event_internal(&event.event);
+        self.basics
+            .event_handler
+            .handle_event(DomainEvent::ClipMatrixChanged(std::slice::from_ref(
+                &event.event,
+            )));
     }
 
     fn process_clip_matrix_event_internal(&self, event: &ClipMatrixEvent) {
@@ -1547,6 +1565,12 @@ impl<EH: DomainEventHandler> MainProcessor<EH> {
         if self.basics.control_mode != ControlMode::Controlling {
             return;
         }
+        // Project-related messages are only interesting for instances in that project.
+        if let Some(project) = evt.payload().project() {
+            if project != self.basics.context.project_or_current_project() {
+                return;
+            }
+        }
         if self.basics.settings.real_input_logging_enabled {
             log_real_control_input(&self.basics.instance_id, evt);
         }
@@ -0,0 +1,166 @@
+use reaper_medium::{MidiInputDeviceId, MidiOutputDeviceId};
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+use std::fmt;
+use std::str::FromStr;
+
+/// Universal SysEx "Identity Request", addressed to all device IDs.
+pub const MIDI_IDENTITY_REQUEST: [u8; 6] = [0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7];
+
+pub type MidiIdentityReplySender = async_channel::Sender<MidiIdentityReply>;
+
+/// An identity reply received on a particular MIDI input device while probing a particular MIDI
+/// output device.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct MidiIdentityReply {
+    pub output_device_id: MidiOutputDeviceId,
+    pub input_device_id: MidiInputDeviceId,
+    pub identity: MidiDeviceIdentity,
+}
+
+/// What a device tells about itself when answering an identity request.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct MidiDeviceIdentity {
+    pub manufacturer_id: MidiManufacturerId,
+    /// 14-bit device family code (LSB is sent first).
+    pub family_code: u16,
+    /// 14-bit device family member code (LSB is sent first).
+    pub model_number: u16,
+}
+
+/// Serialized as hexadecimal bytes separated by spaces, e.g. `41` or `00 20 29`.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, SerializeDisplay, DeserializeFromStr)]
+pub enum MidiManufacturerId {
+    /// One-byte manufacturer ID, e.g. `41` for Roland.
+    Short(u8),
+    /// Three-byte manufacturer ID starting with `00`, e.g. `00 20 29` for Novation.
+    Extended(u8, u8),
+}
+
+impl MidiDeviceIdentity {
+    /// Parses a SysEx identity reply.
+    ///
+    /// Format: `F0 7E <device ID> 06 02 <manufacturer ID> <family> <model> <version> F7`
+    pub fn from_identity_reply(bytes: &[u8]) -> Option<Self> {
+        let (manufacturer_id, rest) = match bytes {
+            [0xF0, 0x7E, _, 0x06, 0x02, 0x00, m1, m2, rest @ ..] => {
+                (MidiManufacturerId::Extended(*m1, *m2), rest)
+            }
+            [0xF0, 0x7E, _, 0x06, 0x02, m, rest @ ..] => (MidiManufacturerId::Short(*m), rest),
+            _ => return None,
+        };
+        match rest {
+            [f_lsb, f_msb, m_lsb, m_msb, ..] => Some(Self {
+                manufacturer_id,
+                family_code: build_14_bit_value(*f_lsb, *f_msb),
+                model_number: build_14_bit_value(*m_lsb, *m_msb),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for MidiManufacturerId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MidiManufacturerId::Short(id) => write!(f, "{:02X}", id),
+            MidiManufacturerId::Extended(b1, b2) => write!(f, "00 {:02X} {:02X}", b1, b2),
+        }
+    }
+}
+
+impl FromStr for MidiManufacturerId {
+    type Err = &'static str;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let bytes: Vec<u8> = text
+            .split_whitespace()
+            .map(|byte| u8::from_str_radix(byte, 16))
+            .collect::<Result<_, _>>()
+            .map_err(|_| "manufacturer ID must consist of hexadecimal bytes, e.g. \"00 20 29\"")?;
+        if bytes.iter().any(|b| *b > 0x7F) {
+            return Err("manufacturer ID bytes must not be greater than 7F");
+        }
+        match bytes.as_slice() {
+            [0x00, b1, b2] => Ok(Self::Extended(*b1, *b2)),
+            [b] if *b != 0x00 => Ok(Self::Short(*b)),
+            _ => Err("manufacturer ID must consist of 1 byte or of 3 bytes starting with 00"),
+        }
+    }
+}
+
+fn build_14_bit_value(lsb: u8, msb: u8) -> u16 {
+    ((msb as u16 & 0x7F) << 7) | (lsb as u16 & 0x7F)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_short_manufacturer_id() {
+        // Given
+        let reply = [
+            0xF0, 0x7E, 0x10, 0x06, 0x02, 0x41, 0x2B, 0x02, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
+            0xF7,
+        ];
+        // When
+        let identity = MidiDeviceIdentity::from_identity_reply(&reply).unwrap();
+        // Then
+        assert_eq!(identity.manufacturer_id, MidiManufacturerId::Short(0x41));
+        assert_eq!(identity.manufacturer_id.to_string(), "41");
+        assert_eq!(identity.family_code, 0x012B);
+        assert_eq!(identity.model_number, 0);
+    }
+
+    #[test]
+    fn parse_extended_manufacturer_id() {
+        // Given
+        let reply = [
+            0xF0, 0x7E, 0x00, 0x06, 0x02, 0x00, 0x20, 0x29, 0x69, 0x00, 0x00, 0x00, 0x00, 0x01,
+            0x02, 0x03, 0xF7,
+        ];
+        // When
+        let identity = MidiDeviceIdentity::from_identity_reply(&reply).unwrap();
+        // Then
+        assert_eq!(
+            identity.manufacturer_id,
+            MidiManufacturerId::Extended(0x20, 0x29)
+        );
+        assert_eq!(identity.manufacturer_id.to_string(), "00 20 29");
+        assert_eq!(identity.family_code, 0x69);
+    }
+
+    #[test]
+    fn parse_manufacturer_id_from_str() {
+        assert_eq!(
+            "41".parse::<MidiManufacturerId>(),
+            Ok(MidiManufacturerId::Short(0x41))
+        );
+        assert_eq!(
+            " 00 20  29 ".parse::<MidiManufacturerId>(),
+            Ok(MidiManufacturerId::Extended(0x20, 0x29))
+        );
+        assert_eq!(
+            "00 0a 0B".parse::<MidiManufacturerId>(),
+            Ok(MidiManufacturerId::Extended(0x0A, 0x0B))
+        );
+        assert!("".parse::<MidiManufacturerId>().is_err());
+        assert!("00".parse::<MidiManufacturerId>().is_err());
+        assert!("20 29".parse::<MidiManufacturerId>().is_err());
+        assert!("002029".parse::<MidiManufacturerId>().is_err());
+        assert!("F0".parse::<MidiManufacturerId>().is_err());
+        assert!("Novation".parse::<MidiManufacturerId>().is_err());
+    }
+
+    #[test]
+    fn reject_other_sys_ex() {
+        assert_eq!(
+            MidiDeviceIdentity::from_identity_reply(&MIDI_IDENTITY_REQUEST),
+            None
+        );
+        assert_eq!(
+            MidiDeviceIdentity::from_identity_reply(&[0xF0, 0x7E, 0x00, 0x06, 0x02, 0x41, 0xF7]),
+            None
+        );
+    }
+}
@@ -99,6 +99,9 @@ pub use key_source::*;
 mod device_change_detector;
 pub use device_change_detector::*;
 
+mod midi_device_identity;
+pub use midi_device_identity::*;
+
 mod small_ascii_string;
 pub use small_ascii_string::*;
 
@@ -1506,7 +1506,11 @@ fn process_real_mapping(
                 );
             }
             RealTimeReaperTarget::ClipTransport(t) => {
-                return t.hit(control_value, control_context);
+                return t.hit(
+                    control_value,
+                    value_event.payload().offset(),
+                    control_context,
+                );
             }
             RealTimeReaperTarget::FxParameter(t) => {
                 if t.should_control_in_real_time(caller.is_vst()) {
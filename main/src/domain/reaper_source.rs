@@ -2,9 +2,11 @@ use core::fmt;
 use derive_more::Display;
 use helgoboss_learn::{
     format_percentage_without_unit, parse_percentage_without_unit, ControlValue,
-    DetailedSourceCharacter, SourceCharacter, UnitValue,
+    DetailedSourceCharacter, Fraction, SourceCharacter, UnitValue,
 };
-use reaper_medium::{MidiInputDeviceId, MidiOutputDeviceId};
+use realearn_api::schema::MusicalTimerUnit;
+use reaper_high::{Project, Reaper};
+use reaper_medium::{MidiInputDeviceId, MidiOutputDeviceId, PositionInSeconds};
 use std::collections::HashSet;
 use std::convert::TryInto;
 use std::fmt::{Display, Formatter};
@@ -15,6 +17,12 @@ pub enum ReaperSource {
     MidiDeviceChanges,
     RealearnInstanceStart,
     Timer(TimerSource),
+    MusicalTimer(MusicalTimerSource),
+    MarkerCrossed,
+    RegionEntered,
+    PlayStateChanges,
+    ProjectSwitch,
+    TrackListChanges,
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -51,10 +59,106 @@ impl TimerSource {
     }
 }
 
+/// Timer which fires in sync with the project timeline (every n beats or bars).
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct MusicalTimerSource {
+    unit: MusicalTimerUnit,
+    interval: f64,
+    offset: f64,
+    only_while_playing: bool,
+    last_slot: Option<i64>,
+    /// When the project is not playing, the timer continues from the edit cursor position as if
+    /// it was playing.
+    free_running_since: Option<(Instant, PositionInSeconds)>,
+}
+
+impl MusicalTimerSource {
+    pub fn new(
+        unit: MusicalTimerUnit,
+        interval: f64,
+        offset: f64,
+        only_while_playing: bool,
+    ) -> Self {
+        Self {
+            unit,
+            interval: if interval > 0.0 { interval } else { 1.0 },
+            offset,
+            only_while_playing,
+            last_slot: None,
+            free_running_since: None,
+        }
+    }
+
+    /// Fires whenever a new interval starts, with the index of the current beat within the bar as
+    /// value.
+    pub fn poll(&mut self, project: Project) -> Option<ControlValue> {
+        let play_state = project.play_state();
+        let pos = if (play_state.is_playing || play_state.is_recording) && !play_state.is_paused {
+            self.free_running_since = None;
+            project.play_position_next_audio_block()
+        } else if self.only_while_playing {
+            self.free_running_since = None;
+            self.last_slot = None;
+            return None;
+        } else {
+            let now = Instant::now();
+            let (since, start_pos) = *self
+                .free_running_since
+                .get_or_insert_with(|| (now, project.play_or_edit_cursor_position()));
+            PositionInSeconds::new(start_pos.get() + (now - since).as_secs_f64())
+        };
+        let res = Reaper::get()
+            .medium_reaper()
+            .time_map_2_time_to_beats(project.context(), pos);
+        let pos = MusicalPosition {
+            full_beats: res.full_beats.get(),
+            measure_index: res.measure_index as f64,
+            beats_since_measure: res.beats_since_measure.get(),
+            beats_per_bar: res.time_signature.numerator.get(),
+        };
+        let slot = pos.slot(self.unit, self.interval, self.offset);
+        // Don't fire for the slot in which we start, only when entering the next one
+        let last_slot = self.last_slot.replace(slot)?;
+        if slot == last_slot {
+            return None;
+        }
+        Some(ControlValue::AbsoluteDiscrete(pos.beat_index()))
+    }
+}
+
+/// Project position in musical terms.
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct MusicalPosition {
+    full_beats: f64,
+    measure_index: f64,
+    beats_since_measure: f64,
+    beats_per_bar: u32,
+}
+
+impl MusicalPosition {
+    /// Returns the index of the interval which contains this position.
+    fn slot(&self, unit: MusicalTimerUnit, interval: f64, offset: f64) -> i64 {
+        let pos_in_units = match unit {
+            MusicalTimerUnit::Beat => self.full_beats,
+            MusicalTimerUnit::Bar => {
+                self.measure_index + self.beats_since_measure / self.beats_per_bar as f64
+            }
+        };
+        ((pos_in_units - offset) / interval).floor() as i64
+    }
+
+    /// Returns the index of the current beat within the bar (first beat is 0, last beat is 1).
+    fn beat_index(&self) -> Fraction {
+        let max_beat_index = self.beats_per_bar.saturating_sub(1);
+        let beat_index = (self.beats_since_measure.max(0.0) as u32).min(max_beat_index);
+        Fraction::new(beat_index, max_beat_index.max(1))
+    }
+}
+
 impl ReaperSource {
     /// If this returns `true`, the `poll` method should be called, on a regular basis.
     pub fn wants_to_be_polled(&self) -> bool {
-        matches!(self, ReaperSource::Timer(_))
+        matches!(self, ReaperSource::Timer(_) | ReaperSource::MusicalTimer(_))
     }
 
     pub fn possible_detailed_characters(&self) -> Vec<DetailedSourceCharacter> {
@@ -63,6 +167,11 @@ impl ReaperSource {
             MidiDeviceChanges => vec![DetailedSourceCharacter::MomentaryOnOffButton],
             RealearnInstanceStart => vec![DetailedSourceCharacter::MomentaryOnOffButton],
             Timer(_) => vec![DetailedSourceCharacter::PressOnlyButton],
+            MusicalTimer(_) | MarkerCrossed | RegionEntered | PlayStateChanges => {
+                vec![DetailedSourceCharacter::RangeControl]
+            }
+            ProjectSwitch => vec![DetailedSourceCharacter::PressOnlyButton],
+            TrackListChanges => vec![DetailedSourceCharacter::MomentaryOnOffButton],
         }
     }
 
@@ -76,14 +185,20 @@ impl ReaperSource {
     }
 
     pub fn character(&self) -> SourceCharacter {
-        SourceCharacter::MomentaryButton
+        use ReaperSource::*;
+        match self {
+            MusicalTimer(_) | MarkerCrossed | RegionEntered | PlayStateChanges => {
+                SourceCharacter::RangeElement
+            }
+            _ => SourceCharacter::MomentaryButton,
+        }
     }
 
-    pub fn poll(&mut self) -> Option<ControlValue> {
-        if let ReaperSource::Timer(t) = self {
-            t.poll()
-        } else {
-            None
+    pub fn poll(&mut self, project: Project) -> Option<ControlValue> {
+        match self {
+            ReaperSource::Timer(t) => t.poll(),
+            ReaperSource::MusicalTimer(t) => t.poll(project),
+            _ => None,
         }
     }
 
@@ -104,6 +219,30 @@ impl ReaperSource {
                 }
                 _ => return None,
             },
+            MarkerCrossed(p) => match self {
+                ReaperSource::MarkerCrossed => p.control_value(),
+                _ => return None,
+            },
+            RegionEntered(p) => match self {
+                ReaperSource::RegionEntered => p.control_value(),
+                _ => return None,
+            },
+            PlayStateChanged(p) => match self {
+                ReaperSource::PlayStateChanges => p.play_state.control_value(),
+                _ => return None,
+            },
+            ProjectSwitched(_) => match self {
+                ReaperSource::ProjectSwitch => ControlValue::AbsoluteContinuous(UnitValue::MAX),
+                _ => return None,
+            },
+            TracksAdded(_) => match self {
+                ReaperSource::TrackListChanges => ControlValue::AbsoluteContinuous(UnitValue::MAX),
+                _ => return None,
+            },
+            TracksRemoved(_) => match self {
+                ReaperSource::TrackListChanges => ControlValue::AbsoluteContinuous(UnitValue::MIN),
+                _ => return None,
+            },
         };
         Some(control_value)
     }
@@ -116,6 +255,109 @@ pub enum ReaperMessage {
     #[display(fmt = "MidiDevicesDisconnected ({})", _0)]
     MidiDevicesDisconnected(MidiDeviceChangePayload),
     RealearnInstanceStarted,
+    #[display(fmt = "MarkerCrossed ({})", _0)]
+    MarkerCrossed(BookmarkPayload),
+    #[display(fmt = "RegionEntered ({})", _0)]
+    RegionEntered(BookmarkPayload),
+    #[display(fmt = "PlayStateChanged ({})", _0)]
+    PlayStateChanged(PlayStatePayload),
+    /// Contains the project which is the current one now.
+    #[display(fmt = "ProjectSwitched ({})", _0)]
+    ProjectSwitched(ProjectPayload),
+    #[display(fmt = "TracksAdded ({})", _0)]
+    TracksAdded(ProjectPayload),
+    #[display(fmt = "TracksRemoved ({})", _0)]
+    TracksRemoved(ProjectPayload),
+}
+
+impl ReaperMessage {
+    /// The project which this message is about, if any.
+    ///
+    /// Messages which are about a particular project should only reach ReaLearn instances in that
+    /// project.
+    pub fn project(&self) -> Option<Project> {
+        use ReaperMessage::*;
+        match self {
+            MidiDevicesConnected(_)
+            | MidiDevicesDisconnected(_)
+            | RealearnInstanceStarted
+            | ProjectSwitched(_) => None,
+            MarkerCrossed(p) | RegionEntered(p) => Some(p.project),
+            PlayStateChanged(p) => Some(p.project),
+            TracksAdded(p) | TracksRemoved(p) => Some(p.project),
+        }
+    }
+}
+
+/// Payload of messages about markers or regions.
+#[derive(PartialEq, Debug, Display)]
+#[display(fmt = "Index {} of {}", index, count)]
+pub struct BookmarkPayload {
+    pub project: Project,
+    /// Index among all bookmarks of the same type (markers or regions).
+    pub index: u32,
+    /// Number of bookmarks of the same type in the project.
+    pub count: u32,
+}
+
+impl BookmarkPayload {
+    fn control_value(&self) -> ControlValue {
+        bookmark_control_value(self.index, self.count)
+    }
+}
+
+/// Spreads the bookmarks of one type evenly over the unit interval.
+fn bookmark_control_value(index: u32, count: u32) -> ControlValue {
+    let max = count.saturating_sub(1).max(1);
+    ControlValue::AbsoluteDiscrete(Fraction::new(index.min(max), max))
+}
+
+#[derive(PartialEq, Debug, Display)]
+#[display(fmt = "{}", play_state)]
+pub struct PlayStatePayload {
+    pub project: Project,
+    pub play_state: ProjectPlayState,
+}
+
+#[derive(PartialEq, Debug, Display)]
+#[display(fmt = "{:?}", project)]
+pub struct ProjectPayload {
+    pub project: Project,
+}
+
+/// Transport state of a project as reported by the "Play state changes" source.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display)]
+pub enum ProjectPlayState {
+    Stopped,
+    Paused,
+    Playing,
+    Recording,
+}
+
+impl ProjectPlayState {
+    pub fn from_flags(is_playing: bool, is_paused: bool, is_recording: bool) -> Self {
+        use ProjectPlayState::*;
+        if is_paused {
+            Paused
+        } else if is_recording {
+            Recording
+        } else if is_playing {
+            Playing
+        } else {
+            Stopped
+        }
+    }
+
+    pub fn is_playing_or_recording(&self) -> bool {
+        matches!(
+            self,
+            ProjectPlayState::Playing | ProjectPlayState::Recording
+        )
+    }
+
+    fn control_value(&self) -> ControlValue {
+        ControlValue::AbsoluteDiscrete(Fraction::new(*self as u32, 3))
+    }
 }
 
 #[derive(PartialEq, Debug)]
@@ -133,3 +375,135 @@ impl Display for MidiDeviceChangePayload {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn play_state_from_flags() {
+        use ProjectPlayState::*;
+        assert_eq!(ProjectPlayState::from_flags(false, false, false), Stopped);
+        assert_eq!(ProjectPlayState::from_flags(true, false, false), Playing);
+        assert_eq!(ProjectPlayState::from_flags(true, true, false), Paused);
+        assert_eq!(ProjectPlayState::from_flags(true, false, true), Recording);
+        assert_eq!(ProjectPlayState::from_flags(true, true, true), Paused);
+    }
+
+    #[test]
+    fn play_state_control_values() {
+        use ProjectPlayState::*;
+        let value = |s: ProjectPlayState| s.control_value();
+        assert_eq!(
+            value(Stopped),
+            ControlValue::AbsoluteDiscrete(Fraction::new(0, 3))
+        );
+        assert_eq!(
+            value(Paused),
+            ControlValue::AbsoluteDiscrete(Fraction::new(1, 3))
+        );
+        assert_eq!(
+            value(Playing),
+            ControlValue::AbsoluteDiscrete(Fraction::new(2, 3))
+        );
+        assert_eq!(
+            value(Recording),
+            ControlValue::AbsoluteDiscrete(Fraction::new(3, 3))
+        );
+    }
+
+    #[test]
+    fn bookmark_control_values() {
+        assert_eq!(
+            bookmark_control_value(0, 5),
+            ControlValue::AbsoluteDiscrete(Fraction::new(0, 4))
+        );
+        assert_eq!(
+            bookmark_control_value(4, 5),
+            ControlValue::AbsoluteDiscrete(Fraction::new(4, 4))
+        );
+        // A single bookmark shouldn't result in a fraction with zero max value
+        assert_eq!(
+            bookmark_control_value(0, 1),
+            ControlValue::AbsoluteDiscrete(Fraction::new(0, 1))
+        );
+        // Index beyond count (bookmarks removed in the meantime) is clamped
+        assert_eq!(
+            bookmark_control_value(7, 3),
+            ControlValue::AbsoluteDiscrete(Fraction::new(2, 2))
+        );
+    }
+
+    fn musical_pos(
+        full_beats: f64,
+        measure_index: f64,
+        beats_since_measure: f64,
+    ) -> MusicalPosition {
+        MusicalPosition {
+            full_beats,
+            measure_index,
+            beats_since_measure,
+            beats_per_bar: 4,
+        }
+    }
+
+    #[test]
+    fn musical_timer_beat_slots() {
+        let slot =
+            |full_beats| musical_pos(full_beats, 0.0, 0.0).slot(MusicalTimerUnit::Beat, 1.0, 0.0);
+        assert_eq!(slot(0.0), 0);
+        assert_eq!(slot(3.99), 3);
+        assert_eq!(slot(4.0), 4);
+        assert_eq!(slot(-0.5), -1);
+    }
+
+    #[test]
+    fn musical_timer_bar_slots() {
+        let slot =
+            |interval| musical_pos(10.0, 2.0, 2.0).slot(MusicalTimerUnit::Bar, interval, 0.0);
+        assert_eq!(slot(1.0), 2);
+        assert_eq!(slot(2.0), 1);
+        assert_eq!(slot(0.5), 5);
+    }
+
+    #[test]
+    fn musical_timer_fractional_interval_slots() {
+        let slot = |full_beats, interval| {
+            musical_pos(full_beats, 0.0, 0.0).slot(MusicalTimerUnit::Beat, interval, 0.0)
+        };
+        assert_eq!(slot(1.2, 0.5), 2);
+        assert_eq!(slot(1.5, 0.5), 3);
+        assert_eq!(slot(2.9, 1.5), 1);
+        assert_eq!(slot(3.0, 1.5), 2);
+    }
+
+    #[test]
+    fn musical_timer_offset_slots() {
+        let slot = |full_beats, offset| {
+            musical_pos(full_beats, 0.0, 0.0).slot(MusicalTimerUnit::Beat, 1.0, offset)
+        };
+        assert_eq!(slot(0.7, -0.25), 0);
+        assert_eq!(slot(0.8, -0.25), 1);
+        assert_eq!(slot(-0.3, -0.25), -1);
+        assert_eq!(slot(1.2, 0.25), 0);
+        assert_eq!(slot(1.3, 0.25), 1);
+    }
+
+    #[test]
+    fn musical_timer_beat_index() {
+        let beat_index = |beats_since_measure, beats_per_bar| {
+            MusicalPosition {
+                full_beats: 0.0,
+                measure_index: 0.0,
+                beats_since_measure,
+                beats_per_bar,
+            }
+            .beat_index()
+        };
+        assert_eq!(beat_index(0.0, 4), Fraction::new(0, 3));
+        assert_eq!(beat_index(2.7, 4), Fraction::new(2, 3));
+        assert_eq!(beat_index(4.2, 4), Fraction::new(3, 3));
+        assert_eq!(beat_index(-0.5, 4), Fraction::new(0, 3));
+        assert_eq!(beat_index(0.5, 1), Fraction::new(0, 1));
+    }
+}
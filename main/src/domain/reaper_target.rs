@@ -18,24 +18,27 @@ use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 
 use helgoboss_learn::{
-    AbsoluteValue, ControlType, ControlValue, NumericValue, PropValue, Target, UnitValue,
+    AbsoluteValue, ControlType, ControlValue, NumericValue, PropValue, RgbColor, Target, UnitValue,
 };
+use playtime_clip_engine::main::ClipSlotCoordinates;
 use playtime_clip_engine::rt::ClipPlayState;
 
 use crate::base::default_util::is_default;
 use crate::base::Global;
 use crate::domain::ui_util::convert_bool_to_unit_value;
+use crate::domain::BackboneState;
 use crate::domain::{
     handle_exclusivity, ActionTarget, AllTrackFxEnableTarget, AutomationModeOverrideTarget,
-    ClipManagementTarget, ClipSeekTarget, ClipTransportTarget, ClipVolumeTarget, ControlContext,
-    FxEnableTarget, FxNavigateTarget, FxOnlineTarget, FxOpenTarget, FxParameterTarget,
-    FxParameterTouchStateTarget, FxPresetTarget, GoToBookmarkTarget, HierarchyEntry,
-    HierarchyEntryProvider, LoadFxSnapshotTarget, MappingControlContext, MidiSendTarget,
-    OscSendTarget, PlayrateTarget, RealTimeClipTransportTarget, RealTimeControlContext,
-    RealTimeFxParameterTarget, RouteMuteTarget, RoutePanTarget, RouteTouchStateTarget,
-    RouteVolumeTarget, SeekTarget, SelectedTrackTarget, TempoTarget, TrackArmTarget,
-    TrackAutomationModeTarget, TrackMonitoringModeTarget, TrackMuteTarget, TrackPanTarget,
-    TrackPeakTarget, TrackSelectionTarget, TrackShowTarget, TrackSoloTarget, TrackTouchStateTarget,
+    ClipManagementTarget, ClipSeekTarget, ClipTransportTarget, ClipViewportTarget,
+    ClipVolumeTarget, ControlContext, FxEnableTarget, FxNavigateTarget, FxOnlineTarget,
+    FxOpenTarget, FxParameterTarget, FxParameterTouchStateTarget, FxPresetTarget,
+    GoToBookmarkTarget, HierarchyEntry, HierarchyEntryProvider, LoadFxSnapshotTarget,
+    MappingControlContext, MidiSendTarget, OscSendTarget, PlayrateTarget,
+    RealTimeClipTransportTarget, RealTimeControlContext, RealTimeFxParameterTarget,
+    RouteMuteTarget, RoutePanTarget, RouteTouchStateTarget, RouteVolumeTarget, SeekTarget,
+    SelectedTrackTarget, TempoTarget, TrackArmTarget, TrackAutomationModeTarget,
+    TrackMonitoringModeTarget, TrackMuteTarget, TrackPanTarget, TrackPeakTarget,
+    TrackSelectionTarget, TrackShowTarget, TrackSoloTarget, TrackTouchStateTarget,
     TrackVolumeTarget, TrackWidthTarget, TransportTarget,
 };
 use crate::domain::{
@@ -127,6 +130,7 @@ pub enum ReaperTarget {
     ClipSeek(ClipSeekTarget),
     ClipVolume(ClipVolumeTarget),
     ClipManagement(ClipManagementTarget),
+    ClipViewport(ClipViewportTarget),
     LoadMappingSnapshot(LoadMappingSnapshotTarget),
     EnableMappings(EnableMappingsTarget),
     EnableInstances(EnableInstancesTarget),
@@ -606,6 +610,7 @@ impl<'a> Target<'a> for ReaperTarget {
             ClipSeek(t) => t.current_value(context),
             ClipVolume(t) => t.current_value(context),
             ClipManagement(t) => t.current_value(context),
+            ClipViewport(t) => t.current_value(context),
             LoadMappingSnapshot(t) => t.current_value(context),
             EnableMappings(t) => t.current_value(context),
             EnableInstances(t) => t.current_value(context),
@@ -660,6 +665,27 @@ pub(crate) fn clip_play_state_unit_value(
     }
 }
 
+/// Returns the color of the given clip with its brightness reflecting the clip play state.
+///
+/// Stopped clips are shown dimmed, playing clips at full brightness.
+pub(crate) fn clip_color_prop_value(
+    context: ControlContext,
+    coordinates: ClipSlotCoordinates,
+) -> Option<PropValue> {
+    BackboneState::get()
+        .with_clip_matrix(context.instance_state, |matrix| {
+            let reaper_medium::RgbColor { r, g, b } = matrix.clip_color(coordinates).ok()??;
+            let play_state = matrix.clip_play_state(coordinates).ok()?;
+            // Map the play state feedback value to the upper part of the brightness range, so that
+            // stopped clips are still visible on pad controllers.
+            let brightness = 0.2 + 0.8 * play_state.feedback_value().get();
+            let scale = |c: u8| (c as f64 * brightness).round() as u8;
+            let color = RgbColor::new(scale(r), scale(g), scale(b));
+            Some(PropValue::Color(color))
+        })
+        .ok()?
+}
+
 pub fn current_value_of_bookmark(
     project: Project,
     bookmark_type: BookmarkType,
@@ -42,11 +42,18 @@ impl RealearnTarget for ClipManagementTarget {
     fn control_type_and_character(&self, _: ControlContext) -> (ControlType, TargetCharacter) {
         use ClipManagementAction as A;
         match self.action {
-            A::ClearSlot | A::FillSlotWithSelectedItem => (
+            A::ClearSlot
+            | A::FillSlotWithSelectedItem
+            | A::ExportClipToItem
+            | A::WriteArrangementToTimeline
+            | A::QuantizeClip
+            | A::UndoAudioOverdub => (
                 ControlType::AbsoluteContinuousRetriggerable,
                 TargetCharacter::Trigger,
             ),
-            A::EditClip => (ControlType::AbsoluteContinuous, TargetCharacter::Switch),
+            A::EditClip | A::RecordArrangement | A::DetectInputAndRecord => {
+                (ControlType::AbsoluteContinuous, TargetCharacter::Switch)
+            }
         }
     }
 
@@ -72,6 +79,48 @@ impl RealearnTarget for ClipManagementTarget {
                         }
                         Ok(None)
                     }
+                    A::ExportClipToItem => {
+                        if value.is_on() {
+                            matrix.export_slot_to_item(self.slot_coordinates)?;
+                        }
+                        Ok(None)
+                    }
+                    A::RecordArrangement => {
+                        if value.is_on() {
+                            if !matrix.is_recording_arrangement() {
+                                matrix.start_arrangement_recording();
+                            }
+                        } else {
+                            matrix.stop_arrangement_recording();
+                        }
+                        Ok(None)
+                    }
+                    A::WriteArrangementToTimeline => {
+                        if value.is_on() {
+                            matrix.write_arrangement_to_timeline()?;
+                        }
+                        Ok(None)
+                    }
+                    A::QuantizeClip => {
+                        if value.is_on() {
+                            matrix.quantize_clip(self.slot_coordinates)?;
+                        }
+                        Ok(None)
+                    }
+                    A::UndoAudioOverdub => {
+                        if value.is_on() {
+                            matrix.undo_audio_overdub(self.slot_coordinates)?;
+                        }
+                        Ok(None)
+                    }
+                    A::DetectInputAndRecord => {
+                        if value.is_on() {
+                            matrix.start_input_detection()?;
+                        } else {
+                            matrix.cancel_input_detection();
+                        }
+                        Ok(None)
+                    }
                     A::EditClip => {
                         if value.is_on() {
                             matrix.start_editing_clip(self.slot_coordinates)?;
@@ -100,7 +149,24 @@ impl<'a> Target<'a> for ClipManagementTarget {
     fn current_value(&self, context: ControlContext<'a>) -> Option<AbsoluteValue> {
         use ClipManagementAction as A;
         match self.action {
-            A::ClearSlot | A::FillSlotWithSelectedItem => Some(AbsoluteValue::default()),
+            A::ClearSlot
+            | A::FillSlotWithSelectedItem
+            | A::ExportClipToItem
+            | A::WriteArrangementToTimeline
+            | A::QuantizeClip
+            | A::UndoAudioOverdub => Some(AbsoluteValue::default()),
+            A::RecordArrangement => BackboneState::get()
+                .with_clip_matrix(context.instance_state, |matrix| {
+                    let value = convert_bool_to_unit_value(matrix.is_recording_arrangement());
+                    Some(AbsoluteValue::Continuous(value))
+                })
+                .ok()?,
+            A::DetectInputAndRecord => BackboneState::get()
+                .with_clip_matrix(context.instance_state, |matrix| {
+                    let value = convert_bool_to_unit_value(matrix.is_detecting_input());
+                    Some(AbsoluteValue::Continuous(value))
+                })
+                .ok()?,
             A::EditClip => BackboneState::get()
                 .with_clip_matrix(context.instance_state, |matrix| {
                     let is_editing = matrix.is_editing_clip(self.slot_coordinates);
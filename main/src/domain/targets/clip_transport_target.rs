@@ -1,12 +1,12 @@
 use crate::domain::{
-    clip_play_state_unit_value, format_value_as_on_off, interpret_current_clip_slot_value,
-    transport_is_enabled_unit_value, BackboneState, CompoundChangeEvent, ControlContext,
-    ExtendedProcessorContext, HitInstructionReturnValue, MappingCompartment, MappingControlContext,
-    RealTimeControlContext, RealTimeReaperTarget, RealearnTarget, ReaperTarget, ReaperTargetType,
-    TargetCharacter, TargetTypeDef, TransportAction, UnresolvedReaperTargetDef, VirtualClipSlot,
-    DEFAULT_TARGET,
+    clip_color_prop_value, clip_play_state_unit_value, format_value_as_on_off,
+    interpret_current_clip_slot_value, transport_is_enabled_unit_value, BackboneState,
+    CompoundChangeEvent, ControlContext, ExtendedProcessorContext, HitInstructionReturnValue,
+    MappingCompartment, MappingControlContext, RealTimeControlContext, RealTimeReaperTarget,
+    RealearnTarget, ReaperTarget, ReaperTargetType, SampleOffset, TargetCharacter, TargetTypeDef,
+    TransportAction, UnresolvedReaperTargetDef, VirtualClipSlot, DEFAULT_TARGET,
 };
-use helgoboss_learn::{AbsoluteValue, ControlType, ControlValue, Target, UnitValue};
+use helgoboss_learn::{AbsoluteValue, ControlType, ControlValue, PropValue, Target, UnitValue};
 use playtime_clip_engine::main::{ClipMatrixEvent, ClipSlotCoordinates, SlotPlayOptions};
 use playtime_clip_engine::rt::{ClipChangedEvent, QualifiedClipChangedEvent};
 use reaper_high::Project;
@@ -100,7 +100,7 @@ impl RealearnTarget for ClipTransportTarget {
                     }
                     RecordStop => {
                         if on {
-                            match matrix.record_clip(self.basics.slot_coordinates) {
+                            match matrix.record_clip(self.basics.slot_coordinates, 0) {
                                 Ok(_) => {
                                     tracing_debug!("Record slot");
                                 }
@@ -163,6 +163,8 @@ impl RealearnTarget for ClipTransportTarget {
                         ),
                         _ => (false, None),
                     },
+                    // Only relevant for the clip color prop.
+                    ClipChangedEvent::ClipColor(_) => (true, None),
                     ClipChangedEvent::Removed => {
                         tracing_debug!("Reacting to clip-removed event");
                         (true, None)
@@ -182,10 +184,17 @@ impl RealearnTarget for ClipTransportTarget {
         Some(ReaperTargetType::ClipTransport)
     }
 
+    fn prop_value(&self, key: &str, context: ControlContext) -> Option<PropValue> {
+        match key {
+            "clip.color" => clip_color_prop_value(context, self.basics.slot_coordinates),
+            _ => None,
+        }
+    }
+
     fn splinter_real_time_target(&self) -> Option<RealTimeReaperTarget> {
         use TransportAction::*;
-        if matches!(self.basics.action, RecordStop | Repeat) {
-            // These are not for real-time usage.
+        if matches!(self.basics.action, Repeat) {
+            // This is not for real-time usage.
             return None;
         }
         let t = RealTimeClipTransportTarget {
@@ -232,26 +241,30 @@ pub struct RealTimeClipTransportTarget {
 }
 
 impl RealTimeClipTransportTarget {
+    /// The offset is the one of the MIDI event which triggered the hit. It's used for starting
+    /// clips sample-accurately.
     pub fn hit(
         &mut self,
         value: ControlValue,
+        offset: SampleOffset,
         context: RealTimeControlContext,
     ) -> Result<(), &'static str> {
         use TransportAction::*;
         let on = value.is_on();
+        let frame_offset = offset.get() as usize;
         let matrix = context.clip_matrix()?;
         let matrix = matrix.lock();
         match self.basics.action {
             PlayStop => {
                 if on {
-                    matrix.play_clip(self.basics.slot_coordinates)
+                    matrix.play_clip(self.basics.slot_coordinates, frame_offset)
                 } else {
                     matrix.stop_clip(self.basics.slot_coordinates)
                 }
             }
             PlayPause => {
                 if on {
-                    matrix.play_clip(self.basics.slot_coordinates)
+                    matrix.play_clip(self.basics.slot_coordinates, frame_offset)
                 } else {
                     matrix.pause_clip(self.basics.slot_coordinates)
                 }
@@ -270,7 +283,13 @@ impl RealTimeClipTransportTarget {
                     Ok(())
                 }
             }
-            RecordStop => Err("record not supported for real-time target"),
+            RecordStop => {
+                if on {
+                    matrix.record_clip(self.basics.slot_coordinates, frame_offset)
+                } else {
+                    matrix.stop_clip(self.basics.slot_coordinates)
+                }
+            }
             Repeat => Err("setting repeated not supported for real-time target"),
         }
     }
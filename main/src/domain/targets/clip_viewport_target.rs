@@ -0,0 +1,180 @@
+use crate::domain::ui_util::convert_bool_to_unit_value;
+use crate::domain::{
+    BackboneState, ClipMatrixViewport, CompoundChangeEvent, ControlContext,
+    ExtendedProcessorContext, HitInstructionReturnValue, InstanceStateChanged, MappingCompartment,
+    MappingControlContext, RealearnTarget, ReaperTarget, ReaperTargetType, TargetCharacter,
+    TargetTypeDef, UnresolvedReaperTargetDef, DEFAULT_TARGET,
+};
+use helgoboss_learn::{AbsoluteValue, ControlType, ControlValue, Target};
+use playtime_clip_engine::main::ClipMatrixEvent;
+use realearn_api::schema::ClipViewportAction;
+
+/// Used if the page size is not given explicitly (fits most pad grids).
+pub const DEFAULT_CLIP_VIEWPORT_PAGE_SIZE: u32 = 8;
+
+#[derive(Debug)]
+pub struct UnresolvedClipViewportTarget {
+    pub action: ClipViewportAction,
+    pub page_size: Option<u32>,
+}
+
+impl UnresolvedReaperTargetDef for UnresolvedClipViewportTarget {
+    fn resolve(
+        &self,
+        _: ExtendedProcessorContext,
+        _: MappingCompartment,
+    ) -> Result<Vec<ReaperTarget>, &'static str> {
+        let target = ClipViewportTarget {
+            action: self.action,
+            page_size: self.page_size.unwrap_or(DEFAULT_CLIP_VIEWPORT_PAGE_SIZE) as usize,
+        };
+        Ok(vec![ReaperTarget::ClipViewport(target)])
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClipViewportTarget {
+    pub action: ClipViewportAction,
+    pub page_size: usize,
+}
+
+impl ClipViewportTarget {
+    /// Returns the viewport after scrolling or `None` if it's not possible to scroll any further
+    /// in the direction of this target.
+    fn scrolled_viewport(&self, context: ControlContext) -> Option<ClipMatrixViewport> {
+        let (column_count, row_count) = BackboneState::get()
+            .with_clip_matrix(context.instance_state, |matrix| {
+                (matrix.column_count(), matrix.row_count())
+            })
+            .ok()?;
+        let viewport = context.instance_state.borrow().clip_matrix_viewport();
+        use ClipViewportAction as A;
+        let new_viewport = match self.action {
+            A::ScrollLeft | A::PageLeft => ClipMatrixViewport {
+                column_offset: scroll_backward(viewport.column_offset, self.step_size())?,
+                ..viewport
+            },
+            A::ScrollRight | A::PageRight => ClipMatrixViewport {
+                column_offset: scroll_forward(
+                    viewport.column_offset,
+                    self.step_size(),
+                    self.page_size,
+                    column_count,
+                )?,
+                ..viewport
+            },
+            A::ScrollUp | A::PageUp => ClipMatrixViewport {
+                row_offset: scroll_backward(viewport.row_offset, self.step_size())?,
+                ..viewport
+            },
+            A::ScrollDown | A::PageDown => ClipMatrixViewport {
+                row_offset: scroll_forward(
+                    viewport.row_offset,
+                    self.step_size(),
+                    self.page_size,
+                    row_count,
+                )?,
+                ..viewport
+            },
+        };
+        Some(new_viewport)
+    }
+
+    fn step_size(&self) -> usize {
+        use ClipViewportAction as A;
+        match self.action {
+            A::ScrollLeft | A::ScrollRight | A::ScrollUp | A::ScrollDown => 1,
+            A::PageLeft | A::PageRight | A::PageUp | A::PageDown => self.page_size.max(1),
+        }
+    }
+}
+
+impl RealearnTarget for ClipViewportTarget {
+    fn control_type_and_character(&self, _: ControlContext) -> (ControlType, TargetCharacter) {
+        (
+            ControlType::AbsoluteContinuousRetriggerable,
+            TargetCharacter::Trigger,
+        )
+    }
+
+    fn hit(
+        &mut self,
+        value: ControlValue,
+        context: MappingControlContext,
+    ) -> Result<HitInstructionReturnValue, &'static str> {
+        if !value.is_on() {
+            return Ok(None);
+        }
+        let new_viewport = self
+            .scrolled_viewport(context.control_context)
+            .ok_or("can't scroll any further")?;
+        context
+            .control_context
+            .instance_state
+            .borrow_mut()
+            .set_clip_matrix_viewport(new_viewport);
+        Ok(None)
+    }
+
+    fn is_available(&self, _: ControlContext) -> bool {
+        true
+    }
+
+    fn process_change_event(
+        &self,
+        evt: CompoundChangeEvent,
+        _: ControlContext,
+    ) -> (bool, Option<AbsoluteValue>) {
+        match evt {
+            CompoundChangeEvent::Instance(InstanceStateChanged::ClipMatrixViewport)
+            | CompoundChangeEvent::ClipMatrix(ClipMatrixEvent::AllClipsChanged) => (true, None),
+            _ => (false, None),
+        }
+    }
+
+    fn reaper_target_type(&self) -> Option<ReaperTargetType> {
+        Some(ReaperTargetType::ClipViewport)
+    }
+}
+
+impl<'a> Target<'a> for ClipViewportTarget {
+    type Context = ControlContext<'a>;
+
+    /// Returns "on" if it's possible to scroll in the direction of this target.
+    fn current_value(&self, context: ControlContext<'a>) -> Option<AbsoluteValue> {
+        let can_scroll = self.scrolled_viewport(context).is_some();
+        Some(AbsoluteValue::Continuous(convert_bool_to_unit_value(
+            can_scroll,
+        )))
+    }
+
+    fn control_type(&self, context: Self::Context) -> ControlType {
+        self.control_type_and_character(context).0
+    }
+}
+
+fn scroll_backward(offset: usize, step_size: usize) -> Option<usize> {
+    if offset == 0 {
+        return None;
+    }
+    Some(offset.saturating_sub(step_size))
+}
+
+fn scroll_forward(
+    offset: usize,
+    step_size: usize,
+    page_size: usize,
+    count: usize,
+) -> Option<usize> {
+    let max_offset = count.saturating_sub(page_size);
+    if offset >= max_offset {
+        return None;
+    }
+    Some((offset + step_size).min(max_offset))
+}
+
+pub const CLIP_VIEWPORT_TARGET: TargetTypeDef = TargetTypeDef {
+    name: "Clip matrix: Scroll viewport",
+    short_name: "Scroll clip viewport",
+    ..DEFAULT_TARGET
+};
@@ -16,6 +16,9 @@ pub use clip_volume_target::*;
 mod clip_management_target;
 pub use clip_management_target::*;
 
+mod clip_viewport_target;
+pub use clip_viewport_target::*;
+
 mod track_peak_target;
 pub use track_peak_target::*;
 
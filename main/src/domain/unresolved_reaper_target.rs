@@ -5,12 +5,12 @@ use crate::domain::{
     FeedbackResolution, MappingCompartment, ReaperTarget, UnresolvedActionTarget,
     UnresolvedAllTrackFxEnableTarget, UnresolvedAnyOnTarget,
     UnresolvedAutomationModeOverrideTarget, UnresolvedClipManagementTarget,
-    UnresolvedClipSeekTarget, UnresolvedClipTransportTarget, UnresolvedClipVolumeTarget,
-    UnresolvedEnableInstancesTarget, UnresolvedEnableMappingsTarget, UnresolvedFxEnableTarget,
-    UnresolvedFxNavigateTarget, UnresolvedFxOnlineTarget, UnresolvedFxOpenTarget,
-    UnresolvedFxParameterTarget, UnresolvedFxParameterTouchStateTarget, UnresolvedFxPresetTarget,
-    UnresolvedGoToBookmarkTarget, UnresolvedLastTouchedTarget, UnresolvedLoadFxSnapshotTarget,
-    UnresolvedLoadMappingSnapshotTarget, UnresolvedMidiSendTarget,
+    UnresolvedClipSeekTarget, UnresolvedClipTransportTarget, UnresolvedClipViewportTarget,
+    UnresolvedClipVolumeTarget, UnresolvedEnableInstancesTarget, UnresolvedEnableMappingsTarget,
+    UnresolvedFxEnableTarget, UnresolvedFxNavigateTarget, UnresolvedFxOnlineTarget,
+    UnresolvedFxOpenTarget, UnresolvedFxParameterTarget, UnresolvedFxParameterTouchStateTarget,
+    UnresolvedFxPresetTarget, UnresolvedGoToBookmarkTarget, UnresolvedLastTouchedTarget,
+    UnresolvedLoadFxSnapshotTarget, UnresolvedLoadMappingSnapshotTarget, UnresolvedMidiSendTarget,
     UnresolvedNavigateWithinGroupTarget, UnresolvedOscSendTarget, UnresolvedPlayrateTarget,
     UnresolvedRouteAutomationModeTarget, UnresolvedRouteMonoTarget, UnresolvedRouteMuteTarget,
     UnresolvedRoutePanTarget, UnresolvedRoutePhaseTarget, UnresolvedRouteTouchStateTarget,
@@ -89,6 +89,7 @@ pub enum UnresolvedReaperTarget {
     ClipSeek(UnresolvedClipSeekTarget),
     ClipVolume(UnresolvedClipVolumeTarget),
     ClipManagement(UnresolvedClipManagementTarget),
+    ClipViewport(UnresolvedClipViewportTarget),
     LoadMappingSnapshot(UnresolvedLoadMappingSnapshotTarget),
     EnableMappings(UnresolvedEnableMappingsTarget),
     NavigateWithinGroup(UnresolvedNavigateWithinGroupTarget),
@@ -485,6 +486,16 @@ impl VirtualClipSlot {
                 ClipSlotCoordinates::new(column_index, row_index)
             }
         };
+        // Slots addressed by index are relative to the clip matrix viewport of this instance
+        let viewport = context
+            .control_context
+            .instance_state
+            .borrow()
+            .clip_matrix_viewport();
+        let coordinates = ClipSlotCoordinates::new(
+            coordinates.column() + viewport.column_offset,
+            coordinates.row() + viewport.row_offset,
+        );
         let slot_exists = BackboneState::get()
             .with_clip_matrix_mut(context.control_context.instance_state, |matrix| {
                 matrix.slot(coordinates).is_some()
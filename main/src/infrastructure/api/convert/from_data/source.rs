@@ -195,6 +195,21 @@ pub fn convert_source(
                 Timer => schema::Source::Timer(schema::TimerSource {
                     duration: data.timer_millis,
                 }),
+                MusicalTimer => schema::Source::MusicalTimer(schema::MusicalTimerSource {
+                    unit: style.required_value(data.musical_timer_unit),
+                    interval: style.optional_value_with_default(data.musical_timer_interval, 1.0),
+                    offset: style.required_value(data.musical_timer_offset),
+                    only_while_playing: style.required_value(data.musical_timer_only_while_playing),
+                }),
+                MarkerCrossed => schema::Source::MarkerCrossed(schema::MarkerCrossedSource),
+                RegionEntered => schema::Source::RegionEntered(schema::RegionEnteredSource),
+                PlayStateChanges => {
+                    schema::Source::PlayStateChanges(schema::PlayStateChangesSource)
+                }
+                ProjectSwitch => schema::Source::ProjectSwitch(schema::ProjectSwitchSource),
+                TrackListChanges => {
+                    schema::Source::TrackListChanges(schema::TrackListChangesSource)
+                }
             }
         }
         Virtual => {
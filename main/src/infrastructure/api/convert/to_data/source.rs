@@ -113,12 +113,34 @@ pub fn convert_source(s: Source) -> ConversionResult<SourceModelData> {
             MidiDeviceChanges(_) => ReaperSourceType::MidiDeviceChanges,
             RealearnInstanceStart(_) => ReaperSourceType::RealearnInstanceStart,
             Timer(_) => ReaperSourceType::Timer,
+            MusicalTimer(_) => ReaperSourceType::MusicalTimer,
+            MarkerCrossed(_) => ReaperSourceType::MarkerCrossed,
+            RegionEntered(_) => ReaperSourceType::RegionEntered,
+            PlayStateChanges(_) => ReaperSourceType::PlayStateChanges,
+            ProjectSwitch(_) => ReaperSourceType::ProjectSwitch,
+            TrackListChanges(_) => ReaperSourceType::TrackListChanges,
             _ => Default::default(),
         },
         timer_millis: match &s {
             Timer(t) => t.duration,
             _ => Default::default(),
         },
+        musical_timer_unit: match &s {
+            MusicalTimer(t) => t.unit.unwrap_or_default(),
+            _ => Default::default(),
+        },
+        musical_timer_interval: match &s {
+            MusicalTimer(t) => t.interval.filter(|i| *i != 1.0),
+            _ => None,
+        },
+        musical_timer_offset: match &s {
+            MusicalTimer(t) => t.offset.unwrap_or_default(),
+            _ => Default::default(),
+        },
+        musical_timer_only_while_playing: match &s {
+            MusicalTimer(t) => t.only_while_playing.unwrap_or_default(),
+            _ => Default::default(),
+        },
     };
     Ok(data)
 }
@@ -127,7 +149,15 @@ fn convert_category(s: &Source) -> SourceCategory {
     use Source::*;
     match s {
         NoneSource => SourceCategory::Never,
-        MidiDeviceChanges(_) | RealearnInstanceStart(_) | Timer(_) => SourceCategory::Reaper,
+        MidiDeviceChanges(_)
+        | RealearnInstanceStart(_)
+        | Timer(_)
+        | MusicalTimer(_)
+        | MarkerCrossed(_)
+        | RegionEntered(_)
+        | PlayStateChanges(_)
+        | ProjectSwitch(_)
+        | TrackListChanges(_) => SourceCategory::Reaper,
         MidiNoteVelocity(_)
         | MidiNoteKeyNumber(_)
         | MidiPolyphonicKeyPressureAmount(_)
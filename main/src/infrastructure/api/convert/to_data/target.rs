@@ -627,6 +627,13 @@ pub fn convert_target(t: Target) -> ConversionResult<TargetModelData> {
             clip_management_action: d.action,
             ..init(d.commons)
         },
+        Target::ClipViewport(d) => TargetModelData {
+            category: TargetCategory::Reaper,
+            r#type: ReaperTargetType::ClipViewport,
+            clip_viewport_action: d.action,
+            clip_viewport_page_size: d.page_size,
+            ..init(d.commons)
+        },
         Target::SendMidi(d) => TargetModelData {
             category: TargetCategory::Reaper,
             r#type: ReaperTargetType::SendMidi,
@@ -0,0 +1,415 @@
+//! Comparison of compartments at the level of the ReaLearn API schema.
+use derive_more::Display;
+use realearn_api::schema::Compartment;
+use serde_json::{Map, Value};
+use std::error::Error;
+use std::fmt;
+
+/// Differences between two versions of a compartment.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CompartmentDiff {
+    pub items: Vec<ItemDiff>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ItemDiff {
+    pub item_type: ItemType,
+    /// Name or ID of the item.
+    pub label: String,
+    pub change: ItemChange,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display)]
+pub enum ItemType {
+    #[display(fmt = "Default group")]
+    DefaultGroup,
+    Parameter,
+    Group,
+    Mapping,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ItemChange {
+    Added,
+    Removed,
+    Changed(Vec<FieldChange>),
+}
+
+/// A changed property, e.g. `glue.source_interval`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldChange {
+    pub path: String,
+    pub old_value: Option<Value>,
+    pub new_value: Option<Value>,
+}
+
+/// Compares two versions of a compartment.
+///
+/// Parameters are identified by their index, groups and mappings by their ID (= key). Groups and
+/// mappings whose ID doesn't appear in the other version are paired by name, because mappings
+/// without key in a preset file get a random key whenever the preset is loaded.
+pub fn diff_compartments(
+    old: &Compartment,
+    new: &Compartment,
+) -> Result<CompartmentDiff, Box<dyn Error>> {
+    let old = CompartmentItems::from_compartment(old)?;
+    let new = CompartmentItems::from_compartment(new)?;
+    let mut items = vec![];
+    diff_item_lists(
+        ItemType::DefaultGroup,
+        &old.default_group.into_iter().collect::<Vec<_>>(),
+        &new.default_group.into_iter().collect::<Vec<_>>(),
+        &mut items,
+    );
+    diff_item_lists(
+        ItemType::Parameter,
+        &old.parameters,
+        &new.parameters,
+        &mut items,
+    );
+    diff_item_lists(ItemType::Group, &old.groups, &new.groups, &mut items);
+    diff_item_lists(ItemType::Mapping, &old.mappings, &new.mappings, &mut items);
+    Ok(CompartmentDiff { items })
+}
+
+impl CompartmentDiff {
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+fn diff_item_lists(item_type: ItemType, old: &[Value], new: &[Value], diffs: &mut Vec<ItemDiff>) {
+    for (old_index, new_index) in match_items(old, new) {
+        let (label, change) = match (old_index, new_index) {
+            (Some(o), Some(n)) => {
+                let field_changes = diff_fields(&old[o], &new[n]);
+                if field_changes.is_empty() {
+                    continue;
+                }
+                (item_label(&new[n]), ItemChange::Changed(field_changes))
+            }
+            (Some(o), None) => (item_label(&old[o]), ItemChange::Removed),
+            (None, Some(n)) => (item_label(&new[n]), ItemChange::Added),
+            (None, None) => continue,
+        };
+        diffs.push(ItemDiff {
+            item_type,
+            label,
+            change,
+        });
+    }
+}
+
+/// The items of a compartment as JSON values.
+pub(super) struct CompartmentItems {
+    pub default_group: Option<Value>,
+    pub parameters: Vec<Value>,
+    pub groups: Vec<Value>,
+    pub mappings: Vec<Value>,
+}
+
+impl CompartmentItems {
+    pub fn from_compartment(compartment: &Compartment) -> Result<Self, Box<dyn Error>> {
+        let mut json = serde_json::to_value(compartment)?;
+        let mut take_list = |key: &str| match json.as_object_mut().and_then(|o| o.remove(key)) {
+            Some(Value::Array(items)) => items,
+            _ => vec![],
+        };
+        let items = Self {
+            parameters: take_list("parameters"),
+            groups: take_list("groups"),
+            mappings: take_list("mappings"),
+            default_group: json.as_object_mut().and_then(|o| o.remove("default_group")),
+        };
+        Ok(items)
+    }
+
+    pub fn into_compartment(self) -> Result<Compartment, Box<dyn Error>> {
+        let mut json = Map::new();
+        if let Some(g) = self.default_group {
+            json.insert("default_group".to_string(), g);
+        }
+        let lists = [
+            ("parameters", self.parameters),
+            ("groups", self.groups),
+            ("mappings", self.mappings),
+        ];
+        for (key, items) in lists {
+            if !items.is_empty() {
+                json.insert(key.to_string(), Value::Array(items));
+            }
+        }
+        Ok(serde_json::from_value(Value::Object(json))?)
+    }
+}
+
+/// Pairs the items of two lists which represent the same thing.
+///
+/// Returns the pairs of matched items in the order of the left list, followed by the unmatched
+/// items of the right list.
+pub(super) fn match_items(left: &[Value], right: &[Value]) -> Vec<(Option<usize>, Option<usize>)> {
+    let mut left_matches: Vec<Option<usize>> = vec![None; left.len()];
+    let mut right_is_matched = vec![false; right.len()];
+    // Match by identity
+    for (l, left_item) in left.iter().enumerate() {
+        let id = match item_identity(left_item) {
+            None => continue,
+            Some(id) => id,
+        };
+        let r = right.iter().enumerate().position(|(r, right_item)| {
+            !right_is_matched[r] && item_identity(right_item).as_ref() == Some(&id)
+        });
+        if let Some(r) = r {
+            left_matches[l] = Some(r);
+            right_is_matched[r] = true;
+        }
+    }
+    // Match remaining items by name if unambiguous
+    let unmatched_with_name = |items: &[Value], is_matched: &dyn Fn(usize) -> bool, name: &str| {
+        let candidates: Vec<_> = items
+            .iter()
+            .enumerate()
+            .filter(|(i, item)| !is_matched(*i) && item_name(item) == Some(name))
+            .map(|(i, _)| i)
+            .collect();
+        match candidates.as_slice() {
+            [i] => Some(*i),
+            _ => None,
+        }
+    };
+    for (l, left_item) in left.iter().enumerate() {
+        if left_matches[l].is_some() {
+            continue;
+        }
+        let name = match item_name(left_item) {
+            None => continue,
+            Some(n) => n,
+        };
+        let left_is_matched = |i: usize| left_matches[i].is_some();
+        if unmatched_with_name(left, &left_is_matched, name) != Some(l) {
+            continue;
+        }
+        let right_is_matched_fn = |i: usize| right_is_matched[i];
+        if let Some(r) = unmatched_with_name(right, &right_is_matched_fn, name) {
+            left_matches[l] = Some(r);
+            right_is_matched[r] = true;
+        }
+    }
+    left_matches
+        .into_iter()
+        .enumerate()
+        .map(|(l, r)| (Some(l), r))
+        .chain(
+            right_is_matched
+                .into_iter()
+                .enumerate()
+                .filter(|(_, is_matched)| !is_matched)
+                .map(|(r, _)| (None, Some(r))),
+        )
+        .collect()
+}
+
+/// Returns the changes between two versions of an item, not taking its identity into account.
+pub(super) fn diff_fields(old: &Value, new: &Value) -> Vec<FieldChange> {
+    let mut changes = vec![];
+    collect_field_changes(
+        "",
+        Some(&without_identity(old)),
+        Some(&without_identity(new)),
+        &mut changes,
+    );
+    changes
+}
+
+fn collect_field_changes(
+    path: &str,
+    old: Option<&Value>,
+    new: Option<&Value>,
+    changes: &mut Vec<FieldChange>,
+) {
+    if old == new {
+        return;
+    }
+    if let (Some(Value::Object(o)), Some(Value::Object(n))) = (old, new) {
+        for key in union_of_keys(o, n) {
+            collect_field_changes(&join_path(path, key), o.get(key), n.get(key), changes);
+        }
+        return;
+    }
+    changes.push(FieldChange {
+        path: path.to_string(),
+        old_value: old.cloned(),
+        new_value: new.cloned(),
+    });
+}
+
+/// Returns the keys of both objects, in the order of the first one.
+pub(super) fn union_of_keys<'a>(
+    a: &'a Map<String, Value>,
+    b: &'a Map<String, Value>,
+) -> impl Iterator<Item = &'a String> {
+    a.keys()
+        .chain(b.keys().filter(move |k| !a.contains_key(*k)))
+}
+
+pub(super) fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+/// Parameters are identified by index, everything else by ID.
+fn identity_key(item: &Value) -> &'static str {
+    if item.get("index").is_some() {
+        "index"
+    } else {
+        "id"
+    }
+}
+
+fn item_identity(item: &Value) -> Option<String> {
+    let id = item.get(identity_key(item))?;
+    let id = match id {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    };
+    Some(id)
+}
+
+pub(super) fn without_identity(item: &Value) -> Value {
+    let mut item = item.clone();
+    if let Some(o) = item.as_object_mut() {
+        let key = if o.contains_key("index") {
+            "index"
+        } else {
+            "id"
+        };
+        o.remove(key);
+    }
+    item
+}
+
+/// Puts the identity of one item into another one.
+pub(super) fn with_identity_of(item: Value, identity_source: &Value) -> Value {
+    let mut item = item;
+    let key = identity_key(identity_source);
+    if let (Some(o), Some(id)) = (item.as_object_mut(), identity_source.get(key)) {
+        o.insert(key.to_string(), id.clone());
+    }
+    item
+}
+
+fn item_name(item: &Value) -> Option<&str> {
+    item.get("name")?.as_str()
+}
+
+pub(super) fn item_label(item: &Value) -> String {
+    if let Some(name) = item_name(item) {
+        return format!("\"{}\"", name);
+    }
+    item_identity(item).unwrap_or_default()
+}
+
+impl fmt::Display for CompartmentDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for item in &self.items {
+            writeln!(f, "{}", item)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ItemDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}: ", self.item_type, self.label)?;
+        match &self.change {
+            ItemChange::Added => f.write_str("added"),
+            ItemChange::Removed => f.write_str("removed"),
+            ItemChange::Changed(field_changes) => {
+                f.write_str("changed")?;
+                for c in field_changes {
+                    write!(
+                        f,
+                        "\n    {}: {} -> {}",
+                        c.path,
+                        format_value(c.old_value.as_ref()),
+                        format_value(c.new_value.as_ref())
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn format_value(value: Option<&Value>) -> String {
+    match value {
+        None => "<none>".to_string(),
+        Some(v) => v.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn compartment(json: Value) -> Compartment {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn diff_mappings() {
+        // Given
+        let old = compartment(json!({
+            "mappings": [
+                { "id": "a", "name": "Volume", "glue": { "source_interval": [0.0, 1.0] } },
+                { "id": "b", "name": "Pan" },
+                { "id": "x1", "name": "Mute" }
+            ]
+        }));
+        let new = compartment(json!({
+            "mappings": [
+                { "id": "a", "name": "Volume", "glue": { "source_interval": [0.0, 0.5] } },
+                { "id": "x2", "name": "Mute" },
+                { "id": "c", "name": "Solo" }
+            ]
+        }));
+        // When
+        let diff = diff_compartments(&old, &new).unwrap();
+        // Then
+        assert_eq!(
+            diff.items,
+            vec![
+                ItemDiff {
+                    item_type: ItemType::Mapping,
+                    label: "\"Volume\"".to_string(),
+                    change: ItemChange::Changed(vec![FieldChange {
+                        path: "glue.source_interval".to_string(),
+                        old_value: Some(json!([0.0, 1.0])),
+                        new_value: Some(json!([0.0, 0.5])),
+                    }])
+                },
+                ItemDiff {
+                    item_type: ItemType::Mapping,
+                    label: "\"Pan\"".to_string(),
+                    change: ItemChange::Removed
+                },
+                ItemDiff {
+                    item_type: ItemType::Mapping,
+                    label: "\"Solo\"".to_string(),
+                    change: ItemChange::Added
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn no_diff() {
+        let c = json!({ "mappings": [{ "id": "a", "name": "Volume" }] });
+        let diff = diff_compartments(&compartment(c.clone()), &compartment(c)).unwrap();
+        assert!(diff.is_empty());
+    }
+}
@@ -0,0 +1,372 @@
+--- ReaLearn Lua library, version 1.
+---
+--- Helpers for generating compartments and mappings in Lua presets and Lua imports. Load it with:
+---
+---     local realearn = require("realearn.v1")
+---
+--- Everything in here is part of a stable API: Functions of version 1 will never be changed in an
+--- incompatible way. Incompatible changes go into a new version (`realearn.v2`), so make sure to
+--- always require a specific version.
+---
+--- All functions return plain Lua tables in the format of the ReaLearn API (the same format you get
+--- when exporting mappings as Lua), so you can adjust the results as you like. Tables created by
+--- `*_compartment`, `mapping`, `group`, `parameter`, `source.*`, `glue.*` and `target.*` remember the
+--- line in which they were created. If the result doesn't match the ReaLearn schema, errors point
+--- to that line.
+local host = ...
+
+local realearn = {
+    version = 1,
+    source = {},
+    glue = {},
+    target = {},
+    track = {},
+    fx = {},
+}
+
+-- Utilities
+
+--- Returns a copy of the given table with the given properties added (later tables win).
+---
+--- @param ... table|nil
+--- @return table
+local function merged(...)
+    local result = {}
+    for i = 1, select("#", ...) do
+        local t = select(i, ...)
+        if t ~= nil then
+            for key, value in pairs(t) do
+                result[key] = value
+            end
+        end
+    end
+    return result
+end
+
+--- Remembers the line in the calling script in which the given table has been created.
+local function remember(t)
+    return host.remember_line(t)
+end
+
+local function with_kind(kind, props, extra_props)
+    return remember(merged({ kind = kind }, extra_props, props))
+end
+
+--- Merges the given tables into a new one (later tables win). `nil` arguments are ignored.
+---
+--- @param ... table|nil
+--- @return table
+realearn.merge = merged
+
+--- Returns the list of integers from `from` to `to` (both inclusive).
+---
+--- @param from integer
+--- @param to integer
+--- @param step integer|nil Defaults to 1
+--- @return integer[]
+function realearn.range(from, to, step)
+    local result = {}
+    for i = from, to, step or 1 do
+        table.insert(result, i)
+    end
+    return result
+end
+
+--- Calls `f(item, index)` for each item of the list and collects the results into one list.
+---
+--- If `f` returns a list (e.g. several mappings), its items are added one by one. If it returns
+--- `nil`, nothing is added. Perfect for generating mappings for each track or MIDI channel.
+---
+--- @param list table
+--- @param f function
+--- @return table
+function realearn.collect(list, f)
+    local result = {}
+    for index, item in ipairs(list) do
+        local r = f(item, index)
+        if type(r) == "table" and r[1] ~= nil then
+            for _, x in ipairs(r) do
+                table.insert(result, x)
+            end
+        elseif r ~= nil then
+            table.insert(result, r)
+        end
+    end
+    return result
+end
+
+--- Returns all MIDI channels (0 to 15, ReaLearn counts MIDI channels from zero).
+---
+--- @return integer[]
+function realearn.midi_channels()
+    return realearn.range(0, 15)
+end
+
+--- Returns the number of tracks in the current project (only works within REAPER, not in
+--- realearn-cli).
+---
+--- @return integer
+function realearn.track_count()
+    return host.track_count()
+end
+
+--- Returns the GUID of the track at the given zero-based index in the current project (only works
+--- within REAPER, not in realearn-cli).
+---
+--- @param index integer
+--- @return string|nil
+function realearn.track_guid(index)
+    return host.track_guid(index)
+end
+
+--- Prints the given value to the REAPER console (for debugging).
+function realearn.print(value)
+    host.print(value)
+end
+
+-- Top-level objects
+
+--- Creates a main compartment, which is what a main preset must return.
+---
+--- @param props table `parameters`, `default_group`, `groups` and `mappings`
+--- @return table
+function realearn.main_compartment(props)
+    return remember({ kind = "MainCompartment", value = remember(props) })
+end
+
+--- Creates a controller compartment, which is what a controller preset must return.
+---
+--- @param props table `parameters`, `default_group`, `groups` and `mappings`
+--- @return table
+function realearn.controller_compartment(props)
+    return remember({ kind = "ControllerCompartment", value = remember(props) })
+end
+
+--- Creates a list of mappings which can be imported via "Import from clipboard".
+---
+--- @param mappings table[]
+--- @return table
+function realearn.mappings(mappings)
+    return remember({ kind = "Mappings", value = mappings })
+end
+
+--- Creates a mapping.
+---
+--- @param props table e.g. `id`, `name`, `group`, `source`, `glue` and `target`
+--- @return table
+function realearn.mapping(props)
+    return remember(merged(props))
+end
+
+--- Creates a group. Mappings refer to it via its `id`.
+---
+--- @param props table e.g. `id`, `name` and `activation_condition`
+--- @return table
+function realearn.group(props)
+    return remember(merged(props))
+end
+
+--- Creates a compartment parameter setting.
+---
+--- @param index integer Zero-based parameter index
+--- @param name string
+--- @param props table|nil e.g. `value_count`
+--- @return table
+function realearn.parameter(index, name, props)
+    return remember(merged({ index = index, name = name }, props))
+end
+
+-- Sources
+
+--- @param channel integer|nil Zero-based MIDI channel, `nil` means any channel
+--- @param controller_number integer|nil `nil` means any controller number
+--- @param props table|nil Additional properties, e.g. `character`
+--- @return table
+function realearn.source.control_change(channel, controller_number, props)
+    return with_kind("MidiControlChangeValue", props, {
+        channel = channel,
+        controller_number = controller_number,
+    })
+end
+
+--- @param channel integer|nil Zero-based MIDI channel, `nil` means any channel
+--- @param key_number integer|nil `nil` means any key
+--- @param props table|nil
+--- @return table
+function realearn.source.note_velocity(channel, key_number, props)
+    return with_kind("MidiNoteVelocity", props, { channel = channel, key_number = key_number })
+end
+
+--- @param channel integer|nil Zero-based MIDI channel, `nil` means any channel
+--- @param props table|nil
+--- @return table
+function realearn.source.pitch_bend(channel, props)
+    return with_kind("MidiPitchBendChangeValue", props, { channel = channel })
+end
+
+--- @param channel integer|nil Zero-based MIDI channel, `nil` means any channel
+--- @param props table|nil
+--- @return table
+function realearn.source.program_change(channel, props)
+    return with_kind("MidiProgramChangeNumber", props, { channel = channel })
+end
+
+--- @param channel integer|nil Zero-based MIDI channel, `nil` means any channel
+--- @param props table|nil
+--- @return table
+function realearn.source.channel_pressure(channel, props)
+    return with_kind("MidiChannelPressureAmount", props, { channel = channel })
+end
+
+--- @param address string OSC address pattern, e.g. "/track/1/volume"
+--- @param props table|nil e.g. `argument`
+--- @return table
+function realearn.source.osc(address, props)
+    return with_kind("Osc", props, { address = address })
+end
+
+--- Source which refers to a control element of the controller compartment (main compartment only).
+---
+--- @param id integer|string Control element ID, e.g. 0 or "play"
+--- @param character string|nil "Button" or "Multi" (default)
+--- @return table
+function realearn.source.virtual(id, character)
+    return with_kind("Virtual", nil, { id = id, character = character })
+end
+
+-- Glue
+
+--- Creates glue section properties.
+---
+--- @param props table e.g. `source_interval`, `target_interval` or `reverse`
+--- @return table
+function realearn.glue.new(props)
+    return remember(merged(props))
+end
+
+--- Glue which toggles the target with each button press.
+---
+--- @param props table|nil Additional glue properties
+--- @return table
+function realearn.glue.toggle(props)
+    return remember(merged({ absolute_mode = "ToggleButton" }, props))
+end
+
+--- Glue which restricts the target value range.
+---
+--- @param min number Between 0.0 and 1.0
+--- @param max number Between 0.0 and 1.0
+--- @param props table|nil Additional glue properties
+--- @return table
+function realearn.glue.target_range(min, max, props)
+    return remember(merged({ target_interval = { min, max } }, props))
+end
+
+-- Track and FX descriptors
+
+--- The track which hosts this ReaLearn instance.
+function realearn.track.this()
+    return { address = "This" }
+end
+
+function realearn.track.master()
+    return { address = "Master" }
+end
+
+--- The currently selected track.
+function realearn.track.selected()
+    return { address = "Selected" }
+end
+
+--- @param index integer Zero-based track index
+function realearn.track.by_index(index)
+    return { address = "ByIndex", index = index }
+end
+
+--- @param name string Track name, wildcards are supported
+function realearn.track.by_name(name)
+    return { address = "ByName", name = name }
+end
+
+--- @param id string Track GUID (see `realearn.track_guid`)
+function realearn.track.by_id(id)
+    return { address = "ById", id = id }
+end
+
+--- The FX which has focus.
+function realearn.fx.focused()
+    return { address = "Focused" }
+end
+
+--- @param track table Track descriptor
+--- @param index integer Zero-based FX index
+function realearn.fx.by_index(track, index)
+    return { address = "ByIndex", chain = { address = "Track", track = track }, index = index }
+end
+
+--- @param track table Track descriptor
+--- @param name string FX name, wildcards are supported
+function realearn.fx.by_name(track, name)
+    return { address = "ByName", chain = { address = "Track", track = track }, name = name }
+end
+
+-- Targets
+
+local function track_target(kind)
+    return function(track, props)
+        return with_kind(kind, props, { track = track })
+    end
+end
+
+--- All track targets take a track descriptor (see `realearn.track`) and optional additional
+--- properties.
+realearn.target.track_volume = track_target("TrackVolume")
+realearn.target.track_pan = track_target("TrackPan")
+realearn.target.track_width = track_target("TrackWidth")
+realearn.target.track_mute = track_target("TrackMuteState")
+realearn.target.track_solo = track_target("TrackSoloState")
+realearn.target.track_arm = track_target("TrackArmState")
+realearn.target.track_selection = track_target("TrackSelectionState")
+realearn.target.track_peak = track_target("TrackPeak")
+
+--- @param fx table FX descriptor (see `realearn.fx`)
+--- @param props table|nil
+--- @return table
+function realearn.target.fx_on_off(fx, props)
+    return with_kind("FxOnOffState", props, { fx = fx })
+end
+
+--- @param fx table FX descriptor (see `realearn.fx`)
+--- @param index integer Zero-based parameter index
+--- @param props table|nil
+--- @return table
+function realearn.target.fx_parameter(fx, index, props)
+    return with_kind("FxParameterValue", props, {
+        parameter = { address = "ByIndex", fx = fx, index = index },
+    })
+end
+
+--- @param command integer|string Command ID, e.g. 40364 or "_SWS_ABOUT"
+--- @param props table|nil
+--- @return table
+function realearn.target.action(command, props)
+    return with_kind("ReaperAction", props, { command = command })
+end
+
+--- @param action string e.g. "PlayStop", "PlayPause", "Stop", "Record", "Repeat"
+--- @param props table|nil
+--- @return table
+function realearn.target.transport(action, props)
+    return with_kind("TransportAction", props, { action = action })
+end
+
+--- Target which refers to a control element (controller compartment only).
+---
+--- @param id integer|string Control element ID, e.g. 0 or "play"
+--- @param character string|nil "Button" or "Multi" (default)
+--- @return table
+function realearn.target.virtual(id, character)
+    return with_kind("Virtual", nil, { id = id, character = character })
+end
+
+return realearn
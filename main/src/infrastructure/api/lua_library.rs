@@ -0,0 +1,312 @@
+//! The ReaLearn Lua library, which Lua imports and Lua presets can load via `require`.
+use crate::domain::SafeLua;
+use jsonschema::JSONSchema;
+use mlua::{Function, Lua, LuaSerdeExt, Table, Value};
+use realearn_api::schema::COMPARTMENT_JSON_SCHEMA;
+use reaper_high::Reaper;
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use std::error::Error;
+use std::iter;
+
+/// Library modules by name.
+///
+/// Released versions must never change in an incompatible way. Add a new version instead.
+const MODULES: &[(&str, &str)] = &[("realearn.v1", include_str!("lua/realearn_v1.lua"))];
+
+const LOADED_MODULES_KEY: &str = "realearn_loaded_modules";
+
+/// Registry table which maps tables created by the library to the script lines they were
+/// created in.
+const SOURCE_LINES_KEY: &str = "realearn_source_lines";
+
+/// Decides whether the functions which ReaLearn offers to Lua scripts may access REAPER.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum LuaHost {
+    /// Running within REAPER.
+    Reaper,
+    /// Running without REAPER, e.g. in the command-line tool. Functions which need REAPER raise
+    /// a Lua error.
+    Headless,
+}
+
+impl LuaHost {
+    fn require_reaper(self, function_name: &str) -> mlua::Result<()> {
+        match self {
+            LuaHost::Reaper => Ok(()),
+            LuaHost::Headless => Err(mlua::Error::RuntimeError(format!(
+                "realearn.{} is not available without REAPER",
+                function_name
+            ))),
+        }
+    }
+}
+
+/// Makes the library modules available via `require` in the given environment.
+pub fn add_lua_library<'a>(
+    lua: &'a SafeLua,
+    env: &Table<'a>,
+    host: LuaHost,
+) -> Result<(), Box<dyn Error>> {
+    let raw_lua: &Lua = lua.as_ref();
+    raw_lua.set_named_registry_value(SOURCE_LINES_KEY, raw_lua.create_table()?)?;
+    let loaded_modules = raw_lua.create_table()?;
+    for (name, code) in MODULES {
+        let module_env = lua.create_fresh_environment()?;
+        let module = lua.compile_as_function(name, code, module_env)?;
+        let exports: Value = module.call(create_host_table(raw_lua, host)?)?;
+        loaded_modules.set(*name, exports)?;
+    }
+    raw_lua.set_named_registry_value(LOADED_MODULES_KEY, loaded_modules)?;
+    let require = raw_lua.create_function(|lua, name: String| {
+        let loaded_modules: Table = lua.named_registry_value(LOADED_MODULES_KEY)?;
+        let exports: Value = loaded_modules.get(name.as_str())?;
+        if let Value::Nil = exports {
+            let available: Vec<_> = MODULES.iter().map(|(name, _)| *name).collect();
+            return Err(mlua::Error::RuntimeError(format!(
+                "module \"{}\" not found (available: {})",
+                name,
+                available.join(", ")
+            )));
+        }
+        Ok(exports)
+    })?;
+    env.set("require", require)?;
+    Ok(())
+}
+
+/// Deserializes the value returned by a Lua import.
+///
+/// If this fails, we validate the value against the ReaLearn JSON schema because this results in
+/// much more helpful error messages, including the script lines of tables created by the library.
+pub fn deserialize_lua_import_result<T: DeserializeOwned>(
+    lua: &Lua,
+    value: Value,
+) -> Result<T, Box<dyn Error>> {
+    let deserialization_error = match lua.from_value(value.clone()) {
+        Ok(v) => return Ok(v),
+        Err(e) => e,
+    };
+    match schema_violations(lua, &value) {
+        Some(violations) if !violations.is_empty() => Err(violations.join("\n").into()),
+        _ => Err(deserialization_error.into()),
+    }
+}
+
+pub fn create_track_guid_function(lua: &Lua, host: LuaHost) -> mlua::Result<Function> {
+    lua.create_function(move |_, index: u32| {
+        host.require_reaper("track_guid")?;
+        let guid = Reaper::get()
+            .current_project()
+            .track_by_index(index)
+            .map(|t| t.guid().to_string_without_braces());
+        Ok(guid)
+    })
+}
+
+pub fn create_print_function(lua: &Lua, host: LuaHost) -> mlua::Result<Function> {
+    lua.create_function(move |_, arg: Value| {
+        host.require_reaper("print")?;
+        let text: String = match arg {
+            Value::String(s) => format!("{}\n", s.to_string_lossy()),
+            arg => format!("{:?}\n", arg),
+        };
+        Reaper::get().show_console_msg(text);
+        Ok(())
+    })
+}
+
+/// Functions which the library modules need from the host.
+fn create_host_table(lua: &Lua, host: LuaHost) -> mlua::Result<Table> {
+    let table = lua.create_table()?;
+    let remember_line = lua.create_function(|lua, table: Table| {
+        if let Some(line) = caller_line(lua) {
+            let source_lines: Table = lua.named_registry_value(SOURCE_LINES_KEY)?;
+            source_lines.set(table.clone(), line)?;
+        }
+        Ok(table)
+    })?;
+    table.set("remember_line", remember_line)?;
+    let track_count = lua.create_function(move |_, ()| {
+        host.require_reaper("track_count")?;
+        Ok(Reaper::get().current_project().track_count())
+    })?;
+    table.set("track_count", track_count)?;
+    table.set("track_guid", create_track_guid_function(lua, host)?)?;
+    table.set("print", create_print_function(lua, host)?)?;
+    Ok(table)
+}
+
+/// Returns the current line of the innermost function on the call stack which is not part of the
+/// library (that is, the line in the user script).
+fn caller_line(lua: &Lua) -> Option<i32> {
+    let mut level = 1;
+    while let Some(debug) = lua.inspect_stack(level) {
+        let line = debug.curr_line();
+        let source = debug.source().source;
+        let is_library = MODULES
+            .iter()
+            .any(|(name, _)| source == Some(name.as_bytes()));
+        if line > 0 && !is_library {
+            return Some(line);
+        }
+        level += 1;
+    }
+    None
+}
+
+/// Returns `None` if the value can't be checked against the schema at all.
+fn schema_violations(lua: &Lua, value: &Value) -> Option<Vec<String>> {
+    let json: serde_json::Value = lua.from_value(value.clone()).ok()?;
+    let kind = json.get("kind")?.as_str()?;
+    let schema = schema_for_api_object_kind(kind)?;
+    let schema = JSONSchema::compile(&schema).ok()?;
+    let errors = match schema.validate(json.get("value")?) {
+        Ok(_) => return Some(vec![]),
+        Err(errors) => errors,
+    };
+    let violations = errors
+        .map(|e| {
+            let pointer = e.instance_path.to_string();
+            let location = if pointer.is_empty() {
+                String::new()
+            } else {
+                format!("{}: ", pointer)
+            };
+            match source_line(lua, value, &pointer) {
+                None => format!("{}{}", location, e),
+                Some(line) => format!("Line {}: {}{}", line, location, e),
+            }
+        })
+        .collect();
+    Some(violations)
+}
+
+/// The schema of the value within the envelope of the given API object kind.
+fn schema_for_api_object_kind(kind: &str) -> Option<serde_json::Value> {
+    let mut schema: serde_json::Value = serde_json::from_str(COMPARTMENT_JSON_SCHEMA).ok()?;
+    let root = schema.as_object_mut()?;
+    let mapping_schema = json!({ "$ref": "#/definitions/Mapping" });
+    match kind {
+        "MainCompartment" | "ControllerCompartment" => {}
+        "Mappings" => {
+            root.remove("properties");
+            root.remove("additionalProperties");
+            root.insert("type".to_string(), json!("array"));
+            root.insert("items".to_string(), mapping_schema);
+        }
+        "Mapping" => {
+            root.remove("properties");
+            root.remove("additionalProperties");
+            root.insert("allOf".to_string(), json!([mapping_schema]));
+        }
+        _ => return None,
+    }
+    Some(schema)
+}
+
+/// Follows the given JSON pointer (relative to the envelope value) into the Lua value and returns
+/// the line of the innermost table on the way which has been created by the library.
+fn source_line(lua: &Lua, root: &Value, pointer: &str) -> Option<i32> {
+    let source_lines: Table = lua.named_registry_value(SOURCE_LINES_KEY).ok()?;
+    let line_of = |table: &Table| -> Option<i32> { source_lines.get(table.clone()).ok()? };
+    let mut current = root.clone();
+    let mut line = None;
+    for chunk in iter::once("value").chain(pointer.split('/').skip(1)) {
+        let table = match current {
+            Value::Table(t) => t,
+            _ => break,
+        };
+        line = line_of(&table).or(line);
+        current = match chunk.parse::<usize>() {
+            Ok(index) => table.get(index + 1).ok()?,
+            Err(_) => table.get(chunk).ok()?,
+        };
+    }
+    if let Value::Table(t) = &current {
+        line = line_of(t).or(line);
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use realearn_api::schema::ApiObject;
+
+    fn execute(code: &str) -> Result<ApiObject, Box<dyn Error>> {
+        let lua = SafeLua::new()?;
+        let env = lua.create_fresh_environment()?;
+        add_lua_library(&lua, &env, LuaHost::Headless)?;
+        let value = lua.compile_and_execute("Test", code, env)?;
+        deserialize_lua_import_result(lua.as_ref(), value)
+    }
+
+    #[test]
+    fn generate_mappings() {
+        // Given
+        let code = r#"
+            local realearn = require("realearn.v1")
+            return realearn.main_compartment {
+                mappings = realearn.collect(realearn.range(0, 7), function(i)
+                    return realearn.mapping {
+                        name = "Volume " .. (i + 1),
+                        source = realearn.source.control_change(0, 20 + i),
+                        target = realearn.target.track_volume(realearn.track.by_index(i)),
+                    }
+                end),
+            }
+        "#;
+        // When
+        let api_object = execute(code).unwrap();
+        // Then
+        let compartment = match api_object {
+            ApiObject::MainCompartment(envelope) => envelope.value,
+            _ => panic!("expected main compartment"),
+        };
+        let mappings = compartment.mappings.unwrap();
+        assert_eq!(mappings.len(), 8);
+        assert_eq!(mappings[7].name.as_deref(), Some("Volume 8"));
+    }
+
+    #[test]
+    fn report_line_of_invalid_mapping() {
+        // Given
+        let code = r#"local realearn = require("realearn.v1")
+            return realearn.mappings {
+                realearn.mapping { name = "Fine" },
+                realearn.mapping { name = "Broken", enabled = "yes" },
+            }
+        "#;
+        // When
+        let result = execute(code);
+        // Then
+        let msg = result.err().unwrap().to_string();
+        assert!(msg.starts_with("Line 4: /1/enabled: "), "{}", msg);
+    }
+
+    #[test]
+    fn fail_on_reaper_access_when_headless() {
+        // Given
+        let code = r#"
+            local realearn = require("realearn.v1")
+            return realearn.mappings {
+                realearn.mapping { name = "Tracks: " .. realearn.track_count() },
+            }
+        "#;
+        // When
+        let result = execute(code);
+        // Then
+        let msg = result.err().unwrap().to_string();
+        assert!(
+            msg.contains("realearn.track_count is not available without REAPER"),
+            "{}",
+            msg
+        );
+    }
+
+    #[test]
+    fn reject_unknown_modules() {
+        assert!(execute(r#"return require("realearn.v0")"#).is_err());
+    }
+}
@@ -0,0 +1,368 @@
+//! Three-way merge of compartments at the level of the ReaLearn API schema.
+//!
+//! Typical use case: A preset has been loaded and modified locally. Meanwhile, the preset itself
+//! has been updated. The merge applies the update without losing the local modifications.
+use crate::infrastructure::api::diff::{
+    item_label, join_path, match_items, union_of_keys, with_identity_of, without_identity,
+    CompartmentItems, ItemType,
+};
+use realearn_api::schema::Compartment;
+use serde_json::{Map, Value};
+use std::error::Error;
+use std::fmt;
+
+pub struct MergeResult {
+    pub compartment: Compartment,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+/// A property which has been changed both locally and in the update. The local change wins.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MergeConflict {
+    pub item_type: ItemType,
+    pub label: String,
+    pub kind: MergeConflictKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MergeConflictKind {
+    /// Property changed in both versions (the path is empty if the complete item is affected).
+    ChangedInBoth { path: String },
+    /// Item removed locally but changed in the update. It stays removed.
+    RemovedLocally,
+    /// Item changed locally but removed in the update. It's kept.
+    RemovedInUpdate,
+}
+
+/// Applies the changes between `base` and `update` onto `local`.
+///
+/// Items are paired as described in [`diff_compartments`]. Properties are merged one by one. If
+/// both sides changed the same property, the local version is kept and a conflict is reported.
+///
+/// [`diff_compartments`]: crate::infrastructure::api::diff::diff_compartments
+pub fn merge_compartments(
+    base: &Compartment,
+    local: &Compartment,
+    update: &Compartment,
+) -> Result<MergeResult, Box<dyn Error>> {
+    let base = CompartmentItems::from_compartment(base)?;
+    let local = CompartmentItems::from_compartment(local)?;
+    let update = CompartmentItems::from_compartment(update)?;
+    let mut conflicts = vec![];
+    let default_group = merge_item_lists(
+        ItemType::DefaultGroup,
+        &base.default_group.into_iter().collect::<Vec<_>>(),
+        &local.default_group.into_iter().collect::<Vec<_>>(),
+        &update.default_group.into_iter().collect::<Vec<_>>(),
+        &mut conflicts,
+    )
+    .into_iter()
+    .next();
+    let merged_items = CompartmentItems {
+        default_group,
+        parameters: merge_item_lists(
+            ItemType::Parameter,
+            &base.parameters,
+            &local.parameters,
+            &update.parameters,
+            &mut conflicts,
+        ),
+        groups: merge_item_lists(
+            ItemType::Group,
+            &base.groups,
+            &local.groups,
+            &update.groups,
+            &mut conflicts,
+        ),
+        mappings: merge_item_lists(
+            ItemType::Mapping,
+            &base.mappings,
+            &local.mappings,
+            &update.mappings,
+            &mut conflicts,
+        ),
+    };
+    let result = MergeResult {
+        compartment: merged_items.into_compartment()?,
+        conflicts,
+    };
+    Ok(result)
+}
+
+/// The result contains the items in the order of the update, followed by items which only
+/// exist locally.
+fn merge_item_lists(
+    item_type: ItemType,
+    base: &[Value],
+    local: &[Value],
+    update: &[Value],
+    conflicts: &mut Vec<MergeConflict>,
+) -> Vec<Value> {
+    let base_in_local = base_indexes_to_other_indexes(base, local);
+    let base_in_update = base_indexes_to_other_indexes(base, update);
+    let local_is_from_base: Vec<_> = (0..local.len())
+        .map(|l| base_in_local.contains(&Some(l)))
+        .collect();
+    // Items which have been added on both sides
+    let local_additions: Vec<_> = (0..local.len())
+        .filter(|l| !local_is_from_base[*l])
+        .collect();
+    let update_additions: Vec<_> = (0..update.len())
+        .filter(|u| !base_in_update.contains(&Some(*u)))
+        .collect();
+    let addition_pairs = match_items(
+        &local_additions
+            .iter()
+            .map(|l| local[*l].clone())
+            .collect::<Vec<_>>(),
+        &update_additions
+            .iter()
+            .map(|u| update[*u].clone())
+            .collect::<Vec<_>>(),
+    );
+    let local_addition_for_update_addition = |u: usize| {
+        let update_addition_index = update_additions.iter().position(|a| *a == u)?;
+        addition_pairs
+            .iter()
+            .find(|(_, ua)| *ua == Some(update_addition_index))
+            .and_then(|(la, _)| *la)
+            .map(|la| local_additions[la])
+    };
+    let mut local_is_handled = vec![false; local.len()];
+    let mut merged = vec![];
+    let mut add_conflict = |item: &Value, kind: MergeConflictKind| {
+        conflicts.push(MergeConflict {
+            item_type,
+            label: item_label(item),
+            kind,
+        })
+    };
+    for (u, update_item) in update.iter().enumerate() {
+        let b = base_in_update.iter().position(|x| *x == Some(u));
+        match b {
+            Some(b) => match base_in_local[b] {
+                Some(l) => {
+                    local_is_handled[l] = true;
+                    merged.push(merge_item(
+                        Some(&base[b]),
+                        &local[l],
+                        update_item,
+                        &mut add_conflict,
+                    ));
+                }
+                None => {
+                    if without_identity(update_item) != without_identity(&base[b]) {
+                        add_conflict(update_item, MergeConflictKind::RemovedLocally);
+                    }
+                }
+            },
+            None => match local_addition_for_update_addition(u) {
+                Some(l) => {
+                    local_is_handled[l] = true;
+                    merged.push(merge_item(None, &local[l], update_item, &mut add_conflict));
+                }
+                None => merged.push(update_item.clone()),
+            },
+        }
+    }
+    // Items which have been removed in the update
+    for (b, base_item) in base.iter().enumerate() {
+        if base_in_update[b].is_some() {
+            continue;
+        }
+        if let Some(l) = base_in_local[b] {
+            local_is_handled[l] = true;
+            if without_identity(&local[l]) != without_identity(base_item) {
+                add_conflict(&local[l], MergeConflictKind::RemovedInUpdate);
+                merged.push(local[l].clone());
+            }
+        }
+    }
+    // Items which have been added locally
+    for (l, local_item) in local.iter().enumerate() {
+        if !local_is_handled[l] {
+            merged.push(local_item.clone());
+        }
+    }
+    merged
+}
+
+/// Returns for each base item the index of the corresponding item in the other list.
+fn base_indexes_to_other_indexes(base: &[Value], other: &[Value]) -> Vec<Option<usize>> {
+    let mut result = vec![None; base.len()];
+    for (b, o) in match_items(base, other) {
+        if let Some(b) = b {
+            result[b] = o;
+        }
+    }
+    result
+}
+
+/// The merged item keeps the local identity.
+fn merge_item(
+    base: Option<&Value>,
+    local: &Value,
+    update: &Value,
+    add_conflict: &mut impl FnMut(&Value, MergeConflictKind),
+) -> Value {
+    let base = base.map(without_identity);
+    let mut conflicting_paths = vec![];
+    let merged = merge_values(
+        "",
+        base.as_ref(),
+        Some(&without_identity(local)),
+        Some(&without_identity(update)),
+        &mut conflicting_paths,
+    )
+    .unwrap_or_default();
+    for path in conflicting_paths {
+        add_conflict(local, MergeConflictKind::ChangedInBoth { path });
+    }
+    with_identity_of(merged, local)
+}
+
+fn merge_values(
+    path: &str,
+    base: Option<&Value>,
+    local: Option<&Value>,
+    update: Option<&Value>,
+    conflicting_paths: &mut Vec<String>,
+) -> Option<Value> {
+    if local == update || update == base {
+        return local.cloned();
+    }
+    if local == base {
+        return update.cloned();
+    }
+    if let (Some(Value::Object(l)), Some(Value::Object(u))) = (local, update) {
+        // Objects of a different kind (e.g. targets of different types) can't be mixed.
+        if l.get("kind") == u.get("kind") {
+            let b = base.and_then(|b| b.as_object());
+            let mut merged = Map::new();
+            for key in union_of_keys(l, u) {
+                let merged_value = merge_values(
+                    &join_path(path, key),
+                    b.and_then(|b| b.get(key)),
+                    l.get(key),
+                    u.get(key),
+                    conflicting_paths,
+                );
+                if let Some(v) = merged_value {
+                    merged.insert(key.clone(), v);
+                }
+            }
+            return Some(Value::Object(merged));
+        }
+    }
+    conflicting_paths.push(path.to_string());
+    local.cloned()
+}
+
+impl fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}: ", self.item_type, self.label)?;
+        match &self.kind {
+            MergeConflictKind::ChangedInBoth { path } if path.is_empty() => {
+                f.write_str("changed locally and in update, kept local version")
+            }
+            MergeConflictKind::ChangedInBoth { path } => write!(
+                f,
+                "{} changed locally and in update, kept local version",
+                path
+            ),
+            MergeConflictKind::RemovedLocally => {
+                f.write_str("removed locally but changed in update, left it removed")
+            }
+            MergeConflictKind::RemovedInUpdate => {
+                f.write_str("changed locally but removed in update, kept it")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn compartment(json: Value) -> Compartment {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn merge_update_into_local_changes() {
+        // Given
+        let base = compartment(json!({
+            "mappings": [
+                { "id": "a", "name": "Volume", "glue": { "source_interval": [0.0, 1.0] } },
+                { "id": "b", "name": "Pan" },
+                { "id": "c", "name": "Mute" }
+            ]
+        }));
+        let local = compartment(json!({
+            "mappings": [
+                { "id": "a", "name": "Volume", "glue": { "source_interval": [0.0, 0.5] } },
+                { "id": "b", "name": "Pan", "enabled": false },
+                { "id": "c", "name": "Mute" },
+                { "id": "l", "name": "Local" }
+            ]
+        }));
+        let update = compartment(json!({
+            "mappings": [
+                {
+                    "id": "a",
+                    "name": "Volume",
+                    "glue": { "source_interval": [0.0, 1.0], "target_interval": [0.0, 0.8] }
+                },
+                { "id": "b", "name": "Pan", "enabled": true },
+                { "id": "u", "name": "Update" }
+            ]
+        }));
+        // When
+        let result = merge_compartments(&base, &local, &update).unwrap();
+        // Then
+        assert_eq!(
+            serde_json::to_value(&result.compartment).unwrap(),
+            json!({
+                "mappings": [
+                    {
+                        "id": "a",
+                        "name": "Volume",
+                        "glue": { "source_interval": [0.0, 0.5], "target_interval": [0.0, 0.8] }
+                    },
+                    { "id": "b", "name": "Pan", "enabled": false },
+                    { "id": "u", "name": "Update" },
+                    { "id": "l", "name": "Local" }
+                ]
+            })
+        );
+        assert_eq!(
+            result.conflicts,
+            vec![MergeConflict {
+                item_type: ItemType::Mapping,
+                label: "\"Pan\"".to_string(),
+                kind: MergeConflictKind::ChangedInBoth {
+                    path: "enabled".to_string()
+                }
+            }]
+        );
+    }
+
+    #[test]
+    fn keep_local_keys_of_mappings_paired_by_name() {
+        // Given
+        let base = compartment(json!({ "mappings": [{ "id": "random1", "name": "Volume" }] }));
+        let local = compartment(json!({ "mappings": [{ "id": "random1", "name": "Volume" }] }));
+        let update = compartment(json!({
+            "mappings": [{ "id": "random2", "name": "Volume", "enabled": false }]
+        }));
+        // When
+        let result = merge_compartments(&base, &local, &update).unwrap();
+        // Then
+        assert_eq!(
+            serde_json::to_value(&result.compartment).unwrap(),
+            json!({ "mappings": [{ "id": "random1", "name": "Volume", "enabled": false }] })
+        );
+        assert!(result.conflicts.is_empty());
+    }
+}
@@ -1 +1,4 @@
 pub mod convert;
+pub mod diff;
+pub mod lua_library;
+pub mod merge;
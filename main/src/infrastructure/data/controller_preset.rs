@@ -1,4 +1,7 @@
-use crate::application::{ControllerPreset, Preset, PresetManager};
+use crate::application::{
+    ControllerDeviceMatcher, ControllerPreset, Preset, PresetManager, PresetVariable,
+    PresetVariableValues,
+};
 use crate::domain::MappingCompartment;
 use crate::infrastructure::data::{
     CompartmentModelData, ExtendedPresetManager, FileBasedPresetManager, PresetData, PresetInfo,
@@ -23,6 +26,18 @@ impl PresetManager for SharedControllerPresetManager {
     fn find_by_id(&self, id: &str) -> Option<ControllerPreset> {
         self.borrow().find_by_id(id)
     }
+
+    fn find_by_id_with_variable_values(
+        &self,
+        id: &str,
+        values: &PresetVariableValues,
+    ) -> Result<ControllerPreset, String> {
+        self.borrow().find_by_id_with_variable_values(id, values)
+    }
+
+    fn variables(&self, id: &str) -> Vec<PresetVariable> {
+        self.borrow().variables(id)
+    }
 }
 
 impl ExtendedPresetManager for SharedControllerPresetManager {
@@ -56,6 +71,8 @@ pub struct ControllerPresetData {
     data: CompartmentModelData,
     #[serde(default, skip_serializing_if = "is_default")]
     custom_data: HashMap<String, serde_json::Value>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    device_matchers: Vec<ControllerDeviceMatcher>,
 }
 
 impl PresetData for ControllerPresetData {
@@ -68,9 +85,29 @@ impl PresetData for ControllerPresetData {
             data: CompartmentModelData::from_model(preset.data()),
             name: preset.name().to_string(),
             custom_data: preset.custom_data().clone(),
+            device_matchers: preset.device_matchers().to_vec(),
         }
     }
 
+    fn from_compartment_data(
+        name: String,
+        version: Option<Version>,
+        data: CompartmentModelData,
+    ) -> Self {
+        Self {
+            version,
+            id: None,
+            name,
+            data,
+            custom_data: Default::default(),
+            device_matchers: Default::default(),
+        }
+    }
+
+    fn compartment() -> MappingCompartment {
+        MappingCompartment::ControllerMappings
+    }
+
     fn to_model(&self, id: String) -> Result<ControllerPreset, String> {
         let preset = ControllerPreset::new(
             id,
@@ -80,6 +117,7 @@ impl PresetData for ControllerPresetData {
                 MappingCompartment::ControllerMappings,
             )?,
             self.custom_data.clone(),
+            self.device_matchers.clone(),
         );
         Ok(preset)
     }
@@ -1,4 +1,4 @@
-use crate::application::{MainPreset, Preset, PresetManager};
+use crate::application::{MainPreset, Preset, PresetManager, PresetVariable, PresetVariableValues};
 use crate::base::default_util::is_default;
 use crate::domain::MappingCompartment;
 use crate::infrastructure::data::{
@@ -15,11 +15,83 @@ pub type FileBasedMainPresetManager = FileBasedPresetManager<MainPreset, MainPre
 
 pub type SharedMainPresetManager = Rc<RefCell<FileBasedMainPresetManager>>;
 
+impl FileBasedMainPresetManager {
+    /// Extends the given preset IDs with the IDs of all presets which extend or include one of
+    /// them (directly or indirectly).
+    pub fn with_dependent_preset_ids(&self, preset_ids: Vec<String>) -> Vec<String> {
+        let dependent_preset_ids: Vec<_> = self
+            .preset_iter()
+            .filter(|p| !preset_ids.iter().any(|id| id == p.id()))
+            .filter(|p| {
+                self.composition_preset_ids(p.id())
+                    .iter()
+                    .any(|id| preset_ids.contains(id))
+            })
+            .map(|p| p.id().to_string())
+            .collect();
+        preset_ids.into_iter().chain(dependent_preset_ids).collect()
+    }
+
+    /// Returns the ID of the given preset and the IDs of all presets which it extends or includes
+    /// (directly or indirectly).
+    fn composition_preset_ids(&self, id: &str) -> Vec<String> {
+        let mut ids = vec![];
+        self.collect_composition_preset_ids(id, &mut ids);
+        ids
+    }
+
+    fn collect_composition_preset_ids(&self, id: &str, ids: &mut Vec<String>) {
+        if ids.iter().any(|i| i == id) {
+            return;
+        }
+        ids.push(id.to_string());
+        let preset = match self.preset_iter().find(|p| p.id() == id) {
+            None => return,
+            Some(p) => p,
+        };
+        let part_ids = preset
+            .base_preset_id()
+            .into_iter()
+            .chain(preset.included_preset_ids().iter().map(|id| id.as_str()));
+        for part_id in part_ids {
+            self.collect_composition_preset_ids(part_id, ids);
+        }
+    }
+}
+
 impl PresetManager for SharedMainPresetManager {
     type PresetType = MainPreset;
 
     fn find_by_id(&self, id: &str) -> Option<MainPreset> {
-        self.borrow().find_by_id(id)
+        self.find_by_id_with_variable_values(id, &Default::default())
+            .ok()
+    }
+
+    /// Returns the preset with its base preset and included presets already resolved.
+    ///
+    /// The variable values apply to the base and included presets as well.
+    fn find_by_id_with_variable_values(
+        &self,
+        id: &str,
+        values: &PresetVariableValues,
+    ) -> Result<MainPreset, String> {
+        let manager = self.borrow();
+        let preset = manager.find_by_id_with_variable_values(id, values)?;
+        preset.resolve(&|id| manager.find_by_id_with_variable_values(id, values))
+    }
+
+    /// Also returns the variables declared by the base and included presets.
+    fn variables(&self, id: &str) -> Vec<PresetVariable> {
+        let manager = self.borrow();
+        let mut variables: Vec<PresetVariable> = vec![];
+        for preset_id in manager.composition_preset_ids(id) {
+            for v in manager.variables(&preset_id) {
+                if !variables.iter().any(|existing| existing.key == v.key) {
+                    variables.push(v);
+                }
+            }
+        }
+        variables
     }
 }
 
@@ -50,6 +122,12 @@ pub struct MainPresetData {
     #[serde(skip_deserializing, skip_serializing_if = "is_default")]
     id: Option<String>,
     name: String,
+    /// ID of the main preset which this preset extends.
+    #[serde(default, skip_serializing_if = "is_default")]
+    base: Option<String>,
+    /// IDs of the main presets whose mappings should be included.
+    #[serde(default, skip_serializing_if = "is_default")]
+    includes: Vec<String>,
     #[serde(flatten)]
     data: CompartmentModelData,
 }
@@ -63,13 +141,36 @@ impl PresetData for MainPresetData {
             id: Some(preset.id().to_string()),
             data: CompartmentModelData::from_model(preset.data()),
             name: preset.name().to_string(),
+            base: preset.base_preset_id().map(|id| id.to_string()),
+            includes: preset.included_preset_ids().to_vec(),
         }
     }
 
+    fn from_compartment_data(
+        name: String,
+        version: Option<Version>,
+        data: CompartmentModelData,
+    ) -> Self {
+        Self {
+            version,
+            id: None,
+            name,
+            base: None,
+            includes: vec![],
+            data,
+        }
+    }
+
+    fn compartment() -> MappingCompartment {
+        MappingCompartment::MainMappings
+    }
+
     fn to_model(&self, id: String) -> Result<MainPreset, String> {
         let preset = MainPreset::new(
             id,
             self.name.clone(),
+            self.base.clone(),
+            self.includes.clone(),
             self.data
                 .to_model(self.version.as_ref(), MappingCompartment::MainMappings)?,
         );
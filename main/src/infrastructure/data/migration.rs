@@ -13,4 +13,15 @@ impl MigrationDescriptor {
             target_interval_transformation_117: preset_version.is_none(),
         }
     }
+
+    /// Describes the migrations which are going to be applied when loading the data.
+    pub fn descriptions(&self) -> Vec<&'static str> {
+        let mut descriptions = vec![];
+        if self.target_interval_transformation_117 {
+            descriptions.push(
+                "Target interval will be transformed (https://github.com/helgoboss/realearn/issues/117)",
+            );
+        }
+        descriptions
+    }
 }
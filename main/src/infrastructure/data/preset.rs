@@ -1,21 +1,38 @@
-use crate::application::{Preset, PresetManager};
+use crate::application::{
+    Preset, PresetManager, PresetVariable, PresetVariableValue, PresetVariableValues,
+};
 
 use crate::base::notification;
+use crate::domain::MappingCompartment;
+use crate::infrastructure::api::convert::to_data;
+use crate::infrastructure::data::CompartmentModelData;
 use crate::infrastructure::plugin::App;
+use crate::infrastructure::ui::deserialize_api_object_from_lua;
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use realearn_api::schema::{ApiObject, Envelope};
 use reaper_high::Reaper;
 use rxrust::prelude::*;
 use semver::Version;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fmt;
 use std::fmt::Debug;
 use std::fs;
 use std::marker::PhantomData;
 use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, SystemTime};
 
 #[derive(Debug)]
 pub struct FileBasedPresetManager<P: Preset, PD: PresetData<P = P>> {
     preset_dir_path: PathBuf,
     presets: Vec<P>,
+    /// Presets which declare variables, by preset ID.
+    parameterized_presets: HashMap<String, ParameterizedPreset>,
+    /// Modification times of the preset files at the time they were loaded.
+    file_modification_times: HashMap<PathBuf, SystemTime>,
     changed_subject: LocalSubject<'static, (), ()>,
     p: PhantomData<PD>,
 }
@@ -39,6 +56,8 @@ impl<P: Preset, PD: PresetData<P = P>> FileBasedPresetManager<P, PD> {
         let mut manager = FileBasedPresetManager {
             preset_dir_path,
             presets: vec![],
+            parameterized_presets: Default::default(),
+            file_modification_times: Default::default(),
             changed_subject: Default::default(),
             p: PhantomData,
         };
@@ -56,34 +75,66 @@ impl<P: Preset, PD: PresetData<P = P>> FileBasedPresetManager<P, PD> {
     }
 
     fn load_presets_internal(&mut self) -> Result<(), String> {
-        let preset_file_paths = fs::read_dir(&self.preset_dir_path)
-            .map_err(|_| "couldn't read preset directory".to_string())?
-            .filter_map(|result| {
-                let dir_entry = result.ok()?;
-                let file_type = dir_entry.file_type().ok()?;
-                if !file_type.is_file() {
-                    return None;
-                }
-                let path = dir_entry.path();
-                if path.extension() != Some(std::ffi::OsStr::new("json")) {
-                    return None;
-                };
-                Some(path)
-            });
+        let mut preset_file_paths = vec![];
+        collect_preset_file_paths(&self.preset_dir_path, &mut preset_file_paths)
+            .map_err(|_| "couldn't read preset directory".to_string())?;
+        self.file_modification_times = get_file_modification_times(&preset_file_paths);
+        // Lua presets take precedence over JSON presets with the same ID (same as when removing
+        // presets), so a JSON preset can be replaced by dropping a Lua file next to it.
+        preset_file_paths.sort_by_key(|p| p.extension() != Some(OsStr::new("lua")));
+        let mut loaded_ids = HashSet::new();
+        let mut parameterized_presets = HashMap::new();
         self.presets = preset_file_paths
-            .filter_map(|p| match Self::load_preset(p) {
-                Ok(p) => Some(p),
+            .into_iter()
+            .filter(|path| match self.get_preset_id(path) {
+                Ok(id) => loaded_ids.insert(id),
+                Err(_) => true,
+            })
+            .filter_map(|p| match self.load_preset(p) {
+                Ok((p, parameterized_preset)) => {
+                    if let Some(pp) = parameterized_preset {
+                        parameterized_presets.insert(p.id().to_string(), pp);
+                    }
+                    Some(p)
+                }
                 Err(msg) => {
                     notification::warn(msg);
                     None
                 }
             })
             .collect();
-        self.presets
-            .sort_unstable_by_key(|p| p.name().to_lowercase());
+        self.parameterized_presets = parameterized_presets;
+        self.presets.sort_unstable_by_key(|p| {
+            (
+                preset_namespace(p.id()).map(|ns| ns.to_lowercase()),
+                p.name().to_lowercase(),
+            )
+        });
         Ok(())
     }
 
+    /// Reloads all presets if preset files have been added, changed or removed since they were
+    /// loaded the last time (e.g. by editing them in a text editor).
+    ///
+    /// Returns the IDs of the presets whose files have been added or changed.
+    pub fn reload_presets_if_changed(&mut self) -> Vec<String> {
+        let mut preset_file_paths = vec![];
+        if collect_preset_file_paths(&self.preset_dir_path, &mut preset_file_paths).is_err() {
+            return vec![];
+        }
+        let file_modification_times = get_file_modification_times(&preset_file_paths);
+        if file_modification_times == self.file_modification_times {
+            return vec![];
+        }
+        let changed_preset_ids = file_modification_times
+            .iter()
+            .filter(|(path, time)| self.file_modification_times.get(*path) != Some(time))
+            .filter_map(|(path, _)| self.get_preset_id(path).ok())
+            .collect();
+        let _ = self.load_presets();
+        changed_preset_ids
+    }
+
     pub fn preset_iter(&self) -> impl Iterator<Item = &P> + ExactSizeIterator {
         self.presets.iter()
     }
@@ -93,9 +144,17 @@ impl<P: Preset, PD: PresetData<P = P>> FileBasedPresetManager<P, PD> {
     }
 
     pub fn add_preset(&mut self, preset: P) -> Result<(), &'static str> {
+        if self.get_lua_preset_file_path(preset.id()).exists() {
+            return Err("Lua presets can't be saved from within ReaLearn, please edit the file");
+        }
+        if self.parameterized_presets.contains_key(preset.id()) {
+            return Err(
+                "Presets with variables can't be saved from within ReaLearn, please edit the file",
+            );
+        }
         let path = self.get_preset_file_path(preset.id());
-        fs::create_dir_all(&self.preset_dir_path)
-            .map_err(|_| "couldn't create preset directory")?;
+        let dir_path = path.parent().unwrap_or(&self.preset_dir_path);
+        fs::create_dir_all(dir_path).map_err(|_| "couldn't create preset directory")?;
         let mut data = PD::from_model(&preset);
         // We don't want to have the ID in the file - because the file name itself is the ID
         data.clear_id();
@@ -133,28 +192,65 @@ impl<P: Preset, PD: PresetData<P = P>> FileBasedPresetManager<P, PD> {
         self.preset_dir_path.join(format!("{}.json", id))
     }
 
-    fn load_preset(path: impl AsRef<Path>) -> Result<P, String> {
-        let path = path.as_ref();
-        let id = path
-            .file_stem()
-            .ok_or_else(|| {
-                format!(
-                    "Preset file \"{}\" only has an extension but not a name. \
-                    The name is necessary because it makes up the preset ID.",
-                    path.display()
-                )
-            })?
-            .to_string_lossy()
-            .to_string();
-        let json = fs::read_to_string(&path)
-            .map_err(|_| format!("Couldn't read preset file \"{}\".", path.display()))?;
-        let data: PD = serde_json::from_str(&json).map_err(|e| {
+    fn get_lua_preset_file_path(&self, id: &str) -> PathBuf {
+        self.preset_dir_path.join(format!("{}.lua", id))
+    }
+
+    /// Derives the preset ID from the path relative to the preset directory.
+    ///
+    /// Presets in subfolders get the folder names as namespace, e.g. "helgoboss/apc-key-25".
+    fn get_preset_id(&self, path: &Path) -> Result<String, String> {
+        let file_stem = path.file_stem().ok_or_else(|| {
             format!(
-                "Preset file {} isn't valid. Details:\n\n{}",
-                path.display(),
-                e
+                "Preset file \"{}\" only has an extension but not a name. \
+                    The name is necessary because it makes up the preset ID.",
+                path.display()
             )
         })?;
+        let namespace_segments = path
+            .strip_prefix(&self.preset_dir_path)
+            .ok()
+            .and_then(|p| p.parent())
+            .into_iter()
+            .flat_map(|p| p.components())
+            .map(|c| c.as_os_str().to_string_lossy().to_string());
+        let segments: Vec<_> = namespace_segments
+            .chain(std::iter::once(file_stem.to_string_lossy().to_string()))
+            .collect();
+        Ok(segments.join("/"))
+    }
+
+    /// Returns the parameterized preset in addition if the preset declares variables.
+    fn load_preset(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<(P, Option<ParameterizedPreset>), String> {
+        let path = path.as_ref();
+        let id = self.get_preset_id(path)?;
+        if path.extension() == Some(OsStr::new("lua")) {
+            return Ok((Self::load_lua_preset(path, id)?, None));
+        }
+        let json = fs::read_to_string(&path)
+            .map_err(|_| format!("Couldn't read preset file \"{}\".", path.display()))?;
+        let mut json: serde_json::Value =
+            serde_json::from_str(&json).map_err(|e| invalid_preset_file(path, &e))?;
+        let variables =
+            take_preset_variables(&mut json).map_err(|e| invalid_preset_file(path, &e))?;
+        if variables.is_empty() {
+            let preset = Self::load_json_preset(path, id, json)?;
+            return Ok((preset, None));
+        }
+        let parameterized_preset = ParameterizedPreset {
+            path: path.to_path_buf(),
+            variables,
+            json,
+        };
+        let preset = parameterized_preset.resolve::<P, PD>(id, &Default::default())?;
+        Ok((preset, Some(parameterized_preset)))
+    }
+
+    fn load_json_preset(path: &Path, id: String, json: serde_json::Value) -> Result<P, String> {
+        let data: PD = serde_json::from_value(json).map_err(|e| invalid_preset_file(path, &e))?;
         if let Some(v) = data.version() {
             if App::version() < v {
                 let msg = format!(
@@ -174,6 +270,44 @@ impl<P: Preset, PD: PresetData<P = P>> FileBasedPresetManager<P, PD> {
         }
         data.to_model(id)
     }
+
+    /// Lua presets are evaluated just like Lua imports. They must return a compartment of the
+    /// kind managed by this preset manager.
+    fn load_lua_preset(path: &Path, id: String) -> Result<P, String> {
+        let invalid = |e: &dyn std::fmt::Display| {
+            format!(
+                "Lua preset file {} isn't valid. Details:\n\n{}",
+                path.display(),
+                e
+            )
+        };
+        let lua = fs::read_to_string(&path)
+            .map_err(|_| format!("Couldn't read preset file \"{}\".", path.display()))?;
+        let api_object = deserialize_api_object_from_lua(&lua).map_err(|e| invalid(&e))?;
+        let (compartment, api_compartment) = match api_object {
+            ApiObject::MainCompartment(Envelope { value }) => {
+                (MappingCompartment::MainMappings, value)
+            }
+            ApiObject::ControllerCompartment(Envelope { value }) => {
+                (MappingCompartment::ControllerMappings, value)
+            }
+            _ => return Err(invalid(&"Lua preset doesn't return a compartment")),
+        };
+        if compartment != PD::compartment() {
+            let msg = format!(
+                "Lua preset returns a {} but is located in the {} preset directory",
+                compartment,
+                PD::compartment()
+            );
+            return Err(invalid(&msg));
+        }
+        let compartment_data =
+            to_data::convert_compartment(*api_compartment).map_err(|e| invalid(&e))?;
+        let name = preset_name_from_id(&id).to_string();
+        // Lua presets are built using the current API, so no migration must be applied.
+        let data = PD::from_compartment_data(name, Some(App::version().clone()), compartment_data);
+        data.to_model(id)
+    }
 }
 
 impl<P: Preset, PD: PresetData<P = P>> ExtendedPresetManager for FileBasedPresetManager<P, PD> {
@@ -187,7 +321,12 @@ impl<P: Preset, PD: PresetData<P = P>> ExtendedPresetManager for FileBasedPreset
     }
 
     fn remove_preset(&mut self, id: &str) -> Result<(), &'static str> {
-        let path = self.get_preset_file_path(id);
+        let lua_path = self.get_lua_preset_file_path(id);
+        let path = if lua_path.exists() {
+            lua_path
+        } else {
+            self.get_preset_file_path(id)
+        };
         fs::remove_file(path).map_err(|_| "couldn't delete preset file")?;
         let _ = self.load_presets();
         Ok(())
@@ -197,7 +336,10 @@ impl<P: Preset, PD: PresetData<P = P>> ExtendedPresetManager for FileBasedPreset
         self.presets
             .iter()
             .map(|p| PresetInfo {
-                name: p.name().to_owned(),
+                name: match preset_namespace(p.id()) {
+                    None => p.name().to_owned(),
+                    Some(ns) => format!("{} / {}", ns, p.name()),
+                },
             })
             .collect()
     }
@@ -209,6 +351,54 @@ impl<P: Preset + Clone, PD: PresetData<P = P>> PresetManager for FileBasedPreset
     fn find_by_id(&self, id: &str) -> Option<P> {
         self.presets.iter().find(|c| c.id() == id).cloned()
     }
+
+    fn find_by_id_with_variable_values(
+        &self,
+        id: &str,
+        values: &PresetVariableValues,
+    ) -> Result<P, String> {
+        match self.parameterized_presets.get(id) {
+            None => self
+                .find_by_id(id)
+                .ok_or_else(|| format!("Preset \"{}\" doesn't exist", id)),
+            Some(p) => p.resolve::<P, PD>(id.to_string(), values),
+        }
+    }
+
+    fn variables(&self, id: &str) -> Vec<PresetVariable> {
+        self.parameterized_presets
+            .get(id)
+            .map(|p| p.variables.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// A preset which declares variables.
+///
+/// We keep its JSON because variable references can appear anywhere, also where numbers are
+/// expected. So they need to be resolved before the preset data is deserialized.
+#[derive(Debug)]
+struct ParameterizedPreset {
+    path: PathBuf,
+    variables: Vec<PresetVariable>,
+    json: serde_json::Value,
+}
+
+impl ParameterizedPreset {
+    fn resolve<P: Preset, PD: PresetData<P = P>>(
+        &self,
+        id: String,
+        values: &PresetVariableValues,
+    ) -> Result<P, String> {
+        let effective_values: HashMap<_, _> = self
+            .variables
+            .iter()
+            .map(|v| (v.key.as_str(), v.effective_value(values)))
+            .collect();
+        let mut json = self.json.clone();
+        substitute_preset_variables(&mut json, &effective_values);
+        FileBasedPresetManager::<P, PD>::load_json_preset(&self.path, id, json)
+    }
 }
 
 pub trait PresetData: Sized + Serialize + DeserializeOwned + Debug {
@@ -216,9 +406,247 @@ pub trait PresetData: Sized + Serialize + DeserializeOwned + Debug {
 
     fn from_model(preset: &Self::P) -> Self;
 
+    /// Used for presets which are not stored as data (e.g. Lua presets).
+    ///
+    /// The version determines which migrations are applied when converting the data to a model.
+    fn from_compartment_data(
+        name: String,
+        version: Option<Version>,
+        data: CompartmentModelData,
+    ) -> Self;
+
+    /// The compartment for which presets of this kind are made.
+    fn compartment() -> MappingCompartment;
+
     fn to_model(&self, id: String) -> Result<Self::P, String>;
 
     fn clear_id(&mut self);
 
     fn version(&self) -> Option<&Version>;
 }
+
+/// Collects all JSON and Lua preset files in the given directory and its subdirectories.
+fn collect_preset_file_paths(dir_path: &Path, paths: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for dir_entry in fs::read_dir(dir_path)? {
+        let dir_entry = match dir_entry {
+            Ok(e) => e,
+            Err(_) => continue,
+        };
+        let file_type = match dir_entry.file_type() {
+            Ok(t) => t,
+            Err(_) => continue,
+        };
+        let path = dir_entry.path();
+        if file_type.is_dir() {
+            // Unreadable subdirectories shouldn't prevent loading the other presets.
+            let _ = collect_preset_file_paths(&path, paths);
+            continue;
+        }
+        if !file_type.is_file() {
+            continue;
+        }
+        let extension = path.extension();
+        if extension == Some(OsStr::new("json")) || extension == Some(OsStr::new("lua")) {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Watches a preset directory (including subfolders) for added, changed or removed files.
+pub struct PresetDirWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+impl PresetDirWatcher {
+    /// Starts watching the given directory.
+    ///
+    /// The given callback is invoked in a background thread whenever something has changed.
+    pub fn new(dir_path: &Path, on_change: impl Fn() + Send + 'static) -> notify::Result<Self> {
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = notify::watcher(sender, PRESET_DIR_WATCHER_DELAY)?;
+        watcher.watch(dir_path, RecursiveMode::Recursive)?;
+        std::thread::Builder::new()
+            .name("ReaLearn preset dir watcher".to_string())
+            .spawn(move || {
+                // Receiving fails as soon as the watcher is dropped, which ends this thread.
+                while let Ok(event) = receiver.recv() {
+                    match event {
+                        DebouncedEvent::Create(_)
+                        | DebouncedEvent::Write(_)
+                        | DebouncedEvent::Remove(_)
+                        | DebouncedEvent::Rename(_, _)
+                        | DebouncedEvent::Rescan => on_change(),
+                        _ => {}
+                    }
+                }
+            })?;
+        Ok(Self { _watcher: watcher })
+    }
+}
+
+impl Debug for PresetDirWatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PresetDirWatcher").finish()
+    }
+}
+
+/// Editors often write a file in several steps, so we wait a bit before reporting a change.
+const PRESET_DIR_WATCHER_DELAY: Duration = Duration::from_millis(500);
+
+fn get_file_modification_times(paths: &[PathBuf]) -> HashMap<PathBuf, SystemTime> {
+    paths
+        .iter()
+        .filter_map(|p| {
+            let time = fs::metadata(p).ok()?.modified().ok()?;
+            Some((p.clone(), time))
+        })
+        .collect()
+}
+
+/// Returns the namespace of the given preset ID (the subfolder path), if any.
+pub fn preset_namespace(id: &str) -> Option<&str> {
+    id.rsplit_once('/').map(|(namespace, _)| namespace)
+}
+
+fn preset_name_from_id(id: &str) -> &str {
+    id.rsplit_once('/').map(|(_, name)| name).unwrap_or(id)
+}
+
+fn invalid_preset_file(path: &Path, e: &dyn std::fmt::Display) -> String {
+    format!(
+        "Preset file {} isn't valid. Details:\n\n{}",
+        path.display(),
+        e
+    )
+}
+
+/// Removes the variable declarations from the given preset JSON and returns them.
+pub fn take_preset_variables(
+    json: &mut serde_json::Value,
+) -> serde_json::Result<Vec<PresetVariable>> {
+    match json.as_object_mut().and_then(|o| o.remove("variables")) {
+        None => Ok(vec![]),
+        Some(variables) => serde_json::from_value(variables),
+    }
+}
+
+/// Replaces variable references (`${key}`) in all strings within the given JSON value.
+///
+/// A string which consists of nothing but a variable reference is replaced with the value itself,
+/// so integer variables can also be used where numbers are expected.
+pub fn substitute_preset_variables(
+    json: &mut serde_json::Value,
+    values: &HashMap<&str, PresetVariableValue>,
+) {
+    use serde_json::Value;
+    match json {
+        Value::String(s) => {
+            if let Some(v) = s
+                .strip_prefix("${")
+                .and_then(|rest| rest.strip_suffix('}'))
+                .and_then(|key| values.get(key))
+            {
+                *json = serde_json::to_value(v).unwrap_or(Value::Null);
+                return;
+            }
+            if s.contains("${") {
+                *s = substitute_variable_references(s, values);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                substitute_preset_variables(item, values);
+            }
+        }
+        Value::Object(map) => {
+            for item in map.values_mut() {
+                substitute_preset_variables(item, values);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Replaces all references to known variables in a single pass, so the result doesn't depend on
+/// the order of the values and substituted values are never substituted again.
+fn substitute_variable_references(s: &str, values: &HashMap<&str, PresetVariableValue>) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        result.push_str(&rest[..start]);
+        let reference = &rest[start..];
+        let value = reference
+            .find('}')
+            .and_then(|end| Some((end, values.get(&reference[2..end])?)));
+        match value {
+            None => {
+                result.push_str("${");
+                rest = &reference[2..];
+            }
+            Some((end, v)) => {
+                result.push_str(&v.to_string());
+                rest = &reference[end + 1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn substitute_variables() {
+        // Given
+        let mut json = json!({
+            "mappings": [
+                {
+                    "name": "Volume of ${track} (${track})",
+                    "source": { "channel": "${channel}" },
+                    "target": { "trackName": "${track}", "fxName": "${unknown}" }
+                }
+            ]
+        });
+        let values: HashMap<_, _> = vec![
+            ("track", PresetVariableValue::Text("Drums".to_string())),
+            ("channel", PresetVariableValue::Integer(9)),
+        ]
+        .into_iter()
+        .collect();
+        // When
+        substitute_preset_variables(&mut json, &values);
+        // Then
+        assert_eq!(
+            json,
+            json!({
+                "mappings": [
+                    {
+                        "name": "Volume of Drums (Drums)",
+                        "source": { "channel": 9 },
+                        "target": { "trackName": "Drums", "fxName": "${unknown}" }
+                    }
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn substitute_variables_in_single_pass() {
+        // Given
+        let mut json = json!("${a}/${b}/${c");
+        let values: HashMap<_, _> = vec![
+            ("a", PresetVariableValue::Text("${b}".to_string())),
+            ("b", PresetVariableValue::Text("x".to_string())),
+        ]
+        .into_iter()
+        .collect();
+        // When
+        substitute_preset_variables(&mut json, &values);
+        // Then
+        assert_eq!(json, json!("${b}/x/${c"));
+    }
+}
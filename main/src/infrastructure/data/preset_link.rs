@@ -1,4 +1,8 @@
-use crate::application::{FxId, FxPresetLinkConfig, PresetLinkManager, PresetLinkMutator};
+use crate::application::{
+    FxId, FxPresetLinkConfig, NamePresetLinkConfig, PresetLinkManager, PresetLinkMutator,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use std::cell::RefCell;
 use std::fs;
 use std::path::PathBuf;
@@ -10,6 +14,16 @@ pub type SharedPresetLinkManager = Rc<RefCell<FileBasedPresetLinkManager>>;
 pub struct FileBasedPresetLinkManager {
     auto_load_configs_dir_path: PathBuf,
     config: FxPresetLinkConfig,
+    track_config: NamePresetLinkConfig,
+    instrument_fx_config: FxPresetLinkConfig,
+    project_config: NamePresetLinkConfig,
+}
+
+/// Identifies one of the name-based link tables.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum NamePresetLinkKind {
+    Track,
+    Project,
 }
 
 impl FileBasedPresetLinkManager {
@@ -17,8 +31,12 @@ impl FileBasedPresetLinkManager {
         let mut manager = FileBasedPresetLinkManager {
             auto_load_configs_dir_path,
             config: Default::default(),
+            track_config: Default::default(),
+            instrument_fx_config: Default::default(),
+            project_config: Default::default(),
         };
         let _ = manager.load_fx_config();
+        let _ = manager.load_other_configs();
         manager
     }
 
@@ -26,10 +44,72 @@ impl FileBasedPresetLinkManager {
         &self.config
     }
 
+    pub fn instrument_fx_config(&self) -> &FxPresetLinkConfig {
+        &self.instrument_fx_config
+    }
+
+    pub fn name_config(&self, kind: NamePresetLinkKind) -> &NamePresetLinkConfig {
+        match kind {
+            NamePresetLinkKind::Track => &self.track_config,
+            NamePresetLinkKind::Project => &self.project_config,
+        }
+    }
+
+    /// Returns a mutator for the links from instrument FX to presets.
+    pub fn instrument_fx_mutator(&mut self) -> InstrumentFxPresetLinkMutator {
+        InstrumentFxPresetLinkMutator { manager: self }
+    }
+
+    pub fn update_name_pattern(
+        &mut self,
+        kind: NamePresetLinkKind,
+        old_pattern: &str,
+        new_pattern: String,
+    ) {
+        self.name_config_mut(kind)
+            .update_pattern(old_pattern, new_pattern);
+        self.save_name_config(kind).unwrap();
+    }
+
+    pub fn remove_name_link(&mut self, kind: NamePresetLinkKind, pattern: &str) {
+        self.name_config_mut(kind).remove_link(pattern);
+        self.save_name_config(kind).unwrap();
+    }
+
+    pub fn link_preset_to_name(
+        &mut self,
+        kind: NamePresetLinkKind,
+        preset_id: String,
+        pattern: String,
+    ) {
+        self.name_config_mut(kind)
+            .link_preset_to_name(preset_id, pattern);
+        self.save_name_config(kind).unwrap();
+    }
+
+    fn name_config_mut(&mut self, kind: NamePresetLinkKind) -> &mut NamePresetLinkConfig {
+        match kind {
+            NamePresetLinkKind::Track => &mut self.track_config,
+            NamePresetLinkKind::Project => &mut self.project_config,
+        }
+    }
+
     fn fx_config_file_path(&self) -> PathBuf {
         self.auto_load_configs_dir_path.join("fx.json")
     }
 
+    fn instrument_fx_config_file_path(&self) -> PathBuf {
+        self.auto_load_configs_dir_path.join("instrument-fx.json")
+    }
+
+    fn name_config_file_path(&self, kind: NamePresetLinkKind) -> PathBuf {
+        let file_name = match kind {
+            NamePresetLinkKind::Track => "track.json",
+            NamePresetLinkKind::Project => "project.json",
+        };
+        self.auto_load_configs_dir_path.join(file_name)
+    }
+
     fn load_fx_config(&mut self) -> Result<(), String> {
         let json = fs::read_to_string(&self.fx_config_file_path())
             .map_err(|_| "couldn't read FX preset link config file".to_string())?;
@@ -38,6 +118,20 @@ impl FileBasedPresetLinkManager {
         Ok(())
     }
 
+    /// Each of these configs is optional, so a missing file is not an error.
+    fn load_other_configs(&mut self) -> Result<(), String> {
+        if let Some(c) = load_config(&self.instrument_fx_config_file_path())? {
+            self.instrument_fx_config = c;
+        }
+        if let Some(c) = load_config(&self.name_config_file_path(NamePresetLinkKind::Track))? {
+            self.track_config = c;
+        }
+        if let Some(c) = load_config(&self.name_config_file_path(NamePresetLinkKind::Project))? {
+            self.project_config = c;
+        }
+        Ok(())
+    }
+
     fn save_fx_config(&self) -> Result<(), String> {
         fs::create_dir_all(&self.auto_load_configs_dir_path)
             .map_err(|_| "couldn't create auto-load-configs directory")?;
@@ -47,12 +141,73 @@ impl FileBasedPresetLinkManager {
             .map_err(|_| "couldn't write FX preset link config file")?;
         Ok(())
     }
+
+    fn save_instrument_fx_config(&self) -> Result<(), String> {
+        save_config(
+            &self.auto_load_configs_dir_path,
+            &self.instrument_fx_config_file_path(),
+            &self.instrument_fx_config,
+        )
+    }
+
+    fn save_name_config(&self, kind: NamePresetLinkKind) -> Result<(), String> {
+        save_config(
+            &self.auto_load_configs_dir_path,
+            &self.name_config_file_path(kind),
+            self.name_config(kind),
+        )
+    }
+}
+
+fn load_config<T: DeserializeOwned>(file_path: &PathBuf) -> Result<Option<T>, String> {
+    if !file_path.exists() {
+        return Ok(None);
+    }
+    let json = fs::read_to_string(file_path)
+        .map_err(|_| format!("couldn't read preset link config file {:?}", file_path))?;
+    let config = serde_json::from_str(&json).map_err(|e| {
+        format!(
+            "Preset link config file {:?} isn't valid. Details:\n\n{}",
+            file_path, e
+        )
+    })?;
+    Ok(Some(config))
+}
+
+fn save_config(
+    dir_path: &PathBuf,
+    file_path: &PathBuf,
+    config: &impl Serialize,
+) -> Result<(), String> {
+    fs::create_dir_all(dir_path).map_err(|_| "couldn't create auto-load-configs directory")?;
+    let json = serde_json::to_string_pretty(config)
+        .map_err(|_| "couldn't serialize preset link config")?;
+    fs::write(file_path, json).map_err(|_| "couldn't write preset link config file")?;
+    Ok(())
 }
 
 impl PresetLinkManager for SharedPresetLinkManager {
     fn find_preset_linked_to_fx(&self, fx_id: &FxId) -> Option<String> {
         self.borrow().config().find_preset_linked_to_fx(fx_id)
     }
+
+    fn find_preset_linked_to_track(&self, track_name: &str) -> Option<String> {
+        self.borrow()
+            .name_config(NamePresetLinkKind::Track)
+            .find_preset_linked_to_name(track_name)
+    }
+
+    fn find_preset_linked_to_instrument_fx(&self, fx_id: &FxId) -> Option<String> {
+        self.borrow()
+            .instrument_fx_config()
+            .find_preset_linked_to_fx(fx_id)
+    }
+
+    fn find_preset_linked_to_project(&self, project_name: &str) -> Option<String> {
+        self.borrow()
+            .name_config(NamePresetLinkKind::Project)
+            .find_preset_linked_to_name(project_name)
+    }
 }
 
 impl PresetLinkMutator for FileBasedPresetLinkManager {
@@ -71,3 +226,137 @@ impl PresetLinkMutator for FileBasedPresetLinkManager {
         self.save_fx_config().unwrap();
     }
 }
+
+/// Makes it possible to edit the instrument FX links with the same UI as the FX links.
+pub struct InstrumentFxPresetLinkMutator<'a> {
+    manager: &'a mut FileBasedPresetLinkManager,
+}
+
+impl<'a> PresetLinkMutator for InstrumentFxPresetLinkMutator<'a> {
+    fn update_fx_id(&mut self, old_fx_id: FxId, new_fx_id: FxId) {
+        self.manager
+            .instrument_fx_config
+            .update_fx_id(old_fx_id, new_fx_id);
+        self.manager.save_instrument_fx_config().unwrap();
+    }
+
+    fn remove_link(&mut self, fx_id: &FxId) {
+        self.manager.instrument_fx_config.remove_link(fx_id);
+        self.manager.save_instrument_fx_config().unwrap();
+    }
+
+    fn link_preset_to_fx(&mut self, preset_id: String, fx_id: FxId) {
+        self.manager
+            .instrument_fx_config
+            .link_preset_to_fx(preset_id, fx_id);
+        self.manager.save_instrument_fx_config().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_preset_linked_to_name() {
+        // Given
+        let dir = tempfile::tempdir().unwrap();
+        let mut manager = FileBasedPresetLinkManager::new(dir.path().to_path_buf());
+        manager.link_preset_to_name(
+            NamePresetLinkKind::Track,
+            "drums".to_string(),
+            "Drums".to_string(),
+        );
+        manager.link_preset_to_name(
+            NamePresetLinkKind::Project,
+            "live".to_string(),
+            "Live Set".to_string(),
+        );
+        let manager: SharedPresetLinkManager = Rc::new(RefCell::new(manager));
+        // When
+        let drums_track_preset = manager.find_preset_linked_to_track("Drums");
+        let bass_track_preset = manager.find_preset_linked_to_track("Bass");
+        let drums_project_preset = manager.find_preset_linked_to_project("Drums");
+        let live_project_preset = manager.find_preset_linked_to_project("Live Set");
+        // Then
+        assert_eq!(drums_track_preset, Some("drums".to_string()));
+        assert_eq!(bass_track_preset, None);
+        assert_eq!(drums_project_preset, None);
+        assert_eq!(live_project_preset, Some("live".to_string()));
+    }
+
+    #[test]
+    fn exact_links_take_precedence_over_wildcard_links() {
+        // Given
+        let dir = tempfile::tempdir().unwrap();
+        let mut manager = FileBasedPresetLinkManager::new(dir.path().to_path_buf());
+        let kind = NamePresetLinkKind::Track;
+        manager.link_preset_to_name(kind, "any-drums".to_string(), "Drums*".to_string());
+        manager.link_preset_to_name(kind, "kick".to_string(), "Drums Kick".to_string());
+        manager.link_preset_to_name(kind, "any-kit".to_string(), "Drums?Kit".to_string());
+        // When
+        let config = manager.name_config(kind);
+        // Then
+        assert_eq!(
+            config.find_preset_linked_to_name("Drums Kick"),
+            Some("kick".to_string())
+        );
+        assert_eq!(
+            config.find_preset_linked_to_name("Drums Snare"),
+            Some("any-drums".to_string())
+        );
+        assert_eq!(
+            config.find_preset_linked_to_name("Drums Kit"),
+            Some("any-drums".to_string())
+        );
+        assert_eq!(config.find_preset_linked_to_name("Bass"), None);
+    }
+
+    #[test]
+    fn persist_links() {
+        // Given
+        let dir = tempfile::tempdir().unwrap();
+        let mut manager = FileBasedPresetLinkManager::new(dir.path().to_path_buf());
+        manager.link_preset_to_name(
+            NamePresetLinkKind::Track,
+            "vocals".to_string(),
+            "Vox*".to_string(),
+        );
+        manager.link_preset_to_name(
+            NamePresetLinkKind::Project,
+            "mix".to_string(),
+            "Mix".to_string(),
+        );
+        manager.link_preset_to_name(
+            NamePresetLinkKind::Project,
+            "obsolete".to_string(),
+            "Old".to_string(),
+        );
+        manager.remove_name_link(NamePresetLinkKind::Project, "Old");
+        // When
+        let reloaded_manager = FileBasedPresetLinkManager::new(dir.path().to_path_buf());
+        // Then
+        assert!(dir.path().join("track.json").exists());
+        assert!(dir.path().join("project.json").exists());
+        assert_eq!(
+            reloaded_manager.name_config(NamePresetLinkKind::Track),
+            manager.name_config(NamePresetLinkKind::Track)
+        );
+        assert_eq!(
+            reloaded_manager.name_config(NamePresetLinkKind::Project),
+            manager.name_config(NamePresetLinkKind::Project)
+        );
+        assert_eq!(
+            reloaded_manager
+                .name_config(NamePresetLinkKind::Track)
+                .find_preset_linked_to_name("Vox Lead"),
+            Some("vocals".to_string())
+        );
+        assert_eq!(
+            reloaded_manager
+                .name_config(NamePresetLinkKind::Project)
+                .find_preset_linked_to_name("Old"),
+            None
+        );
+    }
+}
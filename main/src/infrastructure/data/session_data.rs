@@ -1,16 +1,17 @@
 use crate::application::{
     reaper_supports_global_midi_filter, CompartmentInSession, FxPresetLinkConfig, GroupModel,
-    MainPresetAutoLoadMode, Session,
+    MainPresetAutoLoadMode, PresetVariableValues, Session,
 };
 use crate::base::default_util::{bool_true, is_bool_true, is_default};
 use crate::domain::{
-    compartment_param_index_iter, BackboneState, ClipMatrixRef, CompartmentParamIndex,
-    ControlInput, FeedbackOutput, GroupId, GroupKey, InstanceState, MappingCompartment, MappingId,
-    MidiControlInput, MidiDestination, OscDeviceId, Param, PluginParamIndex, PluginParams, Tag,
+    compartment_param_index_iter, BackboneState, ClipMatrixRef, ClipMatrixViewport,
+    CompartmentParamIndex, ControlInput, FeedbackOutput, GroupId, GroupKey, InstanceState,
+    MappingCompartment, MappingId, MidiControlInput, MidiDestination, OscDeviceId, Param,
+    PluginParamIndex, PluginParams, Tag,
 };
 use crate::infrastructure::data::{
-    ensure_no_duplicate_compartment_data, GroupModelData, MappingModelData, MigrationDescriptor,
-    ParameterData,
+    ensure_no_duplicate_compartment_data, CompartmentModelData, GroupModelData, MappingModelData,
+    MigrationDescriptor, ParameterData,
 };
 use crate::infrastructure::plugin::App;
 
@@ -18,6 +19,7 @@ use crate::infrastructure::api::convert::to_data::ApiToDataConversionContext;
 use crate::infrastructure::data::clip_legacy::{
     create_clip_matrix_from_legacy_slots, QualifiedSlotDescriptor,
 };
+use enum_iterator::IntoEnumIterator;
 use playtime_api::Matrix;
 use reaper_medium::{MidiInputDeviceId, MidiOutputDeviceId};
 use semver::Version;
@@ -96,6 +98,8 @@ pub struct SessionData {
     #[serde(default, skip_serializing_if = "is_default")]
     clip_matrix: Option<ClipMatrixRefData>,
     #[serde(default, skip_serializing_if = "is_default")]
+    clip_matrix_viewport: ClipMatrixViewport,
+    #[serde(default, skip_serializing_if = "is_default")]
     pub tags: Vec<Tag>,
     #[serde(default, skip_serializing_if = "is_default")]
     controller: CompartmentState,
@@ -107,6 +111,18 @@ pub struct SessionData {
     instance_preset_link_config: FxPresetLinkConfig,
     #[serde(default, skip_serializing_if = "is_default")]
     use_instance_preset_links_only: bool,
+    #[serde(default, skip_serializing_if = "is_default")]
+    auto_select_controller_by_device: bool,
+    #[serde(default, skip_serializing_if = "is_default")]
+    main_preset_variable_values: PresetVariableValues,
+    #[serde(default, skip_serializing_if = "is_default")]
+    controller_preset_variable_values: PresetVariableValues,
+    /// Only saved if the compartment has been modified since loading the preset.
+    #[serde(default, skip_serializing_if = "is_default")]
+    main_preset_snapshot: Option<CompartmentModelData>,
+    /// Only saved if the compartment has been modified since loading the preset.
+    #[serde(default, skip_serializing_if = "is_default")]
+    controller_preset_snapshot: Option<CompartmentModelData>,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -114,6 +130,13 @@ pub struct SessionData {
 enum ClipMatrixRefData {
     Own(Matrix),
     Foreign(String),
+    ForeignByInstanceTag(ForeignClipMatrixByInstanceTagData),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ForeignClipMatrixByInstanceTagData {
+    instance_tag: Tag,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default)]
@@ -184,12 +207,18 @@ impl Default for SessionData {
             controller_parameters: Default::default(),
             clip_slots: vec![],
             clip_matrix: None,
+            clip_matrix_viewport: Default::default(),
             tags: vec![],
             controller: Default::default(),
             main: Default::default(),
             active_instance_tags: Default::default(),
             instance_preset_link_config: Default::default(),
             use_instance_preset_links_only: false,
+            auto_select_controller_by_device: false,
+            main_preset_variable_values: Default::default(),
+            controller_preset_variable_values: Default::default(),
+            main_preset_snapshot: None,
+            controller_preset_snapshot: None,
         }
     }
 }
@@ -269,7 +298,13 @@ impl SessionData {
                 MappingCompartment::ControllerMappings,
             ),
             clip_slots: vec![],
-            clip_matrix: {
+            clip_matrix: if let Some(tag) = session.foreign_clip_matrix_instance_tag() {
+                Some(ClipMatrixRefData::ForeignByInstanceTag(
+                    ForeignClipMatrixByInstanceTagData {
+                        instance_tag: tag.clone(),
+                    },
+                ))
+            } else {
                 instance_state
                     .clip_matrix_ref()
                     .and_then(|matrix_ref| match matrix_ref {
@@ -282,6 +317,7 @@ impl SessionData {
                         }
                     })
             },
+            clip_matrix_viewport: instance_state.clip_matrix_viewport(),
             tags: session.tags.get_ref().clone(),
             controller: CompartmentState::from_instance_state(
                 &instance_state,
@@ -294,6 +330,21 @@ impl SessionData {
             active_instance_tags: instance_state.active_instance_tags().clone(),
             instance_preset_link_config: session.instance_preset_link_config().clone(),
             use_instance_preset_links_only: session.use_instance_preset_links_only(),
+            auto_select_controller_by_device: session.auto_select_controller_by_device(),
+            main_preset_variable_values: session
+                .preset_variable_values(MappingCompartment::MainMappings)
+                .clone(),
+            controller_preset_variable_values: session
+                .preset_variable_values(MappingCompartment::ControllerMappings)
+                .clone(),
+            main_preset_snapshot: get_modified_preset_snapshot_data(
+                session,
+                MappingCompartment::MainMappings,
+            ),
+            controller_preset_snapshot: get_modified_preset_snapshot_data(
+                session,
+                MappingCompartment::ControllerMappings,
+            ),
         }
     }
 
@@ -466,10 +517,35 @@ impl SessionData {
         session.tags.set_without_notification(self.tags.clone());
         session.set_instance_preset_link_config(self.instance_preset_link_config.clone());
         session.set_use_instance_preset_links_only(self.use_instance_preset_links_only);
+        session.set_auto_select_controller_by_device(self.auto_select_controller_by_device);
+        session.set_preset_variable_values(
+            MappingCompartment::MainMappings,
+            self.main_preset_variable_values.clone(),
+        );
+        session.set_preset_variable_values(
+            MappingCompartment::ControllerMappings,
+            self.controller_preset_variable_values.clone(),
+        );
+        for compartment in MappingCompartment::enum_iter() {
+            let snapshot_data = match compartment {
+                MappingCompartment::ControllerMappings => &self.controller_preset_snapshot,
+                MappingCompartment::MainMappings => &self.main_preset_snapshot,
+            };
+            let snapshot = if session.active_preset_id(compartment).is_none() {
+                None
+            } else if let Some(data) = snapshot_data {
+                Some(data.to_model(self.version.as_ref(), compartment)?)
+            } else {
+                // Not modified since loading the preset
+                Some(session.extract_compartment_model(compartment))
+            };
+            session.set_preset_snapshot(compartment, snapshot);
+        }
         // Instance state
         {
             let instance_state = session.instance_state().clone();
             let mut instance_state = instance_state.borrow_mut();
+            session.set_foreign_clip_matrix_instance_tag(None);
             if let Some(matrix_ref) = &self.clip_matrix {
                 use ClipMatrixRefData::*;
                 match matrix_ref {
@@ -500,6 +576,24 @@ impl SessionData {
                             );
                         }
                     }
+                    ForeignByInstanceTag(d) => {
+                        session.set_foreign_clip_matrix_instance_tag(Some(d.instance_tag.clone()));
+                        // Check if an instance with that tag and an own clip matrix exists already.
+                        // If not, we resolve it as soon as it's loaded.
+                        let foreign_instance_id = App::get()
+                            .find_clip_matrix_owner_by_instance_tag_ignoring_borrowed_ones(
+                                &d.instance_tag,
+                            );
+                        if let Some(id) = foreign_instance_id {
+                            BackboneState::get().set_instance_clip_matrix_to_foreign_matrix(
+                                &mut instance_state,
+                                id,
+                            );
+                        } else {
+                            BackboneState::get()
+                                .clear_clip_matrix_from_instance_state(&mut instance_state);
+                        }
+                    }
                 };
             } else if !self.clip_slots.is_empty() {
                 let matrix = create_clip_matrix_from_legacy_slots(
@@ -516,6 +610,7 @@ impl SessionData {
             }
             instance_state
                 .set_active_instance_tags_without_notification(self.active_instance_tags.clone());
+            instance_state.set_clip_matrix_viewport_without_notification(self.clip_matrix_viewport);
             // Compartment-specific
             instance_state.set_active_mapping_by_group(
                 MappingCompartment::ControllerMappings,
@@ -534,15 +629,28 @@ impl SessionData {
                 self.main.active_mapping_tags.clone(),
             );
             // Check if some other instances waited for the clip matrix of this instance.
+            let owns_clip_matrix = instance_state.owned_clip_matrix().is_some();
             App::get().with_sessions(|sessions| {
                 let relevant_other_sessions = sessions.iter().filter_map(|other_session| {
                     let other_session = other_session.upgrade()?;
-                    if other_session
-                        .try_borrow()
-                        .ok()?
-                        .unresolved_foreign_clip_matrix_session_id()
-                        == self.id.as_ref()
-                    {
+                    let waits_for_this_session = {
+                        let other_session = other_session.try_borrow().ok()?;
+                        let waits_by_session_id = other_session
+                            .unresolved_foreign_clip_matrix_session_id()
+                            == self.id.as_ref();
+                        let waits_by_instance_tag = owns_clip_matrix
+                            && other_session
+                                .instance_state()
+                                .borrow()
+                                .clip_matrix_ref()
+                                .is_none()
+                            && other_session
+                                .foreign_clip_matrix_instance_tag()
+                                .map(|tag| self.tags.contains(tag))
+                                .unwrap_or(false);
+                        waits_by_session_id || waits_by_instance_tag
+                    };
+                    if waits_for_this_session {
                         Some(other_session)
                     } else {
                         None
@@ -580,6 +688,21 @@ impl SessionData {
     }
 }
 
+/// Returns the snapshot of the active preset if the compartment has been modified since then.
+fn get_modified_preset_snapshot_data(
+    session: &Session,
+    compartment: MappingCompartment,
+) -> Option<CompartmentModelData> {
+    let snapshot_data = CompartmentModelData::from_model(session.preset_snapshot(compartment)?);
+    let current_data =
+        CompartmentModelData::from_model(&session.extract_compartment_model(compartment));
+    if snapshot_data == current_data {
+        None
+    } else {
+        Some(snapshot_data)
+    }
+}
+
 fn get_parameter_data_map(
     plugin_params: &PluginParams,
     compartment: MappingCompartment,
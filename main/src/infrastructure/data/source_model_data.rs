@@ -10,7 +10,7 @@ use crate::infrastructure::data::common::OscValueRange;
 use crate::infrastructure::data::VirtualControlElementIdData;
 use helgoboss_learn::{DisplayType, MidiClockTransportMessage, OscTypeTag, SourceCharacter};
 use helgoboss_midi::{Channel, U14, U7};
-use realearn_api::schema::MidiScriptKind;
+use realearn_api::schema::{MidiScriptKind, MusicalTimerUnit};
 use semver::Version;
 use serde::{Deserialize, Serialize};
 use std::convert::TryInto;
@@ -83,6 +83,15 @@ pub struct SourceModelData {
     pub reaper_source_type: ReaperSourceType,
     #[serde(default, skip_serializing_if = "is_default")]
     pub timer_millis: u64,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub musical_timer_unit: MusicalTimerUnit,
+    /// `None` means 1.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub musical_timer_interval: Option<f64>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub musical_timer_offset: f64,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub musical_timer_only_while_playing: bool,
 }
 
 impl SourceModelData {
@@ -119,6 +128,10 @@ impl SourceModelData {
             ),
             reaper_source_type: model.reaper_source_type(),
             timer_millis: model.timer_millis(),
+            musical_timer_unit: model.musical_timer_unit(),
+            musical_timer_interval: Some(model.musical_timer_interval()).filter(|i| *i != 1.0),
+            musical_timer_offset: model.musical_timer_offset(),
+            musical_timer_only_while_playing: model.musical_timer_only_while_playing(),
         }
     }
 
@@ -198,6 +211,14 @@ impl SourceModelData {
         ));
         model.change(P::SetReaperSourceType(self.reaper_source_type));
         model.change(P::SetTimerMillis(self.timer_millis));
+        model.change(P::SetMusicalTimerUnit(self.musical_timer_unit));
+        model.change(P::SetMusicalTimerInterval(
+            self.musical_timer_interval.unwrap_or(1.0),
+        ));
+        model.change(P::SetMusicalTimerOffset(self.musical_timer_offset));
+        model.change(P::SetMusicalTimerOnlyWhilePlaying(
+            self.musical_timer_only_while_playing,
+        ));
         model.change(P::SetKeystroke(self.keystroke));
     }
 }
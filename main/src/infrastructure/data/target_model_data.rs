@@ -22,7 +22,9 @@ use crate::infrastructure::data::{
 };
 use crate::infrastructure::plugin::App;
 use helgoboss_learn::OscTypeTag;
-use realearn_api::schema::{ClipManagementAction, ClipSlotDescriptor, MonitoringMode};
+use realearn_api::schema::{
+    ClipManagementAction, ClipSlotDescriptor, ClipViewportAction, MonitoringMode,
+};
 use semver::Version;
 use serde::{Deserialize, Serialize};
 use std::convert::TryInto;
@@ -145,6 +147,10 @@ pub struct TargetModelData {
     pub slot_index: usize,
     #[serde(default, skip_serializing_if = "is_default")]
     pub clip_management_action: ClipManagementAction,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub clip_viewport_action: ClipViewportAction,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub clip_viewport_page_size: Option<u32>,
     /// Not supported anymore since v2.12.0-pre.5
     #[serde(default, skip_serializing_if = "is_default")]
     pub next_bar: bool,
@@ -216,6 +222,8 @@ impl TargetModelData {
             osc_dev_id: model.osc_dev_id(),
             slot_index: 0,
             clip_management_action: model.clip_management_action(),
+            clip_viewport_action: model.clip_viewport_action(),
+            clip_viewport_page_size: model.clip_viewport_page_size(),
             next_bar: false,
             buffered: false,
             poll_for_feedback: model.poll_for_feedback(),
@@ -411,6 +419,8 @@ impl TargetModelData {
             });
         model.change(C::SetClipSlot(slot_descriptor));
         model.change(C::SetClipManagementAction(self.clip_management_action));
+        model.change(C::SetClipViewportAction(self.clip_viewport_action));
+        model.change(C::SetClipViewportPageSize(self.clip_viewport_page_size));
     }
 }
 
@@ -0,0 +1,476 @@
+//! Preset processing which works without REAPER, e.g. for validating and converting presets in a
+//! command-line tool.
+use crate::application::PresetVariable;
+use crate::domain::ParamSetting;
+use crate::infrastructure::api::convert::from_data::ConversionStyle;
+use crate::infrastructure::api::convert::{from_data, to_data};
+use crate::infrastructure::api::lua_library::LuaHost;
+use crate::infrastructure::data::{
+    ensure_no_duplicate_compartment_data, substitute_preset_variables, take_preset_variables,
+    CompartmentModelData, ControllerPresetData, GroupModelData, MainPresetData, MappingModelData,
+    MigrationDescriptor, PresetData,
+};
+use crate::infrastructure::plugin::App;
+use crate::infrastructure::ui::{
+    deserialize_api_object_from_lua_in_host, serialize_data_object_to_lua, DataObject,
+};
+use realearn_api::schema::{ApiObject, Compartment, Envelope};
+use semver::Version;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum PresetKind {
+    Controller,
+    Main,
+}
+
+impl PresetKind {
+    /// Derives the kind from the preset directory layout (`presets/controller` or
+    /// `presets/main`).
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.ancestors()
+            .filter_map(|p| p.file_name()?.to_str())
+            .find_map(|name| match name {
+                "controller" => Some(Self::Controller),
+                "main" => Some(Self::Main),
+                _ => None,
+            })
+    }
+}
+
+/// A controller or main preset loaded from a JSON or Lua file.
+#[derive(Debug)]
+pub struct HeadlessPreset {
+    kind: Option<PresetKind>,
+    name: String,
+    version: Option<Version>,
+    base: Option<String>,
+    includes: Vec<String>,
+    variables: Vec<PresetVariable>,
+    unknown_properties: Vec<String>,
+    data: CompartmentModelData,
+}
+
+/// The parts of a JSON preset file which are common to controller and main presets.
+#[derive(Deserialize)]
+struct PresetFileData {
+    #[serde(default)]
+    version: Option<Version>,
+    name: String,
+    #[serde(default)]
+    base: Option<String>,
+    #[serde(default)]
+    includes: Vec<String>,
+    #[serde(flatten)]
+    data: CompartmentModelData,
+}
+
+impl HeadlessPreset {
+    /// Loads a preset from a `.json` or `.lua` file.
+    ///
+    /// If no kind is given, it's derived from the path (JSON) or the returned compartment (Lua).
+    pub fn load(path: &Path, kind: Option<PresetKind>) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Self::from_json(&text, kind.or_else(|| PresetKind::from_path(path))),
+            Some("lua") => {
+                let name = path
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                Self::from_lua(&text, name)
+            }
+            _ => Err("preset file must have extension .json or .lua".into()),
+        }
+    }
+
+    /// Variables are resolved using their default values.
+    pub fn from_json(text: &str, kind: Option<PresetKind>) -> Result<Self, Box<dyn Error>> {
+        let mut json: Value = serde_json::from_str(text)?;
+        let variables = take_preset_variables(&mut json)?;
+        let default_values = variables
+            .iter()
+            .map(|v| (v.key.as_str(), v.default_value()))
+            .collect();
+        substitute_preset_variables(&mut json, &default_values);
+        let unknown_properties = find_unknown_properties(&json, kind);
+        let file_data: PresetFileData = serde_json::from_value(json)?;
+        let preset = Self {
+            kind,
+            name: file_data.name,
+            version: file_data.version,
+            base: file_data.base,
+            includes: file_data.includes,
+            variables,
+            unknown_properties,
+            data: file_data.data,
+        };
+        Ok(preset)
+    }
+
+    pub fn from_lua(text: &str, name: String) -> Result<Self, Box<dyn Error>> {
+        let (kind, compartment) =
+            match deserialize_api_object_from_lua_in_host(text, LuaHost::Headless)? {
+                ApiObject::ControllerCompartment(Envelope { value }) => {
+                    (PresetKind::Controller, value)
+                }
+                ApiObject::MainCompartment(Envelope { value }) => (PresetKind::Main, value),
+                _ => return Err("Lua preset must return a controller or main compartment".into()),
+            };
+        let preset = Self {
+            kind: Some(kind),
+            name,
+            // Lua presets are always up-to-date
+            version: Some(App::version().clone()),
+            base: None,
+            includes: vec![],
+            variables: vec![],
+            // The API types reject unknown properties themselves
+            unknown_properties: vec![],
+            data: to_data::convert_compartment(*compartment)?,
+        };
+        Ok(preset)
+    }
+
+    pub fn kind(&self) -> Option<PresetKind> {
+        self.kind
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn version(&self) -> Option<&Version> {
+        self.version.as_ref()
+    }
+
+    /// ID of the main preset which this preset extends.
+    pub fn base(&self) -> Option<&str> {
+        self.base.as_deref()
+    }
+
+    /// IDs of the main presets which this preset includes.
+    pub fn includes(&self) -> &[String] {
+        &self.includes
+    }
+
+    pub fn variables(&self) -> &[PresetVariable] {
+        &self.variables
+    }
+
+    /// Number of mappings defined in this preset itself (not counting base or included presets).
+    pub fn mapping_count(&self) -> usize {
+        self.data.mappings.len()
+    }
+
+    pub fn group_count(&self) -> usize {
+        self.data.groups.len()
+    }
+
+    /// Describes which migrations ReaLearn applies when loading this preset.
+    pub fn pending_migrations(&self) -> Vec<&'static str> {
+        MigrationDescriptor::new(self.version.as_ref()).descriptions()
+    }
+
+    /// Checks the things which can be checked without loading the preset into ReaLearn.
+    ///
+    /// Returns the compartment as JSON API object, ready for being validated against the schema.
+    pub fn validate(&self) -> Result<Value, Box<dyn Error>> {
+        if !self.unknown_properties.is_empty() {
+            let msg = format!(
+                "Contains unknown properties:\n  {}",
+                self.unknown_properties.join("\n  ")
+            );
+            return Err(msg.into());
+        }
+        ensure_no_duplicate_compartment_data(
+            &self.data.mappings,
+            &self.data.groups,
+            self.data.parameters.values(),
+        )?;
+        let api_compartment = self.to_api_compartment()?;
+        let json = serde_json::to_value(&api_compartment)?;
+        // Make sure that the way back works as well
+        to_data::convert_compartment(api_compartment)?;
+        Ok(json)
+    }
+
+    /// Returns the compartment in the form of the ReaLearn API, e.g. for comparing presets.
+    pub fn to_api_compartment(&self) -> Result<Compartment, Box<dyn Error>> {
+        from_data::convert_compartment(self.data.clone(), ConversionStyle::Minimal)
+    }
+
+    /// Returns a copy of this preset with the given compartment content.
+    ///
+    /// Fails if this preset extends or includes other presets or declares variables because
+    /// the result wouldn't contain them anymore.
+    pub fn with_api_compartment(&self, compartment: Compartment) -> Result<Self, Box<dyn Error>> {
+        if self.base.is_some() || !self.includes.is_empty() || !self.variables.is_empty() {
+            return Err(
+                "presets which extend or include other presets or declare variables are not \
+                supported"
+                    .into(),
+            );
+        }
+        let preset = Self {
+            kind: self.kind,
+            name: self.name.clone(),
+            version: Some(App::version().clone()),
+            base: None,
+            includes: vec![],
+            variables: vec![],
+            data: to_data::convert_compartment(compartment)?,
+        };
+        Ok(preset)
+    }
+
+    pub fn to_lua(&self, include_default_values: bool) -> Result<String, Box<dyn Error>> {
+        if self.base.is_some() || !self.includes.is_empty() {
+            return Err(
+                "presets which extend or include other presets can't be converted to Lua".into(),
+            );
+        }
+        if !self.variables.is_empty() {
+            return Err("presets with variables can't be converted to Lua".into());
+        }
+        let envelope = Envelope {
+            value: Box::new(self.data.clone()),
+        };
+        let data_object = match self.kind {
+            Some(PresetKind::Controller) => DataObject::ControllerCompartment(envelope),
+            Some(PresetKind::Main) => DataObject::MainCompartment(envelope),
+            None => {
+                return Err("couldn't determine whether this is a controller or main preset".into())
+            }
+        };
+        let style = if include_default_values {
+            ConversionStyle::IncludeDefaultValues
+        } else {
+            ConversionStyle::Minimal
+        };
+        serialize_data_object_to_lua(data_object, style)
+    }
+
+    /// Converts this preset to the JSON format in which ReaLearn saves presets.
+    pub fn to_json(&self) -> Result<String, Box<dyn Error>> {
+        let name = self.name.clone();
+        let data = self.data.clone();
+        let json = match self.kind {
+            Some(PresetKind::Controller) => serde_json::to_string_pretty(
+                &ControllerPresetData::from_compartment_data(name, self.version.clone(), data),
+            )?,
+            Some(PresetKind::Main) => serde_json::to_string_pretty(
+                &MainPresetData::from_compartment_data(name, self.version.clone(), data),
+            )?,
+            None => {
+                return Err("couldn't determine whether this is a controller or main preset".into())
+            }
+        };
+        Ok(json)
+    }
+}
+
+/// Returns JSON pointers to all properties which ReaLearn doesn't know (e.g. because they are
+/// misspelled) and would therefore silently ignore when loading the preset.
+///
+/// Presets are saved in the data format, which doesn't have a schema. That's why we probe each
+/// property: If replacing its value with a value of a completely different type still results in
+/// exactly the same preset, the property is not used by ReaLearn.
+fn find_unknown_properties(json: &Value, kind: Option<PresetKind>) -> Vec<String> {
+    let mut pointers = match kind {
+        Some(PresetKind::Controller) => {
+            find_unknown_properties_of::<ControllerPresetData>(json, false)
+        }
+        Some(PresetKind::Main) => find_unknown_properties_of::<MainPresetData>(json, false),
+        None => vec![],
+    };
+    // Probing each mapping and group on its own is much faster than probing the complete preset.
+    let mut probe_nested = |prefix: String, value: &Value, probe: fn(&Value) -> Vec<String>| {
+        pointers.extend(probe(value).into_iter().map(|p| format!("{}{}", prefix, p)));
+    };
+    if let Some(group) = json.get("defaultGroup") {
+        probe_nested(
+            "/defaultGroup".to_string(),
+            group,
+            find_unknown_nested_properties_of::<GroupModelData>,
+        );
+    }
+    for (i, group) in array_items(json, "groups").iter().enumerate() {
+        probe_nested(
+            format!("/groups/{}", i),
+            group,
+            find_unknown_nested_properties_of::<GroupModelData>,
+        );
+    }
+    for (i, mapping) in array_items(json, "mappings").iter().enumerate() {
+        probe_nested(
+            format!("/mappings/{}", i),
+            mapping,
+            find_unknown_nested_properties_of::<MappingModelData>,
+        );
+    }
+    if let Some(Value::Object(parameters)) = json.get("parameters") {
+        for (key, parameter) in parameters {
+            probe_nested(
+                format!("/parameters/{}", escape_json_pointer_token(key)),
+                parameter,
+                find_unknown_nested_properties_of::<ParamSetting>,
+            );
+        }
+    }
+    pointers
+}
+
+fn array_items<'a>(json: &'a Value, key: &str) -> &'a [Value] {
+    json.get(key)
+        .and_then(|v| v.as_array())
+        .map(|items| items.as_slice())
+        .unwrap_or_default()
+}
+
+fn find_unknown_nested_properties_of<T: DeserializeOwned + PartialEq>(
+    value: &Value,
+) -> Vec<String> {
+    find_unknown_properties_of::<T>(value, true)
+}
+
+/// If not recursive, only looks at the top-level properties.
+fn find_unknown_properties_of<T: DeserializeOwned + PartialEq>(
+    value: &Value,
+    recursive: bool,
+) -> Vec<String> {
+    // If it's not deserializable at all, loading the preset fails anyway with a better message.
+    let original: T = match serde_json::from_value(value.clone()) {
+        Ok(v) => v,
+        Err(_) => return vec![],
+    };
+    let mut pointers = vec![];
+    collect_unknown_properties(value, &original, "", recursive, &mut pointers);
+    pointers
+}
+
+fn collect_unknown_properties<T: DeserializeOwned + PartialEq>(
+    root: &Value,
+    original: &T,
+    pointer: &str,
+    recursive: bool,
+    pointers: &mut Vec<String>,
+) {
+    match root.pointer(pointer) {
+        Some(Value::Object(properties)) => {
+            for key in properties.keys() {
+                let child_pointer = format!("{}/{}", pointer, escape_json_pointer_token(key));
+                let mut probe = root.clone();
+                if let Some(value) = probe.pointer_mut(&child_pointer) {
+                    *value = Value::String(UNKNOWN_PROPERTY_PROBE.to_string());
+                }
+                let is_unknown = serde_json::from_value::<T>(probe)
+                    .map(|v| &v == original)
+                    .unwrap_or(false);
+                if is_unknown {
+                    pointers.push(child_pointer);
+                } else if recursive {
+                    collect_unknown_properties(root, original, &child_pointer, true, pointers);
+                }
+            }
+        }
+        Some(Value::Array(items)) if recursive => {
+            for i in 0..items.len() {
+                let child_pointer = format!("{}/{}", pointer, i);
+                collect_unknown_properties(root, original, &child_pointer, true, pointers);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn escape_json_pointer_token(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+/// A value which no known property would accept without changing the result.
+const UNKNOWN_PROPERTY_PROBE: &str = "~realearn-unknown-property-probe~";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(PartialEq, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Example {
+        #[serde(default)]
+        name: String,
+        #[serde(default)]
+        enabled: bool,
+        #[serde(default)]
+        inner: Option<ExampleInner>,
+    }
+
+    #[derive(PartialEq, Deserialize)]
+    struct ExampleInner {
+        #[serde(default)]
+        value: u32,
+    }
+
+    #[test]
+    fn report_unknown_properties() {
+        // Given
+        let json = json!({
+            "name": "foo",
+            "nmae": "foo",
+            "inner": { "value": 5, "valeu": 6 },
+        });
+        // When
+        let mut pointers = find_unknown_nested_properties_of::<Example>(&json);
+        // Then
+        pointers.sort();
+        assert_eq!(
+            pointers,
+            vec!["/inner/valeu".to_string(), "/nmae".to_string()]
+        );
+    }
+
+    #[test]
+    fn dont_report_properties_with_default_value() {
+        // Given
+        let json = json!({
+            "name": "",
+            "enabled": false,
+            "inner": { "value": 0 },
+        });
+        // When
+        let pointers = find_unknown_nested_properties_of::<Example>(&json);
+        // Then
+        assert!(pointers.is_empty());
+    }
+
+    #[test]
+    fn report_only_top_level_properties() {
+        // Given
+        let json = json!({
+            "name": "foo",
+            "inner": { "valeu": 6 },
+            "foo/bar": true,
+        });
+        // When
+        let pointers = find_unknown_properties_of::<Example>(&json, false);
+        // Then
+        assert_eq!(pointers, vec!["/foo~1bar".to_string()]);
+    }
+
+    #[test]
+    fn report_nothing_if_not_deserializable() {
+        // Given
+        let json = json!({ "name": 5, "nmae": "foo" });
+        // When
+        let pointers = find_unknown_nested_properties_of::<Example>(&json);
+        // Then
+        assert!(pointers.is_empty());
+    }
+}
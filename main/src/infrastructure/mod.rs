@@ -1,5 +1,6 @@
-mod api;
+pub(crate) mod api;
 mod data;
+pub mod headless;
 mod plugin;
 mod server;
 mod test;
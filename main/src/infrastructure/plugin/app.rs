@@ -6,19 +6,21 @@ use crate::base::{
     notification, Global, NamedChannelSender, SenderToNormalThread, SenderToRealTimeThread,
 };
 use crate::domain::{
-    ActionInvokedEvent, AdditionalFeedbackEvent, BackboneState, EnableInstancesArgs, Exclusivity,
-    FeedbackAudioHookTask, Garbage, GarbageBin, GroupId, InputDescriptor, InstanceContainer,
-    InstanceId, InstanceOrchestrationEvent, MainProcessor, MappingCompartment, MessageCaptureEvent,
-    MessageCaptureResult, MidiScanResult, NormalAudioHookTask, OscDeviceId, OscFeedbackProcessor,
-    OscFeedbackTask, OscScanResult, QualifiedClipMatrixEvent, RealearnAccelerator,
-    RealearnAudioHook, RealearnControlSurfaceMainTask, RealearnControlSurfaceMiddleware,
+    ActionInvokedEvent, AdditionalFeedbackEvent, BackboneState, ControlInput, EnableInstancesArgs,
+    Exclusivity, FeedbackAudioHookTask, Garbage, GarbageBin, GroupId, InputDescriptor,
+    InstanceContainer, InstanceId, InstanceOrchestrationEvent, MainProcessor, MappingCompartment,
+    MessageCaptureEvent, MessageCaptureResult, MidiControlInput, MidiIdentityReply, MidiScanResult,
+    NormalAudioHookTask, OscDeviceId, OscFeedbackProcessor, OscFeedbackTask, OscScanResult,
+    QualifiedClipMatrixEvent, RealearnAccelerator, RealearnAudioHook,
+    RealearnControlSurfaceMainTask, RealearnControlSurfaceMiddleware,
     RealearnControlSurfaceServerTask, RealearnTarget, RealearnTargetContext, ReaperTarget,
-    SharedMainProcessors, SharedRealTimeProcessor, Tag,
+    SharedMainProcessors, SharedRealTimeProcessor, Tag, MIDI_IDENTITY_REQUEST,
 };
 use crate::infrastructure::data::{
     ExtendedPresetManager, FileBasedControllerPresetManager, FileBasedMainPresetManager,
-    FileBasedPresetLinkManager, OscDevice, OscDeviceManager, SharedControllerPresetManager,
-    SharedMainPresetManager, SharedOscDeviceManager, SharedPresetLinkManager,
+    FileBasedPresetLinkManager, OscDevice, OscDeviceManager, PresetDirWatcher,
+    SharedControllerPresetManager, SharedMainPresetManager, SharedOscDeviceManager,
+    SharedPresetLinkManager,
 };
 use crate::infrastructure::plugin::debug_util;
 use crate::infrastructure::server;
@@ -26,24 +28,27 @@ use crate::infrastructure::server::{RealearnServer, SharedRealearnServer, COMPAN
 use crate::infrastructure::ui::MessagePanel;
 
 use crate::infrastructure::plugin::tracing_util::setup_tracing;
+use helgoboss_learn::{create_raw_midi_events_singleton, RawMidiEvent};
 use metrics_exporter_prometheus::PrometheusBuilder;
 use once_cell::sync::Lazy;
 use reaper_high::{ActionKind, CrashInfo, Fx, MiddlewareControlSurface, Project, Reaper, Track};
 use reaper_low::{PluginContext, Swell};
 use reaper_medium::{
     AcceleratorPosition, ActionValueChange, CommandId, HookPostCommand, HookPostCommand2,
-    ReaProject, RegistrationHandle, SectionContext, WindowContext,
+    MidiInputDeviceId, MidiOutputDeviceId, ReaProject, ReaperString, RegistrationHandle,
+    SectionContext, WindowContext,
 };
 use reaper_rx::{ActionRxHookPostCommand, ActionRxHookPostCommand2};
 use rxrust::prelude::*;
 use semver::Version;
 use serde::{Deserialize, Serialize};
-use slog::{debug, Drain, Logger};
-use std::cell::{Ref, RefCell};
+use slog::{debug, warn, Drain, Logger};
+use std::cell::{Cell, Ref, RefCell};
 use std::collections::HashSet;
 use std::fs;
 use std::path::PathBuf;
 use std::rc::Rc;
+use std::time::Duration;
 use swell_ui::{SharedView, View};
 use tempfile::TempDir;
 use url::Url;
@@ -68,6 +73,8 @@ const GARBAGE_QUEUE_SIZE: usize = 50_000;
 const INSTANCE_ORCHESTRATION_EVENT_QUEUE_SIZE: usize = 5000;
 const NORMAL_AUDIO_HOOK_TASK_QUEUE_SIZE: usize = 2000;
 const OSC_OUTGOING_QUEUE_SIZE: usize = 1000;
+const MIDI_DEVICE_SETTLE_TIME: Duration = Duration::from_millis(2500);
+const MIDI_IDENTITY_REPLY_TIMEOUT: Duration = Duration::from_millis(300);
 
 make_available_globally_in_main_thread!(App);
 
@@ -105,6 +112,9 @@ pub struct App {
     sessions_changed_subject: RefCell<LocalSubject<'static, (), ()>>,
     message_panel: SharedView<MessagePanel>,
     osc_feedback_processor: Rc<RefCell<OscFeedbackProcessor>>,
+    preset_dir_watcher: RefCell<Option<PresetDirWatcher>>,
+    controller_auto_selection_running: Cell<bool>,
+    controller_auto_selection_requested: Cell<bool>,
 }
 
 #[derive(Debug)]
@@ -185,6 +195,14 @@ impl ListOfRecentlyFocusedFx {
     }
 }
 
+/// A connected controller for which a matching controller preset has been found.
+#[derive(Debug)]
+struct ControllerCandidate {
+    preset_id: String,
+    input_device_id: MidiInputDeviceId,
+    output_device_id: Option<MidiOutputDeviceId>,
+}
+
 impl App {
     pub fn detailed_version_label() -> &'static str {
         static VALUE: Lazy<String> = Lazy::new(build_detailed_version);
@@ -289,6 +307,9 @@ impl App {
             osc_feedback_processor: Rc::new(RefCell::new(OscFeedbackProcessor::new(
                 osc_feedback_task_receiver,
             ))),
+            preset_dir_watcher: Default::default(),
+            controller_auto_selection_running: Cell::new(false),
+            controller_auto_selection_requested: Cell::new(false),
         }
     }
 
@@ -343,6 +364,11 @@ impl App {
             .subscribe(move |fx| {
                 list_of_recently_focused_fx.borrow_mut().feed(fx);
             });
+        self.watch_preset_dir();
+        BackboneState::get()
+            .midi_devices_connected()
+            .take_until(self.party_is_over())
+            .subscribe(|_| App::get().auto_select_controllers());
         let shared_main_processors = SharedMainProcessors::default();
         let control_surface = MiddlewareControlSurface::new(RealearnControlSurfaceMiddleware::new(
             App::logger(),
@@ -369,6 +395,275 @@ impl App {
         self.state.replace(AppState::Sleeping(sleeping_state));
     }
 
+    fn watch_preset_dir(&self) {
+        let preset_dir_path = App::realearn_preset_dir_path();
+        let _ = fs::create_dir_all(&preset_dir_path);
+        // The watcher notifies us in a background thread, so we need to get back into the main
+        // thread before reloading.
+        let watcher = PresetDirWatcher::new(&preset_dir_path, || {
+            let _ = Global::task_support().do_later_in_main_thread_asap(|| {
+                App::get().reload_changed_presets();
+            });
+        });
+        match watcher {
+            Ok(w) => {
+                self.preset_dir_watcher.replace(Some(w));
+            }
+            Err(e) => {
+                warn!(App::logger(), "Couldn't watch preset directory: {}", e);
+            }
+        }
+    }
+
+    /// Reloads presets whose files have been changed on disk and re-applies them to all sessions
+    /// which use them.
+    ///
+    /// Sessions with unsaved modifications of a changed preset are left alone unless the config
+    /// says otherwise.
+    pub fn reload_changed_presets(&self) {
+        let changed_controller_preset_ids = self
+            .controller_preset_manager
+            .borrow_mut()
+            .reload_presets_if_changed();
+        let changed_main_preset_ids = {
+            let mut main_preset_manager = self.main_preset_manager.borrow_mut();
+            let changed_ids = main_preset_manager.reload_presets_if_changed();
+            // Presets which are composed of changed presets need to be re-applied as well
+            main_preset_manager.with_dependent_preset_ids(changed_ids)
+        };
+        if changed_controller_preset_ids.is_empty() && changed_main_preset_ids.is_empty() {
+            return;
+        }
+        let overwrite_modifications = self
+            .config
+            .borrow()
+            .preset_reload_overwrites_modifications();
+        let sessions: Vec<_> = self
+            .sessions
+            .borrow()
+            .iter()
+            .filter_map(|s| s.upgrade())
+            .collect();
+        for session in sessions {
+            let mut session = match session.try_borrow_mut() {
+                Ok(s) => s,
+                Err(_) => continue,
+            };
+            for compartment in MappingCompartment::enum_iter() {
+                let changed_preset_ids = match compartment {
+                    MappingCompartment::ControllerMappings => &changed_controller_preset_ids,
+                    MappingCompartment::MainMappings => &changed_main_preset_ids,
+                };
+                let preset_id = match session.active_preset_id(compartment) {
+                    Some(id) if changed_preset_ids.iter().any(|changed_id| changed_id == id) => {
+                        id.to_string()
+                    }
+                    _ => continue,
+                };
+                if !overwrite_modifications && session.compartment_or_preset_is_dirty(compartment) {
+                    continue;
+                }
+                let result = match compartment {
+                    MappingCompartment::ControllerMappings => {
+                        session.activate_controller_preset(Some(preset_id))
+                    }
+                    MappingCompartment::MainMappings => {
+                        session.activate_main_preset(Some(preset_id))
+                    }
+                };
+                if let Err(e) = result {
+                    notification::warn(format!("Couldn't re-apply changed preset: {}", e));
+                }
+            }
+        }
+    }
+
+    /// Probes the connected MIDI devices and lets each instance which has controller
+    /// auto-selection enabled switch to the controller preset (and MIDI I/O) of a matching device.
+    ///
+    /// If a run is already in progress, another run is done as soon as it has finished.
+    pub fn auto_select_controllers(&self) {
+        if self.sessions_with_controller_auto_selection().is_empty() {
+            return;
+        }
+        if self.controller_auto_selection_running.get() {
+            self.controller_auto_selection_requested.set(true);
+            return;
+        }
+        self.controller_auto_selection_running.set(true);
+        Global::future_support().spawn_in_main_thread_from_main_thread(async {
+            loop {
+                // The control surface has just reset the newly connected devices. Give them a
+                // chance to settle before we send anything.
+                futures_timer::Delay::new(MIDI_DEVICE_SETTLE_TIME).await;
+                let replies = App::get().identify_midi_devices().await;
+                App::get().apply_controller_auto_selection(&replies);
+                if !App::get()
+                    .controller_auto_selection_requested
+                    .replace(false)
+                {
+                    break;
+                }
+            }
+            App::get().controller_auto_selection_running.set(false);
+        });
+    }
+
+    fn sessions_with_controller_auto_selection(&self) -> Vec<SharedSession> {
+        self.sessions
+            .borrow()
+            .iter()
+            .filter_map(|s| s.upgrade())
+            .filter(|s| {
+                s.try_borrow()
+                    .map(|s| s.auto_select_controller_by_device())
+                    .unwrap_or(false)
+            })
+            .collect()
+    }
+
+    /// Sends an identity request to each connected MIDI output device, one after the other, and
+    /// collects the replies.
+    ///
+    /// Probing one device at a time is the only way to find out which input and output belong
+    /// together.
+    async fn identify_midi_devices(&self) -> Vec<MidiIdentityReply> {
+        let output_device_ids: Vec<_> = Reaper::get()
+            .midi_output_devices()
+            .filter(|d| d.is_connected())
+            .map(|d| d.id())
+            .collect();
+        let mut replies = vec![];
+        for output_device_id in output_device_ids {
+            let (sender, receiver) = async_channel::bounded(100);
+            self.audio_hook_task_sender.send_complaining(
+                NormalAudioHookTask::StartIdentifyingMidiDevice(output_device_id, sender),
+            );
+            let request = RawMidiEvent::try_from_slice(0, &MIDI_IDENTITY_REQUEST)
+                .expect("identity request invalid");
+            self.feedback_audio_hook_task_sender
+                .send_complaining(FeedbackAudioHookTask::SendMidi(
+                    output_device_id,
+                    create_raw_midi_events_singleton(request),
+                ));
+            futures_timer::Delay::new(MIDI_IDENTITY_REPLY_TIMEOUT).await;
+            self.audio_hook_task_sender.send_complaining(
+                NormalAudioHookTask::StopIdentifyingMidiDevice(output_device_id),
+            );
+            while let Ok(reply) = receiver.try_recv() {
+                replies.push(reply);
+            }
+        }
+        replies
+    }
+
+    fn apply_controller_auto_selection(&self, replies: &[MidiIdentityReply]) {
+        let mut candidates = self.find_controller_candidates(replies);
+        let mut sessions = self.sessions_with_controller_auto_selection();
+        // Instances which already use one of the detected controllers keep it
+        sessions.retain(|session| {
+            let session = session.borrow();
+            let pos = candidates.iter().position(|c| {
+                session.active_controller_preset_id() == Some(c.preset_id.as_str())
+                    && session.control_input()
+                        == ControlInput::Midi(MidiControlInput::Device(c.input_device_id))
+            });
+            if let Some(pos) = pos {
+                candidates.remove(pos);
+                false
+            } else {
+                true
+            }
+        });
+        for (session, candidate) in sessions.into_iter().zip(candidates) {
+            let result = session.borrow_mut().auto_select_controller(
+                &candidate.preset_id,
+                candidate.input_device_id,
+                candidate.output_device_id,
+            );
+            if let Err(e) = result {
+                debug!(
+                    App::logger(),
+                    "Couldn't auto-select controller {}: {}", candidate.preset_id, e
+                );
+            }
+        }
+    }
+
+    /// Returns one candidate per detected controller (first matching controller preset wins).
+    fn find_controller_candidates(
+        &self,
+        replies: &[MidiIdentityReply],
+    ) -> Vec<ControllerCandidate> {
+        let input_devices: Vec<_> = Reaper::get()
+            .midi_input_devices()
+            .filter(|d| d.is_connected())
+            .map(|d| (d.id(), midi_device_name(d.name())))
+            .collect();
+        let output_devices: Vec<_> = Reaper::get()
+            .midi_output_devices()
+            .filter(|d| d.is_connected())
+            .map(|d| (d.id(), midi_device_name(d.name())))
+            .collect();
+        let input_name = |id: MidiInputDeviceId| {
+            input_devices
+                .iter()
+                .find(|(i, _)| *i == id)
+                .map(|(_, name)| name.as_str())
+                .unwrap_or_default()
+        };
+        let output_name = |id: MidiOutputDeviceId| {
+            output_devices
+                .iter()
+                .find(|(i, _)| *i == id)
+                .map(|(_, name)| name.as_str())
+                .unwrap_or_default()
+        };
+        let mut candidates: Vec<ControllerCandidate> = vec![];
+        let preset_manager = self.controller_preset_manager.borrow();
+        for preset in preset_manager.preset_iter() {
+            for matcher in preset.device_matchers() {
+                let candidate = if matcher.needs_identity() {
+                    replies
+                        .iter()
+                        .find(|r| {
+                            let identity = Some(&r.identity);
+                            matcher.matches(input_name(r.input_device_id), identity)
+                                || matcher.matches(output_name(r.output_device_id), identity)
+                        })
+                        .map(|r| (r.input_device_id, Some(r.output_device_id)))
+                } else {
+                    input_devices
+                        .iter()
+                        .find(|(_, name)| matcher.matches(name, None))
+                        .map(|(input_id, _)| {
+                            let output_id = output_devices
+                                .iter()
+                                .find(|(_, name)| matcher.matches(name, None))
+                                .map(|(id, _)| *id);
+                            (*input_id, output_id)
+                        })
+                };
+                let (input_device_id, output_device_id) = match candidate {
+                    None => continue,
+                    Some(c) => c,
+                };
+                if candidates
+                    .iter()
+                    .any(|c| c.input_device_id == input_device_id)
+                {
+                    continue;
+                }
+                candidates.push(ControllerCandidate {
+                    preset_id: preset.id().to_string(),
+                    input_device_id,
+                    output_device_id,
+                });
+            }
+        }
+        candidates
+    }
+
     fn control_surface_metrics_enabled() -> bool {
         static ENABLED: Lazy<bool> = Lazy::new(|| std::env::var("CONTROL_SURFACE_METRICS").is_ok());
         *ENABLED
@@ -854,6 +1149,28 @@ impl App {
         })
     }
 
+    /// Returns the instance ID of the first ReaLearn instance which has the given instance tag and
+    /// owns a clip matrix.
+    pub fn find_clip_matrix_owner_by_instance_tag_ignoring_borrowed_ones(
+        &self,
+        tag: &Tag,
+    ) -> Option<InstanceId> {
+        let session = self.find_session(|session| {
+            if let Ok(session) = session.try_borrow() {
+                session.tags.get_ref().contains(tag)
+                    && session
+                        .instance_state()
+                        .borrow()
+                        .owned_clip_matrix()
+                        .is_some()
+            } else {
+                false
+            }
+        })?;
+        let instance_id = *session.borrow().instance_id();
+        Some(instance_id)
+    }
+
     pub fn find_session(
         &self,
         predicate: impl FnMut(&SharedSession) -> bool,
@@ -1428,6 +1745,10 @@ impl AppConfig {
         self.main.server_enabled > 0
     }
 
+    pub fn preset_reload_overwrites_modifications(&self) -> bool {
+        self.main.preset_reload_overwrites_modifications > 0
+    }
+
     pub fn companion_web_app_url(&self) -> url::Url {
         Url::parse(&self.main.companion_web_app_url).expect("invalid companion web app URL")
     }
@@ -1456,6 +1777,10 @@ struct MainConfig {
         skip_serializing_if = "is_default_companion_web_app_url"
     )]
     companion_web_app_url: String,
+    /// Whether presets changed on disk should be re-applied to sessions even if these have unsaved
+    /// modifications of that preset.
+    #[serde(default, skip_serializing_if = "is_default")]
+    preset_reload_overwrites_modifications: u8,
 }
 
 const DEFAULT_SERVER_HTTP_PORT: u16 = 39080;
@@ -1492,10 +1817,16 @@ impl Default for MainConfig {
             server_http_port: default_server_http_port(),
             server_https_port: default_server_https_port(),
             companion_web_app_url: default_companion_web_app_url(),
+            preset_reload_overwrites_modifications: Default::default(),
         }
     }
 }
 
+fn midi_device_name(name: ReaperString) -> String {
+    // MIDI device names are not necessarily UTF-8
+    name.into_inner().to_string_lossy().to_string()
+}
+
 fn build_detailed_version() -> String {
     use crate::infrastructure::plugin::built_info::*;
     let dirty_mark = if GIT_DIRTY == Some(true) {
@@ -3,12 +3,16 @@
 use crate::application::{Preset, PresetManager, Session, SourceCategory, TargetCategory};
 use crate::base::NamedChannelSender;
 use crate::domain::{
-    MappingCompartment, MappingKey, ProjectionFeedbackValue, RealearnControlSurfaceServerTask,
+    BackboneState, MappingCompartment, MappingKey, ProjectionFeedbackValue,
+    RealearnControlSurfaceServerTask,
 };
 use crate::infrastructure::data::{ControllerPresetData, PresetData};
 use crate::infrastructure::plugin::{App, RealearnControlSurfaceServerTaskSender};
 use helgoboss_learn::UnitValue;
 use maplit::hashmap;
+use playtime_api::ClipColor;
+use playtime_clip_engine::main::{ClipMatrixEvent, ClipSlotCoordinates};
+use playtime_clip_engine::rt::{ClipChangedEvent, ClipPlayState};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
@@ -26,6 +30,8 @@ pub enum DataError {
     OnlyPatchReplaceIsSupported,
     OnlyCustomDataKeyIsSupportedAsPatchPath,
     CouldntUpdateController,
+    SessionHasNoClipMatrix,
+    ClipMatrixCommandFailed(&'static str),
 }
 
 #[derive(Deserialize)]
@@ -41,6 +47,137 @@ enum PatchRequestOp {
     Replace,
 }
 
+#[derive(Deserialize)]
+pub struct ClipSlotCommandRequest {
+    action: ClipSlotAction,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum ClipSlotAction {
+    Play,
+    Stop,
+    Record,
+    ToggleLooped,
+}
+
+/// A clip matrix event in a form suitable for clients.
+///
+/// Slot coordinates are always absolute (they don't take the viewport of the session into account).
+#[derive(Serialize)]
+#[serde(tag = "kind")]
+pub enum ClipMatrixEventData {
+    /// The complete matrix should be fetched again.
+    AllClipsChanged,
+    #[serde(rename_all = "camelCase")]
+    PlayStateChanged {
+        column_index: usize,
+        row_index: usize,
+        play_state: ClipPlayStateData,
+    },
+    #[serde(rename_all = "camelCase")]
+    VolumeChanged {
+        column_index: usize,
+        row_index: usize,
+        volume_db: f64,
+    },
+    #[serde(rename_all = "camelCase")]
+    LoopedChanged {
+        column_index: usize,
+        row_index: usize,
+        looped: bool,
+    },
+    #[serde(rename_all = "camelCase")]
+    ColorChanged {
+        column_index: usize,
+        row_index: usize,
+        color: ClipColor,
+    },
+    /// Sent continuously while a clip is playing or recording.
+    #[serde(rename_all = "camelCase")]
+    PositionChanged {
+        column_index: usize,
+        row_index: usize,
+        /// Position within the clip between 0.0 and 1.0.
+        position: f64,
+    },
+    #[serde(rename_all = "camelCase")]
+    ClipRemoved {
+        column_index: usize,
+        row_index: usize,
+    },
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize)]
+pub enum ClipPlayStateData {
+    Stopped,
+    ScheduledForPlayStart,
+    Playing,
+    Paused,
+    ScheduledForPlayStop,
+    ScheduledForRecordingStart,
+    Recording,
+    ScheduledForRecordingStop,
+}
+
+impl From<ClipPlayState> for ClipPlayStateData {
+    fn from(s: ClipPlayState) -> Self {
+        use ClipPlayState::*;
+        match s {
+            Stopped => Self::Stopped,
+            ScheduledForPlayStart => Self::ScheduledForPlayStart,
+            Playing => Self::Playing,
+            Paused => Self::Paused,
+            ScheduledForPlayStop => Self::ScheduledForPlayStop,
+            ScheduledForRecordingStart => Self::ScheduledForRecordingStart,
+            Recording => Self::Recording,
+            ScheduledForRecordingStop => Self::ScheduledForRecordingStop,
+        }
+    }
+}
+
+impl ClipMatrixEventData {
+    pub fn from_event(event: &ClipMatrixEvent) -> Self {
+        let e = match event {
+            ClipMatrixEvent::AllClipsChanged => return Self::AllClipsChanged,
+            ClipMatrixEvent::ClipChanged(e) => e,
+        };
+        let column_index = e.slot_coordinates.column();
+        let row_index = e.slot_coordinates.row();
+        match &e.event {
+            ClipChangedEvent::PlayState(s) => Self::PlayStateChanged {
+                column_index,
+                row_index,
+                play_state: (*s).into(),
+            },
+            ClipChangedEvent::ClipVolume(db) => Self::VolumeChanged {
+                column_index,
+                row_index,
+                volume_db: db.get(),
+            },
+            ClipChangedEvent::ClipLooped(looped) => Self::LoopedChanged {
+                column_index,
+                row_index,
+                looped: *looped,
+            },
+            ClipChangedEvent::ClipColor(color) => Self::ColorChanged {
+                column_index,
+                row_index,
+                color: color.clone(),
+            },
+            ClipChangedEvent::ClipPosition(pos) => Self::PositionChanged {
+                column_index,
+                row_index,
+                position: pos.get(),
+            },
+            ClipChangedEvent::Removed => Self::ClipRemoved {
+                column_index,
+                row_index,
+            },
+        }
+    }
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ControllerRouting {
@@ -94,6 +231,46 @@ pub fn get_controller_preset_data(session_id: String) -> Result<ControllerPreset
     Ok(ControllerPresetData::from_model(&controller))
 }
 
+/// Returns the clip matrix used by the given session, even if it's owned by another session.
+pub fn get_clip_matrix_data(session_id: String) -> Result<playtime_api::Matrix, DataError> {
+    let session = App::get()
+        .find_session_by_id(&session_id)
+        .ok_or(DataError::SessionNotFound)?;
+    let matrix = get_clip_matrix(&session.borrow()).ok_or(DataError::SessionHasNoClipMatrix)?;
+    Ok(matrix)
+}
+
+pub fn get_clip_matrix(session: &Session) -> Option<playtime_api::Matrix> {
+    BackboneState::get()
+        .with_clip_matrix(session.instance_state(), |matrix| matrix.save())
+        .ok()
+}
+
+pub fn execute_clip_slot_command(
+    session_id: String,
+    column_index: usize,
+    row_index: usize,
+    req: ClipSlotCommandRequest,
+) -> Result<(), DataError> {
+    let session = App::get()
+        .find_session_by_id(&session_id)
+        .ok_or(DataError::SessionNotFound)?;
+    let session = session.borrow();
+    let coordinates = ClipSlotCoordinates::new(column_index, row_index);
+    BackboneState::get()
+        .with_clip_matrix_mut(session.instance_state(), |matrix| {
+            use ClipSlotAction::*;
+            match req.action {
+                Play => matrix.play_clip(coordinates),
+                Stop => matrix.stop_clip(coordinates),
+                Record => matrix.record_clip(coordinates, 0),
+                ToggleLooped => matrix.toggle_looped(coordinates),
+            }
+        })
+        .map_err(|_| DataError::SessionHasNoClipMatrix)?
+        .map_err(DataError::ClipMatrixCommandFailed)
+}
+
 #[cfg(feature = "realearn-metrics")]
 pub async fn obtain_control_surface_metrics_snapshot(
     control_surface_task_sender: RealearnControlSurfaceServerTaskSender,
@@ -203,6 +380,8 @@ pub enum Topic {
     ActiveController { session_id: String },
     ControllerRouting { session_id: String },
     Feedback { session_id: String },
+    ClipMatrix { session_id: String },
+    ClipMatrixEvents { session_id: String },
 }
 
 impl TryFrom<&str> for Topic {
@@ -220,6 +399,12 @@ impl TryFrom<&str> for Topic {
             ["realearn", "session", id, "feedback"] => Topic::Feedback {
                 session_id: id.to_string(),
             },
+            ["realearn", "session", id, "clip-matrix"] => Topic::ClipMatrix {
+                session_id: id.to_string(),
+            },
+            ["realearn", "session", id, "clip-matrix-events"] => Topic::ClipMatrixEvents {
+                session_id: id.to_string(),
+            },
             ["realearn", "session", id] => Topic::Session {
                 session_id: id.to_string(),
             },
@@ -257,6 +442,26 @@ pub fn get_projection_feedback_event(
     )
 }
 
+pub fn get_clip_matrix_updated_event(
+    session_id: &str,
+    matrix: Option<playtime_api::Matrix>,
+) -> Event<Option<playtime_api::Matrix>> {
+    Event::put(
+        format!("/realearn/session/{}/clip-matrix", session_id),
+        matrix,
+    )
+}
+
+pub fn get_clip_matrix_events_event(
+    session_id: &str,
+    events: &[ClipMatrixEvent],
+) -> Event<Vec<ClipMatrixEventData>> {
+    Event::patch(
+        format!("/realearn/session/{}/clip-matrix-events", session_id),
+        events.iter().map(ClipMatrixEventData::from_event).collect(),
+    )
+}
+
 pub fn get_session_updated_event(
     session_id: &str,
     session_data: Option<SessionResponseData>,
@@ -2,10 +2,11 @@ use crate::base::Global;
 use crate::infrastructure::data::ControllerPresetData;
 use crate::infrastructure::plugin::RealearnControlSurfaceServerTaskSender;
 use crate::infrastructure::server::http::{
-    get_controller_preset_data, get_controller_routing_by_session_id, get_session_data,
+    execute_clip_slot_command, get_clip_matrix_data, get_controller_preset_data,
+    get_controller_routing_by_session_id, get_session_data,
     obtain_control_surface_metrics_snapshot, patch_controller, send_initial_events,
-    ControllerRouting, DataError, PatchRequest, ServerClients, SessionResponseData, Topics,
-    WebSocketClient,
+    ClipSlotCommandRequest, ControllerRouting, DataError, PatchRequest, ServerClients,
+    SessionResponseData, Topics, WebSocketClient,
 };
 use axum::body::{boxed, Body, BoxBody};
 use axum::extract::ws::{Message, WebSocket};
@@ -58,6 +59,24 @@ pub async fn patch_controller_handler(
     Ok(StatusCode::OK)
 }
 
+/// Needs to be executed in the main thread!
+pub async fn clip_matrix_handler(
+    Path(session_id): Path<String>,
+) -> Result<Json<playtime_api::Matrix>, SimpleResponse> {
+    let matrix = get_clip_matrix_data(session_id).map_err(translate_data_error)?;
+    Ok(Json(matrix))
+}
+
+/// Needs to be executed in the main thread!
+pub async fn clip_slot_command_handler(
+    Path((session_id, column_index, row_index)): Path<(String, usize, usize)>,
+    Json(command_request): Json<ClipSlotCommandRequest>,
+) -> Result<StatusCode, SimpleResponse> {
+    execute_clip_slot_command(session_id, column_index, row_index, command_request)
+        .map_err(translate_data_error)?;
+    Ok(StatusCode::OK)
+}
+
 pub fn create_cert_response(cert: String, cert_file_name: &str) -> Response<BoxBody> {
     Response::builder()
         .status(StatusCode::OK)
@@ -170,6 +189,8 @@ fn translate_data_error(e: DataError) -> SimpleResponse {
             StatusCode::INTERNAL_SERVER_ERROR,
             "couldn't update controller",
         ),
+        SessionHasNoClipMatrix => not_found("session doesn't have a clip matrix"),
+        ClipMatrixCommandFailed(msg) => (StatusCode::BAD_REQUEST, msg),
     }
 }
 
@@ -5,10 +5,12 @@ use crate::domain::ProjectionFeedbackValue;
 use crate::infrastructure::plugin::App;
 use crate::infrastructure::server::http::client::WebSocketClient;
 use crate::infrastructure::server::http::data::{
-    get_active_controller_updated_event, get_controller_routing_updated_event,
+    get_active_controller_updated_event, get_clip_matrix, get_clip_matrix_events_event,
+    get_clip_matrix_updated_event, get_controller_routing_updated_event,
     get_projection_feedback_event, get_session_updated_event, send_initial_feedback,
     SessionResponseData, Topic,
 };
+use playtime_clip_engine::main::ClipMatrixEvent;
 use rxrust::prelude::*;
 use serde::Serialize;
 use std::rc::Rc;
@@ -32,6 +34,9 @@ fn send_initial_events_for_topic(
             send_initial_feedback(session_id);
             Ok(())
         }
+        ClipMatrix { session_id } => send_initial_clip_matrix(client, session_id),
+        // Events are just streamed, there's no initial state.
+        ClipMatrixEvents { .. } => Ok(()),
     }
 }
 pub fn send_initial_session(
@@ -67,6 +72,17 @@ fn send_initial_controller(client: &WebSocketClient, session_id: &str) -> Result
     client.send(&event)
 }
 
+fn send_initial_clip_matrix(
+    client: &WebSocketClient,
+    session_id: &str,
+) -> Result<(), &'static str> {
+    let matrix = App::get()
+        .find_session_by_id(session_id)
+        .and_then(|session| get_clip_matrix(&session.borrow()));
+    let event = get_clip_matrix_updated_event(session_id, matrix);
+    client.send(&event)
+}
+
 pub fn send_updated_active_controller(session: &Session) -> Result<(), &'static str> {
     send_to_clients_subscribed_to(
         &Topic::ActiveController {
@@ -97,6 +113,33 @@ pub fn send_projection_feedback_to_subscribed_clients(
     )
 }
 
+pub fn send_clip_matrix_events_to_subscribed_clients(
+    session: &Session,
+    events: &[ClipMatrixEvent],
+) -> Result<(), &'static str> {
+    let session_id = session.id();
+    send_to_clients_subscribed_to(
+        &Topic::ClipMatrixEvents {
+            session_id: session_id.to_string(),
+        },
+        || get_clip_matrix_events_event(session_id, events),
+    )?;
+    // Clients interested in the complete matrix get it again only if it changed fundamentally.
+    // Changes of single clips are available via events.
+    if events
+        .iter()
+        .any(|e| matches!(e, ClipMatrixEvent::AllClipsChanged))
+    {
+        send_to_clients_subscribed_to(
+            &Topic::ClipMatrix {
+                session_id: session_id.to_string(),
+            },
+            || get_clip_matrix_updated_event(session_id, get_clip_matrix(session)),
+        )?;
+    }
+    Ok(())
+}
+
 fn send_to_clients_subscribed_to<T: Serialize>(
     topic: &Topic,
     create_message: impl FnOnce() -> T,
@@ -4,7 +4,7 @@ use axum::extract::{Query, WebSocketUpgrade};
 use axum::handler::Handler;
 use axum::http::header::CONTENT_TYPE;
 use axum::http::Method;
-use axum::routing::{get, patch};
+use axum::routing::{get, patch, post};
 use axum::Router;
 use axum_server::Handle;
 use metrics_exporter_prometheus::PrometheusHandle;
@@ -105,6 +105,14 @@ fn create_router(
             "/realearn/session/:id/controller-routing",
             get(controller_routing_handler.layer(MainThreadLayer)),
         )
+        .route(
+            "/realearn/session/:id/clip-matrix",
+            get(clip_matrix_handler.layer(MainThreadLayer)),
+        )
+        .route(
+            "/realearn/session/:id/clip-matrix/slots/:column/:row",
+            post(clip_slot_command_handler.layer(MainThreadLayer)),
+        )
         .route(
             "/realearn/controller/:id",
             patch(patch_controller_handler.layer(MainThreadLayer)),
@@ -0,0 +1,388 @@
+//! Human-readable overviews of compartments, e.g. for printing them and putting them next to the
+//! controller.
+use crate::application::{
+    ActivationConditionModel, ActivationType, MappingModel, Preset, Session, SourceCategory,
+    TargetCategory, TargetModelFormatMultiLine,
+};
+use crate::domain::{CompartmentParams, MappingCompartment};
+use std::fmt::Write;
+
+/// Textual description of a compartment, independent of the output format.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CheatSheet {
+    pub title: String,
+    pub groups: Vec<CheatSheetGroup>,
+    /// Control elements defined by the controller compartment.
+    pub control_elements: Vec<CheatSheetControlElement>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CheatSheetGroup {
+    pub name: String,
+    pub condition: Option<String>,
+    pub tags: Vec<String>,
+    pub mappings: Vec<CheatSheetMapping>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CheatSheetMapping {
+    pub name: String,
+    pub enabled: bool,
+    pub source: Vec<String>,
+    pub target: Vec<String>,
+    pub condition: Option<String>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CheatSheetControlElement {
+    /// E.g. "Multi 1" or "Button play".
+    pub name: String,
+    /// The control element on the real controller.
+    pub source: Vec<String>,
+    pub mapping_name: String,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum CheatSheetFormat {
+    Markdown,
+    Html,
+}
+
+impl CheatSheet {
+    /// Describes the given compartment of the session.
+    ///
+    /// Mappings are listed by group. Whatever the compartment, the control elements defined by
+    /// the controller compartment are listed as well.
+    pub fn from_session(session: &Session, compartment: MappingCompartment) -> Self {
+        let params = session.params().compartment_params(compartment);
+        let active_preset_name = match compartment {
+            MappingCompartment::ControllerMappings => session
+                .active_controller_preset()
+                .map(|p| p.name().to_owned()),
+            MappingCompartment::MainMappings => {
+                session.active_main_preset().map(|p| p.name().to_owned())
+            }
+        };
+        let groups = session
+            .groups_sorted(compartment)
+            .filter_map(|group| {
+                let group = group.borrow();
+                let mappings: Vec<_> = session
+                    .mappings(compartment)
+                    .filter(|m| m.borrow().group_id() == group.id())
+                    .map(|m| describe_mapping(session, &m.borrow(), params))
+                    .collect();
+                if mappings.is_empty() {
+                    return None;
+                }
+                let group = CheatSheetGroup {
+                    name: group.effective_name().to_owned(),
+                    condition: describe_condition(group.activation_condition_model(), params),
+                    tags: group.tags().iter().map(|t| t.to_string()).collect(),
+                    mappings,
+                };
+                Some(group)
+            })
+            .collect();
+        let control_elements = session
+            .mappings(MappingCompartment::ControllerMappings)
+            .filter_map(|m| {
+                let m = m.borrow();
+                if m.target_model.category() != TargetCategory::Virtual {
+                    return None;
+                }
+                let element = CheatSheetControlElement {
+                    name: m.target_model.create_control_element().to_string(),
+                    source: lines(&m.source_model.to_string()),
+                    mapping_name: m.effective_name(),
+                };
+                Some(element)
+            })
+            .collect();
+        Self {
+            title: active_preset_name.unwrap_or_else(|| format!("ReaLearn {}", compartment)),
+            groups,
+            control_elements,
+        }
+    }
+
+    pub fn render(&self, format: CheatSheetFormat) -> String {
+        match format {
+            CheatSheetFormat::Markdown => self.to_markdown(),
+            CheatSheetFormat::Html => self.to_html(),
+        }
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut md = String::new();
+        let _ = writeln!(md, "# {}", escape_markdown(&self.title));
+        for group in &self.groups {
+            let _ = writeln!(md, "\n## {}\n", escape_markdown(&group.name));
+            if let Some(condition) = &group.condition {
+                let _ = writeln!(md, "Active {}\n", escape_markdown(condition));
+            }
+            if !group.tags.is_empty() {
+                let _ = writeln!(md, "Tags: {}\n", escape_markdown(&group.tags.join(", ")));
+            }
+            md.push_str("| Mapping | Source | Target | Condition | Tags |\n");
+            md.push_str("|---|---|---|---|---|\n");
+            for m in &group.mappings {
+                write_markdown_row(&mut md, &mapping_cells(m));
+            }
+        }
+        if !self.control_elements.is_empty() {
+            md.push_str("\n## Controller layout\n\n");
+            md.push_str("| Control element | Controller source | Mapping |\n");
+            md.push_str("|---|---|---|\n");
+            for e in &self.control_elements {
+                write_markdown_row(&mut md, &control_element_cells(e));
+            }
+        }
+        md
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+            <style>{}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+            escape_html(&self.title),
+            HTML_STYLE,
+            escape_html(&self.title)
+        );
+        for group in &self.groups {
+            let _ = writeln!(html, "<h2>{}</h2>", escape_html(&group.name));
+            if let Some(condition) = &group.condition {
+                let _ = writeln!(html, "<p>Active {}</p>", escape_html(condition));
+            }
+            if !group.tags.is_empty() {
+                let _ = writeln!(html, "<p>Tags: {}</p>", escape_html(&group.tags.join(", ")));
+            }
+            html.push_str(
+                "<table>\n<tr><th>Mapping</th><th>Source</th><th>Target</th>\
+                <th>Condition</th><th>Tags</th></tr>\n",
+            );
+            for m in &group.mappings {
+                write_html_row(&mut html, &mapping_cells(m));
+            }
+            html.push_str("</table>\n");
+        }
+        if !self.control_elements.is_empty() {
+            html.push_str(
+                "<h2>Controller layout</h2>\n<table>\n<tr><th>Control element</th>\
+                <th>Controller source</th><th>Mapping</th></tr>\n",
+            );
+            for e in &self.control_elements {
+                write_html_row(&mut html, &control_element_cells(e));
+            }
+            html.push_str("</table>\n");
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+}
+
+const HTML_STYLE: &str = "body { font-family: sans-serif; font-size: 10pt; } \
+    table { border-collapse: collapse; width: 100%; margin-bottom: 1em; } \
+    th, td { border: 1px solid #999; padding: 2px 6px; text-align: left; vertical-align: top; } \
+    h2 { page-break-after: avoid; } tr { page-break-inside: avoid; }";
+
+fn describe_mapping(
+    session: &Session,
+    mapping: &MappingModel,
+    params: &CompartmentParams,
+) -> CheatSheetMapping {
+    let compartment = mapping.compartment();
+    let mut source = lines(&mapping.source_model.to_string());
+    // Show which control elements of the real controller a virtual source corresponds to.
+    if mapping.source_model.category() == SourceCategory::Virtual {
+        let control_element = mapping.source_model.create_control_element();
+        source.extend(
+            session
+                .mappings(MappingCompartment::ControllerMappings)
+                .filter(|m| {
+                    let m = m.borrow();
+                    m.target_model.category() == TargetCategory::Virtual
+                        && m.target_model.create_control_element() == control_element
+                })
+                .map(|m| format!("({})", m.borrow().effective_name())),
+        );
+    }
+    let target = TargetModelFormatMultiLine::new(
+        &mapping.target_model,
+        session.extended_context(),
+        compartment,
+    )
+    .to_string();
+    CheatSheetMapping {
+        name: mapping.effective_name(),
+        enabled: mapping.is_enabled(),
+        source,
+        target: lines(&target),
+        condition: describe_condition(mapping.activation_condition_model(), params),
+        tags: mapping.tags().iter().map(|t| t.to_string()).collect(),
+    }
+}
+
+/// Returns `None` if always active.
+fn describe_condition(
+    condition: &ActivationConditionModel,
+    params: &CompartmentParams,
+) -> Option<String> {
+    let text = match condition.activation_type() {
+        ActivationType::Always => return None,
+        ActivationType::Modifiers => {
+            let modifiers: Vec<_> = [
+                condition.modifier_condition_1(),
+                condition.modifier_condition_2(),
+            ]
+            .iter()
+            .filter_map(|m| {
+                let param_name = params.get_parameter_name(m.param_index()?);
+                let state = if m.is_on() { "on" } else { "off" };
+                Some(format!("{} {}", param_name, state))
+            })
+            .collect();
+            if modifiers.is_empty() {
+                return None;
+            }
+            format!("when {}", modifiers.join(" and "))
+        }
+        ActivationType::Bank => {
+            let bank_condition = condition.bank_condition();
+            format!(
+                "when {} is at bank {}",
+                params.get_parameter_name(bank_condition.param_index()),
+                bank_condition.bank_index()
+            )
+        }
+        ActivationType::Eel => format!("when EEL result > 0: {}", condition.eel_condition()),
+    };
+    Some(text)
+}
+
+fn lines(text: &str) -> Vec<String> {
+    text.lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty())
+        .map(|l| l.to_owned())
+        .collect()
+}
+
+/// Each cell consists of lines.
+fn mapping_cells(mapping: &CheatSheetMapping) -> [Vec<String>; 5] {
+    let title = if mapping.enabled {
+        mapping.name.clone()
+    } else {
+        format!("{} (disabled)", mapping.name)
+    };
+    [
+        vec![title],
+        mapping.source.clone(),
+        mapping.target.clone(),
+        mapping.condition.iter().cloned().collect(),
+        vec![mapping.tags.join(", ")],
+    ]
+}
+
+fn control_element_cells(element: &CheatSheetControlElement) -> [Vec<String>; 3] {
+    [
+        vec![element.name.clone()],
+        element.source.clone(),
+        vec![element.mapping_name.clone()],
+    ]
+}
+
+fn write_markdown_row(md: &mut String, cells: &[Vec<String>]) {
+    for cell in cells {
+        let escaped: Vec<_> = cell.iter().map(|l| escape_markdown(l)).collect();
+        let _ = write!(md, "| {} ", escaped.join("<br>"));
+    }
+    md.push_str("|\n");
+}
+
+fn write_html_row(html: &mut String, cells: &[Vec<String>]) {
+    html.push_str("<tr>");
+    for cell in cells {
+        let escaped: Vec<_> = cell.iter().map(|l| escape_html(l)).collect();
+        let _ = write!(html, "<td>{}</td>", escaped.join("<br>"));
+    }
+    html.push_str("</tr>\n");
+}
+
+/// Labels such as "<Selected>" would otherwise be interpreted as HTML tags.
+fn escape_markdown(text: &str) -> String {
+    text.replace('|', "\\|")
+        .replace('<', "\\<")
+        .replace('>', "\\>")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cheat_sheet() -> CheatSheet {
+        CheatSheet {
+            title: "My <mixer>".to_string(),
+            groups: vec![CheatSheetGroup {
+                name: "Shift layer".to_string(),
+                condition: Some("when Shift on".to_string()),
+                tags: vec!["mixer".to_string()],
+                mappings: vec![CheatSheetMapping {
+                    name: "Volume | Pan".to_string(),
+                    enabled: false,
+                    source: vec!["Virtual".to_string(), "Multi 1".to_string()],
+                    target: vec!["Track: Volume".to_string(), "<Selected>".to_string()],
+                    condition: None,
+                    tags: vec![],
+                }],
+            }],
+            control_elements: vec![CheatSheetControlElement {
+                name: "Multi 1".to_string(),
+                source: vec!["CC value".to_string(), "Channel 1".to_string()],
+                mapping_name: "Fader 1".to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn render_markdown() {
+        assert_eq!(
+            cheat_sheet().to_markdown(),
+            "# My \\<mixer\\>\n\
+            \n\
+            ## Shift layer\n\
+            \n\
+            Active when Shift on\n\
+            \n\
+            Tags: mixer\n\
+            \n\
+            | Mapping | Source | Target | Condition | Tags |\n\
+            |---|---|---|---|---|\n\
+            | Volume \\| Pan (disabled) | Virtual<br>Multi 1 | Track: Volume<br>\\<Selected\\> |  |  |\n\
+            \n\
+            ## Controller layout\n\
+            \n\
+            | Control element | Controller source | Mapping |\n\
+            |---|---|---|\n\
+            | Multi 1 | CC value<br>Channel 1 | Fader 1 |\n"
+        );
+    }
+
+    #[test]
+    fn escape_html_content() {
+        let html = cheat_sheet().to_html();
+        assert!(html.contains("<h1>My &lt;mixer&gt;</h1>"));
+        assert!(html.contains("<td>Track: Volume<br>&lt;Selected&gt;</td>"));
+        assert!(html.contains("<td>Multi 1</td><td>CC value<br>Channel 1</td><td>Fader 1</td>"));
+    }
+}
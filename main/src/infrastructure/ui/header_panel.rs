@@ -10,27 +10,28 @@ use enum_iterator::IntoEnumIterator;
 
 use reaper_high::{MidiInputDevice, MidiOutputDevice, Reaper};
 
-use reaper_medium::{MidiInputDeviceId, MidiOutputDeviceId, ReaperString};
+use reaper_medium::{MasterTrackBehavior, MidiInputDeviceId, MidiOutputDeviceId, ReaperString};
 use slog::debug;
 
 use swell_ui::{MenuBar, Pixels, Point, SharedView, View, ViewContext, Window};
 
 use crate::application::{
-    reaper_supports_global_midi_filter, Affected, CompartmentProp, ControllerPreset, FxId,
-    FxPresetLinkConfig, MainPreset, MainPresetAutoLoadMode, MappingCommand, MappingModel, Preset,
-    PresetLinkMutator, PresetManager, SessionProp, SharedMapping, SharedSession,
-    VirtualControlElementType, WeakSession,
+    reaper_supports_global_midi_filter, Affected, CompartmentModel, CompartmentProp,
+    ControllerPreset, FxId, FxPresetLinkConfig, MainPreset, MainPresetAutoLoadMode, MappingCommand,
+    MappingModel, NamePresetLinkConfig, Preset, PresetLinkMutator, PresetManager, Session,
+    SessionProp, SharedMapping, SharedSession, VirtualControlElementType, WeakSession,
 };
 use crate::base::when;
 use crate::domain::{
     convert_compartment_param_index_range_to_iter, BackboneState, ClipMatrixRef,
-    CompartmentParamIndex, ControlInput, FeedbackOutput, GroupId, MappingCompartment,
-    MessageCaptureEvent, OscDeviceId, ParamSetting, ReaperTarget, COMPARTMENT_PARAMETER_COUNT,
+    ClipMatrixViewport, CompartmentParamIndex, ControlInput, FeedbackOutput, GroupId,
+    MappingCompartment, MessageCaptureEvent, OscDeviceId, ParamSetting, ReaperTarget, Tag,
+    COMPARTMENT_PARAMETER_COUNT,
 };
 use crate::domain::{MidiControlInput, MidiDestination};
 use crate::infrastructure::data::{
     CompartmentModelData, ExtendedPresetManager, FileBasedMainPresetManager, MappingModelData,
-    OscDevice,
+    NamePresetLinkKind, OscDevice,
 };
 use crate::infrastructure::plugin::{
     warn_about_failed_server_start, App, RealearnPluginParameters,
@@ -40,19 +41,22 @@ use crate::infrastructure::ui::bindings::root;
 
 use crate::base::notification::notify_processing_result;
 use crate::infrastructure::api::convert::from_data::ConversionStyle;
+use crate::infrastructure::api::convert::{from_data, to_data};
+use crate::infrastructure::api::diff::diff_compartments;
+use crate::infrastructure::api::merge::merge_compartments;
 use crate::infrastructure::ui::dialog_util::add_group_via_dialog;
 use crate::infrastructure::ui::util::open_in_browser;
 use crate::infrastructure::ui::{
     add_firewall_rule, copy_text_to_clipboard, deserialize_api_object_from_lua,
     deserialize_data_object, deserialize_data_object_from_json, dry_run_lua_script,
     get_text_from_clipboard, serialize_data_object, serialize_data_object_to_json,
-    serialize_data_object_to_lua, DataObject, GroupFilter, GroupPanel, IndependentPanelManager,
-    MappingRowsPanel, SearchExpression, SerializationFormat, SharedIndependentPanelManager,
-    SharedMainState, SourceFilter, UntaggedDataObject,
+    serialize_data_object_to_lua, CheatSheet, CheatSheetFormat, DataObject, GroupFilter,
+    GroupPanel, IndependentPanelManager, MappingRowsPanel, SearchExpression, SerializationFormat,
+    SharedIndependentPanelManager, SharedMainState, SourceFilter, UntaggedDataObject,
 };
 use crate::infrastructure::ui::{dialog_util, CompanionAppPresenter};
 use itertools::Itertools;
-use realearn_api::schema::Envelope;
+use realearn_api::schema::{Compartment, Envelope};
 use std::cell::{Cell, RefCell};
 use std::error::Error;
 use std::net::Ipv4Addr;
@@ -243,6 +247,20 @@ impl HeaderPanel {
                     None
                 }
             });
+            let project = session.processor_context().project_or_current_project();
+            let selected_track_name = project
+                .first_selected_track(MasterTrackBehavior::ExcludeMasterTrack)
+                .and_then(|t| t.name())
+                .map(|n| n.to_str().to_owned());
+            let project_name = project
+                .file()
+                .and_then(|f| Some(f.file_stem()?.to_string_lossy().to_string()));
+            let preset_is_active = session.active_preset_id(compartment).is_some();
+            let preset_snapshot_exists = session.preset_snapshot(compartment).is_some();
+            let active_preset_has_variables = session
+                .active_preset_id(compartment)
+                .map(|id| !session.preset_variables(compartment, id).is_empty())
+                .unwrap_or(false);
             let entries = vec![
                 item("Copy listed mappings", || {
                     ContextMenuAction::CopyListedMappingsAsJson
@@ -367,6 +385,14 @@ impl HeaderPanel {
                             },
                             || ContextMenuAction::ToggleUseInstancePresetLinksOnly,
                         ),
+                        item_with_opts(
+                            "Auto-select controller preset and MIDI I/O by connected device",
+                            ItemOpts {
+                                enabled: true,
+                                checked: session.auto_select_controller_by_device(),
+                            },
+                            || ContextMenuAction::ToggleAutoSelectControllerByDevice,
+                        ),
                     ],
                 ),
                 menu(
@@ -412,6 +438,22 @@ impl HeaderPanel {
                         PresetLinkScope::Instance,
                     ),
                 ),
+                menu(
+                    "Clip matrix",
+                    vec![
+                        item_with_opts(
+                            "Use clip matrix of instance with tag...",
+                            ItemOpts {
+                                enabled: true,
+                                checked: session.foreign_clip_matrix_instance_tag().is_some(),
+                            },
+                            || ContextMenuAction::EditForeignClipMatrixInstanceTag,
+                        ),
+                        item("Set viewport...", || {
+                            ContextMenuAction::EditClipMatrixViewport
+                        }),
+                    ],
+                ),
                 separator(),
                 menu(
                     "Server",
@@ -482,6 +524,33 @@ impl HeaderPanel {
                         PresetLinkScope::Global,
                     ),
                 ),
+                menu(
+                    "Global instrument-to-preset links",
+                    generate_fx_to_preset_links_menu_entries(
+                        last_focused_fx_id.as_ref(),
+                        &main_preset_manager,
+                        preset_link_manager.instrument_fx_config(),
+                        PresetLinkScope::GlobalInstrument,
+                    ),
+                ),
+                menu(
+                    "Global track-to-preset links",
+                    generate_name_to_preset_links_menu_entries(
+                        selected_track_name.as_deref(),
+                        &main_preset_manager,
+                        preset_link_manager.name_config(NamePresetLinkKind::Track),
+                        NamePresetLinkKind::Track,
+                    ),
+                ),
+                menu(
+                    "Global project-to-preset links",
+                    generate_name_to_preset_links_menu_entries(
+                        project_name.as_deref(),
+                        &main_preset_manager,
+                        preset_link_manager.name_config(NamePresetLinkKind::Project),
+                        NamePresetLinkKind::Project,
+                    ),
+                ),
                 menu(
                     "Help",
                     vec![
@@ -500,6 +569,30 @@ impl HeaderPanel {
                 item("Reload all presets from disk", || {
                     ContextMenuAction::ReloadAllPresets
                 }),
+                item_with_opts(
+                    "Edit variables of active preset...",
+                    ItemOpts {
+                        enabled: active_preset_has_variables,
+                        checked: false,
+                    },
+                    || ContextMenuAction::EditPresetVariables,
+                ),
+                item_with_opts(
+                    "Show changes compared to active preset",
+                    ItemOpts {
+                        enabled: preset_is_active,
+                        checked: false,
+                    },
+                    || ContextMenuAction::ShowChangesComparedToActivePreset,
+                ),
+                item_with_opts(
+                    "Merge update of active preset into modified mappings",
+                    ItemOpts {
+                        enabled: preset_is_active && preset_snapshot_exists,
+                        checked: false,
+                    },
+                    || ContextMenuAction::MergeUpdateOfActivePreset,
+                ),
                 separator(),
                 item("Send feedback now", || ContextMenuAction::SendFeedbackNow),
                 item("Log debug info", || ContextMenuAction::LogDebugInfo),
@@ -611,6 +704,9 @@ impl HeaderPanel {
             ContextMenuAction::ToggleUseInstancePresetLinksOnly => {
                 self.toggle_use_instance_preset_links_only()
             }
+            ContextMenuAction::ToggleAutoSelectControllerByDevice => {
+                self.toggle_auto_select_controller_by_device()
+            }
             ContextMenuAction::ToggleServer => {
                 enum ServerAction {
                     Start,
@@ -676,6 +772,10 @@ impl HeaderPanel {
                 self.view.require_window().alert("ReaLearn", msg);
             }
             ContextMenuAction::ChangeSessionId => self.change_session_id(),
+            ContextMenuAction::EditForeignClipMatrixInstanceTag => {
+                self.edit_foreign_clip_matrix_instance_tag()
+            }
+            ContextMenuAction::EditClipMatrixViewport => self.edit_clip_matrix_viewport(),
             ContextMenuAction::OpenOfflineUserGuide => self.open_user_guide_offline(),
             ContextMenuAction::OpenOnlineUserGuide => self.open_user_guide_online(),
             ContextMenuAction::OpenForum => self.open_forum(),
@@ -683,6 +783,21 @@ impl HeaderPanel {
             ContextMenuAction::OpenWebsite => self.open_website(),
             ContextMenuAction::Donate => self.donate(),
             ContextMenuAction::ReloadAllPresets => self.reload_all_presets(),
+            ContextMenuAction::EditPresetVariables => self.edit_preset_variables(),
+            ContextMenuAction::ShowChangesComparedToActivePreset => {
+                if let Err(e) = self.show_changes_compared_to_active_preset() {
+                    self.view
+                        .require_window()
+                        .alert("ReaLearn", e.to_string().as_str());
+                }
+            }
+            ContextMenuAction::MergeUpdateOfActivePreset => {
+                if let Err(e) = self.merge_update_of_active_preset() {
+                    self.view
+                        .require_window()
+                        .alert("ReaLearn", e.to_string().as_str());
+                }
+            }
             ContextMenuAction::SendFeedbackNow => self.session().borrow().send_all_feedback(),
             ContextMenuAction::LogDebugInfo => self.log_debug_info(),
             ContextMenuAction::EditPresetLinkFxId(scope, fx_id) => {
@@ -700,6 +815,21 @@ impl HeaderPanel {
                     link_to_preset(m, fx_id, preset_id);
                 });
             }
+            ContextMenuAction::EditNamePresetLinkPattern(kind, pattern) => {
+                edit_name_preset_link_pattern(kind, pattern);
+            }
+            ContextMenuAction::RemoveNamePresetLink(kind, pattern) => {
+                App::get()
+                    .preset_link_manager()
+                    .borrow_mut()
+                    .remove_name_link(kind, &pattern);
+            }
+            ContextMenuAction::LinkNameToPreset(kind, pattern, preset_id) => {
+                App::get()
+                    .preset_link_manager()
+                    .borrow_mut()
+                    .link_preset_to_name(kind, preset_id, pattern);
+            }
         };
         Ok(())
     }
@@ -1112,6 +1242,19 @@ impl HeaderPanel {
         session.set_use_instance_preset_links_only(new_state);
     }
 
+    fn toggle_auto_select_controller_by_device(&self) {
+        let new_state = {
+            let session = self.session();
+            let mut session = session.borrow_mut();
+            let new_state = !session.auto_select_controller_by_device();
+            session.set_auto_select_controller_by_device(new_state);
+            new_state
+        };
+        if new_state {
+            App::get().auto_select_controllers();
+        }
+    }
+
     fn toggle_upper_floor_membership(&self) {
         let enabled = {
             let session = self.session();
@@ -1777,13 +1920,172 @@ impl HeaderPanel {
             i if i >= 0 => preset_manager.find_id_by_index(i as usize),
             _ => unreachable!(),
         };
-        let mut session = session.borrow_mut();
-        match compartment {
-            MappingCompartment::ControllerMappings => {
-                session.activate_controller_preset(preset_id).unwrap();
+        if let Some(id) = preset_id.as_ref() {
+            if !self.prompt_for_preset_variables(compartment, id, true) {
+                self.invalidate_preset_combo_box_value();
+                return;
             }
-            MappingCompartment::MainMappings => session.activate_main_preset(preset_id).unwrap(),
+        }
+        let result = {
+            let mut session = session.borrow_mut();
+            match compartment {
+                MappingCompartment::ControllerMappings => {
+                    session.activate_controller_preset(preset_id)
+                }
+                MappingCompartment::MainMappings => session.activate_main_preset(preset_id),
+            }
+        };
+        if let Err(msg) = result {
+            self.invalidate_preset_combo_box_value();
+            self.view.require_window().alert("ReaLearn", msg);
+        }
+    }
+
+    /// Asks the user for the values of the variables declared by the given preset and stores
+    /// them in the session.
+    ///
+    /// Returns `false` if the user cancelled.
+    fn prompt_for_preset_variables(
+        &self,
+        compartment: MappingCompartment,
+        preset_id: &str,
+        only_missing: bool,
+    ) -> bool {
+        let session = self.session();
+        let (variables, values) = {
+            let session = session.borrow();
+            (
+                session.preset_variables(compartment, preset_id),
+                session.preset_variable_values(compartment).clone(),
+            )
+        };
+        for variable in variables {
+            let current_value = values.get(&variable.key);
+            if only_missing && current_value.is_some() {
+                continue;
+            }
+            let initial_text = current_value
+                .cloned()
+                .unwrap_or_else(|| variable.default_value())
+                .to_string();
+            let value = loop {
+                let text = match dialog_util::prompt_for(variable.label(), &initial_text) {
+                    None => return false,
+                    Some(t) => t,
+                };
+                match variable.parse_value(&text) {
+                    Ok(v) => break v,
+                    Err(msg) => self.view.require_window().alert("ReaLearn", msg.as_str()),
+                }
+            };
+            session
+                .borrow_mut()
+                .set_preset_variable_value(compartment, variable.key, value);
+        }
+        true
+    }
+
+    /// Lets the user change the variable values of the active preset and reloads it.
+    fn edit_preset_variables(&self) {
+        let compartment = self.active_compartment();
+        let session = self.session();
+        let (preset_id, compartment_is_dirty) = {
+            let session = session.borrow();
+            let preset_id = match session.active_preset_id(compartment) {
+                None => return,
+                Some(id) => id.to_string(),
+            };
+            (
+                preset_id,
+                session.compartment_or_preset_is_dirty(compartment),
+            )
+        };
+        if compartment_is_dirty
+            && !self
+                .view
+                .require_window()
+                .confirm("ReaLearn", COMPARTMENT_CHANGES_WARNING_TEXT)
+        {
+            return;
+        }
+        if !self.prompt_for_preset_variables(compartment, &preset_id, false) {
+            return;
+        }
+        let result = {
+            let mut session = session.borrow_mut();
+            match compartment {
+                MappingCompartment::ControllerMappings => {
+                    session.activate_controller_preset(Some(preset_id))
+                }
+                MappingCompartment::MainMappings => session.activate_main_preset(Some(preset_id)),
+            }
+        };
+        if let Err(msg) = result {
+            self.view.require_window().alert("ReaLearn", msg);
+        }
+    }
+
+    fn show_changes_compared_to_active_preset(&self) -> Result<(), Box<dyn Error>> {
+        let compartment = self.active_compartment();
+        let session = self.session();
+        let session = session.borrow();
+        let preset_id = session
+            .active_preset_id(compartment)
+            .ok_or("no active preset")?;
+        let preset_model = active_preset_model(&session, compartment).ok_or("preset not found")?;
+        let diff = diff_compartments(
+            &convert_compartment_model_to_api(&preset_model)?,
+            &convert_compartment_model_to_api(&session.extract_compartment_model(compartment))?,
+        )?;
+        if diff.is_empty() {
+            self.view.require_window().alert(
+                "ReaLearn",
+                "The mappings don't differ from the active preset.",
+            );
+            return Ok(());
+        }
+        let msg = format!(
+            "\n# Changes compared to preset \"{}\"\n\n{}",
+            preset_id, diff
+        );
+        Reaper::get().show_console_msg(msg);
+        Ok(())
+    }
+
+    /// Applies the changes made to the active preset (since loading it) to the modified mappings.
+    fn merge_update_of_active_preset(&self) -> Result<(), Box<dyn Error>> {
+        let compartment = self.active_compartment();
+        let conflicts = {
+            let session = self.session();
+            let mut session = session.borrow_mut();
+            let update = active_preset_model(&session, compartment).ok_or("preset not found")?;
+            let base = session
+                .preset_snapshot(compartment)
+                .ok_or("the state of the preset at the time of loading it is unknown")?;
+            let result = merge_compartments(
+                &convert_compartment_model_to_api(base)?,
+                &convert_compartment_model_to_api(&session.extract_compartment_model(compartment))?,
+                &convert_compartment_model_to_api(&update)?,
+            )?;
+            let merged = to_data::convert_compartment(result.compartment)?
+                .to_model(Some(App::version()), compartment)?;
+            session.apply_merged_preset_update(compartment, merged, update);
+            result.conflicts
         };
+        if conflicts.is_empty() {
+            return Ok(());
+        }
+        Reaper::get().show_console_msg(format!(
+            "\n# Merge conflicts\n\n{}\n",
+            conflicts.iter().join("\n")
+        ));
+        let msg = format!(
+            "Some properties have been changed both locally and in the preset ({} conflicts). \
+            In each case, the local version has been kept. See the console for details.",
+            conflicts.len()
+        );
+        self.view.require_window().alert("ReaLearn", msg.as_str());
+        Ok(())
     }
 
     fn mappings_are_read_only(&self) -> bool {
@@ -1966,6 +2268,7 @@ impl HeaderPanel {
             ExportSession(SerializationFormat),
             ExportClipMatrix(SerializationFormat),
             ExportCompartment(SerializationFormat),
+            ExportCheatSheet(CheatSheetFormat),
         }
         impl Default for MenuAction {
             fn default() -> Self {
@@ -2003,6 +2306,14 @@ impl HeaderPanel {
                         ))
                     },
                 ),
+                item(
+                    format!("Export {} as Markdown cheat sheet", compartment),
+                    || MenuAction::ExportCheatSheet(CheatSheetFormat::Markdown),
+                ),
+                item(
+                    format!("Export {} as HTML cheat sheet (opens browser)", compartment),
+                    || MenuAction::ExportCheatSheet(CheatSheetFormat::Html),
+                ),
             ];
             let mut root_menu = root_menu(entries);
             root_menu.index(1);
@@ -2070,6 +2381,19 @@ impl HeaderPanel {
                 let text = serialize_data_object(data_object, format)?;
                 copy_text_to_clipboard(text);
             }
+            MenuAction::ExportCheatSheet(format) => {
+                let cheat_sheet = CheatSheet::from_session(&self.session().borrow(), compartment);
+                let text = cheat_sheet.render(format);
+                match (format, App::get_temp_dir()) {
+                    // Opening it in the browser makes it easy to print it
+                    (CheatSheetFormat::Html, Some(dir)) => {
+                        let html_file = dir.path().join("cheat-sheet.html");
+                        std::fs::write(&html_file, text)?;
+                        open_in_browser(&html_file.to_string_lossy());
+                    }
+                    _ => copy_text_to_clipboard(text),
+                }
+            }
         };
         Ok(())
     }
@@ -2080,7 +2404,7 @@ impl HeaderPanel {
         }
     }
 
-    fn delete_active_preset(&self) -> Result<(), &'static str> {
+    fn delete_active_preset(&self) -> Result<(), Box<dyn Error>> {
         if !self
             .view
             .require_window()
@@ -2105,6 +2429,8 @@ impl HeaderPanel {
     }
 
     fn reload_all_presets(&self) {
+        // Re-applies changed presets which are in use
+        App::get().reload_changed_presets();
         let _ = App::get()
             .controller_preset_manager()
             .borrow_mut()
@@ -2141,7 +2467,7 @@ impl HeaderPanel {
                 let mut controller_preset = preset_manager
                     .find_by_id(preset_id)
                     .ok_or("controller preset not found")?;
-                controller_preset.update_realearn_data(compartment_model);
+                controller_preset.update_realearn_data(compartment_model.clone());
                 preset_manager
                     .borrow_mut()
                     .update_preset(controller_preset)?;
@@ -2151,10 +2477,17 @@ impl HeaderPanel {
                 let mut main_preset = preset_manager
                     .find_by_id(preset_id)
                     .ok_or("main preset not found")?;
-                main_preset.update_data(compartment_model);
+                if main_preset.is_composed() {
+                    return Err(
+                        "Main presets which extend or include other presets can't be \
+                        saved from within ReaLearn, please edit the file or use \"Save as\"",
+                    );
+                }
+                main_preset.update_data(compartment_model.clone());
                 preset_manager.borrow_mut().update_preset(main_preset)?;
             }
         };
+        session.set_preset_snapshot(compartment, Some(compartment_model));
         session.compartment_is_dirty[compartment].set(false);
         Ok(())
     }
@@ -2187,13 +2520,111 @@ impl HeaderPanel {
         }
     }
 
+    fn edit_foreign_clip_matrix_instance_tag(&self) {
+        let current_tag = self
+            .session()
+            .borrow()
+            .foreign_clip_matrix_instance_tag()
+            .map(|t| t.to_string())
+            .unwrap_or_default();
+        let new_tag = match dialog_util::prompt_for("Instance tag", &current_tag) {
+            None => return,
+            Some(n) => n,
+        };
+        let new_tag = if new_tag.trim().is_empty() {
+            None
+        } else {
+            match new_tag.parse::<Tag>() {
+                Ok(t) => Some(t),
+                Err(_) => {
+                    self.view
+                        .require_window()
+                        .alert("ReaLearn", "This is not a valid instance tag!");
+                    return;
+                }
+            }
+        };
+        let owns_clip_matrix = self
+            .session()
+            .borrow()
+            .instance_state()
+            .borrow()
+            .owned_clip_matrix()
+            .is_some();
+        if new_tag.is_some()
+            && owns_clip_matrix
+            && !self.view.require_window().confirm(
+                "ReaLearn",
+                "This instance has its own clip matrix, which will be discarded. Do you want to continue?",
+            )
+        {
+            return;
+        }
+        let session = self.session();
+        let mut session = session.borrow_mut();
+        {
+            let mut instance_state = session.instance_state().borrow_mut();
+            let foreign_instance_id = new_tag.as_ref().and_then(|tag| {
+                App::get().find_clip_matrix_owner_by_instance_tag_ignoring_borrowed_ones(tag)
+            });
+            if let Some(id) = foreign_instance_id {
+                BackboneState::get()
+                    .set_instance_clip_matrix_to_foreign_matrix(&mut instance_state, id);
+            } else if session.foreign_clip_matrix_instance_tag().is_some() || new_tag.is_some() {
+                BackboneState::get().clear_clip_matrix_from_instance_state(&mut instance_state);
+            }
+        }
+        session.set_foreign_clip_matrix_instance_tag(new_tag);
+    }
+
+    fn edit_clip_matrix_viewport(&self) {
+        let current_viewport = self
+            .session()
+            .borrow()
+            .instance_state()
+            .borrow()
+            .clip_matrix_viewport();
+        let current_text = format!(
+            "{} {}",
+            current_viewport.column_offset + 1,
+            current_viewport.row_offset + 1
+        );
+        let new_text =
+            match dialog_util::prompt_for("First column and row (e.g. 9 1)", &current_text) {
+                None => return,
+                Some(t) => t,
+            };
+        let positions: Result<Vec<usize>, _> = new_text
+            .split_whitespace()
+            .map(|p| p.parse::<usize>())
+            .collect();
+        let new_viewport = match positions.as_deref() {
+            Ok(&[column, row]) if column > 0 && row > 0 => ClipMatrixViewport {
+                column_offset: column - 1,
+                row_offset: row - 1,
+            },
+            _ => {
+                self.view.require_window().alert(
+                    "ReaLearn",
+                    "Please enter the first column and row position, separated by a space!",
+                );
+                return;
+            }
+        };
+        self.session()
+            .borrow()
+            .instance_state()
+            .borrow_mut()
+            .set_clip_matrix_viewport(new_viewport);
+    }
+
     /// Don't borrow the session while calling this!
     fn ask_user_if_project_independence_desired(&self) -> bool {
         let msg = "Some of the mappings have references to this particular project. This usually doesn't make too much sense for a preset that's supposed to be reusable among different projects. Do you want ReaLearn to automatically adjust the mappings so that track targets refer to tracks by their position and FX targets relate to whatever FX is currently focused?";
         self.view.require_window().confirm("ReaLearn", msg)
     }
 
-    fn save_as_preset(&self) -> Result<(), &'static str> {
+    fn save_as_preset(&self) -> Result<(), Box<dyn Error>> {
         let preset_name = match dialog_util::prompt_for("Preset name", "") {
             None => return Ok(()),
             Some(n) => n,
@@ -2209,15 +2640,16 @@ impl HeaderPanel {
         let compartment_model = session.extract_compartment_model(compartment);
         match compartment {
             MappingCompartment::ControllerMappings => {
-                let custom_data = session
+                let (custom_data, device_matchers) = session
                     .active_controller_preset()
-                    .map(|c| c.custom_data().clone())
+                    .map(|c| (c.custom_data().clone(), c.device_matchers().to_vec()))
                     .unwrap_or_default();
                 let controller = ControllerPreset::new(
                     preset_id.clone(),
                     preset_name,
                     compartment_model,
                     custom_data,
+                    device_matchers,
                 );
                 App::get()
                     .controller_preset_manager()
@@ -2226,8 +2658,13 @@ impl HeaderPanel {
                 session.activate_controller_preset(Some(preset_id))?;
             }
             MappingCompartment::MainMappings => {
-                let main_preset =
-                    MainPreset::new(preset_id.clone(), preset_name, compartment_model);
+                let main_preset = MainPreset::new(
+                    preset_id.clone(),
+                    preset_name,
+                    None,
+                    vec![],
+                    compartment_model,
+                );
                 App::get()
                     .main_preset_manager()
                     .borrow_mut()
@@ -2600,6 +3037,19 @@ fn edit_preset_link_fx_id(mutator: &mut dyn PresetLinkMutator, old_fx_id: FxId)
     mutator.update_fx_id(old_fx_id, new_fx_id);
 }
 
+fn edit_name_preset_link_pattern(kind: NamePresetLinkKind, old_pattern: String) {
+    let new_pattern =
+        match dialog_util::prompt_for("Name pattern (* and ? = wildcards)", &old_pattern) {
+            None => return,
+            Some(p) if p.trim().is_empty() => return,
+            Some(p) => p.trim().to_owned(),
+        };
+    App::get()
+        .preset_link_manager()
+        .borrow_mut()
+        .update_name_pattern(kind, &old_pattern, new_pattern);
+}
+
 fn remove_preset_link(mutator: &mut dyn PresetLinkMutator, fx_id: FxId) {
     mutator.remove_link(&fx_id);
 }
@@ -2824,11 +3274,17 @@ enum ContextMenuAction {
     ToggleUpperFloorMembership,
     ToggleServer,
     ToggleUseInstancePresetLinksOnly,
+    ToggleAutoSelectControllerByDevice,
     AddFirewallRule,
     ChangeSessionId,
+    EditForeignClipMatrixInstanceTag,
+    EditClipMatrixViewport,
     EditPresetLinkFxId(PresetLinkScope, FxId),
     RemovePresetLink(PresetLinkScope, FxId),
     LinkToPreset(PresetLinkScope, FxId, String),
+    EditNamePresetLinkPattern(NamePresetLinkKind, String),
+    RemoveNamePresetLink(NamePresetLinkKind, String),
+    LinkNameToPreset(NamePresetLinkKind, String, String),
     OpenOfflineUserGuide,
     OpenOnlineUserGuide,
     OpenForum,
@@ -2836,6 +3292,9 @@ enum ContextMenuAction {
     OpenWebsite,
     Donate,
     ReloadAllPresets,
+    EditPresetVariables,
+    ShowChangesComparedToActivePreset,
+    MergeUpdateOfActivePreset,
     EditNewOscDevice,
     EditExistingOscDevice(OscDeviceId),
     RemoveOscDevice(OscDeviceId),
@@ -2856,6 +3315,7 @@ impl Default for ContextMenuAction {
 #[derive(Copy, Clone)]
 enum PresetLinkScope {
     Global,
+    GlobalInstrument,
     Instance,
 }
 
@@ -2925,6 +3385,76 @@ fn generate_fx_to_preset_links_menu_entries(
     once(add_link_entry).chain(link_entries).collect()
 }
 
+fn generate_name_to_preset_links_menu_entries(
+    current_name: Option<&str>,
+    main_preset_manager: &FileBasedMainPresetManager,
+    config: &NamePresetLinkConfig,
+    kind: NamePresetLinkKind,
+) -> Vec<swell_ui::menu_tree::Entry<ContextMenuAction>> {
+    use std::iter::once;
+    use swell_ui::menu_tree::*;
+    let add_link_entry = if let Some(name) = current_name {
+        menu(
+            format!("<Add link from \"{}\" to ...>", name),
+            main_preset_manager
+                .preset_iter()
+                .map(move |p| {
+                    let pattern = name.to_owned();
+                    let preset_id = p.id().to_owned();
+                    item(p.name(), move || {
+                        ContextMenuAction::LinkNameToPreset(kind, pattern, preset_id)
+                    })
+                })
+                .collect(),
+        )
+    } else {
+        let label = match kind {
+            NamePresetLinkKind::Track => "<Add link from selected track to preset>",
+            NamePresetLinkKind::Project => "<Add link from current project to preset>",
+        };
+        disabled_item(label)
+    };
+    let link_entries = config.links().map(|link| {
+        let pattern_0 = link.pattern.clone();
+        let pattern_1 = link.pattern.clone();
+        let pattern_2 = link.pattern.clone();
+        let preset_id_0 = link.preset_id.clone();
+        menu(
+            link.pattern.clone(),
+            once(item("<Edit name pattern...>", move || {
+                ContextMenuAction::EditNamePresetLinkPattern(kind, pattern_0)
+            }))
+            .chain(once(item("<Remove link>", move || {
+                ContextMenuAction::RemoveNamePresetLink(kind, pattern_1)
+            })))
+            .chain(main_preset_manager.preset_iter().map(move |p| {
+                let pattern = pattern_2.clone();
+                let preset_id = p.id().to_owned();
+                item_with_opts(
+                    p.name(),
+                    ItemOpts {
+                        enabled: true,
+                        checked: p.id() == preset_id_0,
+                    },
+                    move || ContextMenuAction::LinkNameToPreset(kind, pattern, preset_id),
+                )
+            }))
+            .chain(once(
+                if main_preset_manager
+                    .find_index_by_id(&link.preset_id)
+                    .is_some()
+                {
+                    Entry::Nothing
+                } else {
+                    disabled_item(format!("<Not present> ({})", link.preset_id))
+                },
+            ))
+            .collect(),
+        )
+    });
+    once(add_link_entry).chain(link_entries).collect()
+}
+
 fn with_scoped_preset_link_mutator(
     scope: PresetLinkScope,
     session: &WeakSession,
@@ -2936,6 +3466,11 @@ fn with_scoped_preset_link_mutator(
             let mut mutator = preset_link_manager.borrow_mut();
             f(mutator.deref_mut());
         }
+        PresetLinkScope::GlobalInstrument => {
+            let preset_link_manager = App::get().preset_link_manager();
+            let mut manager = preset_link_manager.borrow_mut();
+            f(&mut manager.instrument_fx_mutator());
+        }
         PresetLinkScope::Instance => {
             let session = session.upgrade().expect("session gone");
             let mut session = session.borrow_mut();
@@ -2944,3 +3479,25 @@ fn with_scoped_preset_link_mutator(
         }
     }
 }
+
+fn active_preset_model(
+    session: &Session,
+    compartment: MappingCompartment,
+) -> Option<CompartmentModel> {
+    let model = match compartment {
+        MappingCompartment::ControllerMappings => {
+            session.active_controller_preset()?.data().clone()
+        }
+        MappingCompartment::MainMappings => session.active_main_preset()?.data().clone(),
+    };
+    Some(model)
+}
+
+fn convert_compartment_model_to_api(
+    model: &CompartmentModel,
+) -> Result<Compartment, Box<dyn Error>> {
+    from_data::convert_compartment(
+        CompartmentModelData::from_model(model),
+        ConversionStyle::Minimal,
+    )
+}
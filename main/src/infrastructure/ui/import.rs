@@ -9,6 +9,10 @@ use crate::domain::SafeLua;
 use crate::infrastructure::api::convert::from_data::ConversionStyle;
 use crate::infrastructure::api::convert::to_data::ApiToDataConversionContext;
 use crate::infrastructure::api::convert::{from_data, to_data};
+use crate::infrastructure::api::lua_library::{
+    add_lua_library, create_print_function, create_track_guid_function,
+    deserialize_lua_import_result, LuaHost,
+};
 use crate::infrastructure::data::{
     CompartmentModelData, MappingModelData, ModeModelData, SessionData, SourceModelData,
     TargetModelData,
@@ -16,7 +20,7 @@ use crate::infrastructure::data::{
 use crate::infrastructure::plugin::App;
 use crate::infrastructure::ui::lua_serializer;
 use crate::infrastructure::ui::util::open_in_browser;
-use mlua::{Lua, LuaSerdeExt, Value};
+use mlua::Lua;
 use realearn_api::schema;
 use realearn_api::schema::{ApiObject, Envelope};
 use realearn_csi::{deserialize_csi_object_from_csi, AnnotatedResult, CsiObject};
@@ -225,7 +229,7 @@ pub fn serialize_data_object_to_json(object: DataObject) -> Result<String, Box<d
 /// Runs without importing the result and also doesn't have an execution time limit.
 pub fn dry_run_lua_script(text: &str) -> Result<(), Box<dyn Error>> {
     let lua = SafeLua::new()?;
-    let value = execute_lua_import_script(&lua, text)?;
+    let value = execute_lua_import_script(&lua, text, LuaHost::Reaper)?;
     let json = serde_json::to_string_pretty(&value)?;
     match App::get_temp_dir() {
         None => {
@@ -266,38 +270,36 @@ pub fn serialize_data_object_to_lua(
 }
 
 pub fn deserialize_api_object_from_lua(text: &str) -> Result<ApiObject, Box<dyn Error>> {
+    deserialize_api_object_from_lua_in_host(text, LuaHost::Reaper)
+}
+
+pub fn deserialize_api_object_from_lua_in_host(
+    text: &str,
+    host: LuaHost,
+) -> Result<ApiObject, Box<dyn Error>> {
     let lua = SafeLua::new()?;
     let lua = lua.start_execution_time_limit_countdown(Duration::from_millis(200))?;
-    let value = execute_lua_import_script(&lua, text)?;
-    Ok(lua.as_ref().from_value(value)?)
+    let value = execute_lua_import_script(&lua, text, host)?;
+    deserialize_lua_import_result(lua.as_ref(), value)
 }
 
 fn execute_lua_import_script<'a>(
     lua: &'a SafeLua,
     text: &str,
+    host: LuaHost,
 ) -> Result<mlua::Value<'a>, Box<dyn Error>> {
     let env = lua.create_fresh_environment()?;
-    // Add some useful functions (hidden, undocumented, subject to change!)
+    add_lua_library(lua, &env, host)?;
+    // Add some useful functions (hidden, undocumented, kept for existing scripts, better use the
+    // library!)
     let realearn_table = {
         let lua: &Lua = lua.as_ref();
         let table = lua.create_table()?;
-        let get_track_guid_by_index = lua.create_function(|_, index: u32| {
-            let guid = Reaper::get()
-                .current_project()
-                .track_by_index(index)
-                .map(|t| t.guid().to_string_without_braces());
-            Ok(guid)
-        })?;
-        table.set("get_track_guid_by_index", get_track_guid_by_index)?;
-        let print = lua.create_function(|_, arg: mlua::Value| {
-            let text: String = match arg {
-                Value::String(s) => format!("{}\n", s.to_string_lossy()),
-                arg => format!("{:?}\n", arg),
-            };
-            Reaper::get().show_console_msg(text);
-            Ok(())
-        })?;
-        table.set("print", print)?;
+        table.set(
+            "get_track_guid_by_index",
+            create_track_guid_function(lua, host)?,
+        )?;
+        table.set("print", create_print_function(lua, host)?)?;
         table
     };
     env.set("realearn", realearn_table)?;
@@ -17,9 +17,11 @@ use crate::domain::{
 };
 use crate::infrastructure::plugin::{App, RealearnPluginParameters};
 use crate::infrastructure::server::http::{
-    send_projection_feedback_to_subscribed_clients, send_updated_controller_routing,
+    send_clip_matrix_events_to_subscribed_clients, send_projection_feedback_to_subscribed_clients,
+    send_updated_controller_routing,
 };
 use crate::infrastructure::ui::util::{format_tags_as_csv, parse_tags_from_csv};
+use playtime_clip_engine::main::ClipMatrixEvent;
 use rxrust::prelude::*;
 use std::borrow::Cow;
 use std::rc::{Rc, Weak};
@@ -315,6 +317,10 @@ impl SessionUi for Weak<MainPanel> {
         let _ = send_projection_feedback_to_subscribed_clients(session.id(), value);
     }
 
+    fn send_clip_matrix_events(&self, session: &Session, events: &[ClipMatrixEvent]) {
+        let _ = send_clip_matrix_events_to_subscribed_clients(session, events);
+    }
+
     fn mapping_matched(&self, event: MappingMatchedEvent) {
         upgrade_panel(self).handle_matched_mapping(event);
     }
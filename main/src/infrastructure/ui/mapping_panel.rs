@@ -25,7 +25,7 @@ use helgoboss_learn::{
     OutOfRangeBehavior, PercentIo, RgbColor, SoftSymmetricUnitValue, SourceCharacter, TakeoverMode,
     Target, UnitValue, ValueSequence, VirtualColor, DEFAULT_OSC_ARG_VALUE_RANGE,
 };
-use realearn_api::schema::{MidiScriptKind, MonitoringMode};
+use realearn_api::schema::{MidiScriptKind, MonitoringMode, MusicalTimerUnit};
 use swell_ui::{
     DialogUnits, MenuBar, Point, SharedView, SwellStringArg, View, ViewContext, WeakView, Window,
 };
@@ -288,9 +288,18 @@ impl MappingPanel {
                                                 view.invalidate_source_line_5_combo_box();
                                             }
                                             P::OscAddressPattern |
-                                            P::RawMidiPattern | P::TimerMillis => {
+                                            P::RawMidiPattern | P::TimerMillis | P::MusicalTimerInterval => {
                                                 view.invalidate_source_line_3_edit_control(initiator);
                                             }
+                                            P::MusicalTimerUnit => {
+                                                view.invalidate_source_line_3_combo_box_2();
+                                            }
+                                            P::MusicalTimerOffset => {
+                                                view.invalidate_source_line_4_edit_control(initiator);
+                                            }
+                                            P::MusicalTimerOnlyWhilePlaying => {
+                                                view.invalidate_source_check_box_2();
+                                            }
                                             P::MidiScriptKind => {
                                                 view.invalidate_source_line_3(initiator);
                                             }
@@ -521,7 +530,10 @@ impl MappingPanel {
                                             P::ActiveMappingsOnly => {
                                                 view.invalidate_target_check_box_2();
                                             }
-                                            P::ClipSlot | P::ClipManagementAction => {}
+                                            P::ClipSlot
+                                            | P::ClipManagementAction
+                                            | P::ClipViewportAction
+                                            | P::ClipViewportPageSize => {}
                                             P::TouchedRouteParameterType => {
                                                 view.invalidate_target_line_3_combo_box_2();
                                             }
@@ -1279,7 +1291,15 @@ impl<'a> MutableMappingPanel<'a> {
                     SourceCommand::SetOscArgIsRelative(checked),
                 ));
             }
-            Reaper | Virtual | Never | Keyboard => {}
+            Reaper => {
+                if self.mapping.source_model.reaper_source_type() == ReaperSourceType::MusicalTimer
+                {
+                    self.change_mapping(MappingCommand::ChangeSource(
+                        SourceCommand::SetMusicalTimerOnlyWhilePlaying(checked),
+                    ));
+                }
+            }
+            Virtual | Never | Keyboard => {}
         };
     }
 
@@ -1471,6 +1491,16 @@ impl<'a> MutableMappingPanel<'a> {
                 }
                 _ => {}
             },
+            Reaper => match self.mapping.source_model.reaper_source_type() {
+                ReaperSourceType::MusicalTimer => {
+                    let i = b.selected_combo_box_item_index();
+                    let unit = i.try_into().expect("invalid musical timer unit");
+                    self.change_mapping(MappingCommand::ChangeSource(
+                        SourceCommand::SetMusicalTimerUnit(unit),
+                    ));
+                }
+                _ => {}
+            },
             _ => {}
         }
     }
@@ -1497,7 +1527,17 @@ impl<'a> MutableMappingPanel<'a> {
                     Some(edit_control_id),
                 );
             }
-            Reaper | Never | Keyboard | Osc => {}
+            Reaper => {
+                if self.mapping.source_model.reaper_source_type() == ReaperSourceType::MusicalTimer
+                {
+                    let value = text.parse().unwrap_or_default();
+                    self.change_mapping_with_initiator(
+                        MappingCommand::ChangeSource(SourceCommand::SetMusicalTimerOffset(value)),
+                        Some(edit_control_id),
+                    );
+                }
+            }
+            Never | Keyboard | Osc => {}
         };
     }
 
@@ -1549,6 +1589,15 @@ impl<'a> MutableMappingPanel<'a> {
                             Some(edit_control_id),
                         )
                     }
+                    ReaperSourceType::MusicalTimer => {
+                        let value = value.parse().unwrap_or(1.0);
+                        self.change_mapping_with_initiator(
+                            MappingCommand::ChangeSource(SourceCommand::SetMusicalTimerInterval(
+                                value,
+                            )),
+                            Some(edit_control_id),
+                        )
+                    }
                     _ => {}
                 },
                 Virtual | Never | Keyboard => {}
@@ -3183,6 +3232,7 @@ impl<'a> ImmutableMappingPanel<'a> {
             Osc => Some("Address"),
             Reaper => match self.source.reaper_source_type() {
                 ReaperSourceType::Timer => Some("Millis"),
+                ReaperSourceType::MusicalTimer => Some("Every"),
                 _ => None,
             },
             Keyboard => Some("Keystroke"),
@@ -3202,6 +3252,10 @@ impl<'a> ImmutableMappingPanel<'a> {
                 MidiSourceType::Display => Some("Protocol"),
                 _ => None,
             },
+            Reaper => match self.source.reaper_source_type() {
+                ReaperSourceType::MusicalTimer => Some("Unit"),
+                _ => None,
+            },
             _ => None,
         };
         self.view
@@ -3261,6 +3315,13 @@ impl<'a> ImmutableMappingPanel<'a> {
                 }
             }
             Osc => Some(("Is relative", self.source.osc_arg_is_relative())),
+            Reaper => match self.source.reaper_source_type() {
+                ReaperSourceType::MusicalTimer => Some((
+                    "Only while playing",
+                    self.source.musical_timer_only_while_playing(),
+                )),
+                _ => None,
+            },
             _ => None,
         };
         self.invalidate_check_box(root::ID_SOURCE_14_BIT_CHECK_BOX, state);
@@ -3330,6 +3391,10 @@ impl<'a> ImmutableMappingPanel<'a> {
             }
             Virtual => Some("ID"),
             Osc => Some("Argument"),
+            Reaper => match self.source.reaper_source_type() {
+                ReaperSourceType::MusicalTimer => Some("Offset"),
+                _ => None,
+            },
             _ => None,
         };
         self.view
@@ -3439,6 +3504,12 @@ impl<'a> ImmutableMappingPanel<'a> {
                 _ => None,
             },
             Virtual => Some(self.source.control_element_id().to_string()),
+            Reaper => match self.source.reaper_source_type() {
+                ReaperSourceType::MusicalTimer => {
+                    Some(self.source.musical_timer_offset().to_string())
+                }
+                _ => None,
+            },
             _ => None,
         };
         self.view
@@ -3493,6 +3564,9 @@ impl<'a> ImmutableMappingPanel<'a> {
             Osc => Some((self.source.osc_address_pattern().to_owned(), true)),
             Reaper => match self.source.reaper_source_type() {
                 ReaperSourceType::Timer => Some((self.source.timer_millis().to_string(), true)),
+                ReaperSourceType::MusicalTimer => {
+                    Some((self.source.musical_timer_interval().to_string(), true))
+                }
                 _ => None,
             },
             Keyboard => {
@@ -3681,6 +3755,17 @@ impl<'a> ImmutableMappingPanel<'a> {
                     b.hide();
                 }
             },
+            Reaper => match self.source.reaper_source_type() {
+                ReaperSourceType::MusicalTimer => {
+                    b.show();
+                    b.fill_combo_box_indexed(MusicalTimerUnit::into_enum_iter());
+                    b.select_combo_box_item_by_index(self.source.musical_timer_unit().into())
+                        .unwrap();
+                }
+                _ => {
+                    b.hide();
+                }
+            },
             _ => {
                 b.hide();
             }
@@ -6629,7 +6714,7 @@ fn prompt_for_color(
                         move || OpenColorPicker(color_target),
                     ),
                 ].into_iter()
-                    .chain(["target.track.color", "target.bookmark.color"].into_iter().map(|key| {
+                    .chain(["target.track.color", "target.bookmark.color", "target.clip.color"].into_iter().map(|key| {
                         item_with_opts(
                             key,
                             ItemOpts {
@@ -53,3 +53,6 @@ mod import;
 pub use import::*;
 
 mod lua_serializer;
+
+mod cheat_sheet;
+pub use cheat_sheet::*;
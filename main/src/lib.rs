@@ -7,6 +7,9 @@ mod application;
 mod domain;
 mod infrastructure;
 
+pub use infrastructure::api::{diff, merge};
+pub use infrastructure::headless;
+
 #[cfg(debug_assertions)]
 #[global_allocator]
 static A: assert_no_alloc::AllocDisabler = assert_no_alloc::AllocDisabler;
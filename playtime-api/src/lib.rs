@@ -82,6 +82,13 @@ pub struct MatrixClipPlayAudioSettings {
     pub resample_mode: VirtualResampleMode,
     pub time_stretch_mode: AudioTimeStretchMode,
     pub cache_behavior: AudioCacheBehavior,
+    /// Maximum amount of memory in megabytes that all clips of the matrix may use together for
+    /// caching audio material in memory.
+    ///
+    /// If exceeded, the least recently played clips are evicted from the cache and streamed from
+    /// disk again. If not set, there's no limit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_budget_in_mb: Option<u32>,
 }
 
 /// Matrix-global settings related to recording clips.
@@ -91,6 +98,15 @@ pub struct MatrixClipRecordSettings {
     pub start_timing: ClipRecordStartTiming,
     pub stop_timing: ClipRecordStopTiming,
     pub duration: RecordLength,
+    /// Length of a count-in phase which precedes the actual recording, e.g. 1 bar.
+    ///
+    /// The count-in starts at the record start position and ends after the given length. If the
+    /// record start position is not on the grid of the given length (e.g. when recording starts
+    /// immediately), the count-in is extended to the next grid position. While counting in, the
+    /// slot reports that it's scheduled for recording start, so controllers can display the
+    /// count-in, and the column plays a click on each beat.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count_in: Option<EvenQuantization>,
     pub play_start_timing: ClipSettingOverrideAfterRecording<ClipPlayStartTiming>,
     pub play_stop_timing: ClipSettingOverrideAfterRecording<ClipPlayStopTiming>,
     pub time_base: ClipRecordTimeBase,
@@ -98,9 +114,19 @@ pub struct MatrixClipRecordSettings {
     // TODO-medium Remove play_after alias
     #[serde(alias = "play_after")]
     pub looped: bool,
-    /// If `true`, sets the global tempo to the tempo of this clip right after recording.
-    // TODO-clip-implement
+    /// If `true`, the first clip recorded into an empty matrix sets the project tempo right after
+    /// recording.
+    ///
+    /// Only has an effect for audio clips which are recorded freely, that is, without quantized
+    /// record end. The tempo is derived from the length of the recorded material and
+    /// [`Self::lead_tempo_bar_count`].
     pub lead_tempo: bool,
+    /// Number of bars which the freely recorded lead-tempo clip is supposed to span.
+    ///
+    /// `None` means it picks the bar count which results in a tempo closest to the current
+    /// project tempo.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lead_tempo_bar_count: Option<u32>,
     pub midi_settings: MatrixClipRecordMidiSettings,
     pub audio_settings: MatrixClipRecordAudioSettings,
 }
@@ -185,11 +211,13 @@ impl Default for MatrixClipRecordSettings {
             start_timing: Default::default(),
             stop_timing: Default::default(),
             duration: Default::default(),
+            count_in: None,
             play_start_timing: Default::default(),
             play_stop_timing: Default::default(),
             time_base: Default::default(),
             looped: true,
             lead_tempo: false,
+            lead_tempo_bar_count: None,
             midi_settings: Default::default(),
             audio_settings: Default::default(),
         }
@@ -204,11 +232,14 @@ pub struct MatrixClipRecordMidiSettings {
     /// the downbeat position from that.
     pub detect_downbeat: bool,
     /// Makes the global record button work for MIDI by allowing global input detection.
-    // TODO-clip-implement
     pub detect_input: bool,
-    /// Applies quantization while recording using the current quantization settings.
-    // TODO-clip-implement
+    /// Quantizes the recorded notes as soon as the recording is committed.
     pub auto_quantize: bool,
+    /// Quantization settings used if auto-quantize is enabled.
+    ///
+    /// If not set, quantizes to 16th notes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quantization: Option<MidiQuantizationSettings>,
 }
 
 impl Default for MatrixClipRecordMidiSettings {
@@ -218,6 +249,7 @@ impl Default for MatrixClipRecordMidiSettings {
             detect_downbeat: true,
             detect_input: true,
             auto_quantize: false,
+            quantization: None,
         }
     }
 }
@@ -230,8 +262,37 @@ pub struct MatrixClipRecordAudioSettings {
     // TODO-clip-implement
     pub detect_downbeat: bool,
     /// Makes the global record button work for audio by allowing global input detection.
-    // TODO-clip-implement
+    ///
+    /// Only considers columns whose track input refers to specific channels.
     pub detect_input: bool,
+    /// Audio input level above which input detection considers a column as receiving input.
+    ///
+    /// Defaults to -40 dB.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detect_input_threshold: Option<Db>,
+    /// If set, recording into a slot which contains an audio clip already doesn't replace the
+    /// clip but layers the new input on top of the existing material while it keeps looping.
+    ///
+    /// Only possible if the clip plays at its original speed (not time-stretched or resampled)
+    /// and has the same frame rate as the audio device.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overdub: Option<AudioOverdubSettings>,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AudioOverdubSettings {
+    /// Factor by which the existing material is attenuated whenever a new layer is mixed in.
+    ///
+    /// 1.0 keeps the existing material as it is, 0.0 replaces it with the new input. Values outside
+    /// of this range are clamped.
+    pub feedback: f64,
+}
+
+impl Default for AudioOverdubSettings {
+    fn default() -> Self {
+        Self { feedback: 1.0 }
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
@@ -241,6 +302,13 @@ pub enum RecordLength {
     OpenEnd,
     /// Records exactly as much material as defined by the given quantization.
     Quantized(EvenQuantization),
+    /// Records exactly the given length (e.g. 4 bars), measured from the record start position.
+    ///
+    /// In contrast to [`RecordLength::Quantized`], the length doesn't depend on where in the bar
+    /// grid the recording starts. Stops recording automatically once the length is reached. If
+    /// the record start position is not on the grid of the given length (e.g. when recording
+    /// starts immediately), the length is measured from the next grid position.
+    Fixed(EvenQuantization),
 }
 
 impl Default for RecordLength {
@@ -362,8 +430,10 @@ pub enum MidiClipRecordMode {
     Overdub,
     /// Records more material onto an existing clip, overwriting existing material.
     ///
+    /// All existing events within the range that passes by while recording are erased, even if
+    /// there's no input at that time.
+    ///
     /// Falls back to Normal when used on an empty slot.
-    // TODO-clip-implement
     Replace,
 }
 
@@ -537,6 +607,13 @@ pub struct ColumnClipPlaySettings {
     /// `None` means it uses the matrix-global stop timing.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stop_timing: Option<ClipPlayStopTiming>,
+    /// If `true`, this column is a tempo leader: Whenever a clip in this column is launched,
+    /// the project tempo switches to the tempo of that clip. The switch happens as soon as the
+    /// launch is scheduled, so the clip starts in the new tempo already.
+    ///
+    /// Only has an effect for audio clips with beat time base. `None` means `false`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lead_tempo: Option<bool>,
     pub audio_settings: ColumnClipPlayAudioSettings,
 }
 
@@ -730,7 +807,6 @@ pub struct Clip {
     /// Relative volume adjustment of clip.
     pub volume: Db,
     /// Color of the clip.
-    // TODO-clip-implement
     pub color: ClipColor,
     /// Defines which portion of the original source should be played.
     pub section: Section,
@@ -821,6 +897,54 @@ pub struct ClipMidiSettings {
     pub loop_reset_settings: MidiResetMessageRange,
     /// For fine-tuning instant start/stop of a MIDI clip when in the middle of a source or section.
     pub interaction_reset_settings: MidiResetMessageRange,
+    /// If set, notes recorded into this clip are quantized as soon as the recording is committed.
+    ///
+    /// Also used as default when quantizing the clip on demand.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record_quantization: Option<MidiQuantizationSettings>,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MidiQuantizationSettings {
+    /// Note value of the grid, e.g. 1/16 for 16th notes.
+    pub grid: EvenQuantization,
+    /// How far notes are moved towards the grid.
+    ///
+    /// 0.0 doesn't move notes at all, 1.0 moves them exactly onto the grid.
+    pub strength: f64,
+    /// Delays every second grid position.
+    ///
+    /// 0.0 means no swing, 1.0 means maximum swing (by half a grid interval).
+    pub swing: f64,
+    /// If `true`, note ends are quantized as well. Otherwise notes keep their length.
+    pub quantize_note_ends: bool,
+    /// Randomly shifts quantized note starts (by up to half a grid interval).
+    ///
+    /// 0.0 doesn't humanize at all, 1.0 means maximum deviation.
+    #[serde(default)]
+    pub humanize: f64,
+    /// Seed for the random deviations when humanizing.
+    ///
+    /// Quantizing the same material with the same settings always gives the same result.
+    #[serde(default)]
+    pub humanize_seed: u64,
+}
+
+impl Default for MidiQuantizationSettings {
+    fn default() -> Self {
+        Self {
+            grid: EvenQuantization {
+                numerator: 1,
+                denominator: 16,
+            },
+            strength: 1.0,
+            swing: 0.0,
+            quantize_note_ends: false,
+            humanize: 0.0,
+            humanize_seed: 0,
+        }
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Debug, Default, Serialize, Deserialize, JsonSchema)]
@@ -899,12 +1023,14 @@ pub enum ClipColor {
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct CustomClipColor {
+    /// RGB color encoded as `0xRRGGBB`.
     pub value: u32,
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct PaletteClipColor {
+    /// Index within the built-in clip color palette (wraps around).
     pub index: u32,
 }
 
@@ -1002,6 +1128,8 @@ impl Bpm {
 pub struct PositiveSecond(f64);
 
 impl PositiveSecond {
+    pub const ZERO: PositiveSecond = PositiveSecond(0.0);
+
     pub fn new(value: f64) -> PlaytimeApiResult<Self> {
         if value < 0.0 {
             return Err("second value must be positive");
@@ -1018,6 +1146,8 @@ impl PositiveSecond {
 pub struct PositiveBeat(f64);
 
 impl PositiveBeat {
+    pub const ZERO: PositiveBeat = PositiveBeat(0.0);
+
     pub fn new(value: f64) -> PlaytimeApiResult<Self> {
         if value < 0.0 {
             return Err("beat value must be positive");
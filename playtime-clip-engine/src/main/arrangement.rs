@@ -0,0 +1,149 @@
+use crate::main::ClipSlotCoordinates;
+use crate::rt::ClipPlayState;
+use reaper_medium::{DurationInSeconds, PositionInSeconds};
+use std::collections::HashMap;
+
+/// Logs when clips start and stop playing while arrangement recording is enabled.
+///
+/// The log can be written to the timeline afterwards, which turns a jam into an arrangement
+/// consisting of normal media items on the column tracks.
+#[derive(Clone, Debug, Default)]
+pub struct ArrangementRecorder {
+    is_recording: bool,
+    /// Clips which are playing at the moment, together with the timeline position at which they
+    /// started playing.
+    open_entries: HashMap<ClipSlotCoordinates, PositionInSeconds>,
+    entries: Vec<ArrangementEntry>,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ArrangementEntry {
+    pub slot_coordinates: ClipSlotCoordinates,
+    pub start_pos: PositionInSeconds,
+    pub length: DurationInSeconds,
+}
+
+impl ArrangementRecorder {
+    pub fn is_recording(&self) -> bool {
+        self.is_recording
+    }
+
+    /// Starts a new arrangement recording, discarding the previous one.
+    ///
+    /// Clips which are playing already are not taken into account. Just retrigger them.
+    pub fn start(&mut self) {
+        self.open_entries.clear();
+        self.entries.clear();
+        self.is_recording = true;
+    }
+
+    /// Stops the arrangement recording and closes the entries of all clips still playing.
+    pub fn stop(&mut self, timeline_pos: PositionInSeconds) {
+        if !self.is_recording {
+            return;
+        }
+        let open_entries: Vec<_> = self.open_entries.drain().collect();
+        for (slot_coordinates, start_pos) in open_entries {
+            self.close_entry(slot_coordinates, start_pos, timeline_pos);
+        }
+        self.entries
+            .sort_by(|a, b| a.start_pos.get().total_cmp(&b.start_pos.get()));
+        self.is_recording = false;
+    }
+
+    pub fn entries(&self) -> &[ArrangementEntry] {
+        &self.entries
+    }
+
+    pub fn process_play_state_change(
+        &mut self,
+        slot_coordinates: ClipSlotCoordinates,
+        play_state: ClipPlayState,
+        timeline_pos: PositionInSeconds,
+    ) {
+        if !self.is_recording {
+            return;
+        }
+        use ClipPlayState::*;
+        match play_state {
+            // A clip which is scheduled for stop is still audible.
+            ScheduledForPlayStart | ScheduledForPlayStop => {}
+            Playing => {
+                self.open_entries
+                    .entry(slot_coordinates)
+                    .or_insert(timeline_pos);
+            }
+            Stopped
+            | Paused
+            | ScheduledForRecordingStart
+            | Recording
+            | ScheduledForRecordingStop => {
+                if let Some(start_pos) = self.open_entries.remove(&slot_coordinates) {
+                    self.close_entry(slot_coordinates, start_pos, timeline_pos);
+                }
+            }
+        }
+    }
+
+    fn close_entry(
+        &mut self,
+        slot_coordinates: ClipSlotCoordinates,
+        start_pos: PositionInSeconds,
+        end_pos: PositionInSeconds,
+    ) {
+        let length = end_pos.get() - start_pos.get();
+        if length <= 0.0 {
+            return;
+        }
+        let entry = ArrangementEntry {
+            slot_coordinates,
+            start_pos,
+            length: DurationInSeconds::new(length),
+        };
+        self.entries.push(entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_clip_starts_and_stops() {
+        // Given
+        let mut recorder = ArrangementRecorder::default();
+        let a = ClipSlotCoordinates::new(0, 0);
+        let b = ClipSlotCoordinates::new(1, 2);
+        // When
+        recorder.process_play_state_change(a, ClipPlayState::Playing, pos(0.5));
+        recorder.start();
+        recorder.process_play_state_change(a, ClipPlayState::ScheduledForPlayStart, pos(1.0));
+        recorder.process_play_state_change(a, ClipPlayState::Playing, pos(2.0));
+        recorder.process_play_state_change(b, ClipPlayState::Playing, pos(3.0));
+        recorder.process_play_state_change(a, ClipPlayState::ScheduledForPlayStop, pos(4.0));
+        recorder.process_play_state_change(a, ClipPlayState::Stopped, pos(6.0));
+        recorder.stop(pos(10.0));
+        recorder.process_play_state_change(a, ClipPlayState::Playing, pos(11.0));
+        // Then
+        assert!(!recorder.is_recording());
+        assert_eq!(
+            recorder.entries(),
+            &[
+                ArrangementEntry {
+                    slot_coordinates: a,
+                    start_pos: pos(2.0),
+                    length: DurationInSeconds::new(4.0),
+                },
+                ArrangementEntry {
+                    slot_coordinates: b,
+                    start_pos: pos(3.0),
+                    length: DurationInSeconds::new(7.0),
+                },
+            ]
+        );
+    }
+
+    fn pos(secs: f64) -> PositionInSeconds {
+        PositionInSeconds::new(secs)
+    }
+}
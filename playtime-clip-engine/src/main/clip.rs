@@ -7,9 +7,11 @@ use crate::source_util::{
 use crate::{rt, source_util, ClipEngineResult};
 use crossbeam_channel::Sender;
 use playtime_api as api;
-use playtime_api::{ClipColor, Db};
-use reaper_high::{OwnedSource, Project, Reaper};
-use reaper_medium::{Bpm, OwnedPcmSource};
+use playtime_api::{ClipColor, Db, MidiQuantizationSettings};
+use reaper_high::{OwnedSource, Project, Reaper, Track};
+use reaper_medium::{Bpm, OwnedPcmSource, RgbColor};
+use std::mem;
+use std::path::Path;
 
 /// Describes a clip.
 ///
@@ -17,6 +19,9 @@ use reaper_medium::{Bpm, OwnedPcmSource};
 #[derive(Clone, Debug)]
 pub struct Clip {
     source: api::Source,
+    /// Source as it was before the last audio overdub layer (for undo).
+    source_before_audio_overdub: Option<api::Source>,
+    color: ClipColor,
     processing_relevant_settings: ProcessingRelevantClipSettings,
 }
 
@@ -25,6 +30,8 @@ impl Clip {
         Self {
             processing_relevant_settings: ProcessingRelevantClipSettings::from_api(&api_clip),
             source: api_clip.source,
+            source_before_audio_overdub: None,
+            color: api_clip.color,
         }
     }
 
@@ -45,6 +52,8 @@ impl Clip {
         };
         let clip = Self {
             source: api_source,
+            source_before_audio_overdub: None,
+            color: ClipColor::PlayTrackColor,
             processing_relevant_settings: clip_settings,
         };
         Ok(clip)
@@ -73,7 +82,7 @@ impl Clip {
             stop_timing: self.processing_relevant_settings.stop_timing,
             looped: self.processing_relevant_settings.looped,
             volume: self.processing_relevant_settings.volume,
-            color: ClipColor::PlayTrackColor,
+            color: self.color.clone(),
             section: self.processing_relevant_settings.section,
             audio_settings: self.processing_relevant_settings.audio_settings,
             midi_settings: self.processing_relevant_settings.midi_settings,
@@ -90,6 +99,10 @@ impl Clip {
         self.source = source;
     }
 
+    pub fn update_api_source(&mut self, source: api::Source) {
+        self.source = source;
+    }
+
     pub fn notify_midi_overdub_finished(
         &mut self,
         mirror_source: &OwnedSource,
@@ -101,6 +114,32 @@ impl Clip {
         Ok(())
     }
 
+    /// Switches to the file which receives the overdubbed audio material.
+    pub fn notify_audio_overdub_finished(
+        &mut self,
+        file: &Path,
+        temporary_project: Option<Project>,
+    ) {
+        let api_source = create_file_api_source(temporary_project, file);
+        self.source_before_audio_overdub = Some(mem::replace(&mut self.source, api_source));
+    }
+
+    pub fn can_undo_audio_overdub(&self) -> bool {
+        self.source_before_audio_overdub.is_some()
+    }
+
+    /// Switches back to the source as it was before the last audio overdub layer.
+    ///
+    /// Should be called only after the real-time clip has undone the layer as well.
+    pub fn notify_audio_overdub_undone(&mut self) -> ClipEngineResult<()> {
+        let source = self
+            .source_before_audio_overdub
+            .take()
+            .ok_or("no audio overdub to undo")?;
+        self.source = source;
+        Ok(())
+    }
+
     pub fn api_source(&self) -> &api::Source {
         &self.source
     }
@@ -141,10 +180,24 @@ impl Clip {
         Ok((rt_clip, pooled_copy))
     }
 
+    pub fn set_time_base(&mut self, time_base: api::ClipTimeBase) {
+        self.processing_relevant_settings.time_base = time_base;
+    }
+
+    pub fn record_quantization(&self) -> Option<MidiQuantizationSettings> {
+        self.processing_relevant_settings
+            .midi_settings
+            .record_quantization
+    }
+
     pub fn looped(&self) -> bool {
         self.processing_relevant_settings.looped
     }
 
+    pub fn section(&self) -> api::Section {
+        self.processing_relevant_settings.section
+    }
+
     pub fn toggle_looped(&mut self) -> bool {
         let looped_new = !self.processing_relevant_settings.looped;
         self.processing_relevant_settings.looped = looped_new;
@@ -159,6 +212,14 @@ impl Clip {
         self.processing_relevant_settings.volume
     }
 
+    pub fn color(&self) -> &ClipColor {
+        &self.color
+    }
+
+    pub fn set_color(&mut self, color: ClipColor) {
+        self.color = color;
+    }
+
     pub fn tempo_factor(&self, timeline_tempo: Bpm, is_midi: bool) -> f64 {
         if let Some(tempo) = self.tempo(is_midi) {
             calc_tempo_factor(tempo, timeline_tempo)
@@ -168,11 +229,50 @@ impl Clip {
     }
 
     /// Returns `None` if time base is not "Beat".
-    fn tempo(&self, is_midi: bool) -> Option<Bpm> {
+    pub fn tempo(&self, is_midi: bool) -> Option<Bpm> {
         determine_tempo_from_time_base(&self.processing_relevant_settings.time_base, is_midi)
     }
 }
 
+/// Built-in palette used for [`ClipColor::PaletteColor`].
+const CLIP_COLOR_PALETTE: [RgbColor; 12] = [
+    rgb(0xE5, 0x39, 0x35),
+    rgb(0xFB, 0x8C, 0x00),
+    rgb(0xFD, 0xD8, 0x35),
+    rgb(0xC0, 0xCA, 0x33),
+    rgb(0x43, 0xA0, 0x47),
+    rgb(0x00, 0x89, 0x7B),
+    rgb(0x00, 0xAC, 0xC1),
+    rgb(0x1E, 0x88, 0xE5),
+    rgb(0x39, 0x49, 0xAB),
+    rgb(0x8E, 0x24, 0xAA),
+    rgb(0xD8, 0x1B, 0x60),
+    rgb(0x9E, 0x9E, 0x9E),
+];
+
+/// Resolves the given clip color to an actual RGB color.
+///
+/// Returns `None` if the clip inherits the play track color but there's no play track or the
+/// play track doesn't have a custom color.
+pub fn resolve_clip_color(color: &ClipColor, play_track: Option<&Track>) -> Option<RgbColor> {
+    match color {
+        ClipColor::PlayTrackColor => play_track?.custom_color(),
+        ClipColor::CustomColor(c) => Some(rgb(
+            ((c.value >> 16) & 0xFF) as u8,
+            ((c.value >> 8) & 0xFF) as u8,
+            (c.value & 0xFF) as u8,
+        )),
+        ClipColor::PaletteColor(c) => {
+            let index = c.index as usize % CLIP_COLOR_PALETTE.len();
+            Some(CLIP_COLOR_PALETTE[index])
+        }
+    }
+}
+
+const fn rgb(r: u8, g: u8, b: u8) -> RgbColor {
+    RgbColor { r, g, b }
+}
+
 pub fn create_api_source_from_recorded_midi_source(
     midi_source: &OwnedSource,
     temporary_project: Option<Project>,
@@ -1,24 +1,33 @@
-use crate::main::{Clip, ClipMatrixHandler, MatrixSettings, Slot};
+use crate::main::{
+    project_time_signature, resolve_clip_color, resolve_track_input, Clip, ClipMatrixHandler,
+    ClipRecordHardwareInput, ClipRecordInput, InputDetectionCandidate, MatrixSettings, Slot,
+    VirtualClipRecordAudioInput,
+};
 use crate::rt::supplier::{ChainEquipment, RecorderRequest};
 use crate::rt::{
     ClipChangedEvent, ClipPlayState, ColumnCommandSender, ColumnEvent, ColumnFillSlotArgs,
-    ColumnPlayClipArgs, ColumnStopClipArgs, OverridableMatrixSettings, SharedColumn, WeakColumn,
+    ColumnPlayClipArgs, ColumnStopClipArgs, NormalRecordingOutcome, OverridableMatrixSettings,
+    SharedColumn, WeakColumn,
 };
-use crate::{clip_timeline, rt, ClipEngineResult};
+use crate::source_util::create_pcm_source_from_api_source;
+use crate::{clip_timeline, rt, source_util, ClipEngineResult};
 use crossbeam_channel::{Receiver, Sender};
 use enumflags2::BitFlags;
-use helgoboss_learn::UnitValue;
+use helgoboss_learn::{UnitValue, BASE_EPSILON};
 use playtime_api as api;
 use playtime_api::{
-    ColumnClipPlayAudioSettings, ColumnClipPlaySettings, ColumnClipRecordSettings, Db,
-    MatrixClipRecordSettings,
+    ClipColor, ColumnClipPlayAudioSettings, ColumnClipPlaySettings, ColumnClipRecordSettings, Db,
+    MatrixClipRecordSettings, MidiQuantizationSettings, RecordOrigin,
 };
-use reaper_high::{Guid, OrCurrentProject, Project, Reaper, Track};
+use reaper_high::{Guid, Item, OrCurrentProject, Project, Reaper, Tempo, Track};
 use reaper_low::raw::preview_register_t;
 use reaper_medium::{
-    create_custom_owned_pcm_source, Bpm, CustomPcmSource, FlexibleOwnedPcmSource, HelpMode,
-    MeasureAlignment, OwnedPreviewRegister, PositionInSeconds, ReaperMutex, ReaperVolumeValue,
+    create_custom_owned_pcm_source, Bpm, CustomPcmSource, DurationInSeconds,
+    FlexibleOwnedPcmSource, HelpMode, MeasureAlignment, OwnedPreviewRegister, PositionInSeconds,
+    ReaperMutex, ReaperVolumeValue, RgbColor, UndoBehavior,
 };
+use std::mem;
+use std::path::Path;
 use std::ptr::NonNull;
 use std::sync::Arc;
 
@@ -34,11 +43,24 @@ pub struct Column {
     slots: Vec<Slot>,
     event_receiver: Receiver<ColumnEvent>,
     project: Option<Project>,
+    /// Last known custom color of the playback track (for detecting color changes).
+    track_color: Option<RgbColor>,
+    /// Quantizations which wait for their clip to be stopped.
+    pending_quantizations: Vec<PendingQuantization>,
+}
+
+#[derive(Clone, Debug)]
+struct PendingQuantization {
+    slot_index: usize,
+    /// For detecting whether the slot content has changed in the meantime.
+    api_source: api::Source,
+    settings: MidiQuantizationSettings,
 }
 
 #[derive(Clone, Debug, Default)]
 pub struct ColumnSettings {
     pub clip_record_settings: ColumnClipRecordSettings,
+    pub lead_tempo: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -66,6 +88,8 @@ impl Column {
             slots: vec![],
             event_receiver,
             project: permanent_project,
+            track_color: None,
+            pending_quantizations: vec![],
         }
     }
 
@@ -91,6 +115,7 @@ impl Column {
         self.preview_register = Some(PlayingPreviewRegister::new(self.rt_column.clone(), track));
         // Settings
         self.settings.clip_record_settings = api_column.clip_record_settings;
+        self.settings.lead_tempo = api_column.clip_play_settings.lead_tempo.unwrap_or(false);
         self.rt_settings.audio_resample_mode =
             api_column.clip_play_settings.audio_settings.resample_mode;
         self.rt_settings.audio_time_stretch_mode = api_column
@@ -144,6 +169,10 @@ impl Column {
         self.slots.len()
     }
 
+    pub(super) fn has_clips(&self) -> bool {
+        self.slots.iter().any(|s| !s.is_empty())
+    }
+
     pub fn save(&self) -> api::Column {
         let track_id = self.preview_register.as_ref().and_then(|reg| {
             reg.track
@@ -157,6 +186,11 @@ impl Column {
                 track: track_id,
                 start_timing: self.rt_settings.clip_play_start_timing,
                 stop_timing: self.rt_settings.clip_play_stop_timing,
+                lead_tempo: if self.settings.lead_tempo {
+                    Some(true)
+                } else {
+                    None
+                },
                 audio_settings: ColumnClipPlayAudioSettings {
                     resample_mode: self.rt_settings.audio_resample_mode,
                     time_stretch_mode: self.rt_settings.audio_time_stretch_mode,
@@ -192,6 +226,14 @@ impl Column {
                     if let Some(slot) = self.slots.get_mut(slot_index) {
                         let _ = slot.update_play_state(play_state);
                     }
+                    // Launches which have been scheduled by the main thread lead the tempo
+                    // already. This catches the ones scheduled directly in the real-time thread.
+                    if matches!(
+                        play_state,
+                        ClipPlayState::ScheduledForPlayStart | ClipPlayState::Playing
+                    ) {
+                        self.lead_tempo(slot_index);
+                    }
                     Some((slot_index, ClipChangedEvent::PlayState(play_state)))
                 }
                 ClipMaterialInfoChanged {
@@ -222,14 +264,38 @@ impl Column {
                     }
                     None
                 }
+                AudioOverdubFinished { slot_index, file } => {
+                    if let Some(slot) = self.slots.get_mut(slot_index) {
+                        slot.notify_audio_overdub_finished(&file, self.project)
+                            .unwrap();
+                    }
+                    None
+                }
+                AudioOverdubUndone { slot_index } => {
+                    if let Some(slot) = self.slots.get_mut(slot_index) {
+                        if let Err(e) = slot.notify_audio_overdub_undone() {
+                            debug!("Couldn't switch to source before audio overdub: {}", e);
+                        }
+                    }
+                    None
+                }
                 NormalRecordingFinished {
                     slot_index,
                     outcome,
                 } => {
+                    let committed = matches!(outcome, NormalRecordingOutcome::Committed(_));
+                    if let NormalRecordingOutcome::Committed(recording) = &outcome {
+                        if let Some(tempo) = recording.lead_tempo {
+                            set_project_tempo(self.project, tempo);
+                        }
+                    }
                     if let Some(slot) = self.slots.get_mut(slot_index) {
                         let event = slot
                             .notify_normal_recording_finished(outcome, self.project)
                             .unwrap();
+                        if committed {
+                            self.request_record_quantization(slot_index);
+                        }
                         event.map(|e| (slot_index, e))
                     } else {
                         None
@@ -265,6 +331,21 @@ impl Column {
             }
         });
         change_events.extend(pos_change_events);
+        // Add color updates for clips which inherit the track color
+        let track_color = self.playback_track().ok().and_then(|t| t.custom_color());
+        if track_color != self.track_color {
+            self.track_color = track_color;
+            let color_change_events = self.slots.iter().enumerate().filter_map(|(row, slot)| {
+                match slot.clip_color().ok()? {
+                    ClipColor::PlayTrackColor => {
+                        let event = ClipChangedEvent::ClipColor(ClipColor::PlayTrackColor);
+                        Some((row, event))
+                    }
+                    _ => None,
+                }
+            });
+            change_events.extend(color_change_events);
+        }
         change_events
     }
 
@@ -290,54 +371,181 @@ impl Column {
         }
     }
 
-    // TODO-high Implement
     pub fn fill_slot_with_selected_item(
         &mut self,
-        _slot_index: usize,
-        _chain_equipment: &ChainEquipment,
-        _recorder_request_sender: &Sender<RecorderRequest>,
-        _matrix_settings: &MatrixSettings,
+        slot_index: usize,
+        chain_equipment: &ChainEquipment,
+        recorder_request_sender: &Sender<RecorderRequest>,
+        matrix_settings: &MatrixSettings,
     ) -> ClipEngineResult<()> {
-        // let slot = get_slot_mut_insert(&mut self.slots, slot_index);
-        // if !slot.is_empty() {
-        //     return Err("slot is not empty");
-        // }
-        // let item = self
-        //     .project
-        //     .or_current_project()
-        //     .first_selected_item()
-        //     .ok_or("no item selected")?;
-        // let clip = api::Clip {
-        //     source: source_util::create_api_source_from_item(item, false)
-        //         .map_err(|_| "couldn't create source from item")?,
-        //     time_base: todo!(),
-        //     start_timing: todo!(),
-        //     stop_timing: todo!(),
-        //     looped: todo!(),
-        //     volume: api::Db::ZERO,
-        //     color: ClipColor::PlayTrackColor,
-        //     section: todo!(),
-        //     audio_settings: todo!(),
-        //     midi_settings: todo!(),
-        // };
-        // let clip = Clip::load(clip);
-        // fill_slot_internal(
-        //     slot,
-        //     clip,
-        //     chain_equipment,
-        //     recorder_request_sender,
-        //     matrix_settings,
-        //     &self.rt_settings,
-        //     &self.rt_command_sender,
-        //     self.project,
-        // )
-        Err("not yet implemented")
+        let item = self
+            .project
+            .or_current_project()
+            .first_selected_item()
+            .ok_or("no item selected")?;
+        let api_source = source_util::create_api_source_from_item(item, false)
+            .map_err(|_| "couldn't create source from item")?;
+        self.fill_slot_with_api_source(
+            slot_index,
+            api_source,
+            chain_equipment,
+            recorder_request_sender,
+            matrix_settings,
+        )
+    }
+
+    pub fn fill_slot_with_file(
+        &mut self,
+        slot_index: usize,
+        file: &Path,
+        chain_equipment: &ChainEquipment,
+        recorder_request_sender: &Sender<RecorderRequest>,
+        matrix_settings: &MatrixSettings,
+    ) -> ClipEngineResult<()> {
+        let api_source = source_util::create_file_api_source(self.project, file);
+        self.fill_slot_with_api_source(
+            slot_index,
+            api_source,
+            chain_equipment,
+            recorder_request_sender,
+            matrix_settings,
+        )
+    }
+
+    /// Fills the slot with a new clip using sensible defaults.
+    ///
+    /// Audio starts with time base "Time" because we don't know its tempo yet. The matrix
+    /// triggers tempo detection and switches to time base "Beat" as soon as the result arrives.
+    fn fill_slot_with_api_source(
+        &mut self,
+        slot_index: usize,
+        api_source: api::Source,
+        chain_equipment: &ChainEquipment,
+        recorder_request_sender: &Sender<RecorderRequest>,
+        matrix_settings: &MatrixSettings,
+    ) -> ClipEngineResult<()> {
+        let slot = get_slot_mut_insert(&mut self.slots, slot_index);
+        if !slot.is_empty() {
+            return Err("slot is not empty");
+        }
+        let pcm_source = create_pcm_source_from_api_source(&api_source, self.project)?;
+        let time_base = if rt::source_util::pcm_source_is_midi(&pcm_source) {
+            api::ClipTimeBase::Beat(api::BeatTimeBase {
+                audio_tempo: None,
+                time_signature: project_time_signature(self.project),
+                downbeat: api::PositiveBeat::ZERO,
+            })
+        } else {
+            api::ClipTimeBase::Time
+        };
+        let api_clip = api::Clip {
+            source: api_source,
+            time_base,
+            start_timing: None,
+            stop_timing: None,
+            looped: true,
+            volume: Db::ZERO,
+            color: ClipColor::PlayTrackColor,
+            section: api::Section {
+                start_pos: api::PositiveSecond::ZERO,
+                length: None,
+            },
+            audio_settings: Default::default(),
+            midi_settings: Default::default(),
+        };
+        fill_slot_internal(
+            slot,
+            Clip::load(api_clip),
+            chain_equipment,
+            recorder_request_sender,
+            matrix_settings,
+            &self.rt_settings,
+            &self.rt_command_sender,
+            self.project,
+        )
     }
 
-    pub fn play_clip(&self, args: ColumnPlayClipArgs) {
+    /// Switches the clip in the given slot to the detected beat time base.
+    ///
+    /// Refuses if the slot content has changed in the meantime or if the clip is not stopped
+    /// (replacing it would interrupt playback or recording).
+    pub fn apply_detected_tempo(
+        &mut self,
+        slot_index: usize,
+        analyzed_api_source: &api::Source,
+        beat_time_base: api::BeatTimeBase,
+        chain_equipment: &ChainEquipment,
+        recorder_request_sender: &Sender<RecorderRequest>,
+        matrix_settings: &MatrixSettings,
+    ) -> ClipEngineResult<()> {
+        let slot = get_slot_mut(&mut self.slots, slot_index)?;
+        if slot.play_state()? != ClipPlayState::Stopped {
+            return Err("clip is not stopped");
+        }
+        let mut clip = slot.clip()?.clone();
+        if clip.api_source() != analyzed_api_source {
+            return Err("slot content has changed since tempo detection was requested");
+        }
+        clip.set_time_base(api::ClipTimeBase::Beat(beat_time_base));
+        fill_slot_internal(
+            slot,
+            clip,
+            chain_equipment,
+            recorder_request_sender,
+            matrix_settings,
+            &self.rt_settings,
+            &self.rt_command_sender,
+            self.project,
+        )
+    }
+
+    pub fn export_slot_to_item(
+        &self,
+        slot_index: usize,
+        pos: PositionInSeconds,
+        length: Option<DurationInSeconds>,
+    ) -> ClipEngineResult<Item> {
+        let timeline = clip_timeline(self.project, false);
+        self.get_slot(slot_index)?
+            .export_to_item(self.playback_track()?, pos, length, &timeline)
+    }
+
+    fn playback_track(&self) -> ClipEngineResult<&Track> {
+        self.preview_register
+            .as_ref()
+            .ok_or("column inactive")?
+            .track
+            .as_ref()
+            .ok_or("no playback track set")
+    }
+
+    pub fn play_clip(&self, mut args: ColumnPlayClipArgs) {
+        // Change the tempo before the real-time thread schedules the launch. This way, the start
+        // position is quantized according to the new tempo already. The real-time thread waits
+        // until its timeline has picked up the tempo change.
+        args.tempo = self.lead_tempo(args.slot_index);
         self.rt_command_sender.play_clip(args);
     }
 
+    /// Makes the project follow the tempo of the clip in the given slot if this column leads the
+    /// tempo.
+    ///
+    /// Returns the new tempo if it has been changed.
+    fn lead_tempo(&self, slot_index: usize) -> Option<Bpm> {
+        if !self.settings.lead_tempo {
+            return None;
+        }
+        let tempo = self
+            .get_slot(slot_index)
+            .and_then(|slot| slot.clip_audio_tempo())
+            .ok()??;
+        if set_project_tempo(self.project, tempo) {
+            Some(tempo)
+        } else {
+            None
+        }
+    }
+
     pub fn stop_clip(&self, args: ColumnStopClipArgs) {
         self.rt_command_sender.stop_clip(args);
     }
@@ -381,14 +589,205 @@ impl Column {
         self.get_slot(slot_index)?.play_state()
     }
 
+    /// Quantizes the notes of the in-project MIDI clip in the given slot as soon as it's stopped.
+    ///
+    /// Uses the clip's record quantization settings or 16th notes.
+    pub fn quantize_clip(&mut self, slot_index: usize) -> ClipEngineResult<()> {
+        let clip = self.get_slot(slot_index)?.clip()?;
+        let settings = clip.record_quantization().unwrap_or_default();
+        self.request_quantization(slot_index, clip.api_source().clone(), settings)
+    }
+
+    /// Requests quantization of a freshly recorded clip if its record settings ask for it.
+    fn request_record_quantization(&mut self, slot_index: usize) {
+        let (api_source, settings) = match self.get_slot(slot_index).and_then(|s| s.clip()) {
+            Ok(clip) => match clip.record_quantization() {
+                None => return,
+                Some(settings) => (clip.api_source().clone(), settings),
+            },
+            Err(_) => return,
+        };
+        if let Err(e) = self.request_quantization(slot_index, api_source, settings) {
+            debug!("Couldn't quantize recorded MIDI: {}", e);
+        }
+    }
+
+    fn request_quantization(
+        &mut self,
+        slot_index: usize,
+        api_source: api::Source,
+        settings: MidiQuantizationSettings,
+    ) -> ClipEngineResult<()> {
+        if !matches!(api_source, api::Source::MidiChunk(_)) {
+            return Err("only in-project MIDI clips can be quantized");
+        }
+        let quantization = PendingQuantization {
+            slot_index,
+            api_source,
+            settings,
+        };
+        self.pending_quantizations.push(quantization);
+        Ok(())
+    }
+
+    /// Applies requested quantizations as soon as the corresponding clip is stopped.
+    ///
+    /// Quantizing replaces the clip with a new one (which would interrupt playback or recording),
+    /// so the material which the real-time clip is playing is never modified.
+    pub fn apply_pending_quantizations(
+        &mut self,
+        chain_equipment: &ChainEquipment,
+        recorder_request_sender: &Sender<RecorderRequest>,
+        matrix_settings: &MatrixSettings,
+    ) {
+        if self.pending_quantizations.is_empty() {
+            return;
+        }
+        for quantization in mem::take(&mut self.pending_quantizations) {
+            let slot = match get_slot_mut(&mut self.slots, quantization.slot_index) {
+                Ok(s) => s,
+                Err(e) => {
+                    debug!("Couldn't quantize clip: {}", e);
+                    continue;
+                }
+            };
+            match slot.play_state() {
+                Ok(ClipPlayState::Stopped) => {
+                    let result = slot
+                        .create_quantized_clip(&quantization.api_source, &quantization.settings)
+                        .and_then(|clip| {
+                            fill_slot_internal(
+                                slot,
+                                clip,
+                                chain_equipment,
+                                recorder_request_sender,
+                                matrix_settings,
+                                &self.rt_settings,
+                                &self.rt_command_sender,
+                                self.project,
+                            )
+                        });
+                    if let Err(e) = result {
+                        debug!("Couldn't quantize clip: {}", e);
+                    }
+                }
+                Ok(_) => self.pending_quantizations.push(quantization),
+                Err(e) => debug!("Couldn't quantize clip: {}", e),
+            }
+        }
+    }
+
+    pub fn undo_audio_overdub(&mut self, slot_index: usize) -> ClipEngineResult<()> {
+        get_slot_mut(&mut self.slots, slot_index)?.undo_audio_overdub(&self.rt_command_sender)
+    }
+
+    pub fn clip_api_source(&self, slot_index: usize) -> ClipEngineResult<&api::Source> {
+        Ok(self.get_slot(slot_index)?.clip()?.api_source())
+    }
+
     pub fn clip_looped(&self, slot_index: usize) -> ClipEngineResult<bool> {
         self.get_slot(slot_index)?.clip_looped()
     }
 
+    /// Returns the resolved color of the clip in the given slot.
+    ///
+    /// `None` if the clip inherits the playback track color and that one doesn't have a custom
+    /// color.
+    pub fn clip_color(&self, slot_index: usize) -> ClipEngineResult<Option<RgbColor>> {
+        let color = self.get_slot(slot_index)?.clip_color()?;
+        Ok(resolve_clip_color(color, self.playback_track().ok()))
+    }
+
+    pub fn set_clip_color(
+        &mut self,
+        slot_index: usize,
+        color: ClipColor,
+    ) -> ClipEngineResult<ClipChangedEvent> {
+        get_slot_mut(&mut self.slots, slot_index)?.set_clip_color(color)
+    }
+
     pub fn proportional_slot_position(&self, slot_index: usize) -> ClipEngineResult<UnitValue> {
         self.get_slot(slot_index)?.proportional_pos()
     }
 
+    /// Returns the input which can be monitored in order to detect whether this column receives
+    /// input.
+    ///
+    /// Only works if the column records from the track input. Audio inputs must refer to a
+    /// specific channel range.
+    pub fn detectable_record_input(&self) -> ClipEngineResult<ClipRecordHardwareInput> {
+        let record_settings = &self.settings.clip_record_settings;
+        if !matches!(record_settings.origin, RecordOrigin::TrackInput) {
+            return Err("input detection only works when recording from track input");
+        }
+        let input = resolve_track_input(record_settings, self.playback_track()?)?;
+        if let ClipRecordHardwareInput::Audio(VirtualClipRecordAudioInput::Detect { .. }) = input {
+            return Err("input detection doesn't support audio inputs without specific channels");
+        }
+        Ok(input)
+    }
+
+    /// Returns the index of the first slot which is neither filled nor recording.
+    ///
+    /// If there's no such slot, returns the index of a new slot at the end.
+    pub fn first_empty_slot_index(&self) -> usize {
+        self.slots
+            .iter()
+            .position(|s| s.is_empty())
+            .unwrap_or(self.slots.len())
+    }
+
+    /// Prepares recording into the first empty slot, to be started as soon as input is detected
+    /// in this column.
+    ///
+    /// The slot stays reserved until the recording is started or
+    /// [`Self::cancel_prepared_recording`] is called.
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare_input_detection_candidate(
+        &mut self,
+        column_index: usize,
+        matrix_record_settings: &MatrixClipRecordSettings,
+        chain_equipment: &ChainEquipment,
+        recorder_request_sender: &Sender<RecorderRequest>,
+        containing_track: Option<&Track>,
+        overridable_matrix_settings: &OverridableMatrixSettings,
+    ) -> ClipEngineResult<InputDetectionCandidate> {
+        let slot_index = self.first_empty_slot_index();
+        let playback_track = self.playback_track()?.clone();
+        let slot = get_slot_mut_insert(&mut self.slots, slot_index);
+        let (task, instruction) = slot.prepare_recording(
+            matrix_record_settings,
+            &self.settings.clip_record_settings,
+            &self.rt_settings,
+            chain_equipment,
+            recorder_request_sender,
+            containing_track,
+            overridable_matrix_settings,
+            &playback_track,
+            &self.rt_column,
+        )?;
+        let input = match task.input {
+            ClipRecordInput::HardwareInput(input) => input,
+            ClipRecordInput::FxInput(_) => {
+                slot.cancel_prepared_recording();
+                return Err("input detection only works with hardware input");
+            }
+        };
+        let candidate = InputDetectionCandidate {
+            column_index,
+            input,
+            destination: task.destination,
+            instruction: Some(instruction),
+        };
+        Ok(candidate)
+    }
+
+    pub fn cancel_prepared_recording(&mut self, slot_index: usize) {
+        if let Ok(slot) = get_slot_mut(&mut self.slots, slot_index) {
+            slot.cancel_prepared_recording();
+        }
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn record_clip<H: ClipMatrixHandler>(
         &mut self,
@@ -399,7 +798,9 @@ impl Column {
         handler: &H,
         containing_track: Option<&Track>,
         overridable_matrix_settings: &OverridableMatrixSettings,
+        frame_offset: usize,
     ) -> ClipEngineResult<()> {
+        let playback_track = self.playback_track()?.clone();
         // Insert slot if it doesn't exist already.
         let slot = get_slot_mut_insert(&mut self.slots, slot_index);
         slot.record_clip(
@@ -411,14 +812,10 @@ impl Column {
             handler,
             containing_track,
             overridable_matrix_settings,
-            self.preview_register
-                .as_ref()
-                .ok_or("column inactive")?
-                .track
-                .as_ref()
-                .ok_or("no playback track set")?,
+            &playback_track,
             &self.rt_column,
             &self.rt_command_sender,
+            frame_offset,
         )
     }
 }
@@ -541,3 +938,14 @@ fn fill_slot_internal(
     rt_command_sender.fill_slot(Box::new(Some(args)));
     Ok(())
 }
+
+/// Returns whether the tempo has been changed.
+fn set_project_tempo(project: Option<Project>, tempo: Bpm) -> bool {
+    let project = project.or_current_project();
+    if (project.tempo().bpm().get() - tempo.get()).abs() < BASE_EPSILON {
+        return false;
+    }
+    debug!("Leading tempo: {}", tempo.get());
+    project.set_tempo(Tempo::from_bpm(tempo), UndoBehavior::OmitUndoPoint);
+    true
+}
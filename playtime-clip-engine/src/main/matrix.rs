@@ -1,29 +1,36 @@
 use crate::main::row::Row;
-use crate::main::{Column, Slot};
+use crate::main::{
+    keep_processing_tempo_detection_requests, project_time_signature, ArrangementRecorder, Column,
+    Slot, TempoDetectionRequest, TempoDetectionResponse,
+};
 use crate::rt::supplier::{
     keep_processing_cache_requests, keep_processing_pre_buffer_requests,
-    keep_processing_recorder_requests, AudioRecordingEquipment, ChainEquipment,
-    ChainPreBufferCommandProcessor, MidiRecordingEquipment, QuantizationSettings, RecorderRequest,
-    RecordingEquipment,
+    keep_processing_recorder_requests, AudioRecordingEquipment, CacheRequest, ChainEquipment,
+    ChainPreBufferCommandProcessor, MidiRecordingEquipment, RecorderRequest, RecordingEquipment,
 };
 use crate::rt::{
-    ClipPlayState, ColumnHandle, ColumnPlayClipArgs, ColumnStopClipArgs, OverridableMatrixSettings,
-    QualifiedClipChangedEvent, RtMatrixCommandSender, WeakColumn,
+    ClipChangedEvent, ClipPlayState, ColumnHandle, ColumnPlayClipArgs, ColumnStopClipArgs,
+    OverridableMatrixSettings, QualifiedClipChangedEvent, RtMatrixCommandSender,
+    SlotRecordInstruction, WeakColumn,
 };
+use crate::source_util::create_pcm_source_from_api_source;
 use crate::timeline::clip_timeline;
-use crate::{rt, ClipEngineResult, HybridTimeline};
+use crate::{rt, ClipEngineResult, HybridTimeline, Timeline};
 use crossbeam_channel::{Receiver, Sender};
 use helgoboss_learn::UnitValue;
 use helgoboss_midi::Channel;
 use playtime_api as api;
 use playtime_api::{
-    ChannelRange, Db, MatrixClipPlayAudioSettings, MatrixClipPlaySettings,
+    ChannelRange, ClipColor, Db, MatrixClipPlayAudioSettings, MatrixClipPlaySettings,
     MatrixClipRecordSettings, TempoRange,
 };
 use reaper_high::{OrCurrentProject, Project, Reaper, Track};
-use reaper_medium::{Bpm, MidiInputDeviceId, PositionInSeconds};
+use reaper_medium::{Bpm, MidiInputDeviceId, PositionInSeconds, RgbColor};
+use std::path::Path;
+use std::sync::atomic::{AtomicIsize, Ordering};
+use std::sync::{Arc, Weak};
 use std::thread::JoinHandle;
-use std::{cmp, thread};
+use std::{cmp, mem, thread};
 
 #[derive(Debug)]
 pub struct Matrix<H> {
@@ -38,6 +45,12 @@ pub struct Matrix<H> {
     containing_track: Option<Track>,
     command_receiver: Receiver<MatrixCommand>,
     rt_command_sender: Sender<rt::MatrixCommand>,
+    tempo_detection_request_sender: Sender<TempoDetectionRequest>,
+    /// Detected tempos which wait for their clip to be stopped.
+    pending_detected_tempos: Vec<TempoDetectionResponse>,
+    arrangement_recorder: ArrangementRecorder,
+    /// Set while detecting which column receives input first.
+    input_detection: Option<InputDetection>,
     // We use this just for RAII (joining worker threads when dropped)
     _worker_pool: WorkerPool,
 }
@@ -46,12 +59,17 @@ pub struct Matrix<H> {
 pub struct MatrixSettings {
     pub common_tempo_range: TempoRange,
     pub clip_record_settings: MatrixClipRecordSettings,
+    pub audio_cache_budget_in_mb: Option<u32>,
     pub overridable: OverridableMatrixSettings,
 }
 
 #[derive(Debug)]
 pub enum MatrixCommand {
     ThrowAway(ColumnHandle),
+    ApplyDetectedTempo(TempoDetectionResponse),
+    /// Sent by the real-time matrix because preparing a recording is only possible in the main
+    /// thread.
+    RecordClip(ClipSlotCoordinates, usize),
 }
 
 pub trait MainMatrixCommandSender {
@@ -110,6 +128,8 @@ impl<H: ClipMatrixHandler> Matrix<H> {
             crossbeam_channel::bounded(500);
         let (rt_command_sender, rt_command_receiver) = crossbeam_channel::bounded(500);
         let (main_command_sender, main_command_receiver) = crossbeam_channel::bounded(500);
+        let (tempo_detection_request_sender, tempo_detection_request_receiver) =
+            crossbeam_channel::bounded(500);
         let mut worker_pool = WorkerPool::default();
         worker_pool.add_worker("Playtime recording worker", move || {
             keep_processing_recorder_requests(recorder_request_receiver);
@@ -123,6 +143,13 @@ impl<H: ClipMatrixHandler> Matrix<H> {
                 ChainPreBufferCommandProcessor,
             );
         });
+        let tempo_detection_command_sender = main_command_sender.clone();
+        worker_pool.add_worker("Playtime tempo detection worker", move || {
+            keep_processing_tempo_detection_requests(
+                tempo_detection_request_receiver,
+                tempo_detection_command_sender,
+            );
+        });
         let project = containing_track.as_ref().map(|t| t.project());
         let rt_matrix = rt::Matrix::new(rt_command_receiver, main_command_sender, project);
         Self {
@@ -139,6 +166,10 @@ impl<H: ClipMatrixHandler> Matrix<H> {
             containing_track,
             command_receiver: main_command_receiver,
             rt_command_sender,
+            tempo_detection_request_sender,
+            pending_detected_tempos: vec![],
+            arrangement_recorder: Default::default(),
+            input_detection: None,
             _worker_pool: worker_pool,
         }
     }
@@ -160,6 +191,12 @@ impl<H: ClipMatrixHandler> Matrix<H> {
             .time_stretch_mode;
         self.settings.overridable.audio_cache_behavior =
             api_matrix.clip_play_settings.audio_settings.cache_behavior;
+        self.set_audio_cache_budget(
+            api_matrix
+                .clip_play_settings
+                .audio_settings
+                .cache_budget_in_mb,
+        );
         self.settings.clip_record_settings = api_matrix.clip_record_settings;
         // Real-time settings
         self.settings.overridable.clip_play_start_timing =
@@ -209,6 +246,7 @@ impl<H: ClipMatrixHandler> Matrix<H> {
                     resample_mode: self.settings.overridable.audio_resample_mode,
                     time_stretch_mode: self.settings.overridable.audio_time_stretch_mode,
                     cache_behavior: self.settings.overridable.audio_cache_behavior,
+                    cache_budget_in_mb: self.settings.audio_cache_budget_in_mb,
                 },
             },
             clip_record_settings: self.settings.clip_record_settings,
@@ -216,6 +254,16 @@ impl<H: ClipMatrixHandler> Matrix<H> {
         }
     }
 
+    /// Limits the memory used for caching audio material in memory (`None` means no limit).
+    pub fn set_audio_cache_budget(&mut self, budget_in_mb: Option<u32>) {
+        self.settings.audio_cache_budget_in_mb = budget_in_mb;
+        let budget_in_bytes = budget_in_mb.map(|mb| mb as usize * 1024 * 1024);
+        self.chain_equipment
+            .cache_request_sender
+            .try_send(CacheRequest::SetBudget(budget_in_bytes))
+            .unwrap();
+    }
+
     fn permanent_project(&self) -> Option<Project> {
         self.containing_track.as_ref().map(|t| t.project())
     }
@@ -269,9 +317,126 @@ impl<H: ClipMatrixHandler> Matrix<H> {
             &self.chain_equipment,
             &self.recorder_request_sender,
             &self.settings,
+        )?;
+        self.request_tempo_detection_if_possible(coordinates);
+        Ok(())
+    }
+
+    pub fn fill_slot_with_file(
+        &mut self,
+        coordinates: ClipSlotCoordinates,
+        file: &Path,
+    ) -> ClipEngineResult<()> {
+        let column = get_column_mut(&mut self.columns, coordinates.column)?;
+        column.fill_slot_with_file(
+            coordinates.row,
+            file,
+            &self.chain_equipment,
+            &self.recorder_request_sender,
+            &self.settings,
+        )?;
+        self.request_tempo_detection_if_possible(coordinates);
+        Ok(())
+    }
+
+    /// The slot is filled already, so failing to detect the tempo is not a reason to fail. The
+    /// clip just keeps time base "Time".
+    fn request_tempo_detection_if_possible(&self, coordinates: ClipSlotCoordinates) {
+        if let Err(e) = self.request_tempo_detection(coordinates) {
+            debug!("Couldn't request tempo detection: {}", e);
+        }
+    }
+
+    /// Analyzes the audio clip in the given slot in the background in order to let it follow the
+    /// project tempo. Doesn't do anything for MIDI clips because they have a tempo already.
+    fn request_tempo_detection(&self, coordinates: ClipSlotCoordinates) -> ClipEngineResult<()> {
+        let column = get_column(&self.columns, coordinates.column)?;
+        let api_source = column.clip_api_source(coordinates.row)?.clone();
+        if matches!(api_source, api::Source::MidiChunk(_)) {
+            return Ok(());
+        }
+        let permanent_project = self.permanent_project();
+        let pcm_source = create_pcm_source_from_api_source(&api_source, permanent_project)?;
+        if rt::source_util::pcm_source_is_midi(&pcm_source) {
+            return Ok(());
+        }
+        let request = TempoDetectionRequest {
+            slot_coordinates: coordinates,
+            api_source,
+            pcm_source,
+            tempo_range: self.settings.common_tempo_range,
+            time_signature: project_time_signature(permanent_project),
+        };
+        self.tempo_detection_request_sender
+            .try_send(request)
+            .map_err(|_| "couldn't request tempo detection")
+    }
+
+    fn apply_detected_tempo(&mut self, response: TempoDetectionResponse) -> ClipEngineResult<()> {
+        let coordinates = response.slot_coordinates;
+        let column = get_column_mut(&mut self.columns, coordinates.column)?;
+        column.apply_detected_tempo(
+            coordinates.row,
+            &response.api_source,
+            response.beat_time_base,
+            &self.chain_equipment,
+            &self.recorder_request_sender,
+            &self.settings,
         )
     }
 
+    /// Creates a media item on the column track at the play or edit cursor position.
+    pub fn export_slot_to_item(&self, coordinates: ClipSlotCoordinates) -> ClipEngineResult<()> {
+        let project = self.permanent_project().or_current_project();
+        let column = get_column(&self.columns, coordinates.column)?;
+        column.export_slot_to_item(
+            coordinates.row,
+            project.play_or_edit_cursor_position(),
+            None,
+        )?;
+        Reaper::get().medium_reaper().update_arrange();
+        Ok(())
+    }
+
+    pub fn is_recording_arrangement(&self) -> bool {
+        self.arrangement_recorder.is_recording()
+    }
+
+    /// Starts logging clip starts and stops with their timeline positions.
+    pub fn start_arrangement_recording(&mut self) {
+        self.arrangement_recorder.start();
+    }
+
+    pub fn stop_arrangement_recording(&mut self) {
+        let timeline_pos = self.timeline().cursor_pos();
+        self.arrangement_recorder.stop(timeline_pos);
+    }
+
+    /// Writes the last arrangement recording as media items onto the column tracks.
+    ///
+    /// Uses the current slot contents. Entries whose slot has been cleared in the meantime are
+    /// skipped.
+    pub fn write_arrangement_to_timeline(&self) -> ClipEngineResult<()> {
+        if self.arrangement_recorder.is_recording() {
+            return Err("arrangement recording still in progress");
+        }
+        let entries = self.arrangement_recorder.entries();
+        if entries.is_empty() {
+            return Err("no arrangement recorded");
+        }
+        for entry in entries {
+            let coordinates = entry.slot_coordinates;
+            let result = get_column(&self.columns, coordinates.column).and_then(|column| {
+                column.export_slot_to_item(coordinates.row, entry.start_pos, Some(entry.length))
+            });
+            if let Err(e) = result {
+                debug!("Couldn't write arrangement entry {:?}: {}", entry, e);
+            }
+        }
+        Reaper::get().medium_reaper().update_arrange();
+        Ok(())
+    }
+
     pub fn play_clip(&self, coordinates: ClipSlotCoordinates) -> ClipEngineResult<()> {
         let timeline = self.timeline();
         let column = get_column(&self.columns, coordinates.column)?;
@@ -279,11 +444,25 @@ impl<H: ClipMatrixHandler> Matrix<H> {
             slot_index: coordinates.row,
             timeline,
             ref_pos: None,
+            tempo: None,
+            frame_offset: 0,
         };
         column.play_clip(args);
         Ok(())
     }
 
+    /// Quantizes the notes of the given in-project MIDI clip as soon as it's stopped.
+    pub fn quantize_clip(&mut self, coordinates: ClipSlotCoordinates) -> ClipEngineResult<()> {
+        let column = get_column_mut(&mut self.columns, coordinates.column)?;
+        column.quantize_clip(coordinates.row)
+    }
+
+    /// Restores the audio material of the given clip as it was before the last overdub layer.
+    pub fn undo_audio_overdub(&mut self, coordinates: ClipSlotCoordinates) -> ClipEngineResult<()> {
+        let column = get_column_mut(&mut self.columns, coordinates.column)?;
+        column.undo_audio_overdub(coordinates.row)
+    }
+
     pub fn stop_clip(&self, coordinates: ClipSlotCoordinates) -> ClipEngineResult<()> {
         let timeline = self.timeline();
         let column = get_column(&self.columns, coordinates.column)?;
@@ -305,13 +484,49 @@ impl<H: ClipMatrixHandler> Matrix<H> {
         while let Ok(task) = self.command_receiver.try_recv() {
             match task {
                 MatrixCommand::ThrowAway(_) => {}
+                MatrixCommand::ApplyDetectedTempo(response) => {
+                    self.pending_detected_tempos.push(response);
+                }
+                MatrixCommand::RecordClip(coordinates, frame_offset) => {
+                    if let Err(e) = self.record_clip(coordinates, frame_offset) {
+                        debug!("Couldn't record clip requested in real-time: {}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Applies detected tempos as soon as the corresponding clip is stopped.
+    fn apply_pending_detected_tempos(&mut self) {
+        if self.pending_detected_tempos.is_empty() {
+            return;
+        }
+        for response in mem::take(&mut self.pending_detected_tempos) {
+            match self.clip_play_state(response.slot_coordinates) {
+                Ok(ClipPlayState::Stopped) => {
+                    if let Err(e) = self.apply_detected_tempo(response) {
+                        debug!("Couldn't apply detected tempo: {}", e);
+                    }
+                }
+                Ok(_) => self.pending_detected_tempos.push(response),
+                Err(e) => debug!("Couldn't apply detected tempo: {}", e),
             }
         }
     }
 
     pub fn poll(&mut self, timeline_tempo: Bpm) -> Vec<ClipMatrixEvent> {
         self.process_commands();
-        self.columns
+        self.apply_pending_detected_tempos();
+        for column in &mut self.columns {
+            column.apply_pending_quantizations(
+                &self.chain_equipment,
+                &self.recorder_request_sender,
+                &self.settings,
+            );
+        }
+        self.process_input_detection();
+        let events: Vec<_> = self
+            .columns
             .iter_mut()
             .enumerate()
             .flat_map(|(column_index, column)| {
@@ -325,7 +540,24 @@ impl<H: ClipMatrixHandler> Matrix<H> {
                         })
                     })
             })
-            .collect()
+            .collect();
+        if self.arrangement_recorder.is_recording() {
+            let timeline_pos = self.timeline().cursor_pos();
+            for event in &events {
+                if let ClipMatrixEvent::ClipChanged(QualifiedClipChangedEvent {
+                    slot_coordinates,
+                    event: ClipChangedEvent::PlayState(play_state),
+                }) = event
+                {
+                    self.arrangement_recorder.process_play_state_change(
+                        *slot_coordinates,
+                        *play_state,
+                        timeline_pos,
+                    );
+                }
+            }
+        }
+        events
     }
 
     pub fn toggle_looped(&mut self, coordinates: ClipSlotCoordinates) -> ClipEngineResult<()> {
@@ -357,6 +589,11 @@ impl<H: ClipMatrixHandler> Matrix<H> {
         get_column(&self.columns, coordinates.column())?.clip_looped(coordinates.row())
     }
 
+    /// Returns whether any slot in this matrix is filled or being recorded.
+    pub fn has_clips(&self) -> bool {
+        self.columns.iter().any(|c| c.has_clips())
+    }
+
     pub fn column_count(&self) -> usize {
         self.columns.len()
     }
@@ -375,18 +612,182 @@ impl<H: ClipMatrixHandler> Matrix<H> {
         get_column(&self.columns, coordinates.column())?.clip_volume(coordinates.row())
     }
 
-    pub fn record_clip(&mut self, coordinates: ClipSlotCoordinates) -> ClipEngineResult<()> {
+    /// Returns the resolved color of the given clip.
+    ///
+    /// `None` means the clip doesn't have a specific color (inherits a track color which is not
+    /// set).
+    pub fn clip_color(
+        &self,
+        coordinates: ClipSlotCoordinates,
+    ) -> ClipEngineResult<Option<RgbColor>> {
+        get_column(&self.columns, coordinates.column())?.clip_color(coordinates.row())
+    }
+
+    pub fn set_clip_color(
+        &mut self,
+        coordinates: ClipSlotCoordinates,
+        color: ClipColor,
+    ) -> ClipEngineResult<()> {
+        let event = get_column_mut(&mut self.columns, coordinates.column())?
+            .set_clip_color(coordinates.row(), color)?;
+        let event = ClipMatrixEvent::ClipChanged(QualifiedClipChangedEvent {
+            slot_coordinates: coordinates,
+            event,
+        });
+        self.handler.emit_event(event);
+        Ok(())
+    }
+
+    /// Records a clip in the given slot.
+    ///
+    /// The frame offset is the offset within the audio block at which the recording should start
+    /// if its start timing is immediate (usually the offset of the triggering MIDI event). Pass 0
+    /// if unknown.
+    pub fn record_clip(
+        &mut self,
+        coordinates: ClipSlotCoordinates,
+        frame_offset: usize,
+    ) -> ClipEngineResult<()> {
+        let mut record_settings = self.settings.clip_record_settings;
+        // Only the first clip in the matrix is allowed to lead the tempo.
+        record_settings.lead_tempo &= !self.has_clips();
         get_column_mut(&mut self.columns, coordinates.column())?.record_clip(
             coordinates.row(),
-            &self.settings.clip_record_settings,
+            &record_settings,
             &self.chain_equipment,
             &self.recorder_request_sender,
             &self.handler,
             self.containing_track.as_ref(),
             &self.settings.overridable,
+            frame_offset,
         )
     }
 
+    /// Starts detecting which column receives input first and records into the first empty slot
+    /// of that column as soon as detected (global record).
+    ///
+    /// Only columns which record from their track input are considered. Whether MIDI and/or audio
+    /// columns are considered depends on the `detect_input` record settings. The recordings are
+    /// prepared in advance and started by the real-time thread right in the audio block in which
+    /// input has been detected, so the input which triggered the detection is recorded as well.
+    pub fn start_input_detection(&mut self) -> ClipEngineResult<()> {
+        self.cancel_input_detection();
+        let mut record_settings = self.settings.clip_record_settings;
+        // Only the first clip in the matrix is allowed to lead the tempo.
+        record_settings.lead_tempo &= !self.has_clips();
+        let mut candidates = vec![];
+        for (column_index, column) in self.columns.iter_mut().enumerate() {
+            let detection_enabled = match column.detectable_record_input() {
+                Ok(ClipRecordHardwareInput::Midi(_)) => record_settings.midi_settings.detect_input,
+                Ok(ClipRecordHardwareInput::Audio(_)) => {
+                    record_settings.audio_settings.detect_input
+                }
+                Err(e) => {
+                    debug!(
+                        "Column {} not eligible for input detection: {}",
+                        column_index, e
+                    );
+                    false
+                }
+            };
+            if !detection_enabled {
+                continue;
+            }
+            let result = column.prepare_input_detection_candidate(
+                column_index,
+                &record_settings,
+                &self.chain_equipment,
+                &self.recorder_request_sender,
+                self.containing_track.as_ref(),
+                &self.settings.overridable,
+            );
+            match result {
+                Ok(c) => candidates.push(c),
+                Err(e) => debug!(
+                    "Couldn't prepare recording in column {} for input detection: {}",
+                    column_index, e
+                ),
+            }
+        }
+        if candidates.is_empty() {
+            return Err("no column is eligible for input detection");
+        }
+        let threshold_db = record_settings
+            .audio_settings
+            .detect_input_threshold
+            .map(|t| t.get())
+            .unwrap_or(DEFAULT_INPUT_DETECTION_THRESHOLD_DB);
+        let state = Arc::new(AtomicIsize::new(INPUT_DETECTION_RUNNING));
+        let input_detection = InputDetection {
+            state: state.clone(),
+            reserved_slots: candidates
+                .iter()
+                .map(|c| ClipSlotCoordinates::new(c.column_index, c.destination.slot_index))
+                .collect(),
+        };
+        let task = InputDetectionTask {
+            candidates,
+            audio_threshold: 10f64.powf(threshold_db / 20.0),
+            state: Arc::downgrade(&state),
+        };
+        self.handler.request_input_detection(task);
+        self.input_detection = Some(input_detection);
+        Ok(())
+    }
+
+    /// Stops detecting input without recording anything.
+    ///
+    /// If the real-time thread has detected input already, the recording goes on.
+    pub fn cancel_input_detection(&mut self) {
+        let input_detection = match self.input_detection.take() {
+            None => return,
+            Some(d) => d,
+        };
+        let detected_column = input_detection
+            .state
+            .compare_exchange(
+                INPUT_DETECTION_RUNNING,
+                INPUT_DETECTION_CANCELLED,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .err()
+            .map(|column_index| column_index as usize);
+        self.finish_input_detection(input_detection, detected_column);
+    }
+
+    pub fn is_detecting_input(&self) -> bool {
+        self.input_detection.is_some()
+    }
+
+    fn process_input_detection(&mut self) {
+        let detected_column = match &self.input_detection {
+            None => return,
+            Some(d) => d.state.load(Ordering::Acquire),
+        };
+        if detected_column < 0 {
+            return;
+        }
+        let input_detection = self.input_detection.take().unwrap();
+        self.finish_input_detection(input_detection, Some(detected_column as usize));
+    }
+
+    /// Releases all slots which have been reserved for input detection, except the one whose
+    /// recording has been started.
+    fn finish_input_detection(
+        &mut self,
+        input_detection: InputDetection,
+        detected_column: Option<usize>,
+    ) {
+        for coordinates in input_detection.reserved_slots {
+            if Some(coordinates.column()) == detected_column {
+                debug!("Detected input in column {}", coordinates.column());
+            } else if let Ok(column) = get_column_mut(&mut self.columns, coordinates.column()) {
+                column.cancel_prepared_recording(coordinates.row());
+            }
+        }
+    }
+
     pub fn pause_clip_legacy(&self, coordinates: ClipSlotCoordinates) -> ClipEngineResult<()> {
         get_column(&self.columns, coordinates.column())?.pause_clip(coordinates.row());
         Ok(())
@@ -429,7 +830,7 @@ fn get_column_mut(columns: &mut [Column], index: usize) -> ClipEngineResult<&mut
 
 const NO_SUCH_COLUMN: &str = "no such column";
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
 pub struct ClipSlotCoordinates {
     column: usize,
     row: usize,
@@ -455,19 +856,87 @@ pub struct ClipRecordTask {
     pub destination: ClipRecordDestination,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct ClipRecordDestination {
     pub column_source: WeakColumn,
     pub slot_index: usize,
-    /// If this is set, it's important to write the MIDI events during the *post* phase of the audio
-    /// callback, otherwise the written MIDI events would be played back a moment later, which
-    /// would result in duplicated note playback during recording.
+    /// If this is set (MIDI or audio overdub), it's important to write the material during the
+    /// *post* phase of the audio callback, otherwise the written material would be played back a
+    /// moment later, which would result in duplicated playback during recording.
     ///
     /// If this is not set, it's important to write it in the *pre* phase because we don't want
     /// to miss playing back any material when we change back from recording to ready.
-    pub is_midi_overdub: bool,
+    pub is_overdub: bool,
+    /// If this is set (MIDI replace), blocks without any MIDI events must be written as well
+    /// because that's what erases the existing events.
+    pub is_midi_replace: bool,
+}
+
+/// Main-thread side of a running input detection.
+#[derive(Debug)]
+struct InputDetection {
+    state: Arc<AtomicIsize>,
+    reserved_slots: Vec<ClipSlotCoordinates>,
+}
+
+/// Used for detecting which column receives input first.
+#[derive(Debug)]
+pub struct InputDetectionTask {
+    pub candidates: Vec<InputDetectionCandidate>,
+    /// Linear audio level above which a column is considered as receiving input.
+    pub audio_threshold: f64,
+    /// Contains the index of the detected column, [`INPUT_DETECTION_RUNNING`] or
+    /// [`INPUT_DETECTION_CANCELLED`].
+    state: Weak<AtomicIsize>,
 }
 
+/// A column which is monitored during input detection, together with the recording which has
+/// been prepared for it.
+#[derive(Debug)]
+pub struct InputDetectionCandidate {
+    pub column_index: usize,
+    pub input: ClipRecordHardwareInput,
+    pub destination: ClipRecordDestination,
+    /// Taken as soon as input is detected in this column.
+    pub instruction: Option<SlotRecordInstruction>,
+}
+
+impl InputDetectionTask {
+    /// Returns `false` if the detection has been cancelled or has detected something already.
+    pub fn is_still_relevant(&self) -> bool {
+        match self.state.upgrade() {
+            None => false,
+            Some(s) => s.load(Ordering::Acquire) == INPUT_DETECTION_RUNNING,
+        }
+    }
+
+    /// Marks the candidate at the given index as detected and hands out its record instruction.
+    ///
+    /// Returns `None` if the detection has been cancelled in the meantime. In this case, the main
+    /// thread releases the reserved slot, so the recording must not be started.
+    pub fn claim_candidate(
+        &mut self,
+        candidate_index: usize,
+    ) -> Option<(&InputDetectionCandidate, SlotRecordInstruction)> {
+        let state = self.state.upgrade()?;
+        let candidate = self.candidates.get_mut(candidate_index)?;
+        state
+            .compare_exchange(
+                INPUT_DETECTION_RUNNING,
+                candidate.column_index as isize,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .ok()?;
+        let instruction = candidate.instruction.take()?;
+        Some((candidate, instruction))
+    }
+}
+
+const INPUT_DETECTION_RUNNING: isize = -1;
+const INPUT_DETECTION_CANCELLED: isize = -2;
+const DEFAULT_INPUT_DETECTION_THRESHOLD_DB: f64 = -40.0;
+
 #[derive(Debug)]
 pub enum ClipRecordInput {
     HardwareInput(ClipRecordHardwareInput),
@@ -479,18 +948,12 @@ impl ClipRecordInput {
     pub fn create_recording_equipment(
         &self,
         project: Option<Project>,
-        auto_quantize_midi: bool,
     ) -> ClipEngineResult<RecordingEquipment> {
         use ClipRecordInput::*;
         match &self {
             HardwareInput(ClipRecordHardwareInput::Midi(_)) => {
-                let quantization_settings = if auto_quantize_midi {
-                    // TODO-high Use project quantization settings
-                    Some(QuantizationSettings {})
-                } else {
-                    None
-                };
-                let equipment = MidiRecordingEquipment::new(quantization_settings);
+                // Auto-quantization of new recordings happens when the recording is committed.
+                let equipment = MidiRecordingEquipment::new(None);
                 Ok(RecordingEquipment::Midi(equipment))
             }
             HardwareInput(ClipRecordHardwareInput::Audio(virtual_input))
@@ -508,13 +971,13 @@ impl ClipRecordInput {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum ClipRecordHardwareInput {
     Midi(VirtualClipRecordHardwareMidiInput),
     Audio(VirtualClipRecordAudioInput),
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum VirtualClipRecordHardwareMidiInput {
     Specific(ClipRecordHardwareMidiInput),
     Detect,
@@ -526,7 +989,7 @@ pub struct ClipRecordHardwareMidiInput {
     pub channel: Option<Channel>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum VirtualClipRecordAudioInput {
     Specific(ChannelRange),
     Detect { channel_count: u32 },
@@ -544,6 +1007,7 @@ impl VirtualClipRecordAudioInput {
 
 pub trait ClipMatrixHandler {
     fn request_recording_input(&self, task: ClipRecordTask);
+    fn request_input_detection(&self, task: InputDetectionTask);
     fn emit_event(&self, event: ClipMatrixEvent);
 }
 
@@ -0,0 +1,419 @@
+use crate::ClipEngineResult;
+use playtime_api as api;
+use playtime_api::MidiQuantizationSettings;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::mem;
+
+/// Returns a copy of the given in-project MIDI source with quantized notes.
+///
+/// Doesn't touch any existing PCM source. The caller is responsible for replacing the clip.
+pub fn quantize_midi_api_source(
+    source: &api::Source,
+    settings: &MidiQuantizationSettings,
+) -> ClipEngineResult<api::Source> {
+    match source {
+        api::Source::MidiChunk(s) => {
+            let quantized_source = api::MidiChunkSource {
+                chunk: quantize_midi_chunk(&s.chunk, settings)?,
+            };
+            Ok(api::Source::MidiChunk(quantized_source))
+        }
+        api::Source::File(_) => Err("only in-project MIDI clips can be quantized"),
+    }
+}
+
+/// Quantizes the notes contained in the given REAPER MIDI chunk (contents of `<SOURCE MIDI`).
+///
+/// Non-note events stay where they are. The last event marks the end of the source and is never
+/// exceeded.
+pub fn quantize_midi_chunk(
+    chunk: &str,
+    settings: &MidiQuantizationSettings,
+) -> ClipEngineResult<String> {
+    check_unit_range(
+        settings.strength,
+        "quantization strength must be between 0.0 and 1.0",
+    )?;
+    check_unit_range(settings.swing, "swing must be between 0.0 and 1.0")?;
+    check_unit_range(settings.humanize, "humanize must be between 0.0 and 1.0")?;
+    let mut parsed = ParsedChunk::parse(chunk)?;
+    let grid_in_ticks = settings.grid.numerator() as f64 * 4.0 * parsed.ticks_per_quarter_note
+        / settings.grid.denominator() as f64;
+    let end_tick = parsed.events.iter().map(|e| e.tick).max().unwrap_or(0);
+    let quantize = |tick: u64| -> u64 {
+        let grid_index = (tick as f64 / grid_in_ticks).round();
+        let is_off_beat = grid_index as u64 % 2 == 1;
+        let swing_offset = if is_off_beat {
+            settings.swing * grid_in_ticks / 2.0
+        } else {
+            0.0
+        };
+        let target = grid_index * grid_in_ticks + swing_offset;
+        let moved = tick as f64 + settings.strength * (target - tick as f64);
+        moved.round().max(0.0) as u64
+    };
+    let max_humanize_offset = settings.humanize * grid_in_ticks / 2.0;
+    let mut rng = ChaCha8Rng::seed_from_u64(settings.humanize_seed);
+    let mut events = mem::take(&mut parsed.events);
+    for (note_on_index, note_off_index) in find_notes(&events) {
+        let start = events[note_on_index].tick;
+        let end = events[note_off_index].tick;
+        let mut new_start = quantize(start);
+        if max_humanize_offset > 0.0 {
+            let offset = rng.gen_range(-max_humanize_offset..=max_humanize_offset);
+            new_start = (new_start as f64 + offset).round().max(0.0) as u64;
+        }
+        if new_start >= end_tick {
+            // Belongs to the beginning of the next loop cycle.
+            new_start = 0;
+        }
+        let new_end = if settings.quantize_note_ends {
+            let quantized_end = quantize(end);
+            if quantized_end > new_start {
+                quantized_end
+            } else {
+                new_start + (end - start).max(1)
+            }
+        } else {
+            new_start + (end - start)
+        };
+        events[note_on_index].tick = new_start;
+        events[note_off_index].tick = new_end.min(end_tick);
+    }
+    // Stable sort, so events at the same position keep their order. Note-offs come before
+    // note-ons in order to not cut off a repeated note.
+    events.sort_by_key(|e| (e.tick, e.kind.sort_priority()));
+    Ok(parsed.render(&events))
+}
+
+fn check_unit_range(value: f64, error_msg: &'static str) -> ClipEngineResult<()> {
+    if (0.0..=1.0).contains(&value) {
+        Ok(())
+    } else {
+        Err(error_msg)
+    }
+}
+
+/// Event lines start with "E" (normal), "X" (non-short message) or their lower-case variants
+/// (selected), optionally followed by "m" (muted).
+fn is_event_type(token: &str) -> bool {
+    let mut chars = token.chars();
+    matches!(chars.next(), Some('E' | 'e' | 'X' | 'x')) && chars.all(|c| c == 'm')
+}
+
+/// Returns pairs of note-on and corresponding note-off event indexes.
+fn find_notes(events: &[Event]) -> Vec<(usize, usize)> {
+    let mut open_notes: Vec<(u8, u8, usize)> = vec![];
+    let mut notes = vec![];
+    for (i, event) in events.iter().enumerate() {
+        match event.kind {
+            EventKind::NoteOn { channel, key } => open_notes.push((channel, key, i)),
+            EventKind::NoteOff { channel, key } => {
+                if let Some(pos) = open_notes
+                    .iter()
+                    .position(|(c, k, _)| *c == channel && *k == key)
+                {
+                    let (_, _, note_on_index) = open_notes.remove(pos);
+                    notes.push((note_on_index, i));
+                }
+            }
+            EventKind::Other => {}
+        }
+    }
+    notes
+}
+
+struct ParsedChunk<'a> {
+    ticks_per_quarter_note: f64,
+    header: Vec<&'a str>,
+    events: Vec<Event<'a>>,
+    trailer: Vec<&'a str>,
+}
+
+struct Event<'a> {
+    tick: u64,
+    kind: EventKind,
+    /// Event type token (e.g. "E" or "e").
+    prefix: &'a str,
+    /// Everything after the delta (for multi-line sysex events including the following lines).
+    remainder: Vec<&'a str>,
+}
+
+#[derive(Copy, Clone)]
+enum EventKind {
+    NoteOn { channel: u8, key: u8 },
+    NoteOff { channel: u8, key: u8 },
+    Other,
+}
+
+impl EventKind {
+    fn sort_priority(&self) -> u8 {
+        match self {
+            EventKind::NoteOff { .. } => 0,
+            EventKind::Other => 1,
+            EventKind::NoteOn { .. } => 2,
+        }
+    }
+
+    fn from_short_message(status: u8, data_1: u8, data_2: u8) -> Self {
+        let channel = status & 0x0f;
+        match status & 0xf0 {
+            0x90 if data_2 > 0 => EventKind::NoteOn {
+                channel,
+                key: data_1,
+            },
+            0x80 | 0x90 => EventKind::NoteOff {
+                channel,
+                key: data_1,
+            },
+            _ => EventKind::Other,
+        }
+    }
+}
+
+impl<'a> ParsedChunk<'a> {
+    fn parse(chunk: &'a str) -> ClipEngineResult<Self> {
+        let mut ticks_per_quarter_note = None;
+        let mut header = vec![];
+        let mut events: Vec<Event> = vec![];
+        let mut trailer = vec![];
+        let mut current_tick = 0;
+        let mut lines = chunk.lines();
+        while let Some(line) = lines.next() {
+            let mut tokens = line.split_whitespace();
+            let first_token = tokens.next().unwrap_or_default();
+            if first_token == "HASDATA" {
+                let ppq: f64 = tokens
+                    .nth(1)
+                    .and_then(|t| t.parse().ok())
+                    .ok_or("couldn't parse MIDI resolution")?;
+                ticks_per_quarter_note = Some(ppq);
+            }
+            let is_block = first_token.starts_with('<');
+            let event_type = first_token.trim_start_matches('<');
+            if !is_event_type(event_type) {
+                if events.is_empty() {
+                    header.push(line);
+                } else {
+                    trailer.push(line);
+                }
+                continue;
+            }
+            let delta: u64 = tokens
+                .next()
+                .and_then(|t| t.parse().ok())
+                .ok_or("couldn't parse MIDI event delta")?;
+            current_tick += delta;
+            let data: Vec<_> = tokens.collect();
+            let kind = if event_type.starts_with(|c| c == 'E' || c == 'e') && data.len() >= 3 {
+                let parse_byte = |t: &str| u8::from_str_radix(t, 16).ok();
+                match (
+                    parse_byte(data[0]),
+                    parse_byte(data[1]),
+                    parse_byte(data[2]),
+                ) {
+                    (Some(s), Some(d1), Some(d2)) => EventKind::from_short_message(s, d1, d2),
+                    _ => EventKind::Other,
+                }
+            } else {
+                EventKind::Other
+            };
+            let mut remainder = vec![line
+                .trim_start()
+                .splitn(3, char::is_whitespace)
+                .nth(2)
+                .unwrap_or_default()];
+            if is_block {
+                // Take over the block contents unchanged (including the closing line).
+                for block_line in lines.by_ref() {
+                    remainder.push(block_line);
+                    if block_line.trim() == ">" {
+                        break;
+                    }
+                }
+            }
+            let event = Event {
+                tick: current_tick,
+                kind,
+                prefix: if is_block { first_token } else { event_type },
+                remainder,
+            };
+            events.push(event);
+        }
+        let parsed = Self {
+            ticks_per_quarter_note: ticks_per_quarter_note.ok_or("MIDI chunk has no data")?,
+            header,
+            events,
+            trailer,
+        };
+        Ok(parsed)
+    }
+
+    fn render(&self, events: &[Event]) -> String {
+        let mut lines: Vec<String> = self.header.iter().map(|l| l.to_string()).collect();
+        let mut previous_tick = 0;
+        for event in events {
+            let delta = event.tick - previous_tick;
+            previous_tick = event.tick;
+            let (first_line, other_lines) = event.remainder.split_first().unwrap();
+            lines.push(format!("{} {} {}", event.prefix, delta, first_line));
+            lines.extend(other_lines.iter().map(|l| l.to_string()));
+        }
+        lines.extend(self.trailer.iter().map(|l| l.to_string()));
+        let mut chunk = lines.join("\n");
+        chunk.push('\n');
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use playtime_api::EvenQuantization;
+
+    #[test]
+    fn quantize_note_starts() {
+        // Given
+        let chunk = "\
+HASDATA 1 960 QN
+CCINTERP 32
+E 250 90 3c 60
+E 200 80 3c 00
+e 200 b0 07 07
+E 150 90 3e 60
+E 480 80 3e 00
+E 2560 b0 7b 00
+IGNTEMPO 0 120 4 4
+";
+        let settings = MidiQuantizationSettings {
+            grid: EvenQuantization::new(1, 4).unwrap(),
+            strength: 1.0,
+            swing: 0.0,
+            quantize_note_ends: false,
+            ..Default::default()
+        };
+        // When
+        let quantized = quantize_midi_chunk(chunk, &settings).unwrap();
+        // Then
+        assert_eq!(
+            quantized,
+            "\
+HASDATA 1 960 QN
+CCINTERP 32
+E 0 90 3c 60
+E 200 80 3c 00
+e 450 b0 07 07
+E 310 90 3e 60
+E 480 80 3e 00
+E 2400 b0 7b 00
+IGNTEMPO 0 120 4 4
+"
+        );
+    }
+
+    #[test]
+    fn quantize_with_swing_and_half_strength() {
+        // Given
+        let chunk = "\
+HASDATA 1 960 QN
+E 700 90 3c 60
+E 100 80 3c 00
+E 3040 b0 7b 00
+EVTFILTER 0 -1 -1 -1 -1 0 0 0 0 -1 -1 -1 -1 0 -1 0 -1 -1
+";
+        let settings = MidiQuantizationSettings {
+            grid: EvenQuantization::new(1, 8).unwrap(),
+            strength: 0.5,
+            swing: 0.5,
+            quantize_note_ends: true,
+            ..Default::default()
+        };
+        // When
+        let quantized = quantize_midi_chunk(chunk, &settings).unwrap();
+        // Then
+        // Start 700 is closest to the second 8th (480), which is swung to 600 => 650.
+        // End 800 is closest to the third 8th (960) => 880.
+        assert_eq!(
+            quantized,
+            "\
+HASDATA 1 960 QN
+E 650 90 3c 60
+E 230 80 3c 00
+E 2960 b0 7b 00
+EVTFILTER 0 -1 -1 -1 -1 0 0 0 0 -1 -1 -1 -1 0 -1 0 -1 -1
+"
+        );
+    }
+
+    #[test]
+    fn humanize_reproducibly() {
+        // Given
+        let chunk = "\
+HASDATA 1 960 QN
+E 250 90 3c 60
+E 200 80 3c 00
+E 500 90 3e 60
+E 200 80 3e 00
+E 2690 b0 7b 00
+";
+        let settings = MidiQuantizationSettings {
+            grid: EvenQuantization::new(1, 4).unwrap(),
+            humanize: 0.5,
+            humanize_seed: 42,
+            ..Default::default()
+        };
+        let other_seed_settings = MidiQuantizationSettings {
+            humanize_seed: 43,
+            ..settings
+        };
+        // When
+        let first = quantize_midi_chunk(chunk, &settings).unwrap();
+        let second = quantize_midi_chunk(chunk, &settings).unwrap();
+        let other_seed = quantize_midi_chunk(chunk, &other_seed_settings).unwrap();
+        // Then
+        assert_eq!(first, second);
+        assert_ne!(first, other_seed);
+        // Humanize 0.5 with a grid of 960 ticks moves note starts by up to 240 ticks.
+        let note_starts: Vec<_> = ParsedChunk::parse(&first)
+            .unwrap()
+            .events
+            .iter()
+            .filter(|e| matches!(e.kind, EventKind::NoteOn { .. }))
+            .map(|e| e.tick)
+            .collect();
+        assert_eq!(note_starts.len(), 2);
+        assert!(note_starts[0] <= 240, "{:?}", note_starts);
+        assert!((720..=1200).contains(&note_starts[1]), "{:?}", note_starts);
+    }
+
+    #[test]
+    fn reject_out_of_range_settings() {
+        // Given
+        let chunk = "\
+HASDATA 1 960 QN
+E 3840 b0 7b 00
+";
+        let invalid_settings = [
+            MidiQuantizationSettings {
+                strength: 1.5,
+                ..Default::default()
+            },
+            MidiQuantizationSettings {
+                swing: -0.1,
+                ..Default::default()
+            },
+            MidiQuantizationSettings {
+                strength: f64::NAN,
+                ..Default::default()
+            },
+        ];
+        // When
+        let results: Vec<_> = invalid_settings
+            .iter()
+            .map(|settings| quantize_midi_chunk(chunk, settings))
+            .collect();
+        // Then
+        assert!(results.iter().all(|r| r.is_err()), "{:?}", results);
+    }
+}
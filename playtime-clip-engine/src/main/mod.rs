@@ -1,10 +1,16 @@
+mod arrangement;
 mod clip;
 mod column;
 mod matrix;
+mod midi_quantization;
 mod row;
 mod slot;
+mod tempo_detection;
 
+pub use arrangement::*;
 pub use clip::*;
 pub use column::*;
 pub use matrix::*;
+pub use midi_quantization::*;
 pub use slot::*;
+pub use tempo_detection::*;
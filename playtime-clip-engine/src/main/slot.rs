@@ -3,34 +3,36 @@ use crate::conversion_util::{
     convert_position_in_frames_to_seconds,
 };
 use crate::main::{
-    create_api_source_from_recorded_midi_source, Clip, ClipMatrixHandler, ClipRecordDestination,
-    ClipRecordHardwareInput, ClipRecordHardwareMidiInput, ClipRecordInput, ClipRecordTask,
-    VirtualClipRecordAudioInput, VirtualClipRecordHardwareMidiInput,
+    create_api_source_from_recorded_midi_source, quantize_midi_api_source, Clip, ClipMatrixHandler,
+    ClipRecordDestination, ClipRecordHardwareInput, ClipRecordHardwareMidiInput, ClipRecordInput,
+    ClipRecordTask, VirtualClipRecordAudioInput, VirtualClipRecordHardwareMidiInput,
 };
 use crate::rt::supplier::{
-    ChainEquipment, MaterialInfo, MidiOverdubSettings, QuantizationSettings, Recorder,
-    RecorderRequest, RecordingArgs, RecordingEquipment, SupplierChain,
+    AudioOverdubEquipment, ChainEquipment, MaterialInfo, MidiOverdubSettings, QuantizationSettings,
+    Recorder, RecorderRequest, RecordingArgs, RecordingEquipment, SupplierChain,
 };
 use crate::rt::{
-    ClipChangedEvent, ClipPlayState, ClipRecordArgs, ColumnCommandSender, ColumnSetClipLoopedArgs,
-    MidiOverdubInstruction, NormalRecordingOutcome, OverridableMatrixSettings,
-    RecordNewClipInstruction, SharedColumn, SlotRecordInstruction, SlotRuntimeData,
+    AudioOverdubInstruction, ClipChangedEvent, ClipPlayState, ClipRecordArgs, ColumnCommandSender,
+    ColumnSetClipLoopedArgs, MidiOverdubInstruction, NormalRecordingOutcome,
+    OverridableMatrixSettings, RecordNewClipInstruction, SharedColumn, SlotRecordInstruction,
+    SlotRuntimeData,
 };
 use crate::source_util::create_pcm_source_from_file_based_api_source;
 use crate::{clip_timeline, rt, ClipEngineResult, HybridTimeline, Timeline};
 use crossbeam_channel::Sender;
-use helgoboss_learn::UnitValue;
+use helgoboss_learn::{UnitValue, BASE_EPSILON};
 use playtime_api as api;
 use playtime_api::{
-    ChannelRange, ColumnClipRecordSettings, Db, MatrixClipRecordSettings, MidiClipRecordMode,
-    RecordOrigin,
+    ChannelRange, ClipColor, ColumnClipRecordSettings, Db, MatrixClipRecordSettings,
+    MidiClipRecordMode, MidiQuantizationSettings, RecordOrigin,
 };
 use reaper_high::{Guid, Item, OwnedSource, Project, Reaper, Take, Track, TrackRoute};
 use reaper_medium::{
-    Bpm, CommandId, DurationInSeconds, OwnedPcmSource, PositionInSeconds, RecordingInput,
-    RequiredViewMode, SectionId, TrackArea, UiRefreshBehavior,
+    Bpm, CommandId, DurationInSeconds, ItemAttributeKey, OwnedPcmSource, PositionInSeconds,
+    RecordingInput, RequiredViewMode, SectionId, TakeAttributeKey, TrackArea, UiRefreshBehavior,
 };
 use std::mem;
+use std::path::Path;
 
 #[derive(Clone, Debug)]
 pub struct Slot {
@@ -145,38 +147,52 @@ impl Slot {
         playback_track: &Track,
         rt_column: &SharedColumn,
         column_command_sender: &ColumnCommandSender,
+        frame_offset: usize,
     ) -> ClipEngineResult<()> {
         if self.state.is_pretty_much_recording() {
             return Err("recording already");
         }
         // Check preconditions and prepare stuff.
         let project = playback_track.project();
-        let desired_midi_overdub_instruction = if let Some(content) = &self.content {
-            if content.runtime_data.play_state.is_somehow_recording() {
-                return Err("recording already according to play state");
-            }
-            use MidiClipRecordMode::*;
-            let want_midi_overdub = match matrix_record_settings.midi_settings.record_mode {
-                Normal => false,
-                Overdub | Replace => {
-                    // Only allow MIDI overdub if existing clip is a MIDI clip already.
-                    content.runtime_data.material_info.is_midi()
+        let (desired_midi_overdub_instruction, desired_audio_overdub_instruction) =
+            if let Some(content) = &self.content {
+                if content.runtime_data.play_state.is_somehow_recording() {
+                    return Err("recording already according to play state");
+                }
+                use MidiClipRecordMode::*;
+                let want_midi_overdub = match matrix_record_settings.midi_settings.record_mode {
+                    Normal => false,
+                    Overdub | Replace => {
+                        // Only allow MIDI overdub if existing clip is a MIDI clip already.
+                        content.runtime_data.material_info.is_midi()
+                    }
+                };
+                if want_midi_overdub {
+                    let instruction = create_midi_overdub_instruction(
+                        matrix_record_settings.midi_settings.record_mode,
+                        matrix_record_settings.midi_settings.auto_quantize,
+                        content.clip.api_source(),
+                        Some(project),
+                    )?;
+                    (Some(instruction), None)
+                } else if let (Some(settings), false) = (
+                    matrix_record_settings.audio_settings.overdub,
+                    content.runtime_data.material_info.is_midi(),
+                ) {
+                    // Only allow audio overdub if existing clip is an audio clip already.
+                    check_audio_overdub_speed(content, project)?;
+                    let mut source = content.clip.create_pcm_source(Some(project))?;
+                    let instruction = AudioOverdubInstruction {
+                        equipment: AudioOverdubEquipment::new(&mut source, Some(project))?,
+                        settings,
+                    };
+                    (None, Some(instruction))
+                } else {
+                    (None, None)
                 }
-            };
-            if want_midi_overdub {
-                let instruction = create_midi_overdub_instruction(
-                    matrix_record_settings.midi_settings.record_mode,
-                    matrix_record_settings.midi_settings.auto_quantize,
-                    content.clip.api_source(),
-                    Some(project),
-                )?;
-                Some(instruction)
             } else {
-                None
-            }
-        } else {
-            None
-        };
+                (None, None)
+            };
         let (common_stuff, mode_specific_stuff) = create_record_stuff(
             self.index,
             containing_track,
@@ -185,6 +201,7 @@ impl Slot {
             playback_track,
             rt_column,
             desired_midi_overdub_instruction,
+            desired_audio_overdub_instruction,
         )?;
         match mode_specific_stuff {
             ModeSpecificRecordStuff::FromScratch(from_scratch_stuff) => self.record_from_scratch(
@@ -198,6 +215,7 @@ impl Slot {
                 project,
                 common_stuff,
                 from_scratch_stuff,
+                frame_offset,
             ),
             ModeSpecificRecordStuff::MidiOverdub(midi_overdub_stuff) => self
                 .record_as_midi_overdub(
@@ -206,7 +224,83 @@ impl Slot {
                     project,
                     common_stuff,
                     midi_overdub_stuff,
+                    frame_offset,
                 ),
+            ModeSpecificRecordStuff::AudioOverdub(audio_overdub_stuff) => {
+                self.initiate_recording(
+                    column_command_sender,
+                    handler,
+                    SlotState::RequestedOverdubbing,
+                    SlotRecordInstruction::AudioOverdub(audio_overdub_stuff.instruction),
+                    common_stuff.temporary_route,
+                    common_stuff.task,
+                    frame_offset,
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Prepares recording into this empty slot without starting it.
+    ///
+    /// Used for global record with input detection. Whoever detects the input starts the
+    /// recording by passing the returned instruction to the real-time column, which acknowledges
+    /// it as usual. Until then, the slot is reserved. Call [`Self::cancel_prepared_recording`] if
+    /// the recording is not going to be started.
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare_recording(
+        &mut self,
+        matrix_record_settings: &MatrixClipRecordSettings,
+        column_record_settings: &ColumnClipRecordSettings,
+        rt_column_settings: &rt::ColumnSettings,
+        chain_equipment: &ChainEquipment,
+        recorder_request_sender: &Sender<RecorderRequest>,
+        containing_track: Option<&Track>,
+        overridable_matrix_settings: &OverridableMatrixSettings,
+        playback_track: &Track,
+        rt_column: &SharedColumn,
+    ) -> ClipEngineResult<(ClipRecordTask, SlotRecordInstruction)> {
+        if self.state.is_pretty_much_recording() {
+            return Err("recording already");
+        }
+        if self.content.is_some() {
+            return Err("slot is not empty");
+        }
+        let (common_stuff, mode_specific_stuff) = create_record_stuff(
+            self.index,
+            containing_track,
+            matrix_record_settings,
+            column_record_settings,
+            playback_track,
+            rt_column,
+            None,
+            None,
+        )?;
+        let from_scratch_stuff = match mode_specific_stuff {
+            ModeSpecificRecordStuff::FromScratch(s) => s,
+            _ => return Err("empty slot can only be recorded from scratch"),
+        };
+        let instruction = self.create_from_scratch_instruction(
+            matrix_record_settings,
+            overridable_matrix_settings,
+            rt_column_settings,
+            recorder_request_sender,
+            chain_equipment,
+            playback_track.project(),
+            from_scratch_stuff.recording_equipment,
+        )?;
+        self.state = SlotState::RequestedRecording(RequestedRecordingState {
+            pooled_midi_source: from_scratch_stuff.pooled_midi_source,
+        });
+        self.temporary_route = common_stuff.temporary_route;
+        Ok((common_stuff.task, instruction))
+    }
+
+    /// Releases this slot if it has been reserved by [`Self::prepare_recording`].
+    pub fn cancel_prepared_recording(&mut self) {
+        if let SlotState::RequestedRecording(_) = &self.state {
+            self.remove_temporary_route();
+            self.state = SlotState::Normal;
         }
     }
 
@@ -223,11 +317,49 @@ impl Slot {
         project: Project,
         common_stuff: CommonRecordStuff,
         specific_stuff: FromScratchRecordStuff,
+        frame_offset: usize,
     ) -> ClipEngineResult<()> {
-        // Build slot instruction
+        let instruction = self.create_from_scratch_instruction(
+            matrix_record_settings,
+            overridable_matrix_settings,
+            rt_column_settings,
+            recorder_request_sender,
+            chain_equipment,
+            project,
+            specific_stuff.recording_equipment,
+        )?;
+        let next_state = SlotState::RequestedRecording(RequestedRecordingState {
+            pooled_midi_source: specific_stuff.pooled_midi_source,
+        });
+        // Above code was only for checking preconditions and preparing stuff.
+        // Here we can't fail anymore, do the actual state changes and distribute tasks.
+        self.initiate_recording(
+            column_command_sender,
+            handler,
+            next_state,
+            instruction,
+            common_stuff.temporary_route,
+            common_stuff.task,
+            frame_offset,
+        );
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_from_scratch_instruction(
+        &self,
+        matrix_record_settings: &MatrixClipRecordSettings,
+        overridable_matrix_settings: &OverridableMatrixSettings,
+        rt_column_settings: &rt::ColumnSettings,
+        recorder_request_sender: &Sender<RecorderRequest>,
+        chain_equipment: &ChainEquipment,
+        project: Project,
+        recording_equipment: RecordingEquipment,
+    ) -> ClipEngineResult<SlotRecordInstruction> {
         let args = ClipRecordArgs {
-            recording_equipment: specific_stuff.recording_equipment,
+            recording_equipment,
             settings: *matrix_record_settings,
+            frame_offset: 0,
         };
         let instruction = if self.content.is_some() {
             // There's a clip already. That makes it easy because we have the clip struct
@@ -260,20 +392,7 @@ impl Slot {
             };
             SlotRecordInstruction::NewClip(new_clip_instruction)
         };
-        let next_state = SlotState::RequestedRecording(RequestedRecordingState {
-            pooled_midi_source: specific_stuff.pooled_midi_source,
-        });
-        // Above code was only for checking preconditions and preparing stuff.
-        // Here we can't fail anymore, do the actual state changes and distribute tasks.
-        self.initiate_recording(
-            column_command_sender,
-            handler,
-            next_state,
-            instruction,
-            common_stuff.temporary_route,
-            common_stuff.task,
-        );
-        Ok(())
+        Ok(instruction)
     }
 
     fn record_as_midi_overdub<H: ClipMatrixHandler>(
@@ -283,6 +402,7 @@ impl Slot {
         project: Project,
         common_stuff: CommonRecordStuff,
         specific_stuff: MidiOverdubRecordStuff,
+        frame_offset: usize,
     ) -> ClipEngineResult<()> {
         // If we had a file-based source before and now have an in-project source, make a pooled
         // copy of the in-project source.
@@ -320,10 +440,12 @@ impl Slot {
             SlotRecordInstruction::MidiOverdub(specific_stuff.instruction),
             common_stuff.temporary_route,
             common_stuff.task,
+            frame_offset,
         );
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn initiate_recording<H: ClipMatrixHandler>(
         &mut self,
         column_command_sender: &ColumnCommandSender,
@@ -332,11 +454,12 @@ impl Slot {
         instruction: SlotRecordInstruction,
         temporary_route: Option<TrackRoute>,
         task: ClipRecordTask,
+        frame_offset: usize,
     ) {
         // 1. The main slot needs to know what's going on.
         self.state = next_state;
         // 2. The real-time slot needs to be prepared.
-        column_command_sender.record_clip(self.index, instruction);
+        column_command_sender.record_clip(self.index, instruction, frame_offset);
         // 3. The context needs to deliver our input.
         handler.request_recording_input(task);
         // 4. When recording track output, we must set up a send.
@@ -350,6 +473,10 @@ impl Slot {
         }
     }
 
+    pub fn clip(&self) -> ClipEngineResult<&Clip> {
+        Ok(&self.get_content()?.clip)
+    }
+
     fn get_content(&self) -> ClipEngineResult<&Content> {
         self.content.as_ref().ok_or(SLOT_NOT_FILLED)
     }
@@ -379,6 +506,65 @@ impl Slot {
         Ok(())
     }
 
+    /// Creates a media item on the given track which plays the clip in this slot.
+    ///
+    /// If no length is given, the item will be as long as the clip (one loop cycle).
+    pub fn export_to_item(
+        &self,
+        track: &Track,
+        pos: PositionInSeconds,
+        length: Option<DurationInSeconds>,
+        timeline: &HybridTimeline,
+    ) -> ClipEngineResult<Item> {
+        let content = self.get_content()?;
+        let length = match length {
+            None => content.length_in_seconds(timeline)?,
+            Some(l) => l,
+        };
+        let source = if let Some(s) = content.pooled_midi_source.as_ref() {
+            // Not pooled. Editing the exported item shouldn't change the clip.
+            Reaper::get().with_pref_pool_midi_when_duplicating(false, || s.clone())
+        } else {
+            OwnedSource::new(content.clip.create_pcm_source(Some(track.project()))?)
+        };
+        let item = track.add_item().map_err(|e| e.message())?;
+        let take = item.add_take().map_err(|e| e.message())?;
+        take.set_source(source);
+        item.set_position(pos, UiRefreshBehavior::NoRefresh)
+            .map_err(|e| e.message())?;
+        item.set_length(length, UiRefreshBehavior::NoRefresh)
+            .map_err(|e| e.message())?;
+        let reaper = Reaper::get().medium_reaper();
+        let looped = if content.clip.looped() { 1.0 } else { 0.0 };
+        let start_offset = content.clip.section().start_pos.get();
+        unsafe {
+            reaper
+                .set_media_item_info_value(item.raw(), ItemAttributeKey::LoopSrc, looped)
+                .map_err(|e| e.message())?;
+            reaper
+                .set_media_item_take_info_value(
+                    take.raw(),
+                    TakeAttributeKey::StartOffs,
+                    start_offset,
+                )
+                .map_err(|e| e.message())?;
+        }
+        // MIDI items follow the project tempo on their own, audio items need to be stretched.
+        if !content.runtime_data.material_info.is_midi() {
+            let tempo_factor = content.tempo_factor(timeline.tempo_at(pos));
+            unsafe {
+                reaper
+                    .set_media_item_take_info_value(
+                        take.raw(),
+                        TakeAttributeKey::PlayRate,
+                        tempo_factor,
+                    )
+                    .map_err(|e| e.message())?;
+            }
+        }
+        Ok(item)
+    }
+
     pub fn stop_editing_clip(&self, temporary_project: Project) -> ClipEngineResult<()> {
         let content = self.get_content()?;
         let editor_track = find_editor_track(temporary_project).ok_or("editor track not found")?;
@@ -414,6 +600,25 @@ impl Slot {
         Ok(self.get_content()?.clip.looped())
     }
 
+    /// Returns the native tempo of the clip if it's an audio clip with beat time base.
+    pub fn clip_audio_tempo(&self) -> ClipEngineResult<Option<Bpm>> {
+        let content = self.get_content()?;
+        if content.runtime_data.material_info.is_midi() {
+            return Ok(None);
+        }
+        Ok(content.clip.tempo(false))
+    }
+
+    pub fn clip_color(&self) -> ClipEngineResult<&ClipColor> {
+        Ok(self.get_content()?.clip.color())
+    }
+
+    pub fn set_clip_color(&mut self, color: ClipColor) -> ClipEngineResult<ClipChangedEvent> {
+        let content = get_content_mut(&mut self.content)?;
+        content.clip.set_color(color.clone());
+        Ok(ClipChangedEvent::ClipColor(color))
+    }
+
     pub fn set_clip_volume(
         &mut self,
         volume: Db,
@@ -581,6 +786,61 @@ impl Slot {
             .notify_midi_overdub_finished(&OwnedSource::new(mirror_source), temporary_project)
     }
 
+    pub fn notify_audio_overdub_finished(
+        &mut self,
+        file: &Path,
+        temporary_project: Option<Project>,
+    ) -> ClipEngineResult<()> {
+        self.remove_temporary_route();
+        get_content_mut(&mut self.content)?
+            .clip
+            .notify_audio_overdub_finished(file, temporary_project);
+        Ok(())
+    }
+
+    /// Restores the audio material as it was before the last overdub layer.
+    ///
+    /// The clip switches its source only after the real-time clip confirmed the undo.
+    pub fn undo_audio_overdub(
+        &self,
+        column_command_sender: &ColumnCommandSender,
+    ) -> ClipEngineResult<()> {
+        if self.state.is_pretty_much_recording() {
+            return Err("recording");
+        }
+        if !self.get_content()?.clip.can_undo_audio_overdub() {
+            return Err("no audio overdub to undo");
+        }
+        column_command_sender.undo_audio_overdub(self.index);
+        Ok(())
+    }
+
+    pub fn notify_audio_overdub_undone(&mut self) -> ClipEngineResult<()> {
+        get_content_mut(&mut self.content)?
+            .clip
+            .notify_audio_overdub_undone()
+    }
+
+    /// Returns a copy of the in-project MIDI clip in this slot with quantized notes.
+    ///
+    /// Refuses if the slot content has changed since the quantization was requested.
+    pub fn create_quantized_clip(
+        &self,
+        requested_api_source: &api::Source,
+        settings: &MidiQuantizationSettings,
+    ) -> ClipEngineResult<Clip> {
+        if self.state.is_pretty_much_recording() {
+            return Err("recording");
+        }
+        let mut clip = self.clip()?.clone();
+        if clip.api_source() != requested_api_source {
+            return Err("slot content has changed since quantization was requested");
+        }
+        let api_source = quantize_midi_api_source(clip.api_source(), settings)?;
+        clip.update_api_source(api_source);
+        Ok(clip)
+    }
+
     pub fn slot_cleared(&mut self) -> Option<ClipChangedEvent> {
         self.content.take().map(|_| ClipChangedEvent::Removed)
     }
@@ -674,6 +934,7 @@ struct CommonRecordStuff {
 enum ModeSpecificRecordStuff {
     FromScratch(FromScratchRecordStuff),
     MidiOverdub(MidiOverdubRecordStuff),
+    AudioOverdub(AudioOverdubRecordStuff),
 }
 
 struct FromScratchRecordStuff {
@@ -685,6 +946,10 @@ struct MidiOverdubRecordStuff {
     instruction: MidiOverdubInstruction,
 }
 
+struct AudioOverdubRecordStuff {
+    instruction: AudioOverdubInstruction,
+}
+
 fn create_record_stuff(
     slot_index: usize,
     containing_track: Option<&Track>,
@@ -693,17 +958,14 @@ fn create_record_stuff(
     playback_track: &Track,
     column_source: &SharedColumn,
     desired_midi_overdub_instruction: Option<MidiOverdubInstruction>,
+    desired_audio_overdub_instruction: Option<AudioOverdubInstruction>,
 ) -> ClipEngineResult<(CommonRecordStuff, ModeSpecificRecordStuff)> {
     let (input, temporary_route) = {
         use RecordOrigin::*;
         match &column_settings.origin {
             TrackInput => {
                 debug!("Input: track input");
-                let track = resolve_recording_track(column_settings, playback_track)?;
-                let track_input = track
-                    .recording_input()
-                    .ok_or("track doesn't have any recording input")?;
-                let hw_input = translate_track_input_to_hw_input(track_input)?;
+                let hw_input = resolve_track_input(column_settings, playback_track)?;
                 (ClipRecordInput::HardwareInput(hw_input), None)
             }
             TrackAudioOutput => {
@@ -743,26 +1005,31 @@ fn create_record_stuff(
             }
         }
     };
-    let recording_equipment = input.create_recording_equipment(
-        Some(playback_track.project()),
-        matrix_record_settings.midi_settings.auto_quantize,
-    )?;
-    let final_midi_overdub_instruction = if recording_equipment.is_midi() {
-        desired_midi_overdub_instruction
-    } else {
-        // Want overdub but we have a audio input, so don't use overdub mode after all.
-        None
-    };
+    let recording_equipment = input.create_recording_equipment(Some(playback_track.project()))?;
+    let (final_midi_overdub_instruction, final_audio_overdub_instruction) =
+        if recording_equipment.is_midi() {
+            // Want audio overdub but we have a MIDI input, so don't use it after all.
+            (desired_midi_overdub_instruction, None)
+        } else {
+            // Want MIDI overdub but we have a audio input, so don't use it after all.
+            (None, desired_audio_overdub_instruction)
+        };
     let task = ClipRecordTask {
         input,
         destination: ClipRecordDestination {
             column_source: column_source.downgrade(),
             slot_index,
-            is_midi_overdub: final_midi_overdub_instruction.is_some(),
+            is_overdub: final_midi_overdub_instruction.is_some()
+                || final_audio_overdub_instruction.is_some(),
+            is_midi_replace: final_midi_overdub_instruction
+                .as_ref()
+                .map_or(false, |i| i.settings.mode == MidiClipRecordMode::Replace),
         },
     };
     let mode_specific_stuff = if let Some(instruction) = final_midi_overdub_instruction {
         ModeSpecificRecordStuff::MidiOverdub(MidiOverdubRecordStuff { instruction })
+    } else if let Some(instruction) = final_audio_overdub_instruction {
+        ModeSpecificRecordStuff::AudioOverdub(AudioOverdubRecordStuff { instruction })
     } else {
         let pooled_midi_source = match &recording_equipment {
             RecordingEquipment::Midi(e) => {
@@ -782,6 +1049,18 @@ fn create_record_stuff(
     Ok((common_stuff, mode_specific_stuff))
 }
 
+/// Resolves the hardware input of the recording track.
+pub(crate) fn resolve_track_input(
+    column_settings: &ColumnClipRecordSettings,
+    playback_track: &Track,
+) -> ClipEngineResult<ClipRecordHardwareInput> {
+    let track = resolve_recording_track(column_settings, playback_track)?;
+    let track_input = track
+        .recording_input()
+        .ok_or("track doesn't have any recording input")?;
+    translate_track_input_to_hw_input(track_input)
+}
+
 fn resolve_recording_track(
     column_settings: &ColumnClipRecordSettings,
     playback_track: &Track,
@@ -827,6 +1106,18 @@ fn translate_track_input_to_hw_input(
     };
     Ok(hw_input)
 }
+
+/// The input is mixed in at source positions, so the material must play at its original
+/// speed.
+fn check_audio_overdub_speed(content: &Content, project: Project) -> ClipEngineResult<()> {
+    let timeline = clip_timeline(Some(project), false);
+    let tempo_factor = content.tempo_factor(timeline.tempo_at(timeline.cursor_pos()));
+    if (tempo_factor - 1.0).abs() > BASE_EPSILON {
+        return Err("audio overdub not possible because clip is time-stretched or resampled");
+    }
+    Ok(())
+}
+
 pub fn create_midi_overdub_instruction(
     mode: MidiClipRecordMode,
     auto_quantize: bool,
@@ -0,0 +1,469 @@
+use crate::main::{ClipSlotCoordinates, MatrixCommand};
+use crate::rt::supplier::{
+    AudioSupplier, MaterialInfo, SupplyAudioRequest, SupplyRequestInfo, WithMaterialInfo,
+};
+use crate::rt::OwnedAudioBuffer;
+use crate::ClipEngineResult;
+use crossbeam_channel::{Receiver, Sender};
+use playtime_api as api;
+use playtime_api::{TempoRange, TimeSignature};
+use reaper_high::{OrCurrentProject, Project, Reaper};
+use reaper_medium::{Hz, OwnedPcmSource, PositionInSeconds};
+
+/// Number of frames that make up one analysis window of the onset detection function.
+const HOP_SIZE: usize = 512;
+
+/// We don't analyze more than that. Long stems usually don't change their tempo anyway and it
+/// keeps memory consumption of the worker bounded.
+const MAX_ANALYSIS_DURATION_IN_SECS: f64 = 90.0;
+
+/// If the tempo estimated from the onsets is closer than this to a tempo that makes the
+/// material consist of a whole number of beats, we take the latter. Loops are usually cut exactly.
+const MAX_LOOP_TEMPO_DEVIATION: f64 = 0.02;
+
+/// Detection is biased towards this tempo in order to resolve octave ambiguities (60 vs. 120).
+const PREFERRED_TEMPO: f64 = 120.0;
+
+#[derive(Debug)]
+pub struct TempoDetectionRequest {
+    pub slot_coordinates: ClipSlotCoordinates,
+    /// Used to check whether the slot still contains the analyzed clip when the result arrives.
+    pub api_source: api::Source,
+    pub pcm_source: OwnedPcmSource,
+    pub tempo_range: TempoRange,
+    pub time_signature: TimeSignature,
+}
+
+#[derive(Clone, Debug)]
+pub struct TempoDetectionResponse {
+    pub slot_coordinates: ClipSlotCoordinates,
+    pub api_source: api::Source,
+    pub beat_time_base: api::BeatTimeBase,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct DetectedTempo {
+    /// Native tempo of the material.
+    pub tempo: f64,
+    /// Position of the first bar downbeat in beats (material before it is a pick-up).
+    pub downbeat_in_beats: f64,
+}
+
+pub fn keep_processing_tempo_detection_requests(
+    receiver: Receiver<TempoDetectionRequest>,
+    command_sender: Sender<MatrixCommand>,
+) {
+    while let Ok(mut request) = receiver.recv() {
+        let beat_time_base = match analyze_source(&mut request) {
+            Ok(b) => b,
+            Err(e) => {
+                debug!("Tempo detection failed: {}", e);
+                continue;
+            }
+        };
+        let response = TempoDetectionResponse {
+            slot_coordinates: request.slot_coordinates,
+            api_source: request.api_source,
+            beat_time_base,
+        };
+        if command_sender
+            .try_send(MatrixCommand::ApplyDetectedTempo(response))
+            .is_err()
+        {
+            debug!("Matrix not interested in detected tempo anymore");
+        }
+    }
+}
+
+fn analyze_source(request: &mut TempoDetectionRequest) -> ClipEngineResult<api::BeatTimeBase> {
+    let info = match request.pcm_source.material_info()? {
+        MaterialInfo::Audio(i) => i,
+        MaterialInfo::Midi(_) => return Err("tempo detection only makes sense for audio"),
+    };
+    if info.frame_count == 0 || info.channel_count == 0 {
+        return Err("source is empty");
+    }
+    let max_frame_count = (MAX_ANALYSIS_DURATION_IN_SECS * info.frame_rate.get()) as usize;
+    let frame_count = info.frame_count.min(max_frame_count);
+    let mut content = OwnedAudioBuffer::new(info.channel_count, frame_count);
+    let supply_request = SupplyAudioRequest {
+        start_frame: 0,
+        dest_sample_rate: None,
+        info: SupplyRequestInfo {
+            audio_block_frame_offset: 0,
+            requester: "tempo-detection",
+            note: "",
+            is_realtime: false,
+        },
+        parent_request: None,
+        general_info: &Default::default(),
+    };
+    request
+        .pcm_source
+        .supply_audio(&supply_request, &mut content.to_buf_mut());
+    let mono_samples = mix_down_to_mono(content.to_buf().data_as_slice(), info.channel_count);
+    let detected = detect_tempo_and_downbeat(
+        &mono_samples,
+        info.frame_rate,
+        info.duration().get(),
+        request.tempo_range,
+        request.time_signature.numerator,
+    )
+    .ok_or("no rhythmic material found")?;
+    debug!("Detected tempo: {:?}", detected);
+    let beat_time_base = api::BeatTimeBase {
+        audio_tempo: Some(api::Bpm::new(detected.tempo)?),
+        time_signature: request.time_signature,
+        downbeat: api::PositiveBeat::new(detected.downbeat_in_beats)?,
+    };
+    Ok(beat_time_base)
+}
+
+/// Returns the time signature at the start of the given project (or the current one).
+pub(crate) fn project_time_signature(project: Option<Project>) -> TimeSignature {
+    let project = project.or_current_project();
+    let result = Reaper::get()
+        .medium_reaper()
+        .time_map_2_time_to_beats(project.context(), PositionInSeconds::ZERO);
+    TimeSignature {
+        numerator: result.time_signature.numerator.get(),
+        denominator: result.time_signature.denominator.get(),
+    }
+}
+
+fn mix_down_to_mono(interleaved: &[f64], channel_count: usize) -> Vec<f64> {
+    interleaved
+        .chunks_exact(channel_count)
+        .map(|frame| frame.iter().sum::<f64>() / channel_count as f64)
+        .collect()
+}
+
+/// Estimates the tempo and the position of the first bar downbeat of the given mono material.
+///
+/// Works in four steps:
+///
+/// 1. Onset detection: Computes a half-wave rectified log-energy difference per hop.
+/// 2. Tempo estimation: Autocorrelates the onset envelope within the given tempo range. If the
+///    material length suggests a loop, the tempo is snapped so that the loop has a whole number
+///    of beats.
+/// 3. Beat phase: Finds the offset at which a beat grid with the estimated tempo matches the
+///    onsets best.
+/// 4. Bar phase: Finds the beat within the bar which is accented most (usually the downbeat).
+///
+/// Returns `None` if the material doesn't contain anything resembling a rhythm.
+pub fn detect_tempo_and_downbeat(
+    mono_samples: &[f64],
+    frame_rate: Hz,
+    material_duration_in_secs: f64,
+    tempo_range: TempoRange,
+    beats_per_bar: u32,
+) -> Option<DetectedTempo> {
+    let envelope = calculate_onset_envelope(mono_samples);
+    if envelope.len() < 4 || envelope.iter().all(|v| *v == 0.0) {
+        return None;
+    }
+    let envelope_rate = frame_rate.get() / HOP_SIZE as f64;
+    let estimated_tempo = estimate_tempo(&envelope, envelope_rate, tempo_range)?;
+    let tempo = snap_tempo_to_loop_length(estimated_tempo, material_duration_in_secs, tempo_range);
+    let period = 60.0 / tempo * envelope_rate;
+    let phase = find_beat_phase(&envelope, period);
+    let beat_phase_in_beats = if phase / period > 0.98 {
+        0.0
+    } else {
+        phase / period
+    };
+    let bar_offset_in_beats = find_bar_offset(&envelope, phase, period, beats_per_bar);
+    let downbeat_in_beats = beat_phase_in_beats + bar_offset_in_beats as f64;
+    let detected = DetectedTempo {
+        tempo,
+        downbeat_in_beats,
+    };
+    Some(detected)
+}
+
+fn calculate_onset_envelope(mono_samples: &[f64]) -> Vec<f64> {
+    let log_energies: Vec<f64> = mono_samples
+        .chunks(HOP_SIZE)
+        .map(|hop| {
+            let mean_square = hop.iter().map(|s| s * s).sum::<f64>() / hop.len() as f64;
+            (1.0 + 1000.0 * mean_square.sqrt()).ln()
+        })
+        .collect();
+    let raw: Vec<f64> = log_energies
+        .iter()
+        .enumerate()
+        .map(|(i, e)| {
+            let previous = if i == 0 { 0.0 } else { log_energies[i - 1] };
+            (e - previous).max(0.0)
+        })
+        .collect();
+    // A bit of smoothing makes the autocorrelation robust against fractional beat periods.
+    (0..raw.len())
+        .map(|i| {
+            let left = if i == 0 { 0.0 } else { raw[i - 1] };
+            let right = raw.get(i + 1).copied().unwrap_or(0.0);
+            0.25 * left + 0.5 * raw[i] + 0.25 * right
+        })
+        .collect()
+}
+
+fn estimate_tempo(envelope: &[f64], envelope_rate: f64, tempo_range: TempoRange) -> Option<f64> {
+    let min_tempo = tempo_range.min().get();
+    let max_tempo = tempo_range.max().get();
+    let mut best: Option<(f64, f64)> = None;
+    let mut tempo = min_tempo;
+    while tempo <= max_tempo {
+        let lag = 60.0 / tempo * envelope_rate;
+        if lag >= envelope.len() as f64 {
+            tempo += TEMPO_STEP;
+            continue;
+        }
+        let octave_distance = (tempo / PREFERRED_TEMPO).log2();
+        let prior = (-0.5 * octave_distance * octave_distance).exp();
+        let score = autocorrelate(envelope, lag) * prior;
+        if best.map(|(_, s)| score > s).unwrap_or(true) {
+            best = Some((tempo, score));
+        }
+        tempo += TEMPO_STEP;
+    }
+    let (tempo, score) = best?;
+    if score <= 0.0 {
+        return None;
+    }
+    Some(tempo)
+}
+
+const TEMPO_STEP: f64 = 0.25;
+
+/// Autocorrelation at a fractional lag (linear interpolation), normalized by overlap length.
+fn autocorrelate(envelope: &[f64], lag: f64) -> f64 {
+    let overlap = envelope.len() as f64 - lag.ceil();
+    if overlap <= 0.0 {
+        return 0.0;
+    }
+    let sum: f64 = (0..overlap as usize)
+        .map(|i| envelope[i] * interpolate(envelope, i as f64 + lag))
+        .sum();
+    sum / overlap
+}
+
+fn interpolate(values: &[f64], pos: f64) -> f64 {
+    let index = pos.floor() as usize;
+    let fraction = pos - index as f64;
+    let left = values.get(index).copied().unwrap_or(0.0);
+    let right = values.get(index + 1).copied().unwrap_or(0.0);
+    left + (right - left) * fraction
+}
+
+fn snap_tempo_to_loop_length(
+    estimated_tempo: f64,
+    material_duration_in_secs: f64,
+    tempo_range: TempoRange,
+) -> f64 {
+    if material_duration_in_secs <= 0.0 {
+        return estimated_tempo;
+    }
+    let beat_count = (material_duration_in_secs * estimated_tempo / 60.0).round();
+    if beat_count < 1.0 {
+        return estimated_tempo;
+    }
+    let loop_tempo = beat_count * 60.0 / material_duration_in_secs;
+    let deviation = (loop_tempo - estimated_tempo).abs() / estimated_tempo;
+    if deviation <= MAX_LOOP_TEMPO_DEVIATION
+        && loop_tempo >= tempo_range.min().get()
+        && loop_tempo <= tempo_range.max().get()
+    {
+        loop_tempo
+    } else {
+        estimated_tempo
+    }
+}
+
+/// Returns the beat phase in envelope frames (between 0 and `period`).
+fn find_beat_phase(envelope: &[f64], period: f64) -> f64 {
+    let mut best_phase = 0.0;
+    let mut best_score = f64::MIN;
+    let mut phase = 0.0;
+    while phase < period {
+        let mut score = 0.0;
+        let mut pos = phase;
+        while pos < envelope.len() as f64 {
+            score += interpolate(envelope, pos);
+            pos += period;
+        }
+        if score > best_score {
+            best_score = score;
+            best_phase = phase;
+        }
+        phase += 0.5;
+    }
+    best_phase
+}
+
+/// Relative margin by which a later beat of the bar must beat the current best one in order to be
+/// considered the downbeat.
+///
+/// Prevents random fluctuations of unaccented material from moving the downbeat.
+const BAR_OFFSET_MARGIN: f64 = 0.1;
+
+/// Returns the index of the beat (relative to the beat phase) which has the strongest onsets when
+/// looking at every bar. Prefers earlier beats if there's no clear accent.
+fn find_bar_offset(envelope: &[f64], phase: f64, period: f64, beats_per_bar: u32) -> u32 {
+    let bar_period = period * beats_per_bar.max(1) as f64;
+    let mut best: Option<(u32, f64)> = None;
+    for offset in 0..beats_per_bar.max(1) {
+        let mut pos = phase + offset as f64 * period;
+        let mut score = 0.0;
+        let mut count = 0;
+        while pos < envelope.len() as f64 {
+            score += interpolate(envelope, pos);
+            count += 1;
+            pos += bar_period;
+        }
+        if count == 0 {
+            continue;
+        }
+        let mean_score = score / count as f64;
+        let is_better = match best {
+            None => true,
+            Some((_, best_score)) => mean_score - best_score > BAR_OFFSET_MARGIN * best_score.abs(),
+        };
+        if is_better {
+            best = Some((offset, mean_score));
+        }
+    }
+    best.map(|(offset, _)| offset).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use playtime_api::Bpm;
+
+    fn click_track(tempo: f64, offset_in_secs: f64, duration_in_secs: f64, rate: f64) -> Vec<f64> {
+        accented_click_track(tempo, offset_in_secs, duration_in_secs, rate, |_| 0.9)
+    }
+
+    fn accented_click_track(
+        tempo: f64,
+        offset_in_secs: f64,
+        duration_in_secs: f64,
+        rate: f64,
+        amplitude: impl Fn(usize) -> f64,
+    ) -> Vec<f64> {
+        let frame_count = (duration_in_secs * rate) as usize;
+        let mut samples = vec![0.0; frame_count];
+        let beat_length = 60.0 / tempo;
+        let mut beat_pos = offset_in_secs;
+        let mut beat_index = 0;
+        while beat_pos < duration_in_secs {
+            let start = (beat_pos * rate) as usize;
+            let amplitude = amplitude(beat_index);
+            for (i, sample) in samples.iter_mut().skip(start).take(200).enumerate() {
+                *sample = amplitude * (1.0 - i as f64 / 200.0);
+            }
+            beat_pos += beat_length;
+            beat_index += 1;
+        }
+        samples
+    }
+
+    fn default_range() -> TempoRange {
+        TempoRange::new(Bpm::new(60.0).unwrap(), Bpm::new(200.0).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn detect_loop_tempo() {
+        // Given
+        let rate = 48000.0;
+        let samples = click_track(120.0, 0.0, 8.0, rate);
+        // When
+        let detected =
+            detect_tempo_and_downbeat(&samples, Hz::new(rate), 8.0, default_range(), 4).unwrap();
+        // Then
+        assert!((detected.tempo - 120.0).abs() < 0.01, "{:?}", detected);
+        assert!(detected.downbeat_in_beats < 0.05, "{:?}", detected);
+    }
+
+    #[test]
+    fn detect_tempo_with_pick_up() {
+        // Given
+        let rate = 44100.0;
+        let samples = click_track(96.0, 0.25, 10.0, rate);
+        // When
+        let detected =
+            detect_tempo_and_downbeat(&samples, Hz::new(rate), 10.0, default_range(), 4).unwrap();
+        // Then
+        assert!((detected.tempo - 96.0).abs() < 0.01, "{:?}", detected);
+        assert!(
+            (detected.downbeat_in_beats - 0.4).abs() < 0.05,
+            "{:?}",
+            detected
+        );
+    }
+
+    #[test]
+    fn reject_silence() {
+        // Given
+        let samples = vec![0.0; 48000 * 4];
+        // When
+        let detected =
+            detect_tempo_and_downbeat(&samples, Hz::new(48000.0), 4.0, default_range(), 4);
+        // Then
+        assert_eq!(detected, None);
+    }
+
+    #[test]
+    fn detect_accented_bar_downbeat() {
+        // Given
+        let rate = 48000.0;
+        // One pick-up beat, then bars in 4/4 with an accent on each downbeat
+        let samples = accented_click_track(120.0, 0.0, 8.0, rate, |beat_index| {
+            if beat_index % 4 == 1 {
+                0.9
+            } else {
+                0.15
+            }
+        });
+        // When
+        let detected =
+            detect_tempo_and_downbeat(&samples, Hz::new(rate), 8.0, default_range(), 4).unwrap();
+        // Then
+        assert!((detected.tempo - 120.0).abs() < 0.01, "{:?}", detected);
+        assert!(
+            (detected.downbeat_in_beats - 1.0).abs() < 0.05,
+            "{:?}",
+            detected
+        );
+    }
+
+    #[test]
+    fn prefer_first_beat_without_clear_accent() {
+        // Given
+        let envelope = [1.0, 0.0, 1.05, 0.0, 0.98, 0.0, 1.02, 0.0];
+        // When
+        let offset = find_bar_offset(&envelope, 0.0, 2.0, 4);
+        // Then
+        assert_eq!(offset, 0);
+    }
+
+    #[test]
+    fn prefer_first_beat_without_clear_accent_with_negative_envelope() {
+        // Given
+        let envelope = [-1.0, -2.0, -1.05, -2.0, -1.02, -2.0, -1.08, -2.0];
+        // When
+        let offset = find_bar_offset(&envelope, 0.0, 2.0, 4);
+        // Then
+        assert_eq!(offset, 0);
+    }
+
+    #[test]
+    fn find_accented_beat_with_negative_envelope() {
+        // Given
+        let envelope = [-1.0, -2.0, -0.2, -2.0, -1.0, -2.0, -1.0, -2.0];
+        // When
+        let offset = find_bar_offset(&envelope, 0.0, 2.0, 4);
+        // Then
+        assert_eq!(offset, 1);
+    }
+}
@@ -35,10 +35,25 @@ pub fn measure_time<R>(id: &'static str, f: impl FnOnce() -> R) -> R {
         id,
         delta: start.elapsed(),
     };
+    record(task);
+    result
+}
+
+pub fn set_gauge(id: &'static str, value: f64) {
+    record(MetricsTask::Gauge { id, value });
+}
+
+pub fn increment_counter(id: &'static str) {
+    record(MetricsTask::Counter { id });
+}
+
+fn record(task: MetricsTask) {
+    if !*METRICS_ENABLED {
+        return;
+    }
     if METRICS_CHANNEL.sender.try_send(task).is_err() {
         debug!("Metrics channel is full");
     }
-    result
 }
 
 struct MetricsChannel {
@@ -55,6 +70,8 @@ impl Default for MetricsChannel {
 
 enum MetricsTask {
     Histogram { id: &'static str, delta: Duration },
+    Gauge { id: &'static str, value: f64 },
+    Counter { id: &'static str },
 }
 
 fn keep_recording_metrics(receiver: Receiver<MetricsTask>) {
@@ -63,6 +80,12 @@ fn keep_recording_metrics(receiver: Receiver<MetricsTask>) {
             MetricsTask::Histogram { id, delta } => {
                 metrics::histogram!(id, delta);
             }
+            MetricsTask::Gauge { id, value } => {
+                metrics::gauge!(id, value);
+            }
+            MetricsTask::Counter { id } => {
+                metrics::increment_counter!(id);
+            }
         }
     }
 }
@@ -4,17 +4,17 @@ use crate::conversion_util::{
 };
 use crate::main::ClipSlotCoordinates;
 use crate::rt::buffer::AudioBufMut;
-use crate::rt::schedule_util::calc_distance_from_quantized_pos;
+use crate::rt::schedule_util::{calc_distance_from_pos, calc_distance_from_quantized_pos};
 use crate::rt::supplier::{
-    AudioSupplier, ChainEquipment, ChainSettings, CompleteRecordingData,
-    KindSpecificRecordingOutcome, MaterialInfo, MidiOverdubSettings, MidiSupplier,
+    AudioOverdubEquipment, AudioSupplier, ChainEquipment, ChainSettings, CompleteRecordingData,
+    CountInClick, KindSpecificRecordingOutcome, MaterialInfo, MidiOverdubSettings, MidiSupplier,
     PollRecordingOutcome, RecordState, Recorder, RecorderRequest, RecordingArgs,
     RecordingEquipment, RecordingOutcome, StopRecordingOutcome, SupplierChain, SupplyAudioRequest,
     SupplyMidiRequest, SupplyRequestGeneralInfo, SupplyRequestInfo, SupplyResponse,
     SupplyResponseStatus, WithMaterialInfo, WriteAudioRequest, WriteMidiRequest, MIDI_BASE_BPM,
     MIDI_FRAME_RATE,
 };
-use crate::rt::tempo_util::{calc_tempo_factor, determine_tempo_from_time_base};
+use crate::rt::tempo_util::{calc_lead_tempo, calc_tempo_factor, determine_tempo_from_time_base};
 use crate::rt::{ColumnSettings, OverridableMatrixSettings};
 use crate::timeline::{HybridTimeline, Timeline};
 use crate::{ClipEngineResult, ErrorWithPayload, Laziness, QuantizedPosition};
@@ -22,14 +22,16 @@ use crossbeam_channel::Sender;
 use helgoboss_learn::UnitValue;
 use playtime_api as api;
 use playtime_api::{
-    ClipAudioSettings, ClipMidiSettings, ClipPlayStartTiming, ClipPlayStopTiming, ClipTimeBase, Db,
-    EvenQuantization, MatrixClipRecordSettings, PositiveSecond,
+    AudioOverdubSettings, ClipAudioSettings, ClipMidiSettings, ClipPlayStartTiming,
+    ClipPlayStopTiming, ClipTimeBase, Db, EvenQuantization, MatrixClipRecordSettings,
+    PositiveSecond,
 };
 use reaper_high::Project;
 use reaper_medium::{
     BorrowedMidiEventList, Bpm, DurationInSeconds, Hz, OnAudioBufferArgs, OwnedPcmSource,
     PcmSourceTransfer, PositionInSeconds,
 };
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicIsize, Ordering};
 use std::sync::Arc;
 
@@ -309,6 +311,26 @@ impl Clip {
         }
     }
 
+    pub fn audio_overdub(
+        &mut self,
+        args: AudioOverdubInstruction,
+    ) -> Result<(), ErrorWithPayload<AudioOverdubInstruction>> {
+        use ClipState::*;
+        match &mut self.state {
+            Ready(s) => s.audio_overdub(args, &mut self.supplier_chain),
+            Recording(_) => Err(ErrorWithPayload::new("clip is recording", args)),
+        }
+    }
+
+    /// Restores the audio material as it was before the last overdub layer.
+    pub fn undo_audio_overdub(&mut self) -> ClipEngineResult<()> {
+        use ClipState::*;
+        match &mut self.state {
+            Ready(_) => self.supplier_chain.undo_audio_overdub(),
+            Recording(_) => Err("clip is recording"),
+        }
+    }
+
     pub fn record(
         &mut self,
         args: ClipRecordArgs,
@@ -363,10 +385,18 @@ impl Clip {
     ) -> bool {
         use ClipState::*;
         match &mut self.state {
-            Ready(s) => match &s.state {
-                ReadySubState::Playing(s) => s.overdubbing,
-                _ => false,
-            },
+            Ready(s) => {
+                let overdubbing = match &s.state {
+                    ReadySubState::Playing(s) => s.overdubbing,
+                    _ => false,
+                };
+                if !overdubbing {
+                    if let Some(file) = self.supplier_chain.finish_audio_overdub() {
+                        event_handler.audio_overdub_finished(file);
+                    }
+                }
+                overdubbing
+            }
             Recording(s) => {
                 use PollRecordingOutcome::*;
                 match self.supplier_chain.poll_recording(args.audio_request_props) {
@@ -393,27 +423,42 @@ impl Clip {
 
     /// Writes the events in the given request into the currently recording MIDI source.
     pub fn write_midi(&mut self, request: WriteMidiRequest) {
-        use ClipState::*;
-        let play_pos = match &self.state {
-            Ready(s) => match s.state {
-                ReadySubState::Playing(PlayingState {
-                    overdubbing: true,
-                    pos: Some(pos),
-                    ..
-                }) => Some(pos),
-                _ => return,
-            },
-            Recording(_) => None,
+        let play_pos = match self.overdub_play_pos() {
+            Ok(p) => p,
+            Err(_) => return,
         };
         self.supplier_chain.write_midi(request, play_pos).unwrap();
     }
 
-    /// Writes the samples in the given request into the currently recording audio source.
+    /// Writes the samples in the given request into the currently recording audio source (or into
+    /// the material in memory when overdubbing).
     ///
     /// Also drives processing during recording because it's called exactly once per audio block
     /// anyway.
     pub fn write_audio(&mut self, request: impl WriteAudioRequest) {
-        self.supplier_chain.write_audio(request);
+        let play_pos = match self.overdub_play_pos() {
+            Ok(p) => p,
+            Err(_) => return,
+        };
+        if let Err(e) = self.supplier_chain.write_audio(request, play_pos) {
+            debug!("Couldn't write audio: {}", e);
+        }
+    }
+
+    /// Returns the play position if overdubbing, `None` if recording and an error if neither.
+    fn overdub_play_pos(&self) -> ClipEngineResult<Option<isize>> {
+        use ClipState::*;
+        match &self.state {
+            Ready(s) => match s.state {
+                ReadySubState::Playing(PlayingState {
+                    overdubbing: true,
+                    pos: Some(pos),
+                    ..
+                }) => Ok(Some(pos)),
+                _ => Err("neither recording nor overdubbing"),
+            },
+            Recording(_) => Ok(None),
+        }
     }
 
     pub fn set_volume(&mut self, volume: Db) {
@@ -449,6 +494,14 @@ impl Clip {
         }
     }
 
+    /// Returns the click which makes the count-in audible if counting in.
+    pub fn count_in_click(&self) -> Option<CountInClick> {
+        match &self.state {
+            ClipState::Ready(_) => None,
+            ClipState::Recording(_) => self.supplier_chain.count_in_click(),
+        }
+    }
+
     pub fn process(&mut self, args: &mut ClipProcessArgs) -> ClipProcessingOutcome {
         use ClipState::*;
         match &mut self.state {
@@ -585,7 +638,7 @@ impl ReadyState {
         let start_timing = play_args.resolve_start_timing(self.play_settings.start_timing);
         use ClipPlayStartTiming::*;
         match start_timing {
-            Immediately => VirtualPosition::Now,
+            Immediately => VirtualPosition::now_at_frame_offset(play_args.frame_offset),
             Quantized(q) => {
                 let ref_pos = play_args
                     .ref_pos
@@ -1103,6 +1156,32 @@ impl ReadyState {
         }
     }
 
+    pub fn audio_overdub(
+        &mut self,
+        args: AudioOverdubInstruction,
+        supplier_chain: &mut SupplierChain,
+    ) -> Result<(), ErrorWithPayload<AudioOverdubInstruction>> {
+        use ReadySubState::*;
+        if let Playing(s) = self.state {
+            let settings = args.settings;
+            supplier_chain
+                .start_audio_overdub(args.equipment, settings)
+                .map_err(|e| {
+                    e.map_payload(|equipment| AudioOverdubInstruction {
+                        equipment,
+                        settings,
+                    })
+                })?;
+            self.state = Playing(PlayingState {
+                overdubbing: true,
+                ..s
+            });
+            Ok(())
+        } else {
+            Err(ErrorWithPayload::new("clip not playing", args))
+        }
+    }
+
     pub fn record(
         &mut self,
         args: ClipRecordArgs,
@@ -1111,13 +1190,16 @@ impl ReadyState {
         matrix_settings: &OverridableMatrixSettings,
         column_settings: &ColumnSettings,
     ) -> Option<RecordingState> {
-        let recording_args = RecordingArgs::from_stuff(
-            project,
-            column_settings,
-            matrix_settings,
-            &args.settings,
-            args.recording_equipment,
-        );
+        let recording_args = RecordingArgs {
+            frame_offset: args.frame_offset,
+            ..RecordingArgs::from_stuff(
+                project,
+                column_settings,
+                matrix_settings,
+                &args.settings,
+                args.recording_equipment,
+            )
+        };
         supplier_chain.prepare_recording(recording_args);
         let recording_state = RecordingState {
             rollback_data: {
@@ -1322,9 +1404,11 @@ impl RecordingState {
         column_settings: &ColumnSettings,
     ) -> ReadyState {
         debug!("Finishing recording");
+        let lead_tempo = determine_lead_tempo(&self.settings, &outcome.data);
         let clip_settings = ProcessingRelevantClipSettings::derive_from_recording(
             &self.settings,
             &outcome.data,
+            lead_tempo,
             matrix_settings,
             column_settings,
         );
@@ -1354,6 +1438,7 @@ impl RecordingState {
             kind_specific: outcome.kind_specific,
             clip_settings,
             material_info,
+            lead_tempo,
         };
         event_handler
             .normal_recording_finished(NormalRecordingOutcome::Committed(committed_recording));
@@ -1366,6 +1451,30 @@ impl RecordingState {
     }
 }
 
+/// Returns the tempo which the given recording should impose on the project, if any.
+///
+/// Only freely recorded audio (without quantized end) can lead the tempo.
+fn determine_lead_tempo(
+    record_settings: &MatrixClipRecordSettings,
+    data: &CompleteRecordingData,
+) -> Option<Bpm> {
+    if !record_settings.lead_tempo
+        || data.is_midi
+        || data.section_and_downbeat_data.quantized_end_pos.is_some()
+    {
+        return None;
+    }
+    let duration =
+        convert_duration_in_frames_to_seconds(data.effective_frame_count(), data.frame_rate);
+    let tempo = calc_lead_tempo(
+        duration,
+        data.time_signature.numerator.get(),
+        record_settings.lead_tempo_bar_count,
+        data.tempo,
+    );
+    Some(tempo)
+}
+
 pub enum SlotInstruction {
     ClearSlot,
 }
@@ -1382,6 +1491,11 @@ pub struct ClipPlayArgs<'a> {
     pub timeline: &'a HybridTimeline,
     /// Set this if you already have the current timeline position or want to play a batch of clips.
     pub ref_pos: Option<PositionInSeconds>,
+    /// Frame offset within the current audio block at which an immediate start should happen.
+    ///
+    /// Set this to the offset of the triggering MIDI event in order to start sample-accurately.
+    /// Doesn't have any effect if the start is quantized.
+    pub frame_offset: usize,
     pub matrix_settings: &'a OverridableMatrixSettings,
     pub column_settings: &'a ColumnSettings,
 }
@@ -1442,9 +1556,23 @@ impl<'a> ClipStopArgs<'a> {
 #[derive(Copy, Clone, Debug)]
 pub enum VirtualPosition {
     Now,
+    /// Like `Now` but not at the start of the audio block in which the clip starts playing.
+    ///
+    /// Contains the frame offset within that block (in output frames).
+    NowAtFrameOffset(usize),
     Quantized(QuantizedPosition),
 }
 
+impl VirtualPosition {
+    pub fn now_at_frame_offset(frame_offset: usize) -> Self {
+        if frame_offset == 0 {
+            Self::Now
+        } else {
+            Self::NowAtFrameOffset(frame_offset)
+        }
+    }
+}
+
 impl Default for VirtualPosition {
     fn default() -> Self {
         Self::Now
@@ -1462,6 +1590,26 @@ pub enum SlotRecordInstruction {
     NewClip(RecordNewClipInstruction),
     ExistingClip(ClipRecordArgs),
     MidiOverdub(MidiOverdubInstruction),
+    AudioOverdub(AudioOverdubInstruction),
+}
+
+impl SlotRecordInstruction {
+    /// Makes the recording start at the given frame within the audio block in which it starts.
+    ///
+    /// Only has an effect if the recording starts immediately. Overdubs are not affected.
+    pub fn set_frame_offset(&mut self, frame_offset: usize) -> ClipEngineResult<()> {
+        use SlotRecordInstruction::*;
+        match self {
+            NewClip(instruction) => instruction
+                .supplier_chain
+                .set_recording_frame_offset(frame_offset),
+            ExistingClip(args) => {
+                args.frame_offset = frame_offset;
+                Ok(())
+            }
+            MidiOverdub(_) | AudioOverdub(_) => Ok(()),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -1483,10 +1631,18 @@ pub struct MidiOverdubInstruction {
     pub settings: MidiOverdubSettings,
 }
 
+#[derive(Debug)]
+pub struct AudioOverdubInstruction {
+    pub equipment: AudioOverdubEquipment,
+    pub settings: AudioOverdubSettings,
+}
+
 #[derive(Debug)]
 pub struct ClipRecordArgs {
     pub recording_equipment: RecordingEquipment,
     pub settings: MatrixClipRecordSettings,
+    /// Frame offset within the audio block in which the recording starts.
+    pub frame_offset: usize,
 }
 
 #[derive(PartialEq, Debug)]
@@ -1603,6 +1759,7 @@ pub enum ClipChangedEvent {
     PlayState(ClipPlayState),
     ClipVolume(Db),
     ClipLooped(bool),
+    ClipColor(api::ClipColor),
     ClipPosition(UnitValue),
     Removed,
 }
@@ -1668,6 +1825,7 @@ struct FillSamplesOutcome {
 
 pub trait HandleSlotEvent {
     fn midi_overdub_finished(&self, mirror_source: OwnedPcmSource);
+    fn audio_overdub_finished(&self, file: PathBuf);
     fn normal_recording_finished(&self, outcome: NormalRecordingOutcome);
     fn slot_cleared(&self, clip: Clip);
 }
@@ -1688,6 +1846,8 @@ pub struct CommittedRecording {
     pub kind_specific: KindSpecificRecordingOutcome,
     pub clip_settings: ProcessingRelevantClipSettings,
     pub material_info: MaterialInfo,
+    /// Tempo to which the project should switch because this recording leads the tempo.
+    pub lead_tempo: Option<Bpm>,
 }
 
 /// All settings of a clip that affect processing.
@@ -1719,15 +1879,24 @@ impl ProcessingRelevantClipSettings {
         }
     }
 
+    /// If a lead tempo is given, the clip is assumed to have this tempo instead of the tempo at
+    /// the time of recording.
     pub fn derive_from_recording(
         record_settings: &MatrixClipRecordSettings,
         data: &CompleteRecordingData,
+        lead_tempo: Option<Bpm>,
         matrix_settings: &OverridableMatrixSettings,
         column_settings: &ColumnSettings,
     ) -> ClipEngineResult<Self> {
         let current_play_start_timing = column_settings
             .clip_play_start_timing
             .unwrap_or(matrix_settings.clip_play_start_timing);
+        let midi_record_settings = &record_settings.midi_settings;
+        let record_quantization = if data.is_midi && midi_record_settings.auto_quantize {
+            Some(midi_record_settings.quantization.unwrap_or_default())
+        } else {
+            None
+        };
         let settings = Self {
             start_timing: record_settings.effective_play_start_timing(
                 data.initial_play_start_timing,
@@ -1739,10 +1908,11 @@ impl ProcessingRelevantClipSettings {
             ),
             looped: record_settings.looped,
             time_base: {
+                let tempo = lead_tempo.unwrap_or(data.tempo);
                 let audio_tempo = if data.is_midi {
                     None
                 } else {
-                    Some(api::Bpm::new(data.tempo.get())?)
+                    Some(api::Bpm::new(tempo.get())?)
                 };
                 record_settings.effective_play_time_base(
                     data.initial_play_start_timing,
@@ -1751,7 +1921,7 @@ impl ProcessingRelevantClipSettings {
                         numerator: data.time_signature.numerator.get(),
                         denominator: data.time_signature.denominator.get(),
                     },
-                    api::PositiveBeat::new(data.downbeat_in_beats().get())?,
+                    api::PositiveBeat::new(data.downbeat_in_beats(tempo).get())?,
                 )
             },
             volume: api::Db::ZERO,
@@ -1768,7 +1938,10 @@ impl ProcessingRelevantClipSettings {
                 resample_mode: None,
                 cache_behavior: None,
             },
-            midi_settings: ClipMidiSettings::default(),
+            midi_settings: ClipMidiSettings {
+                record_quantization,
+                ..Default::default()
+            },
         };
         Ok(settings)
     }
@@ -1946,6 +2119,16 @@ fn resolve_virtual_pos(
     use VirtualPosition::*;
     match virtual_pos {
         Now => 0,
+        NowAtFrameOffset(frame_offset) => {
+            let equipment = QuantizedPosCalcEquipment {
+                audio_request_props: process_args.basic_audio_request_props(),
+                timeline: process_args.timeline,
+                timeline_cursor_pos: process_args.timeline_cursor_pos,
+                clip_tempo_factor,
+                source_frame_rate: material_info.frame_rate(),
+            };
+            calc_distance_from_frame_offset(frame_offset, equipment)
+        }
         Quantized(qp) => {
             let equipment = QuantizedPosCalcEquipment {
                 audio_request_props: process_args.basic_audio_request_props(),
@@ -1998,6 +2181,18 @@ impl BasicAudioRequestProps {
     }
 }
 
+/// Results in a short count-in which ends exactly at the given frame within the current block.
+fn calc_distance_from_frame_offset(
+    frame_offset: usize,
+    equipment: QuantizedPosCalcEquipment,
+) -> isize {
+    let offset_in_secs = convert_duration_in_frames_to_seconds(
+        frame_offset,
+        equipment.audio_request_props.frame_rate,
+    );
+    calc_distance_from_pos(equipment.timeline_cursor_pos + offset_in_secs, equipment)
+}
+
 pub struct QuantizedPosCalcEquipment<'a> {
     pub audio_request_props: BasicAudioRequestProps,
     pub timeline: &'a HybridTimeline,
@@ -2039,3 +2234,79 @@ fn modulo_frame(frame: isize, frame_count: usize) -> isize {
         frame % frame_count as isize
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timeline::{global_steady_timeline_state, SteadyTimeline};
+
+    #[test]
+    fn immediate_start_without_frame_offset_is_now() {
+        // When
+        let pos = VirtualPosition::now_at_frame_offset(0);
+        // Then
+        assert!(matches!(pos, VirtualPosition::Now));
+    }
+
+    #[test]
+    fn immediate_start_with_frame_offset() {
+        // When
+        let pos = VirtualPosition::now_at_frame_offset(64);
+        // Then
+        assert!(matches!(pos, VirtualPosition::NowAtFrameOffset(64)));
+    }
+
+    #[test]
+    fn frame_offset_results_in_count_in_until_frame() {
+        // Given
+        let timeline = steady_timeline();
+        let equipment = equipment(&timeline, 1.0, Hz::new(48000.0));
+        // When
+        let distance = calc_distance_from_frame_offset(100, equipment);
+        // Then
+        assert_eq!(distance, -100);
+    }
+
+    #[test]
+    fn frame_offset_count_in_respects_source_frame_rate() {
+        // Given
+        let timeline = steady_timeline();
+        let equipment = equipment(&timeline, 1.0, Hz::new(24000.0));
+        // When
+        let distance = calc_distance_from_frame_offset(100, equipment);
+        // Then
+        assert_eq!(distance, -50);
+    }
+
+    #[test]
+    fn frame_offset_count_in_respects_tempo_factor() {
+        // Given
+        let timeline = steady_timeline();
+        let equipment = equipment(&timeline, 2.0, Hz::new(48000.0));
+        // When
+        let distance = calc_distance_from_frame_offset(100, equipment);
+        // Then
+        assert_eq!(distance, -200);
+    }
+
+    fn steady_timeline() -> HybridTimeline {
+        HybridTimeline::GlobalSteady(SteadyTimeline::new(global_steady_timeline_state()))
+    }
+
+    fn equipment(
+        timeline: &HybridTimeline,
+        clip_tempo_factor: f64,
+        source_frame_rate: Hz,
+    ) -> QuantizedPosCalcEquipment {
+        QuantizedPosCalcEquipment {
+            audio_request_props: BasicAudioRequestProps {
+                block_length: 512,
+                frame_rate: Hz::new(48000.0),
+            },
+            timeline,
+            timeline_cursor_pos: PositionInSeconds::new(0.5),
+            clip_tempo_factor,
+            source_frame_rate,
+        }
+    }
+}
@@ -6,23 +6,24 @@ use crate::rt::{
     Slot, SlotProcessTransportChangeArgs, SlotRecordInstruction, SlotRuntimeData, TransportChange,
 };
 use crate::timeline::{clip_timeline, HybridTimeline, Timeline};
-use crate::ClipEngineResult;
+use crate::{ClipEngineResult, ErrorWithPayload};
 use assert_no_alloc::assert_no_alloc;
 use crossbeam_channel::{Receiver, Sender};
-use helgoboss_learn::UnitValue;
+use helgoboss_learn::{UnitValue, BASE_EPSILON};
 use playtime_api::{
     AudioCacheBehavior, AudioTimeStretchMode, ClipPlayStartTiming, ClipPlayStopTiming,
     ColumnPlayMode, Db, VirtualResampleMode,
 };
 use reaper_high::Project;
 use reaper_medium::{
-    reaper_str, CustomPcmSource, DurationInBeats, DurationInSeconds, ExtendedArgs, GetPeakInfoArgs,
-    GetSamplesArgs, Hz, LoadStateArgs, OwnedPcmSource, PcmSource, PeaksClearArgs,
+    reaper_str, Bpm, CustomPcmSource, DurationInBeats, DurationInSeconds, ExtendedArgs,
+    GetPeakInfoArgs, GetSamplesArgs, Hz, LoadStateArgs, OwnedPcmSource, PcmSource, PeaksClearArgs,
     PositionInSeconds, PropertiesWindowArgs, ReaperStr, SaveStateArgs, SetAvailableArgs,
     SetFileNameArgs, SetSourceArgs,
 };
 use std::error::Error;
-use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError, Weak};
 
 /// Only such methods are public which are allowed to use from real-time threads. Other ones
 /// are private and called from the method that processes the incoming commands.
@@ -38,6 +39,8 @@ pub struct Column {
     /// Enough reserved memory to hold one audio block of an arbitrary size.
     mix_buffer_chunk: Vec<f64>,
     timeline_was_paused_in_last_block: bool,
+    /// Play request which waits for the timeline to pick up a tempo change.
+    pending_play_clip: Option<PendingPlayClip>,
 }
 
 #[derive(Clone, Debug)]
@@ -55,6 +58,15 @@ impl SharedColumn {
         non_blocking_lock(&self.0, "real-time column")
     }
 
+    /// Returns `None` if the column is locked already (e.g. by the main thread).
+    pub fn try_lock(&self) -> Option<MutexGuard<Column>> {
+        match self.0.try_lock() {
+            Ok(g) => Some(g),
+            Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+
     pub fn downgrade(&self) -> WeakColumn {
         WeakColumn(Arc::downgrade(&self.0))
     }
@@ -131,10 +143,21 @@ impl ColumnCommandSender {
         self.send_task(ColumnCommand::SetClipVolume(args));
     }
 
-    pub fn record_clip(&self, slot_index: usize, instruction: SlotRecordInstruction) {
+    pub fn undo_audio_overdub(&self, slot_index: usize) {
+        let args = ColumnUndoAudioOverdubArgs { slot_index };
+        self.send_task(ColumnCommand::UndoAudioOverdub(args));
+    }
+
+    pub fn record_clip(
+        &self,
+        slot_index: usize,
+        instruction: SlotRecordInstruction,
+        frame_offset: usize,
+    ) {
         let args = ColumnRecordClipArgs {
             slot_index,
             instruction,
+            frame_offset,
         };
         self.send_task(ColumnCommand::RecordClip(Box::new(Some(args))));
     }
@@ -159,6 +182,7 @@ pub enum ColumnCommand {
     SeekClip(ColumnSeekClipArgs),
     SetClipVolume(ColumnSetClipVolumeArgs),
     SetClipLooped(ColumnSetClipLoopedArgs),
+    UndoAudioOverdub(ColumnUndoAudioOverdubArgs),
     RecordClip(Box<Option<ColumnRecordClipArgs>>),
 }
 
@@ -177,6 +201,10 @@ pub trait ColumnEventSender {
 
     fn midi_overdub_finished(&self, slot_index: usize, mirror_source: OwnedPcmSource);
 
+    fn audio_overdub_finished(&self, slot_index: usize, file: PathBuf);
+
+    fn audio_overdub_undone(&self, slot_index: usize);
+
     fn normal_recording_finished(&self, slot_index: usize, outcome: NormalRecordingOutcome);
 
     fn interaction_failed(&self, failure: InteractionFailure);
@@ -225,6 +253,16 @@ impl ColumnEventSender for Sender<ColumnEvent> {
         self.send_event(event);
     }
 
+    fn audio_overdub_finished(&self, slot_index: usize, file: PathBuf) {
+        let event = ColumnEvent::AudioOverdubFinished { slot_index, file };
+        self.send_event(event);
+    }
+
+    fn audio_overdub_undone(&self, slot_index: usize) {
+        let event = ColumnEvent::AudioOverdubUndone { slot_index };
+        self.send_event(event);
+    }
+
     fn normal_recording_finished(&self, slot_index: usize, outcome: NormalRecordingOutcome) {
         let event = ColumnEvent::NormalRecordingFinished {
             slot_index,
@@ -290,6 +328,7 @@ impl Column {
             mix_buffer_chunk: OwnedAudioBuffer::new(MAX_AUDIO_CHANNEL_COUNT, MAX_BLOCK_SIZE)
                 .into_inner(),
             timeline_was_paused_in_last_block: false,
+            pending_play_clip: None,
         }
     }
 
@@ -320,6 +359,7 @@ impl Column {
         let clip_args = ClipPlayArgs {
             timeline: &args.timeline,
             ref_pos: Some(ref_pos),
+            frame_offset: args.frame_offset,
             matrix_settings: &self.matrix_settings,
             column_settings: &self.settings,
         };
@@ -388,6 +428,10 @@ impl Column {
         get_slot_mut_insert(&mut self.slots, args.slot_index).set_clip_looped(args.looped)
     }
 
+    pub fn undo_audio_overdub(&mut self, slot_index: usize) -> ClipEngineResult<()> {
+        get_slot_mut(&mut self.slots, slot_index)?.undo_audio_overdub()
+    }
+
     pub fn clip_play_state(&self, slot_index: usize) -> ClipEngineResult<ClipPlayState> {
         Ok(get_slot(&self.slots, slot_index)?.clip()?.play_state())
     }
@@ -412,14 +456,28 @@ impl Column {
         }
     }
 
-    fn record_clip(
+    /// Starts recording right away.
+    ///
+    /// Usually, recording is started by sending a command. Calling this directly is useful when
+    /// the recording must start within the current audio block (e.g. after input detection). The
+    /// main thread receives an acknowledgement in both cases.
+    ///
+    /// The frame offset is the offset within the current audio block at which the recording
+    /// should start if its start timing is immediate (usually the offset of the triggering event).
+    pub fn record_clip(
         &mut self,
         slot_index: usize,
-        instruction: SlotRecordInstruction,
+        mut instruction: SlotRecordInstruction,
         audio_request_props: BasicAudioRequestProps,
+        frame_offset: usize,
     ) -> ClipEngineResult<()> {
         let slot = get_slot_mut_insert(&mut self.slots, slot_index);
-        let result = slot.record_clip(instruction, &self.matrix_settings, &self.settings);
+        let result = match check_record_instruction(&instruction, audio_request_props)
+            .and_then(|_| instruction.set_frame_offset(frame_offset))
+        {
+            Ok(_) => slot.record_clip(instruction, &self.matrix_settings, &self.settings),
+            Err(msg) => Err(ErrorWithPayload::new(msg, instruction)),
+        };
         let (informative_result, ack_result) = match result {
             Ok(slot_runtime_data) => {
                 if self.settings.play_mode.is_exclusive() {
@@ -491,7 +549,47 @@ impl Column {
         Ok(())
     }
 
+    /// Plays the clip as soon as the timeline runs at the tempo required by the play request.
+    ///
+    /// The timeline picks up tempo changes made by the main thread at the beginning of an audio
+    /// block only. Scheduling the clip before would quantize the start position according to the
+    /// old tempo.
+    fn play_clip_in_tempo(
+        &mut self,
+        mut pending: PendingPlayClip,
+        audio_request_props: BasicAudioRequestProps,
+    ) {
+        if let Some(tempo) = pending.args.tempo {
+            let current_tempo = pending.args.timeline.tempo_at(
+                pending
+                    .args
+                    .ref_pos
+                    .unwrap_or_else(|| pending.args.timeline.cursor_pos()),
+            );
+            let tempo_matches = (current_tempo.get() - tempo.get()).abs() < BASE_EPSILON;
+            if !tempo_matches && pending.remaining_block_count > 0 {
+                pending.remaining_block_count -= 1;
+                if let Some(obsolete) = self.pending_play_clip.replace(pending) {
+                    let result = self.play_clip(obsolete.args, audio_request_props);
+                    self.notify_user_about_failed_interaction(result);
+                }
+                return;
+            }
+            if pending.remaining_block_count < MAX_TEMPO_SYNC_BLOCK_COUNT {
+                // The reference position has been determined before the tempo change and the
+                // frame offset refers to an earlier block.
+                pending.args.ref_pos = None;
+                pending.args.frame_offset = 0;
+            }
+        }
+        let result = self.play_clip(pending.args, audio_request_props);
+        self.notify_user_about_failed_interaction(result);
+    }
+
     fn process_commands(&mut self, audio_request_props: BasicAudioRequestProps) {
+        if let Some(pending) = self.pending_play_clip.take() {
+            self.play_clip_in_tempo(pending, audio_request_props);
+        }
         while let Ok(task) = self.command_receiver.try_recv() {
             use ColumnCommand::*;
             match task {
@@ -515,8 +613,11 @@ impl Column {
                         .dispose(ColumnGarbage::FillSlotArgs(boxed_args));
                 }
                 PlayClip(args) => {
-                    let result = self.play_clip(args, audio_request_props);
-                    self.notify_user_about_failed_interaction(result);
+                    let pending = PendingPlayClip {
+                        args,
+                        remaining_block_count: MAX_TEMPO_SYNC_BLOCK_COUNT,
+                    };
+                    self.play_clip_in_tempo(pending, audio_request_props);
                 }
                 ProcessTransportChange(args) => {
                     self.process_transport_change(args);
@@ -537,10 +638,21 @@ impl Column {
                 SetClipLooped(args) => {
                     self.set_clip_looped(args).unwrap();
                 }
+                UndoAudioOverdub(args) => {
+                    let result = self.undo_audio_overdub(args.slot_index);
+                    if result.is_ok() {
+                        self.event_sender.audio_overdub_undone(args.slot_index);
+                    }
+                    self.notify_user_about_failed_interaction(result);
+                }
                 RecordClip(mut boxed_args) => {
                     let args = boxed_args.take().unwrap();
-                    let result =
-                        self.record_clip(args.slot_index, args.instruction, audio_request_props);
+                    let result = self.record_clip(
+                        args.slot_index,
+                        args.instruction,
+                        audio_request_props,
+                        args.frame_offset,
+                    );
                     self.notify_user_about_failed_interaction(result);
                     self.event_sender
                         .dispose(ColumnGarbage::RecordClipArgs(boxed_args));
@@ -611,6 +723,8 @@ impl Column {
             };
             // rt_debug!("block sr = {}, block length = {}, block time = {}, timeline cursor pos = {}, timeline cursor frame = {}",
             //          sample_rate, args.block.length(), args.block.time_s(), timeline_cursor_pos, timeline_cursor_frame);
+            // If multiple slots count in at the same time, we want to hear just one click.
+            let mut count_in_click = None;
             for (row, slot) in self.slots.iter_mut().enumerate() {
                 // Our strategy is to always write all available source channels into the mix
                 // buffer. From a performance perspective, it would actually be enough to take
@@ -674,6 +788,12 @@ impl Column {
                             .clip_play_state_changed(row, changed_play_state);
                     }
                 }
+                if count_in_click.is_none() {
+                    count_in_click = slot.clip().ok().and_then(|clip| clip.count_in_click());
+                }
+            }
+            if let Some(click) = count_in_click {
+                click.add_to(&mut output_buffer, args.block.sample_rate());
             }
         });
         debug_assert_eq!(args.block.samples_out(), args.block.length());
@@ -811,8 +931,23 @@ pub struct ColumnPlayClipArgs {
     pub timeline: HybridTimeline,
     /// Set this if you already have the current timeline position or want to play a batch of clips.
     pub ref_pos: Option<PositionInSeconds>,
+    /// Set this if the tempo has just been changed in order to play this clip. Then the clip
+    /// doesn't start before the timeline runs at this tempo.
+    pub tempo: Option<Bpm>,
+    /// Frame offset within the current audio block (relevant for immediate starts only).
+    pub frame_offset: usize,
 }
 
+#[derive(Debug)]
+struct PendingPlayClip {
+    args: ColumnPlayClipArgs,
+    /// Number of blocks after which the clip plays even if the tempo doesn't match.
+    remaining_block_count: u32,
+}
+
+/// Prevents waiting forever if something else (e.g. a tempo envelope) overrides the tempo.
+const MAX_TEMPO_SYNC_BLOCK_COUNT: u32 = 4;
+
 #[derive(Debug)]
 pub struct ColumnStopClipArgs {
     pub slot_index: usize,
@@ -842,6 +977,9 @@ pub struct ColumnSetClipVolumeArgs {
 pub struct ColumnRecordClipArgs {
     pub slot_index: usize,
     pub instruction: SlotRecordInstruction,
+    /// Frame offset within the audio block in which the recording starts (relevant for immediate
+    /// starts only).
+    pub frame_offset: usize,
 }
 
 #[derive(Debug)]
@@ -850,6 +988,11 @@ pub struct ColumnSetClipLoopedArgs {
     pub looped: bool,
 }
 
+#[derive(Debug)]
+pub struct ColumnUndoAudioOverdubArgs {
+    pub slot_index: usize,
+}
+
 pub struct ColumnWithSlotArgs<'a> {
     pub index: usize,
     pub use_slot: &'a dyn Fn(),
@@ -872,6 +1015,19 @@ fn get_slot_mut_insert(slots: &mut Vec<Slot>, index: usize) -> &mut Slot {
     slots.get_mut(index).unwrap()
 }
 
+fn check_record_instruction(
+    instruction: &SlotRecordInstruction,
+    audio_request_props: BasicAudioRequestProps,
+) -> ClipEngineResult<()> {
+    if let SlotRecordInstruction::AudioOverdub(i) = instruction {
+        // The input is mixed into the material without resampling.
+        if i.equipment.frame_rate() != audio_request_props.frame_rate {
+            return Err("audio overdub not possible because clip and device frame rate differ");
+        }
+    }
+    Ok(())
+}
+
 #[derive(Debug)]
 pub enum ColumnEvent {
     ClipPlayStateChanged {
@@ -896,6 +1052,15 @@ pub enum ColumnEvent {
         slot_index: usize,
         mirror_source: OwnedPcmSource,
     },
+    AudioOverdubFinished {
+        slot_index: usize,
+        /// File to which the overdubbed material is being written.
+        file: PathBuf,
+    },
+    /// The real-time clip switched back to the material before the last audio overdub layer.
+    AudioOverdubUndone {
+        slot_index: usize,
+    },
     NormalRecordingFinished {
         slot_index: usize,
         outcome: NormalRecordingOutcome,
@@ -937,6 +1102,11 @@ impl<'a> HandleSlotEvent for ClipEventHandler<'a> {
             .midi_overdub_finished(self.slot_index, mirror_source);
     }
 
+    fn audio_overdub_finished(&self, file: PathBuf) {
+        self.event_sender
+            .audio_overdub_finished(self.slot_index, file);
+    }
+
     fn normal_recording_finished(&self, outcome: NormalRecordingOutcome) {
         self.event_sender
             .normal_recording_finished(self.slot_index, outcome);
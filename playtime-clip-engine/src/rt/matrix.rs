@@ -171,18 +171,26 @@ impl Matrix {
         }
     }
 
-    pub fn play_clip(&self, coordinates: ClipSlotCoordinates) -> ClipEngineResult<()> {
+    /// Plays the given clip.
+    ///
+    /// The frame offset is the offset within the current audio block at which the clip should
+    /// start if its start timing is immediate (usually the offset of the triggering MIDI event).
+    pub fn play_clip(
+        &self,
+        coordinates: ClipSlotCoordinates,
+        frame_offset: usize,
+    ) -> ClipEngineResult<()> {
         let handle = self.column_handle(coordinates.column())?;
         let args = ColumnPlayClipArgs {
             slot_index: coordinates.row(),
             // TODO-medium This could be optimized. In real-time context, getting the timeline only
             //  once per block could save some resources. Sample with clip stop.
             timeline: self.timeline(),
-            // TODO-medium We could even take the frame offset of the MIDI
-            //  event into account and from that calculate the exact timeline position (within the
-            //  block). That amount of accuracy is probably not necessary, but it's almost too easy
-            //  to implement to not do it ... same with clip stop.
+            // TODO-medium We could also take the frame offset into account when calculating the
+            //  reference position for quantized starts ... same with clip stop.
             ref_pos: None,
+            tempo: None,
+            frame_offset,
         };
         handle.command_sender.play_clip(args);
         Ok(())
@@ -199,6 +207,22 @@ impl Matrix {
         Ok(())
     }
 
+    /// Requests recording a clip in the given slot.
+    ///
+    /// The recording needs to be prepared in the main thread, so it can't start within the
+    /// current audio block. It starts at the given frame offset of a later block, which at least
+    /// keeps the latency free of jitter.
+    pub fn record_clip(
+        &self,
+        coordinates: ClipSlotCoordinates,
+        frame_offset: usize,
+    ) -> ClipEngineResult<()> {
+        self.column_handle(coordinates.column())?;
+        self.main_command_sender
+            .try_send(main::MatrixCommand::RecordClip(coordinates, frame_offset))
+            .map_err(|_| "couldn't request recording from main thread")
+    }
+
     fn timeline(&self) -> HybridTimeline {
         clip_timeline(self.project, false)
     }
@@ -163,9 +163,29 @@ impl Slot {
                     Err(e) => Err(e.map_payload(MidiOverdub)),
                 }
             }
+            AudioOverdub(instruction) => {
+                debug!("Audio overdub");
+                let clip = match self.clip.as_mut() {
+                    None => {
+                        return Err(ErrorWithPayload::new(
+                            "slot empty",
+                            AudioOverdub(instruction),
+                        ));
+                    }
+                    Some(c) => c,
+                };
+                match clip.audio_overdub(instruction) {
+                    Ok(_) => Ok(None),
+                    Err(e) => Err(e.map_payload(AudioOverdub)),
+                }
+            }
         }
     }
 
+    pub fn undo_audio_overdub(&mut self) -> ClipEngineResult<()> {
+        self.clip_mut_internal()?.undo_audio_overdub()
+    }
+
     pub fn pause_clip(&mut self) -> ClipEngineResult<()> {
         self.clip_mut_internal()?.pause();
         Ok(())
@@ -283,6 +303,7 @@ impl Slot {
                     clip.play(ClipPlayArgs {
                         timeline: &args.column_args.timeline,
                         ref_pos: Some(args.column_args.timeline_cursor_pos),
+                        frame_offset: 0,
                         matrix_settings: args.matrix_settings,
                         column_settings: args.column_settings,
                     })?;
@@ -406,6 +427,7 @@ fn play_clip_by_transport(
     let args = ClipPlayArgs {
         timeline: &args.column_args.timeline,
         ref_pos: Some(args.column_args.timeline_cursor_pos),
+        frame_offset: 0,
         matrix_settings: args.matrix_settings,
         column_settings: args.column_settings,
     };
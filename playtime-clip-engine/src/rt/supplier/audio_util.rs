@@ -1,8 +1,11 @@
 use crate::conversion_util::adjust_proportionally_positive;
-use crate::rt::buffer::{AudioBuf, AudioBufMut};
+use crate::rt::buffer::{AudioBuf, AudioBufMut, OwnedAudioBuffer};
 use crate::rt::supplier::log_util::print_distance_from_beat_start_at;
-use crate::rt::supplier::{SupplyAudioRequest, SupplyResponse, SupplyResponseStatus};
-use reaper_medium::Hz;
+use crate::rt::supplier::{
+    AudioMaterialInfo, AudioSupplier, SupplyAudioRequest, SupplyRequestInfo, SupplyResponse,
+    SupplyResponseStatus,
+};
+use reaper_medium::{Hz, OwnedPcmSource};
 use std::cmp;
 
 /// Helper function for suppliers that read from sources and don't want to deal with
@@ -118,3 +121,27 @@ pub fn transfer_samples_from_buffer(buf: AudioBuf, req: SourceMaterialRequest) -
         buf.frame_count(),
     )
 }
+
+/// Reads the complete material of the given audio source into a new buffer.
+///
+/// Allocates, so don't call this in a real-time thread.
+pub fn read_complete_audio_material(
+    source: &mut OwnedPcmSource,
+    material_info: &AudioMaterialInfo,
+) -> OwnedAudioBuffer {
+    let mut content = OwnedAudioBuffer::new(material_info.channel_count, material_info.frame_count);
+    let request = SupplyAudioRequest {
+        start_frame: 0,
+        dest_sample_rate: None,
+        info: SupplyRequestInfo {
+            audio_block_frame_offset: 0,
+            requester: "read-complete-audio-material",
+            note: "",
+            is_realtime: false,
+        },
+        parent_request: None,
+        general_info: &Default::default(),
+    };
+    source.supply_audio(&request, &mut content.to_buf_mut());
+    content
+}
@@ -1,22 +1,32 @@
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 
 use crossbeam_channel::{Receiver, Sender};
 use playtime_api::AudioCacheBehavior;
 use reaper_medium::{BorrowedMidiEventList, OwnedPcmSource};
 
+use crate::metrics_util::{increment_counter, set_gauge};
 use crate::rt::buffer::{AudioBufMut, OwnedAudioBuffer};
 use crate::rt::source_util::pcm_source_is_midi;
-use crate::rt::supplier::audio_util::{supply_audio_material, transfer_samples_from_buffer};
+use crate::rt::supplier::audio_util::{
+    read_complete_audio_material, supply_audio_material, transfer_samples_from_buffer,
+};
 use crate::rt::supplier::{
     AudioMaterialInfo, AudioSupplier, MaterialInfo, MidiSupplier, PositionTranslationSkill,
-    SupplyAudioRequest, SupplyMidiRequest, SupplyRequestInfo, SupplyResponse, WithMaterialInfo,
-    WithSource,
+    SupplyAudioRequest, SupplyMidiRequest, SupplyResponse, WithMaterialInfo, WithSource,
 };
 use crate::ClipEngineResult;
 
+/// Incremented whenever a clip supplies material from its cache. Used for determining the least
+/// recently played clips.
+static ACCESS_CLOCK: AtomicU64 = AtomicU64::new(0);
+
 #[derive(Debug)]
 pub struct Cache<S> {
+    behavior: AudioCacheBehavior,
     cached_data: Option<CachedData>,
     request_sender: Sender<CacheRequest>,
     response_channel: CacheResponseChannel,
@@ -49,15 +59,22 @@ pub enum CacheRequest {
         response_sender: Sender<CacheResponse>,
     },
     DiscardCachedData(CachedData),
+    /// Sets the maximum number of bytes that all caches of the matrix may use together.
+    SetBudget(Option<usize>),
 }
 
 #[derive(Debug)]
 pub enum CacheResponse {
     CachedSource(CachedData),
+    /// Asks the cache to give back its cached data because the budget is exceeded.
+    EvictCachedData,
 }
 
 #[derive(Debug)]
 pub struct CachedData {
+    entry_id: u64,
+    /// Value of the access clock when the data was supplied the last time.
+    last_access: Arc<AtomicU64>,
     material_info: AudioMaterialInfo,
     file_path: PathBuf,
     content: OwnedAudioBuffer,
@@ -78,6 +95,7 @@ impl CachedData {
 impl<S: WithSource> Cache<S> {
     pub fn new(supplier: S, request_sender: Sender<CacheRequest>) -> Self {
         Self {
+            behavior: AudioCacheBehavior::DirectFromDisk,
             cached_data: None,
             request_sender,
             response_channel: CacheResponseChannel::new(),
@@ -93,7 +111,12 @@ impl<S: WithSource> Cache<S> {
         &mut self.supplier
     }
 
+    pub fn audio_cache_behavior(&self) -> AudioCacheBehavior {
+        self.behavior
+    }
+
     pub fn set_audio_cache_behavior(&mut self, cache_behavior: AudioCacheBehavior) {
+        self.behavior = cache_behavior;
         use AudioCacheBehavior::*;
         let cache_enabled = match cache_behavior {
             DirectFromDisk => false,
@@ -147,11 +170,17 @@ impl<S: WithSource> Cache<S> {
                 debug!("Cached audio material completely in memory");
                 self.cached_data = Some(cache_data);
             }
+            CacheResponse::EvictCachedData => {
+                // From now on, we read directly from disk.
+                debug!("Evicting audio material from memory");
+                self.disable();
+            }
         }
     }
 }
 
 pub fn keep_processing_cache_requests(receiver: Receiver<CacheRequest>) {
+    let mut manager = CacheManager::default();
     while let Ok(request) = receiver.recv() {
         use CacheRequest::*;
         match request {
@@ -159,42 +188,155 @@ pub fn keep_processing_cache_requests(receiver: Receiver<CacheRequest>) {
                 mut source,
                 response_sender,
             } => {
-                let _ = cache_source(&mut source, response_sender);
+                if let Err(e) = manager.cache_source(&mut source, response_sender) {
+                    debug!("Not caching audio material: {}", e);
+                }
+            }
+            DiscardCachedData(data) => {
+                manager.forget_entry(data.entry_id);
+            }
+            SetBudget(budget) => {
+                manager.budget = budget;
+                manager.enforce_budget();
+            }
+        }
+        manager.report_statistics();
+    }
+}
+
+/// Keeps track of the memory consumed by all caches of one matrix.
+///
+/// Lives in the cache worker thread.
+#[derive(Debug, Default)]
+struct CacheManager {
+    budget: Option<usize>,
+    entries: HashMap<u64, CacheEntry>,
+    next_entry_id: u64,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    size_in_bytes: usize,
+    last_access: Arc<AtomicU64>,
+    response_sender: Sender<CacheResponse>,
+    eviction_requested: bool,
+}
+
+impl CacheManager {
+    fn cache_source(
+        &mut self,
+        source: &mut OwnedPcmSource,
+        response_sender: Sender<CacheResponse>,
+    ) -> ClipEngineResult<()> {
+        let audio_material_info = match source.material_info() {
+            Ok(MaterialInfo::Audio(i)) => i,
+            _ => return Err("no audio source"),
+        };
+        let size_in_bytes = audio_material_info.channel_count
+            * audio_material_info.frame_count
+            * std::mem::size_of::<f64>();
+        if let Some(budget) = self.budget {
+            if size_in_bytes > budget {
+                return Err("material exceeds cache budget");
             }
-            DiscardCachedData(_) => {}
         }
+        let entry_id = self.next_entry_id;
+        self.next_entry_id += 1;
+        let last_access = Arc::new(AtomicU64::new(ACCESS_CLOCK.fetch_add(1, Ordering::Relaxed)));
+        cache_source(
+            source,
+            audio_material_info,
+            entry_id,
+            last_access.clone(),
+            response_sender.clone(),
+        )?;
+        let entry = CacheEntry {
+            size_in_bytes,
+            last_access,
+            response_sender,
+            eviction_requested: false,
+        };
+        self.entries.insert(entry_id, entry);
+        self.enforce_budget();
+        Ok(())
+    }
+
+    fn forget_entry(&mut self, entry_id: u64) {
+        self.entries.remove(&entry_id);
+    }
+
+    /// Asks the least recently played clips to give back their cached data until the memory
+    /// consumption fits into the budget again.
+    fn enforce_budget(&mut self) {
+        // Cached data which has been dropped without being discarded explicitly (e.g. because the
+        // clip is gone) doesn't consume memory anymore.
+        self.entries
+            .retain(|_, e| Arc::strong_count(&e.last_access) > 1);
+        let budget = match self.budget {
+            None => return,
+            Some(b) => b,
+        };
+        let mut bytes_to_free = self.bytes_used().saturating_sub(budget);
+        if bytes_to_free == 0 {
+            return;
+        }
+        let mut candidates: Vec<_> = self
+            .entries
+            .iter_mut()
+            .filter(|(_, e)| !e.eviction_requested)
+            .collect();
+        candidates.sort_by_key(|(_, e)| e.last_access.load(Ordering::Relaxed));
+        let mut disconnected_entry_ids = vec![];
+        for (id, entry) in candidates {
+            if bytes_to_free == 0 {
+                break;
+            }
+            if entry
+                .response_sender
+                .try_send(CacheResponse::EvictCachedData)
+                .is_err()
+            {
+                disconnected_entry_ids.push(*id);
+            }
+            entry.eviction_requested = true;
+            bytes_to_free = bytes_to_free.saturating_sub(entry.size_in_bytes);
+            increment_counter("cache.evictions");
+        }
+        for id in disconnected_entry_ids {
+            self.entries.remove(&id);
+        }
+    }
+
+    /// Includes the memory of entries which are about to be evicted.
+    fn bytes_used(&self) -> usize {
+        self.entries
+            .values()
+            .filter(|e| !e.eviction_requested)
+            .map(|e| e.size_in_bytes)
+            .sum()
+    }
+
+    fn report_statistics(&self) {
+        let total_bytes: usize = self.entries.values().map(|e| e.size_in_bytes).sum();
+        set_gauge("cache.bytes_used", total_bytes as f64);
+        set_gauge("cache.entry_count", self.entries.len() as f64);
     }
 }
 
 fn cache_source(
     source: &mut OwnedPcmSource,
+    audio_material_info: AudioMaterialInfo,
+    entry_id: u64,
+    last_access: Arc<AtomicU64>,
     response_sender: Sender<CacheResponse>,
 ) -> ClipEngineResult<()> {
-    let audio_material_info = match source.material_info() {
-        Ok(MaterialInfo::Audio(i)) => i,
-        _ => return Err("no audio source"),
-    };
     let file_path = source
         .get_file_name(|path| path.map(|p| p.to_path_buf()))
         .ok_or("source without file name")?;
-    let mut content = OwnedAudioBuffer::new(
-        audio_material_info.channel_count,
-        audio_material_info.frame_count,
-    );
-    let request = SupplyAudioRequest {
-        start_frame: 0,
-        dest_sample_rate: None,
-        info: SupplyRequestInfo {
-            audio_block_frame_offset: 0,
-            requester: "cache",
-            note: "",
-            is_realtime: false,
-        },
-        parent_request: None,
-        general_info: &Default::default(),
-    };
-    source.supply_audio(&request, &mut content.to_buf_mut());
+    let content = read_complete_audio_material(source, &audio_material_info);
     let cached_data = CachedData {
+        entry_id,
+        last_access,
         material_info: audio_material_info,
         file_path,
         content,
@@ -243,6 +385,8 @@ impl<S: AudioSupplier + WithSource> AudioSupplier for Cache<S> {
             None => return self.supplier.supply_audio(request, dest_buffer),
             Some(d) => d,
         };
+        let access_time = ACCESS_CLOCK.fetch_add(1, Ordering::Relaxed);
+        d.last_access.store(access_time, Ordering::Relaxed);
         let buf = d.content.to_buf();
         supply_audio_material(request, dest_buffer, d.material_info.frame_rate, |input| {
             transfer_samples_from_buffer(buf, input)
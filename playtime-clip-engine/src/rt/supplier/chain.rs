@@ -1,23 +1,24 @@
 use crate::mutex_util::non_blocking_lock;
 use crate::rt::supplier::{
-    Amplifier, AudioSupplier, Cache, CacheRequest, CommandProcessor, Downbeat, InteractionHandler,
-    LoopBehavior, Looper, MaterialInfo, MidiOverdubSettings, MidiSupplier, PollRecordingOutcome,
-    PositionTranslationSkill, PreBuffer, PreBufferCacheMissBehavior, PreBufferFillRequest,
-    PreBufferOptions, PreBufferRequest, PreBufferSourceSkill, RecordState, Recorder, RecordingArgs,
-    Resampler, Section, StartEndHandler, StopRecordingOutcome, SupplyAudioRequest,
-    SupplyMidiRequest, SupplyResponse, TimeStretcher, WithMaterialInfo, WriteAudioRequest,
-    WriteMidiRequest,
+    Amplifier, AudioOverdubEquipment, AudioSupplier, Cache, CacheRequest, CommandProcessor,
+    CountInClick, Downbeat, InteractionHandler, LoopBehavior, Looper, MaterialInfo,
+    MidiOverdubSettings, MidiSupplier, PollRecordingOutcome, PositionTranslationSkill, PreBuffer,
+    PreBufferCacheMissBehavior, PreBufferFillRequest, PreBufferOptions, PreBufferRequest,
+    PreBufferSourceSkill, RecordState, Recorder, RecordingArgs, Resampler, Section,
+    StartEndHandler, StopRecordingOutcome, SupplyAudioRequest, SupplyMidiRequest, SupplyResponse,
+    TimeStretcher, WithMaterialInfo, WriteAudioRequest, WriteMidiRequest,
 };
 use crate::rt::tempo_util::determine_tempo_from_beat_time_base;
 use crate::rt::{AudioBufMut, BasicAudioRequestProps};
-use crate::{ClipEngineResult, HybridTimeline};
+use crate::{ClipEngineResult, ErrorWithPayload, HybridTimeline};
 use crossbeam_channel::Sender;
 use playtime_api as api;
 use playtime_api::{
-    AudioCacheBehavior, AudioTimeStretchMode, ClipTimeBase, Db, MidiResetMessageRange,
-    PositiveBeat, PositiveSecond, VirtualResampleMode,
+    AudioCacheBehavior, AudioOverdubSettings, AudioTimeStretchMode, ClipTimeBase, Db,
+    MidiResetMessageRange, PositiveBeat, PositiveSecond, VirtualResampleMode,
 };
 use reaper_medium::{BorrowedMidiEventList, Bpm, OwnedPcmSource, PositionInSeconds};
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex, MutexGuard};
 
 /// The head of the supplier chain (just an alias).
@@ -130,6 +131,14 @@ type RecorderTail = Recorder;
 #[derive(Debug)]
 pub struct SupplierChain {
     head: Head,
+    /// Set while overdubbing audio in order to restore the chain afterwards.
+    state_before_audio_overdub: Option<StateBeforeAudioOverdub>,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct StateBeforeAudioOverdub {
+    pre_buffer_was_active: bool,
+    cache_behavior: AudioCacheBehavior,
 }
 
 impl SupplierChain {
@@ -157,6 +166,7 @@ impl SupplierChain {
                     )),
                 ))))
             },
+            state_before_audio_overdub: None,
         };
         // Configure resampler
         let resampler = chain.resampler_mut();
@@ -321,18 +331,90 @@ impl SupplierChain {
             .write_midi(request, translated_play_pos)
     }
 
-    pub fn write_audio(&mut self, request: impl WriteAudioRequest) {
-        // When recording, there's no contention.
+    pub fn start_audio_overdub(
+        &mut self,
+        equipment: AudioOverdubEquipment,
+        settings: AudioOverdubSettings,
+    ) -> Result<(), ErrorWithPayload<AudioOverdubEquipment>> {
+        // From now on, the recorder serves the material from memory and modifies it while playing.
+        // Neither the cache nor the pre-buffer must get in the way, otherwise we would hear stale
+        // material.
+        let pre_buffer_was_active = self.pre_buffer_supplier().is_active();
+        self.pre_buffer_mut().deactivate();
+        let mut entrance = self.pre_buffer_wormhole();
+        let cache_behavior = entrance.cache().audio_cache_behavior();
+        entrance
+            .cache()
+            .set_audio_cache_behavior(AudioCacheBehavior::DirectFromDisk);
+        let result = entrance.recorder().start_audio_overdub(equipment, settings);
+        drop(entrance);
+        // If we are overdubbing already, the saved state is the one before that overdub.
+        if self.state_before_audio_overdub.is_none() {
+            self.state_before_audio_overdub = Some(StateBeforeAudioOverdub {
+                pre_buffer_was_active,
+                cache_behavior,
+            });
+            if result.is_err() {
+                self.restore_state_before_audio_overdub();
+            }
+        }
+        result
+    }
+
+    /// Returns the file to which the overdubbed material is going to be written.
+    pub fn finish_audio_overdub(&mut self) -> Option<PathBuf> {
+        let file = self.pre_buffer_wormhole().recorder().finish_audio_overdub();
+        self.restore_state_before_audio_overdub();
+        file
+    }
+
+    fn restore_state_before_audio_overdub(&mut self) {
+        let state = match self.state_before_audio_overdub.take() {
+            None => return,
+            Some(s) => s,
+        };
+        self.pre_buffer_wormhole()
+            .cache()
+            .set_audio_cache_behavior(state.cache_behavior);
+        if state.pre_buffer_was_active {
+            let _ = self.pre_buffer_mut().activate();
+        }
+    }
+
+    pub fn undo_audio_overdub(&mut self) -> ClipEngineResult<()> {
+        self.pre_buffer_wormhole().recorder().undo_audio_overdub()
+    }
+
+    /// If we are in audio overdub mode, the play position parameter must be set.
+    pub fn write_audio(
+        &mut self,
+        request: impl WriteAudioRequest,
+        play_pos: Option<isize>,
+    ) -> ClipEngineResult<()> {
+        // When recording or overdubbing, there's no contention.
+        let translated_play_pos = match play_pos {
+            None => None,
+            Some(play_pos) => {
+                let translated = self.translate_play_pos_to_source_pos(play_pos);
+                if translated < 0 {
+                    return Err("translated play position is not within source bounds");
+                }
+                Some(translated as usize)
+            }
+        };
         self.pre_buffer_wormhole()
             .recorder()
-            .write_audio(request)
-            .unwrap();
+            .write_audio(request, translated_play_pos)
     }
 
     pub fn record_state(&self) -> Option<RecordState> {
         self.pre_buffer_wormhole().recorder().record_state()
     }
 
+    pub fn count_in_click(&self) -> Option<CountInClick> {
+        self.pre_buffer_wormhole().recorder().count_in_click()
+    }
+
     pub fn poll_recording(
         &mut self,
         audio_request_props: BasicAudioRequestProps,
@@ -363,6 +445,13 @@ impl SupplierChain {
             .unwrap();
     }
 
+    pub fn set_recording_frame_offset(&mut self, frame_offset: usize) -> ClipEngineResult<()> {
+        // When recording, there's no contention.
+        self.pre_buffer_wormhole()
+            .recorder()
+            .set_recording_frame_offset(frame_offset)
+    }
+
     fn set_audio_cache_behavior(&mut self, cache_behavior: AudioCacheBehavior) {
         use AudioCacheBehavior::*;
         let pre_buffer_enabled = match &cache_behavior {
@@ -372,7 +461,9 @@ impl SupplierChain {
         let command = ChainPreBufferCommand::SetAudioCacheBehavior(cache_behavior);
         self.pre_buffer_supplier().send_command(command);
         // Enable/disable pre-buffer accordingly (pre-buffering not necessary if we have the
-        // complete source material in memory already).
+        // complete source material in memory already). If the cache manager evicts the material
+        // because the cache budget is exceeded, the clip reads directly from disk, just like
+        // before the cache has been built.
         let pre_buffer = self.pre_buffer_mut();
         if pre_buffer_enabled {
             let _ = pre_buffer.activate();
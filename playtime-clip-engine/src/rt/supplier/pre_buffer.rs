@@ -360,6 +360,10 @@ where
         self.enabled = enabled;
     }
 
+    pub fn is_active(&self) -> bool {
+        self.state.is_active()
+    }
+
     /// # Errors
     ///
     /// Returns an error if the material can't or doesn't need to be buffered. In that case
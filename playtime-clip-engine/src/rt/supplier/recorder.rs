@@ -5,7 +5,9 @@ use crate::conversion_util::{
 use crate::file_util::get_path_for_new_media_file;
 use crate::rt::buffer::{AudioBuf, AudioBufMut, OwnedAudioBuffer};
 use crate::rt::schedule_util::{calc_distance_from_pos, calc_distance_from_quantized_pos};
-use crate::rt::supplier::audio_util::{supply_audio_material, transfer_samples_from_buffer};
+use crate::rt::supplier::audio_util::{
+    read_complete_audio_material, supply_audio_material, transfer_samples_from_buffer,
+};
 use crate::rt::supplier::{
     AudioMaterialInfo, AudioSupplier, MaterialInfo, MidiMaterialInfo, MidiSupplier,
     PositionTranslationSkill, SectionBounds, SupplyAudioRequest, SupplyMidiRequest, SupplyResponse,
@@ -16,12 +18,12 @@ use crate::rt::{
 };
 use crate::source_util::create_empty_midi_source;
 use crate::timeline::{clip_timeline, Timeline};
-use crate::{ClipEngineResult, HybridTimeline, Laziness, QuantizedPosition};
+use crate::{ClipEngineResult, ErrorWithPayload, HybridTimeline, Laziness, QuantizedPosition};
 use crossbeam_channel::{Receiver, Sender};
 use helgoboss_midi::Channel;
 use playtime_api::{
-    ClipPlayStartTiming, ClipRecordStartTiming, ClipRecordStopTiming, EvenQuantization,
-    MatrixClipRecordSettings, MidiClipRecordMode, RecordLength,
+    AudioOverdubSettings, ClipPlayStartTiming, ClipRecordStartTiming, ClipRecordStopTiming,
+    EvenQuantization, MatrixClipRecordSettings, MidiClipRecordMode, RecordLength,
 };
 use reaper_high::{OwnedSource, Project, Reaper};
 use reaper_low::raw;
@@ -31,9 +33,11 @@ use reaper_medium::{
     BorrowedMidiEventList, Bpm, DurationInBeats, DurationInSeconds, Hz, MidiFrameOffset,
     MidiImportBehavior, OwnedPcmSink, OwnedPcmSource, PositionInSeconds, TimeSignature,
 };
+use std::f64::consts::PI;
 use std::ffi::{c_void, CString};
 use std::path::{Path, PathBuf};
 use std::ptr::{null, null_mut, NonNull};
+use std::sync::Arc;
 use std::time::Duration;
 use std::{cmp, mem, thread};
 
@@ -74,6 +78,14 @@ pub enum RecorderRequest {
         file: PathBuf,
         old_source: Option<OwnedPcmSource>,
     },
+    /// Writes the given material to a file (if a sink is given) and responds with a copy of it.
+    SnapshotAudioOverdubMaterial {
+        material: Arc<OwnedAudioBuffer>,
+        pcm_sink: Option<OwnedPcmSink>,
+        response_sender: Sender<RecorderResponse>,
+    },
+    DiscardAudioOverdubData(AudioOverdubData),
+    DiscardAudioOverdubMaterial(Arc<OwnedAudioBuffer>),
 }
 
 #[derive(Debug)]
@@ -82,8 +94,9 @@ struct AudioRecordingFinishedResponse {
 }
 
 #[derive(Debug)]
-enum RecorderResponse {
+pub enum RecorderResponse {
     AudioRecordingFinished(AudioRecordingFinishedResponse),
+    AudioOverdubMaterialSnapshotTaken(Arc<OwnedAudioBuffer>),
 }
 
 /// State of the recorder.
@@ -105,6 +118,170 @@ struct ReadyState {
     /// This is updated with every overdub request and never cleared. So it can be `Some`
     /// even we are not currently overdubbing.   
     midi_overdub_settings: Option<MidiOverdubSettings>,
+    /// Set as soon as audio overdubbing starts. From then on, we play the material from memory.
+    audio_overdub_state: Option<AudioOverdubState>,
+    midi_replace_state: MidiReplaceState,
+}
+
+/// Keeps track of what happened during a MIDI overdub in replace mode.
+#[derive(Debug, Default)]
+struct MidiReplaceState {
+    /// The source frame of the block that has been replaced most recently.
+    ///
+    /// If input from multiple devices is written into the same block, only the first write must
+    /// erase the existing events. Otherwise it would erase the events written just before.
+    last_replaced_block_frame: Option<usize>,
+    /// Bit field of currently held notes (16 channels * 128 notes), maintained by REAPER.
+    overwrite_actives: [u32; MIDI_OVERWRITE_ACTIVES_LEN],
+}
+
+const MIDI_OVERWRITE_ACTIVES_LEN: usize = 16 * 128 / 32;
+
+impl MidiReplaceState {
+    /// Decides how to write the MIDI events of the block at the given source frame.
+    ///
+    /// Returns `None` if there's nothing to write.
+    fn effective_write_mode(
+        &mut self,
+        record_mode: MidiClipRecordMode,
+        block_frame: usize,
+        has_events: bool,
+    ) -> Option<MidiClipRecordMode> {
+        match record_mode {
+            MidiClipRecordMode::Replace if self.last_replaced_block_frame != Some(block_frame) => {
+                // Write even if there are no events. This erases the existing events in this
+                // block.
+                self.last_replaced_block_frame = Some(block_frame);
+                Some(MidiClipRecordMode::Replace)
+            }
+            // Replaced already in this block, so just add the events.
+            MidiClipRecordMode::Replace => Some(MidiClipRecordMode::Overdub).filter(|_| has_events),
+            m => Some(m).filter(|_| has_events),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct AudioOverdubState {
+    data: AudioOverdubData,
+    settings: AudioOverdubSettings,
+    /// Set while recording a layer.
+    layer: Option<AudioOverdubLayer>,
+    can_undo: bool,
+}
+
+/// Everything needed in order to overdub audio material.
+///
+/// Allocates, so it must be created in the main thread.
+#[derive(Debug)]
+pub struct AudioOverdubEquipment {
+    data: AudioOverdubData,
+    layer: AudioOverdubLayer,
+}
+
+/// The material buffers are never copied in the real-time thread. Copies are made either in the
+/// main thread when creating the equipment or by the recorder worker.
+#[derive(Debug)]
+pub struct AudioOverdubData {
+    /// Current material including all layers recorded so far.
+    ///
+    /// Shared with the recorder worker while it takes a snapshot. Modified only if not shared.
+    material: Arc<OwnedAudioBuffer>,
+    /// Material before the last layer was recorded (used for undo).
+    previous_material: Arc<OwnedAudioBuffer>,
+    /// Copy of the current material, taken by the recorder worker after finishing a layer.
+    ///
+    /// Becomes the previous material as soon as the next layer starts. `None` while the worker is
+    /// still busy.
+    snapshot: Option<Arc<OwnedAudioBuffer>>,
+    frame_rate: Hz,
+}
+
+/// Used for persisting a recorded layer.
+#[derive(Debug)]
+struct AudioOverdubLayer {
+    pcm_sink: OwnedPcmSink,
+    file: PathBuf,
+}
+
+impl AudioOverdubEquipment {
+    /// Reads the complete material of the given audio source into memory and prepares a new
+    /// file into which the result of the overdub will be written.
+    pub fn new(source: &mut OwnedPcmSource, project: Option<Project>) -> ClipEngineResult<Self> {
+        let material_info = match source.material_info()? {
+            MaterialInfo::Audio(i) => i,
+            MaterialInfo::Midi(_) => return Err("audio overdub not possible with MIDI source"),
+        };
+        let channel_count = material_info.channel_count;
+        if channel_count > MAX_AUDIO_CHANNEL_COUNT {
+            return Err("too many channels for audio overdub");
+        }
+        let material = read_complete_audio_material(source, &material_info);
+        let previous_material = copy_audio_buffer(&material);
+        let sink_outcome = create_audio_sink(project, channel_count, material_info.frame_rate);
+        let equipment = Self {
+            data: AudioOverdubData {
+                material: Arc::new(material),
+                previous_material: Arc::new(previous_material),
+                snapshot: None,
+                frame_rate: material_info.frame_rate,
+            },
+            layer: AudioOverdubLayer {
+                pcm_sink: sink_outcome.sink,
+                file: sink_outcome.file,
+            },
+        };
+        Ok(equipment)
+    }
+
+    /// The file to which the overdubbed material will be written.
+    pub fn file(&self) -> &Path {
+        &self.layer.file
+    }
+
+    /// Frame rate of the material. The input must have the same frame rate.
+    pub fn frame_rate(&self) -> Hz {
+        self.data.frame_rate
+    }
+}
+
+impl AudioOverdubState {
+    /// Mixes the given input into the material, starting at the given frame.
+    fn write_audio(&mut self, request: impl WriteAudioRequest, start_frame: usize) {
+        let audio_request_props = request.audio_request_props();
+        if audio_request_props.frame_rate != self.data.frame_rate {
+            // We don't resample the input, so we would mix it in at the wrong positions. Starting
+            // the overdub is refused in this case, so this only happens if the device frame rate
+            // changes while overdubbing.
+            return;
+        }
+        let material = match Arc::get_mut(&mut self.data.material) {
+            // Starting the overdub is refused while the recorder worker takes a snapshot of the
+            // material, so this shouldn't happen.
+            None => return,
+            Some(m) => m,
+        };
+        let mut material = material.to_buf_mut();
+        let channel_count = material.channel_count();
+        let frame_count = material.frame_count();
+        if frame_count == 0 {
+            return;
+        }
+        let feedback = self.settings.feedback;
+        let material_slice = material.data_as_mut_slice();
+        for ch in 0..channel_count {
+            let channel_buf = request.get_channel_buffer(ch);
+            for i in 0..audio_request_props.block_length {
+                let frame = (start_frame + i) % frame_count;
+                let index = frame * channel_count + ch;
+                let input = channel_buf
+                    .as_ref()
+                    .map(|b| b.data_as_slice()[i])
+                    .unwrap_or(0.0);
+                material_slice[index] = material_slice[index] * feedback + input;
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -123,7 +300,11 @@ struct RecordingState {
     time_signature: TimeSignature,
     start_timing: RecordInteractionTiming,
     stop_timing: RecordInteractionTiming,
+    /// Frame offset within the audio block in which the recording starts (relevant for immediate
+    /// starts only).
+    frame_offset: usize,
     recording: Option<Recording>,
+    count_in: Option<EvenQuantization>,
     length: RecordLength,
     committed: bool,
     initial_play_start_timing: ClipPlayStartTiming,
@@ -293,6 +474,8 @@ impl Recorder {
         let ready_state = ReadyState {
             source,
             midi_overdub_settings: None,
+            audio_overdub_state: None,
+            midi_replace_state: Default::default(),
         };
         Self {
             state: Some(State::Ready(ready_state)),
@@ -313,7 +496,9 @@ impl Recorder {
             time_signature: args.time_signature,
             start_timing: args.start_timing,
             stop_timing: args.stop_timing,
+            frame_offset: args.frame_offset,
             recording: None,
+            count_in: args.count_in,
             length: args.length,
             committed: false,
             initial_play_start_timing: args.initial_play_start_timing,
@@ -350,27 +535,32 @@ impl Recorder {
     pub fn record_state(&self) -> Option<RecordState> {
         match self.state.as_ref().unwrap() {
             State::Ready(_) => None,
-            State::Recording(s) => {
-                use RecordState::*;
-                let state = match s.recording {
-                    None => ScheduledForStart,
-                    Some(r) => {
-                        if r.is_still_in_count_in_phase() {
-                            ScheduledForStart
-                        } else if let Some(end) = r.scheduled_end {
-                            if end.is_predefined {
-                                Recording
-                            } else {
-                                ScheduledForStop
-                            }
-                        } else {
-                            Recording
-                        }
-                    }
-                };
-                Some(state)
-            }
+            State::Recording(s) => Some(record_state_of(s.recording.as_ref())),
+        }
+    }
+
+    /// Returns the click which makes the count-in audible if currently counting in.
+    pub fn count_in_click(&self) -> Option<CountInClick> {
+        let s = match self.state.as_ref().unwrap() {
+            State::Ready(_) => return None,
+            State::Recording(s) => s,
+        };
+        s.count_in?;
+        let recording = s.recording?;
+        if !recording.is_still_in_count_in_phase() {
+            return None;
         }
+        let remaining_frames = recording.num_count_in_frames - recording.total_frame_offset;
+        let click = CountInClick {
+            seconds_until_start: convert_duration_in_frames_to_seconds(
+                remaining_frames,
+                recording.frame_rate,
+            )
+            .get(),
+            beat_duration: 60.0 / s.tempo.get(),
+            beats_per_bar: s.time_signature.numerator.get(),
+        };
+        Some(click)
     }
 
     pub fn start_midi_overdub(
@@ -387,17 +577,150 @@ impl Recorder {
                     self.request_sender.discard_source(obsolete_source);
                 }
                 s.midi_overdub_settings = Some(settings);
+                s.midi_replace_state = Default::default();
                 Ok(())
             }
             State::Recording(_) => Err("recorder can't start overdubbing because it's recording"),
         }
     }
 
+    pub fn start_audio_overdub(
+        &mut self,
+        equipment: AudioOverdubEquipment,
+        settings: AudioOverdubSettings,
+    ) -> Result<(), ErrorWithPayload<AudioOverdubEquipment>> {
+        let s = match self.state.as_mut().unwrap() {
+            State::Ready(s) => s,
+            State::Recording(_) => {
+                return Err(ErrorWithPayload::new(
+                    "recorder can't start overdubbing because it's recording",
+                    equipment,
+                ))
+            }
+        };
+        let settings = sanitize_audio_overdub_settings(settings);
+        match s.audio_overdub_state.as_mut() {
+            None => {
+                // The equipment contains a copy of the material already, so we can undo.
+                s.audio_overdub_state = Some(AudioOverdubState {
+                    data: equipment.data,
+                    settings,
+                    layer: Some(equipment.layer),
+                    can_undo: true,
+                });
+            }
+            Some(overdub_state) => {
+                // We have the current material in memory already. Reading it from the file again
+                // is not reliable because the file of the last layer might not be written yet.
+                if overdub_state.layer.is_some() {
+                    return Err(ErrorWithPayload::new(
+                        "recorder is overdubbing already",
+                        equipment,
+                    ));
+                }
+                let data = &mut overdub_state.data;
+                let current_buf = data.material.to_buf();
+                let new_buf = equipment.data.material.to_buf();
+                if new_buf.channel_count() != current_buf.channel_count()
+                    || new_buf.frame_count() != current_buf.frame_count()
+                {
+                    return Err(ErrorWithPayload::new(
+                        "audio overdub equipment doesn't match material",
+                        equipment,
+                    ));
+                }
+                if Arc::strong_count(&data.material) > 1 {
+                    return Err(ErrorWithPayload::new(
+                        "last audio overdub layer is still being saved",
+                        equipment,
+                    ));
+                }
+                let snapshot = match data.snapshot.take() {
+                    None => {
+                        return Err(ErrorWithPayload::new(
+                            "last audio overdub layer is still being saved",
+                            equipment,
+                        ))
+                    }
+                    Some(s) => s,
+                };
+                let obsolete_material = mem::replace(&mut data.previous_material, snapshot);
+                self.request_sender
+                    .discard_audio_overdub_material(obsolete_material);
+                overdub_state.settings = settings;
+                overdub_state.layer = Some(equipment.layer);
+                overdub_state.can_undo = true;
+                self.request_sender
+                    .discard_audio_overdub_data(equipment.data);
+            }
+        };
+        Ok(())
+    }
+
+    /// Finishes the current audio overdub layer and returns the file to which the result is going
+    /// to be written.
+    ///
+    /// Returns `None` if not overdubbing audio.
+    pub fn finish_audio_overdub(&mut self) -> Option<PathBuf> {
+        let overdub_state = match self.state.as_mut().unwrap() {
+            State::Ready(ReadyState {
+                audio_overdub_state: Some(s),
+                ..
+            }) => s,
+            _ => return None,
+        };
+        let layer = overdub_state.layer.take()?;
+        // Writing the file and taking the snapshot for the next layer is up to the worker.
+        self.request_sender.snapshot_audio_overdub_material(
+            overdub_state.data.material.clone(),
+            Some(layer.pcm_sink),
+            self.response_channel.sender.clone(),
+        );
+        Some(layer.file)
+    }
+
+    /// Restores the material as it was before the last audio overdub layer.
+    pub fn undo_audio_overdub(&mut self) -> ClipEngineResult<()> {
+        let overdub_state = match self.state.as_mut().unwrap() {
+            State::Ready(ReadyState {
+                audio_overdub_state: Some(s),
+                ..
+            }) => s,
+            _ => return Err("no audio overdub happened"),
+        };
+        if overdub_state.layer.is_some() {
+            return Err("still overdubbing");
+        }
+        if !overdub_state.can_undo {
+            return Err("nothing to undo");
+        }
+        let data = &mut overdub_state.data;
+        // The snapshot doesn't match the restored material, so we need a new one.
+        let obsolete_snapshot = data
+            .snapshot
+            .take()
+            .ok_or("last audio overdub layer is still being saved")?;
+        mem::swap(&mut data.material, &mut data.previous_material);
+        self.request_sender
+            .discard_audio_overdub_material(obsolete_snapshot);
+        self.request_sender.snapshot_audio_overdub_material(
+            data.material.clone(),
+            None,
+            self.response_channel.sender.clone(),
+        );
+        overdub_state.can_undo = false;
+        Ok(())
+    }
+
     /// Can be called in a real-time thread (doesn't allocate).
     pub fn prepare_recording(&mut self, args: RecordingArgs) -> ClipEngineResult<()> {
         use State::*;
         let (res, next_state) = match self.state.take().unwrap() {
             Ready(s) => {
+                if let Some(overdub_state) = s.audio_overdub_state {
+                    self.request_sender
+                        .discard_audio_overdub_data(overdub_state.data);
+                }
                 let recording_state = RecordingState {
                     kind_state: KindState::new(args.equipment, &self.response_channel.sender),
                     old_source: Some(s.source),
@@ -407,7 +730,9 @@ impl Recorder {
                     time_signature: args.time_signature,
                     start_timing: args.start_timing,
                     stop_timing: args.stop_timing,
+                    frame_offset: args.frame_offset,
                     recording: None,
+                    count_in: args.count_in,
                     length: args.length,
                     committed: false,
                     initial_play_start_timing: args.initial_play_start_timing,
@@ -420,6 +745,21 @@ impl Recorder {
         res
     }
 
+    /// Makes a prepared recording start at the given frame within the audio block in which it
+    /// starts (if the start is immediate).
+    ///
+    /// Can be called in a real-time thread (doesn't allocate).
+    pub fn set_recording_frame_offset(&mut self, frame_offset: usize) -> ClipEngineResult<()> {
+        match self.state.as_mut().unwrap() {
+            State::Recording(s) if s.recording.is_none() => {
+                s.frame_offset = frame_offset;
+                Ok(())
+            }
+            State::Recording(_) => Err("recording started already"),
+            State::Ready(_) => Err("not recording"),
+        }
+    }
+
     pub fn stop_recording(
         &mut self,
         timeline: &HybridTimeline,
@@ -456,9 +796,22 @@ impl Recorder {
         outcome
     }
 
-    pub fn write_audio(&mut self, request: impl WriteAudioRequest) -> ClipEngineResult<()> {
+    pub fn write_audio(
+        &mut self,
+        request: impl WriteAudioRequest,
+        overdub_frame: Option<usize>,
+    ) -> ClipEngineResult<()> {
         match self.state.as_mut().unwrap() {
-            State::Ready(_) => Err("not recording"),
+            State::Ready(s) => match s.audio_overdub_state.as_mut() {
+                Some(overdub_state) if overdub_state.layer.is_some() => {
+                    overdub_state.write_audio(
+                        request,
+                        overdub_frame.ok_or("no audio overdub frame given")?,
+                    );
+                    Ok(())
+                }
+                _ => Err("neither recording nor overdubbing"),
+            },
             State::Recording(s) => {
                 if s.committed {
                     return Err("already committed");
@@ -536,12 +889,28 @@ impl Recorder {
             State::Ready(s) => match s.midi_overdub_settings.as_mut() {
                 None => Err("neither recording nor overdubbing"),
                 Some(overdub_settings) => {
+                    let overdub_frame = overdub_frame.expect("no MIDI overdub frame given");
+                    let replace_state = &mut s.midi_replace_state;
+                    let mode = match replace_state.effective_write_mode(
+                        overdub_settings.mode,
+                        overdub_frame,
+                        request.events.get_size() > 0,
+                    ) {
+                        None => return Ok(()),
+                        Some(m) => m,
+                    };
+                    let overwrite_actives = if mode == MidiClipRecordMode::Replace {
+                        Some(&mut replace_state.overwrite_actives)
+                    } else {
+                        None
+                    };
                     write_midi(
                         request,
                         &mut s.source,
-                        overdub_frame.expect("no MIDI overdub frame given"),
-                        overdub_settings.mode,
+                        overdub_frame,
+                        mode,
                         overdub_settings.quantization_settings.as_ref(),
+                        overwrite_actives,
                     );
                     Ok(())
                 }
@@ -579,12 +948,16 @@ impl Recorder {
                                 recording.first_play_frame = Some(event_frame);
                             }
                         }
+                        if request.events.get_size() == 0 {
+                            return Ok(());
+                        }
                         write_midi(
                             request,
                             &mut midi_state.new_source,
                             recording.total_frame_offset,
                             MidiClipRecordMode::Normal,
                             midi_state.quantization_settings.as_ref(),
+                            None,
                         );
                         Ok(())
                     }
@@ -617,6 +990,8 @@ impl Recorder {
                             let ready_state = ReadyState {
                                 source,
                                 midi_overdub_settings: None,
+                                audio_overdub_state: None,
+                                midi_replace_state: Default::default(),
                             };
                             Ready(ready_state)
                         }
@@ -638,6 +1013,23 @@ impl Recorder {
                 };
                 self.state = Some(next_state);
             }
+            RecorderResponse::AudioOverdubMaterialSnapshotTaken(snapshot) => {
+                let data = match self.state.as_mut().unwrap() {
+                    State::Ready(ReadyState {
+                        audio_overdub_state: Some(s),
+                        ..
+                    }) => &mut s.data,
+                    _ => {
+                        // Not overdubbing anymore
+                        self.request_sender.discard_audio_overdub_material(snapshot);
+                        return;
+                    }
+                };
+                if let Some(obsolete_snapshot) = data.snapshot.replace(snapshot) {
+                    self.request_sender
+                        .discard_audio_overdub_material(obsolete_snapshot);
+                }
+            }
         }
     }
 }
@@ -693,6 +1085,8 @@ impl RecordingState {
                         let ready_state = ReadyState {
                             source: old_source,
                             midi_overdub_settings: None,
+                            audio_overdub_state: None,
+                            midi_replace_state: Default::default(),
                         };
                         (
                             Ok(StopRecordingOutcome::Canceled),
@@ -794,22 +1188,66 @@ impl RecordingState {
             let timeline = clip_timeline(self.project, false);
             let timeline_cursor_pos = timeline.cursor_pos();
             let timeline_tempo = timeline.tempo_at(timeline_cursor_pos);
-            let (start_pos, frames_to_start_pos) = match self.start_timing {
-                RecordInteractionTiming::Immediately => (timeline_cursor_pos, 0),
+            let quantized_start_pos = match self.start_timing {
+                RecordInteractionTiming::Immediately => None,
                 RecordInteractionTiming::Quantized(quantization) => {
-                    let equipment = QuantizedPosCalcEquipment::new_with_unmodified_tempo(
-                        &timeline,
-                        timeline_cursor_pos,
-                        timeline_tempo,
-                        audio_request_props,
-                        self.kind_state.is_midi(),
-                    );
                     let quantized_start_pos = timeline.next_quantized_pos_at(
                         timeline_cursor_pos,
                         quantization,
                         Laziness::EagerForNextPos,
                     );
                     debug!("Calculated quantized start pos {:?}", quantized_start_pos);
+                    Some(quantized_start_pos)
+                }
+            };
+            // The count-in just defers the start of the recording. Material played during the
+            // count-in phase is recorded as well (it might contain pick-up beats).
+            let quantized_start_pos = match self.count_in {
+                None => quantized_start_pos,
+                Some(count_in) => {
+                    match quantized_pos_after(
+                        &timeline,
+                        timeline_cursor_pos,
+                        quantized_start_pos,
+                        count_in,
+                    ) {
+                        Ok(pos) => {
+                            debug!("Calculated start pos after count-in {:?}", pos);
+                            Some(pos)
+                        }
+                        Err(e) => {
+                            debug!("Couldn't calculate count-in, starting without: {}", e);
+                            quantized_start_pos
+                        }
+                    }
+                }
+            };
+            let equipment = QuantizedPosCalcEquipment::new_with_unmodified_tempo(
+                &timeline,
+                timeline_cursor_pos,
+                timeline_tempo,
+                audio_request_props,
+                self.kind_state.is_midi(),
+            );
+            let (start_pos, frames_to_start_pos) = match quantized_start_pos {
+                // Neither quantized nor counting in. Start immediately.
+                None if self.frame_offset == 0 => (timeline_cursor_pos, 0),
+                // Start immediately but not at the beginning of the block. The material before
+                // the frame offset is treated like count-in material.
+                None => {
+                    let frame_offset = cmp::min(
+                        self.frame_offset,
+                        audio_request_props.block_length.saturating_sub(1),
+                    );
+                    let offset_in_secs = convert_duration_in_frames_to_seconds(
+                        frame_offset,
+                        audio_request_props.frame_rate,
+                    );
+                    let start_pos = timeline_cursor_pos + offset_in_secs;
+                    let frames_from_start_pos = calc_distance_from_pos(start_pos, equipment);
+                    (start_pos, cmp::max(0, -frames_from_start_pos) as usize)
+                }
+                Some(quantized_start_pos) => {
                     let start_pos = timeline.pos_of_quantized_pos(quantized_start_pos);
                     let frames_from_start_pos = calc_distance_from_pos(start_pos, equipment);
                     assert!(frames_from_start_pos < 0);
@@ -830,6 +1268,7 @@ impl RecordingState {
                     &timeline,
                     audio_request_props,
                     start_pos,
+                    quantized_start_pos,
                     frames_to_start_pos,
                 ),
             };
@@ -890,6 +1329,8 @@ impl RecordingState {
                 let ready_state = ReadyState {
                     source: midi_state.new_source,
                     midi_overdub_settings: None,
+                    audio_overdub_state: None,
+                    midi_replace_state: Default::default(),
                 };
                 (outcome, State::Ready(ready_state))
             }
@@ -928,6 +1369,7 @@ impl RecordingState {
         timeline: &HybridTimeline,
         audio_request_props: BasicAudioRequestProps,
         start_pos: PositionInSeconds,
+        quantized_start_pos: Option<QuantizedPosition>,
         frames_to_start_pos: usize,
     ) -> Option<ScheduledEnd> {
         match self.length {
@@ -944,6 +1386,30 @@ impl RecordingState {
                 );
                 Some(end)
             }
+            RecordLength::Fixed(length) => {
+                let quantized_end_pos =
+                    match quantized_pos_after(timeline, start_pos, quantized_start_pos, length) {
+                        Ok(p) => p,
+                        Err(e) => {
+                            debug!(
+                                "Couldn't calculate fixed end pos, recording open-end: {}",
+                                e
+                            );
+                            return None;
+                        }
+                    };
+                debug!("Calculated fixed end pos {:?}", quantized_end_pos);
+                let end = calculate_scheduled_end_at(
+                    timeline,
+                    start_pos,
+                    audio_request_props,
+                    quantized_end_pos,
+                    frames_to_start_pos,
+                    self.kind_state.is_midi(),
+                    true,
+                );
+                Some(end)
+            }
         }
     }
 }
@@ -1060,7 +1526,15 @@ impl AudioSupplier for Recorder {
     ) -> SupplyResponse {
         self.process_worker_response();
         match self.state.as_mut().unwrap() {
-            State::Ready(s) => s.source.supply_audio(request, dest_buffer),
+            State::Ready(s) => match &s.audio_overdub_state {
+                None => s.source.supply_audio(request, dest_buffer),
+                Some(overdub_state) => {
+                    let data = &overdub_state.data;
+                    supply_audio_material(request, dest_buffer, data.frame_rate, |input| {
+                        transfer_samples_from_buffer(data.material.to_buf(), input)
+                    })
+                }
+            },
             State::Recording(s) => {
                 match &s.kind_state {
                     KindState::Audio(RecordingAudioState::Finishing(finishing_state)) => {
@@ -1225,12 +1699,13 @@ impl CompleteRecordingData {
         ))
     }
 
-    pub fn downbeat_in_beats(&self) -> DurationInBeats {
+    /// Converts the downbeat position to beats using the given tempo (usually the recording tempo).
+    pub fn downbeat_in_beats(&self, tempo: Bpm) -> DurationInBeats {
         let downbeat_in_secs = convert_duration_in_frames_to_seconds(
             self.section_and_downbeat_data.downbeat_frame,
             self.frame_rate,
         );
-        let bps = self.tempo.get() / 60.0;
+        let bps = tempo.get() / 60.0;
         DurationInBeats::new(downbeat_in_secs.get() * bps)
     }
 }
@@ -1238,6 +1713,9 @@ impl CompleteRecordingData {
 impl WithSource for Recorder {
     fn source(&self) -> Option<&OwnedPcmSource> {
         match self.state.as_ref().unwrap() {
+            // While overdubbing audio (and as long as the layer can be undone), the material is
+            // served from memory, so the file doesn't reflect what's playing.
+            State::Ready(s) if s.audio_overdub_state.is_some() => None,
             State::Ready(s) => Some(&s.source),
             State::Recording(_) => {
                 // The "current source" during recording state can change quickly. We don't want
@@ -1257,8 +1735,14 @@ pub struct RecordingArgs {
     pub detect_downbeat: bool,
     pub start_timing: RecordInteractionTiming,
     pub stop_timing: RecordInteractionTiming,
+    pub count_in: Option<EvenQuantization>,
     pub length: RecordLength,
     pub initial_play_start_timing: ClipPlayStartTiming,
+    /// Frame offset within the audio block in which the recording starts.
+    ///
+    /// Set this to the offset of the triggering event in order to start sample-accurately.
+    /// Doesn't have any effect if the start is quantized or counted in.
+    pub frame_offset: usize,
 }
 
 impl RecordingArgs {
@@ -1292,8 +1776,10 @@ impl RecordingArgs {
                 matrix_record_settings.start_timing,
                 initial_play_start_timing,
             ),
+            count_in: matrix_record_settings.count_in,
             length: matrix_record_settings.duration,
             initial_play_start_timing,
+            frame_offset: 0,
         }
     }
 }
@@ -1348,6 +1834,17 @@ trait RecorderRequestSender {
         old_source: Option<OwnedPcmSource>,
     );
 
+    fn snapshot_audio_overdub_material(
+        &self,
+        material: Arc<OwnedAudioBuffer>,
+        pcm_sink: Option<OwnedPcmSink>,
+        response_sender: Sender<RecorderResponse>,
+    );
+
+    fn discard_audio_overdub_data(&self, data: AudioOverdubData);
+
+    fn discard_audio_overdub_material(&self, material: Arc<OwnedAudioBuffer>);
+
     fn send_request(&self, request: RecorderRequest);
 }
 
@@ -1375,6 +1872,30 @@ impl RecorderRequestSender for Sender<RecorderRequest> {
         let _ = self.try_send(request);
     }
 
+    fn snapshot_audio_overdub_material(
+        &self,
+        material: Arc<OwnedAudioBuffer>,
+        pcm_sink: Option<OwnedPcmSink>,
+        response_sender: Sender<RecorderResponse>,
+    ) {
+        let request = RecorderRequest::SnapshotAudioOverdubMaterial {
+            material,
+            pcm_sink,
+            response_sender,
+        };
+        self.send_request(request);
+    }
+
+    fn discard_audio_overdub_data(&self, data: AudioOverdubData) {
+        let request = RecorderRequest::DiscardAudioOverdubData(data);
+        let _ = self.try_send(request);
+    }
+
+    fn discard_audio_overdub_material(&self, material: Arc<OwnedAudioBuffer>) {
+        let request = RecorderRequest::DiscardAudioOverdubMaterial(material);
+        let _ = self.try_send(request);
+    }
+
     fn send_request(&self, request: RecorderRequest) {
         self.try_send(request).unwrap();
     }
@@ -1418,6 +1939,24 @@ impl RecorderWorker {
             }
             DiscardSource(_) => {}
             DiscardAudioRecordingFinishingData { .. } => {}
+            SnapshotAudioOverdubMaterial {
+                material,
+                pcm_sink,
+                response_sender,
+            } => {
+                if let Some(pcm_sink) = pcm_sink {
+                    write_audio_overdub_layer(&material, pcm_sink);
+                }
+                let snapshot = Arc::new(copy_audio_buffer(&material));
+                // The real-time thread may modify the material again as soon as we don't share it
+                // anymore, so release it before responding.
+                drop(material);
+                let _ = response_sender.try_send(
+                    RecorderResponse::AudioOverdubMaterialSnapshotTaken(snapshot),
+                );
+            }
+            DiscardAudioOverdubData(_) => {}
+            DiscardAudioOverdubMaterial(_) => {}
         }
     }
 
@@ -1438,6 +1977,52 @@ impl RecorderWorker {
     }
 }
 
+fn write_audio_overdub_layer(material: &OwnedAudioBuffer, mut pcm_sink: OwnedPcmSink) {
+    let buf = material.to_buf();
+    let spacing = buf.channel_count();
+    // Equipment with more channels is refused, so this is just a safety net.
+    let channel_count = spacing.min(MAX_AUDIO_CHANNEL_COUNT);
+    if channel_count == 0 || buf.frame_count() == 0 {
+        return;
+    }
+    // The buffer is interleaved, so each channel pointer points to the first sample of the
+    // channel and the spacing is the channel count.
+    let mut channel_pointers: [*mut f64; MAX_AUDIO_CHANNEL_COUNT] =
+        [null_mut(); MAX_AUDIO_CHANNEL_COUNT];
+    let data_ptr = buf.data_as_mut_ptr();
+    for (ch, channel_pointer) in channel_pointers.iter_mut().enumerate().take(channel_count) {
+        *channel_pointer = unsafe { data_ptr.add(ch) };
+    }
+    let sink = pcm_sink.as_mut().as_mut();
+    unsafe {
+        sink.WriteDoubles(
+            &mut channel_pointers as *mut _,
+            buf.frame_count() as _,
+            channel_count as _,
+            0,
+            spacing as _,
+        );
+    }
+    // Dropping the sink finishes writing the file.
+}
+
+fn copy_audio_buffer(buffer: &OwnedAudioBuffer) -> OwnedAudioBuffer {
+    let buf = buffer.to_buf();
+    let mut copy = OwnedAudioBuffer::new(buf.channel_count(), buf.frame_count());
+    buf.copy_to(&mut copy.to_buf_mut());
+    copy
+}
+
+/// Makes sure that the feedback neither amplifies the material nor makes it unusable.
+fn sanitize_audio_overdub_settings(settings: AudioOverdubSettings) -> AudioOverdubSettings {
+    let feedback = if settings.feedback.is_nan() {
+        1.0
+    } else {
+        settings.feedback.clamp(0.0, 1.0)
+    };
+    AudioOverdubSettings { feedback }
+}
+
 /// Writes the given slice to the sink.
 ///
 /// The slice must contain all channels in sequence.
@@ -1486,6 +2071,7 @@ fn write_midi(
     block_pos_frame: usize,
     record_mode: MidiClipRecordMode,
     quantization_settings: Option<&QuantizationSettings>,
+    overwrite_actives: Option<&mut [u32; MIDI_OVERWRITE_ACTIVES_LEN]>,
 ) {
     let global_time = convert_duration_in_frames_to_seconds(block_pos_frame, MIDI_FRAME_RATE);
     let overwrite_mode = match record_mode {
@@ -1511,8 +2097,10 @@ fn write_midi(
         overwritemode: overwrite_mode,
         events: unsafe { request.events.as_ptr().as_mut() },
         latency: 0.0,
-        // Not used
-        overwrite_actives: null_mut(),
+        // Used in replace mode in order to end notes which are held while replacing
+        overwrite_actives: overwrite_actives
+            .map(|a| a.as_mut_ptr() as *mut _)
+            .unwrap_or(null_mut()),
     };
     let quantize_mode = quantization_settings.map(|_| raw::midi_quantize_mode_t {
         doquant: true,
@@ -1583,6 +2171,26 @@ fn calculate_scheduled_end(
         Laziness::EagerForNextPos,
     );
     debug!("Calculated quantized end pos {:?}", quantized_end_pos);
+    calculate_scheduled_end_at(
+        timeline,
+        timeline_cursor_pos,
+        audio_request_props,
+        quantized_end_pos,
+        total_frame_offset,
+        is_midi,
+        is_predefined,
+    )
+}
+
+fn calculate_scheduled_end_at(
+    timeline: &HybridTimeline,
+    timeline_cursor_pos: PositionInSeconds,
+    audio_request_props: BasicAudioRequestProps,
+    quantized_end_pos: QuantizedPosition,
+    total_frame_offset: usize,
+    is_midi: bool,
+    is_predefined: bool,
+) -> ScheduledEnd {
     let equipment = QuantizedPosCalcEquipment::new_with_unmodified_tempo(
         timeline,
         timeline_cursor_pos,
@@ -1601,13 +2209,461 @@ fn calculate_scheduled_end(
     }
 }
 
+/// Returns the quantized position which is the given length after the given start.
+///
+/// If the start is a quantized position which lies on the grid defined by the length (e.g. a bar
+/// when the length is measured in bars), the length is added to it exactly. Otherwise the start
+/// is moved forward to the next grid position first, staying on the current one if it's on the
+/// grid already.
+fn quantized_pos_after(
+    timeline: &HybridTimeline,
+    pos: PositionInSeconds,
+    quantized_pos: Option<QuantizedPosition>,
+    length: EvenQuantization,
+) -> ClipEngineResult<QuantizedPosition> {
+    let start = match quantized_pos.and_then(|qp| convert_quantized_pos(qp, length.denominator())) {
+        Some(start) => start,
+        None => {
+            let pos = quantized_pos
+                .map(|qp| timeline.pos_of_quantized_pos(qp))
+                .unwrap_or(pos);
+            let grid = EvenQuantization::new(1, length.denominator())?;
+            timeline.next_quantized_pos_at(pos, grid, Laziness::DwellingOnCurrentPos)
+        }
+    };
+    advance_quantized_pos(start, length)
+}
+
+/// Expresses the given quantized position in the given denominator, if possible without loss.
+fn convert_quantized_pos(pos: QuantizedPosition, denominator: u32) -> Option<QuantizedPosition> {
+    if denominator % pos.denominator() != 0 {
+        return None;
+    }
+    let factor = (denominator / pos.denominator()) as i64;
+    QuantizedPosition::new(pos.position() * factor, denominator).ok()
+}
+
+/// Adds the given length to the given position, which must be expressed in the length's
+/// denominator.
+fn advance_quantized_pos(
+    pos: QuantizedPosition,
+    length: EvenQuantization,
+) -> ClipEngineResult<QuantizedPosition> {
+    if pos.denominator() != length.denominator() {
+        return Err("position and length have different denominators");
+    }
+    QuantizedPosition::new(
+        pos.position() + length.numerator() as i64,
+        length.denominator(),
+    )
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub enum RecordState {
     ScheduledForStart,
     Recording,
     ScheduledForStop,
 }
 
+/// The count-in phase counts as "scheduled for start", so controllers can display it.
+fn record_state_of(recording: Option<&Recording>) -> RecordState {
+    use RecordState::*;
+    match recording {
+        None => ScheduledForStart,
+        Some(r) => {
+            if r.is_still_in_count_in_phase() {
+                ScheduledForStart
+            } else if let Some(end) = r.scheduled_end {
+                if end.is_predefined {
+                    Recording
+                } else {
+                    ScheduledForStop
+                }
+            } else {
+                Recording
+            }
+        }
+    }
+}
+
+/// Click which makes the count-in audible.
+///
+/// We don't use REAPER's metronome because it's audible only while the project is playing.
+#[derive(Copy, Clone, Debug)]
+pub struct CountInClick {
+    seconds_until_start: f64,
+    beat_duration: f64,
+    beats_per_bar: u32,
+}
+
+const COUNT_IN_CLICK_DURATION: f64 = 0.03;
+const COUNT_IN_CLICK_AMPLITUDE: f64 = 0.5;
+const COUNT_IN_CLICK_FREQUENCY: f64 = 1000.0;
+const COUNT_IN_CLICK_ACCENT_FREQUENCY: f64 = 1500.0;
+
+impl CountInClick {
+    /// Adds the click to the given buffer, which is supposed to start now.
+    pub fn add_to(&self, dest_buffer: &mut AudioBufMut, frame_rate: Hz) {
+        dest_buffer.modify_frames(|sample| {
+            let seconds_since_now = sample.index.frame as f64 / frame_rate.get();
+            sample.value + self.sample_value_at(self.seconds_until_start - seconds_since_now)
+        });
+    }
+
+    /// Returns the click sample at the given time before the recording starts.
+    ///
+    /// There's one click on each beat of the count-in, the first beat of each bar is accented.
+    fn sample_value_at(&self, seconds_until_start: f64) -> f64 {
+        if seconds_until_start <= 0.0 {
+            return 0.0;
+        }
+        let beats_until_start = (seconds_until_start / self.beat_duration).ceil();
+        let seconds_since_beat = beats_until_start * self.beat_duration - seconds_until_start;
+        if seconds_since_beat >= COUNT_IN_CLICK_DURATION {
+            return 0.0;
+        }
+        let frequency = if beats_until_start as u32 % self.beats_per_bar == 0 {
+            COUNT_IN_CLICK_ACCENT_FREQUENCY
+        } else {
+            COUNT_IN_CLICK_FREQUENCY
+        };
+        let envelope = 1.0 - seconds_since_beat / COUNT_IN_CLICK_DURATION;
+        COUNT_IN_CLICK_AMPLITUDE * envelope * (2.0 * PI * frequency * seconds_since_beat).sin()
+    }
+}
+
 const MAX_AUDIO_CHANNEL_COUNT: usize = 64;
 
 #[derive(Clone, Debug)]
 pub struct QuantizationSettings {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn midi_replace_erases_block_without_events() {
+        // Given
+        let mut state = MidiReplaceState::default();
+        // When
+        let mode = state.effective_write_mode(MidiClipRecordMode::Replace, 0, false);
+        // Then
+        assert_eq!(mode, Some(MidiClipRecordMode::Replace));
+    }
+
+    #[test]
+    fn midi_replace_erases_only_once_per_block() {
+        // Given
+        let mut state = MidiReplaceState::default();
+        state.effective_write_mode(MidiClipRecordMode::Replace, 512, true);
+        // When
+        let mode_with_events = state.effective_write_mode(MidiClipRecordMode::Replace, 512, true);
+        let mode_without_events =
+            state.effective_write_mode(MidiClipRecordMode::Replace, 512, false);
+        // Then
+        assert_eq!(mode_with_events, Some(MidiClipRecordMode::Overdub));
+        assert_eq!(mode_without_events, None);
+    }
+
+    #[test]
+    fn midi_replace_erases_again_in_next_block() {
+        // Given
+        let mut state = MidiReplaceState::default();
+        state.effective_write_mode(MidiClipRecordMode::Replace, 512, true);
+        // When
+        let mode = state.effective_write_mode(MidiClipRecordMode::Replace, 1024, false);
+        // Then
+        assert_eq!(mode, Some(MidiClipRecordMode::Replace));
+        assert_eq!(state.last_replaced_block_frame, Some(1024));
+    }
+
+    #[test]
+    fn midi_overdub_skips_block_without_events() {
+        // Given
+        let mut state = MidiReplaceState::default();
+        // When
+        let mode_without_events = state.effective_write_mode(MidiClipRecordMode::Overdub, 0, false);
+        let mode_with_events = state.effective_write_mode(MidiClipRecordMode::Overdub, 0, true);
+        // Then
+        assert_eq!(mode_without_events, None);
+        assert_eq!(mode_with_events, Some(MidiClipRecordMode::Overdub));
+        assert_eq!(state.last_replaced_block_frame, None);
+    }
+
+    #[test]
+    fn audio_overdub_mixes_input_with_feedback() {
+        // Given
+        let mut state = audio_overdub_state(&[0.5, -0.5, 0.5, -0.5], 2, 1.0);
+        let request = TestWriteAudioRequest::new(vec![vec![0.1, 0.2], vec![0.3, 0.4]]);
+        // When
+        state.write_audio(request, 0);
+        // Then
+        assert_material(&state, &[0.6, -0.2, 0.7, -0.1]);
+    }
+
+    #[test]
+    fn audio_overdub_attenuates_existing_material() {
+        // Given
+        let mut state = audio_overdub_state(&[1.0, 1.0, 1.0, 1.0], 1, 0.5);
+        let request = TestWriteAudioRequest::new(vec![vec![0.0, 0.25]]);
+        // When
+        state.write_audio(request, 1);
+        // Then
+        assert_material(&state, &[1.0, 0.5, 0.75, 1.0]);
+    }
+
+    #[test]
+    fn audio_overdub_replaces_material_with_zero_feedback() {
+        // Given
+        let mut state = audio_overdub_state(&[1.0, 1.0, 1.0], 1, 0.0);
+        let request = TestWriteAudioRequest::new(vec![vec![0.1, 0.2, 0.3]]);
+        // When
+        state.write_audio(request, 0);
+        // Then
+        assert_material(&state, &[0.1, 0.2, 0.3]);
+    }
+
+    #[test]
+    fn audio_overdub_wraps_around_material_end() {
+        // Given
+        let mut state = audio_overdub_state(&[0.0, 0.0, 0.0], 1, 1.0);
+        let request = TestWriteAudioRequest::new(vec![vec![0.1, 0.2]]);
+        // When
+        state.write_audio(request, 2);
+        // Then
+        assert_material(&state, &[0.2, 0.0, 0.1]);
+    }
+
+    #[test]
+    fn audio_overdub_treats_missing_input_channel_as_silence() {
+        // Given
+        let mut state = audio_overdub_state(&[0.5, 0.5], 2, 0.5);
+        let request = TestWriteAudioRequest::new(vec![vec![0.1]]);
+        // When
+        state.write_audio(request, 0);
+        // Then
+        assert_material(&state, &[0.35, 0.25]);
+    }
+
+    #[test]
+    fn audio_overdub_ignores_input_with_other_frame_rate() {
+        // Given
+        let mut state = audio_overdub_state(&[0.5, 0.5], 1, 1.0);
+        let mut request = TestWriteAudioRequest::new(vec![vec![0.1, 0.1]]);
+        request.props.frame_rate = Hz::new(44100.0);
+        // When
+        state.write_audio(request, 0);
+        // Then
+        assert_material(&state, &[0.5, 0.5]);
+    }
+
+    #[test]
+    fn audio_overdub_clamps_feedback() {
+        // Given
+        let mut state = audio_overdub_state(&[1.0, 1.0], 1, 2.0);
+        let request = TestWriteAudioRequest::new(vec![vec![0.5, 0.5]]);
+        // When
+        state.write_audio(request, 0);
+        // Then
+        assert_material(&state, &[1.5, 1.5]);
+    }
+
+    #[test]
+    fn audio_overdub_doesnt_modify_material_while_worker_takes_snapshot() {
+        // Given
+        let mut state = audio_overdub_state(&[0.5, 0.5], 1, 1.0);
+        let shared_material = state.data.material.clone();
+        let request = TestWriteAudioRequest::new(vec![vec![0.1, 0.1]]);
+        // When
+        state.write_audio(request, 0);
+        // Then
+        drop(shared_material);
+        assert_material(&state, &[0.5, 0.5]);
+    }
+
+    /// Creates an overdub state with the given interleaved material.
+    fn audio_overdub_state(
+        interleaved_material: &[f64],
+        channel_count: usize,
+        feedback: f64,
+    ) -> AudioOverdubState {
+        let frame_count = interleaved_material.len() / channel_count;
+        let mut material = OwnedAudioBuffer::new(channel_count, frame_count);
+        material
+            .to_buf_mut()
+            .data_as_mut_slice()
+            .copy_from_slice(interleaved_material);
+        AudioOverdubState {
+            data: AudioOverdubData {
+                material: Arc::new(material),
+                previous_material: Arc::new(OwnedAudioBuffer::new(channel_count, frame_count)),
+                snapshot: None,
+                frame_rate: Hz::new(48000.0),
+            },
+            settings: sanitize_audio_overdub_settings(AudioOverdubSettings { feedback }),
+            layer: None,
+            can_undo: false,
+        }
+    }
+
+    fn assert_material(state: &AudioOverdubState, expected_interleaved_material: &[f64]) {
+        let material = state.data.material.to_buf();
+        let actual = material.data_as_slice();
+        assert_eq!(actual.len(), expected_interleaved_material.len());
+        for (a, e) in actual.iter().zip(expected_interleaved_material) {
+            assert!((a - e).abs() < 0.000001, "{:?}", actual);
+        }
+    }
+
+    struct TestWriteAudioRequest {
+        props: BasicAudioRequestProps,
+        /// One vector per channel.
+        channels: Vec<Vec<f64>>,
+    }
+
+    impl TestWriteAudioRequest {
+        fn new(channels: Vec<Vec<f64>>) -> Self {
+            Self {
+                props: BasicAudioRequestProps {
+                    block_length: channels[0].len(),
+                    frame_rate: Hz::new(48000.0),
+                },
+                channels,
+            }
+        }
+    }
+
+    impl WriteAudioRequest for TestWriteAudioRequest {
+        fn audio_request_props(&self) -> BasicAudioRequestProps {
+            self.props
+        }
+
+        fn get_channel_buffer(&self, channel_index: usize) -> Option<AudioBuf> {
+            let channel = self.channels.get(channel_index)?;
+            AudioBuf::from_slice(channel, 1, channel.len()).ok()
+        }
+    }
+
+    #[test]
+    fn count_in_on_grid_doesnt_round_up() {
+        // Given
+        let start = QuantizedPosition::bar(5);
+        let one_bar = EvenQuantization::new(1, 1).unwrap();
+        // When
+        let end = advance_quantized_pos(start, one_bar).unwrap();
+        // Then
+        assert_eq!(end.position(), 6);
+        assert_eq!(end.denominator(), 1);
+    }
+
+    #[test]
+    fn convert_bar_to_finer_grid() {
+        // Given
+        let start = QuantizedPosition::bar(3);
+        // When
+        let converted = convert_quantized_pos(start, 4).unwrap();
+        // Then
+        assert_eq!(converted.position(), 12);
+        assert_eq!(converted.denominator(), 4);
+        let end = advance_quantized_pos(converted, EvenQuantization::new(1, 4).unwrap()).unwrap();
+        assert_eq!(end.position(), 13);
+    }
+
+    #[test]
+    fn dont_convert_to_coarser_grid() {
+        // Given
+        let start = QuantizedPosition::new(5, 16).unwrap();
+        // When
+        let converted = convert_quantized_pos(start, 1);
+        // Then
+        assert!(converted.is_none());
+        assert!(advance_quantized_pos(start, EvenQuantization::new(4, 1).unwrap()).is_err());
+    }
+
+    #[test]
+    fn record_state_transitions_with_count_in_and_fixed_length() {
+        // Given
+        let mut recording = Recording {
+            total_frame_offset: 0,
+            num_count_in_frames: 1000,
+            frame_rate: Hz::new(48000.0),
+            first_play_frame: None,
+            scheduled_end: Some(ScheduledEnd {
+                quantized_end_pos: QuantizedPosition::bar(6),
+                complete_length: 5000,
+                is_predefined: true,
+            }),
+        };
+        // When
+        let state_before_polling = record_state_of(None);
+        let state_at_count_in_start = record_state_of(Some(&recording));
+        recording.total_frame_offset = 999;
+        let state_at_count_in_end = record_state_of(Some(&recording));
+        recording.total_frame_offset = 1000;
+        let state_after_count_in = record_state_of(Some(&recording));
+        // Then
+        assert_eq!(state_before_polling, RecordState::ScheduledForStart);
+        assert_eq!(state_at_count_in_start, RecordState::ScheduledForStart);
+        assert_eq!(state_at_count_in_end, RecordState::ScheduledForStart);
+        assert_eq!(state_after_count_in, RecordState::Recording);
+    }
+
+    #[test]
+    fn record_state_transitions_with_scheduled_stop() {
+        // Given
+        let mut recording = Recording {
+            total_frame_offset: 2000,
+            num_count_in_frames: 0,
+            frame_rate: Hz::new(48000.0),
+            first_play_frame: None,
+            scheduled_end: None,
+        };
+        // When
+        let state_before_stop = record_state_of(Some(&recording));
+        recording.scheduled_end = Some(ScheduledEnd {
+            quantized_end_pos: QuantizedPosition::bar(2),
+            complete_length: 5000,
+            is_predefined: false,
+        });
+        let state_after_stop = record_state_of(Some(&recording));
+        // Then
+        assert_eq!(state_before_stop, RecordState::Recording);
+        assert_eq!(state_after_stop, RecordState::ScheduledForStop);
+    }
+
+    #[test]
+    fn count_in_click_sounds_shortly_after_each_beat() {
+        // Given
+        let click = CountInClick {
+            seconds_until_start: 2.0,
+            beat_duration: 0.5,
+            beats_per_bar: 4,
+        };
+        // When
+        let value_after_beat = click.sample_value_at(1.5 - 0.001);
+        let value_between_beats = click.sample_value_at(1.25);
+        let value_after_start = click.sample_value_at(-0.001);
+        // Then
+        assert!(value_after_beat.abs() > 0.0);
+        assert_eq!(value_between_beats, 0.0);
+        assert_eq!(value_after_start, 0.0);
+    }
+
+    #[test]
+    fn count_in_click_accents_first_beat_of_bar() {
+        // Given
+        let click = CountInClick {
+            seconds_until_start: 2.0,
+            beat_duration: 0.5,
+            beats_per_bar: 4,
+        };
+        // Peak of the accented click's sine
+        let seconds_since_beat = 1.0 / (4.0 * COUNT_IN_CLICK_ACCENT_FREQUENCY);
+        // When
+        let value_after_bar_start = click.sample_value_at(2.0 - seconds_since_beat);
+        let value_after_other_beat = click.sample_value_at(1.5 - seconds_since_beat);
+        // Then
+        assert!(value_after_bar_start > value_after_other_beat);
+        assert!(value_after_other_beat > 0.0);
+    }
+}
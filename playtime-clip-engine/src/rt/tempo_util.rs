@@ -49,3 +49,55 @@ pub fn calc_tempo_factor(clip_tempo: Bpm, timeline_tempo: Bpm) -> f64 {
 }
 
 const MIN_TEMPO_FACTOR: f64 = 0.0000000001;
+
+/// Calculates the tempo at which material of the given duration spans a whole number of bars.
+///
+/// If no bar count is given, it picks the bar count which results in a tempo closest to the given
+/// current tempo.
+pub fn calc_lead_tempo(
+    duration: DurationInSeconds,
+    beats_per_bar: u32,
+    bar_count: Option<u32>,
+    current_tempo: Bpm,
+) -> Bpm {
+    if duration.get() <= 0.0 || beats_per_bar == 0 {
+        return current_tempo;
+    }
+    let tempo_for_bar_count =
+        |bar_count: f64| bar_count * beats_per_bar as f64 * 60.0 / duration.get();
+    let tempo = match bar_count {
+        Some(c) => tempo_for_bar_count(c.max(1) as f64),
+        None => {
+            let exact_bar_count =
+                current_tempo.get() * duration.get() / 60.0 / beats_per_bar as f64;
+            let lower = tempo_for_bar_count(exact_bar_count.floor().max(1.0));
+            let upper = tempo_for_bar_count(exact_bar_count.ceil().max(1.0));
+            if (lower - current_tempo.get()).abs() <= (upper - current_tempo.get()).abs() {
+                lower
+            } else {
+                upper
+            }
+        }
+    };
+    Bpm::new(tempo.clamp(Bpm::MIN.get(), Bpm::MAX.get()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lead_tempo_with_bar_count() {
+        let tempo = calc_lead_tempo(DurationInSeconds::new(7.5), 4, Some(4), Bpm::new(120.0));
+        assert_eq!(tempo, Bpm::new(128.0));
+    }
+
+    #[test]
+    fn lead_tempo_closest_to_current_tempo() {
+        // 4 bars would be 100 BPM, 5 bars 125 BPM
+        let tempo = calc_lead_tempo(DurationInSeconds::new(9.6), 4, None, Bpm::new(118.0));
+        assert_eq!(tempo, Bpm::new(125.0));
+        let tempo = calc_lead_tempo(DurationInSeconds::new(9.6), 4, None, Bpm::new(110.0));
+        assert_eq!(tempo, Bpm::new(100.0));
+    }
+}
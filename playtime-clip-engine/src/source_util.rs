@@ -14,7 +14,6 @@ use std::path::{Path, PathBuf};
 ///
 /// If the item uses pooled MIDI instead of a file, this method exports the MIDI data to a new
 /// file in the recording directory and uses that one.
-#[allow(dead_code)]
 pub fn create_api_source_from_item(
     item: Item,
     force_export_to_file: bool,